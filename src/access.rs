@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use iron::headers::{Authorization, Basic, Headers};
+use iron::{status, IronError, IronResult, Response};
+
+use crate::config_file;
+use crate::middlewares::Credential;
+use crate::util::{same_secret, StringError};
+
+/// The per-directory access control file (`--access-files`), never served
+pub const ACCESS_FILE: &str = ".shs-access";
+
+/// The rules of an access file, for its directory and everything below. `name = value` lines
+/// as in the `--config` files:
+/// - `auth = user:pass[:r|w|rw]`, repeatable: only these users, instead of anyone
+/// - `ignore = pattern`, repeatable: gitignore syntax, relative to the directory
+/// - `listing = false`: no directory listing
+#[derive(Default)]
+struct AccessFile {
+    users: Vec<Credential>,
+    ignore: Option<Gitignore>,
+    listing: Option<bool>,
+    // Of an invalid file, which denies its whole subtree
+    error: Option<String>,
+}
+
+impl AccessFile {
+    fn parse(path: &Path) -> Result<AccessFile, StringError> {
+        let invalid = |e: String| StringError(format!("Invalid {}: {}", path.display(), e));
+        let mut file = AccessFile::default();
+        let mut ignore = GitignoreBuilder::new("");
+        let mut patterns = 0;
+        for (name, value) in config_file::parse(path)? {
+            match (name.as_str(), value) {
+                ("auth", Some(value)) => file.users.push(
+                    Credential::parse(&value, (true, true, false)).map_err(|e| invalid(e.0))?,
+                ),
+                ("ignore", Some(value)) => {
+                    ignore
+                        .add_line(None, &value)
+                        .map_err(|e| invalid(format!("{}: {}", value, e)))?;
+                    patterns += 1;
+                }
+                ("listing", value) => {
+                    file.listing = Some(match value.as_deref() {
+                        None | Some("true" | "on" | "yes" | "1") => true,
+                        Some("false" | "off" | "no" | "0") => false,
+                        Some(value) => return Err(invalid(format!("listing = {}", value))),
+                    })
+                }
+                (name, _) => return Err(invalid(format!("unknown rule {}", name))),
+            }
+        }
+        if patterns > 0 {
+            file.ignore = Some(ignore.build().map_err(|e| invalid(e.to_string()))?);
+        }
+        Ok(file)
+    }
+}
+
+/// The `.shs-access` files of the served directories, reread once changed: no restart needed
+#[derive(Default)]
+pub struct AccessRules {
+    // directory => (mtime of its access file, parsed rules)
+    cache: Mutex<HashMap<PathBuf, (SystemTime, Arc<AccessFile>)>>,
+}
+
+impl AccessRules {
+    pub fn new() -> AccessRules {
+        AccessRules::default()
+    }
+
+    /// `path` must be inside the `root` directory it's served from: the access files themselves,
+    /// and the paths matching the `ignore` rules of one above
+    pub fn is_ignored(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        if path.file_name() == Some(OsStr::new(ACCESS_FILE)) {
+            return true;
+        }
+        let parent = match path.parent() {
+            Some(parent) if path != root => parent,
+            _ => return false,
+        };
+        self.files(root, parent).iter().any(|(dir, file)| {
+            let relative = path.strip_prefix(dir).unwrap_or(path);
+            file.ignore.as_ref().is_some_and(|rules| {
+                rules
+                    .matched_path_or_any_parents(relative, is_dir)
+                    .is_ignore()
+            })
+        })
+    }
+
+    /// The nearest access file with a `listing` rule decides, allowed otherwise
+    pub fn listing(&self, root: &Path, dir: &Path) -> bool {
+        self.files(root, dir)
+            .iter()
+            .find_map(|(_, file)| file.listing)
+            .unwrap_or(true)
+    }
+
+    /// The users of the nearest access file with some, for `path` (the directory, or the file in
+    /// it): 401 without their credentials, 403 without the permission to `write` or read
+    pub fn check(
+        &self,
+        headers: &Headers,
+        write: bool,
+        root: &Path,
+        path: &Path,
+    ) -> IronResult<()> {
+        let dir = match path.parent() {
+            Some(parent) if !path.is_dir() => parent,
+            _ => path,
+        };
+        let files = self.files(root, dir);
+        if let Some(error) = files.iter().find_map(|(_, file)| file.error.as_ref()) {
+            return Err(IronError::new(
+                StringError(error.clone()),
+                status::InternalServerError,
+            ));
+        }
+        let (dir, users) = match files.iter().find(|(_, file)| !file.users.is_empty()) {
+            Some((dir, file)) => (dir, &file.users),
+            None => return Ok(()),
+        };
+        match headers.get::<Authorization<Basic>>() {
+            Some(Authorization(Basic { username, password })) => {
+                let credential = users.iter().find(|c| {
+                    *username == c.username
+                        && password
+                            .as_deref()
+                            .is_some_and(|p| same_secret(p, &c.password))
+                });
+                match credential {
+                    Some(c) if (write && c.write) || (!write && c.read) => Ok(()),
+                    Some(_) => Err(IronError::new(
+                        StringError("permission denied".to_owned()),
+                        (
+                            status::Forbidden,
+                            if write {
+                                "No permission to upload."
+                            } else {
+                                "No permission to download."
+                            },
+                        ),
+                    )),
+                    None => Err(IronError::new(
+                        StringError("authorization error".to_owned()),
+                        (status::Unauthorized, "Wrong username or password."),
+                    )),
+                }
+            }
+            None => {
+                // A realm of its own, the browsers ask again for another directory
+                let realm = format!(
+                    "Basic realm=\"/{}\"",
+                    dir.strip_prefix(root).unwrap_or(dir).to_string_lossy()
+                );
+                let mut resp = Response::with(status::Unauthorized);
+                resp.headers
+                    .set_raw("WWW-Authenticate", vec![realm.into_bytes()]);
+                Err(IronError {
+                    error: Box::new(StringError("authorization error".to_owned())),
+                    response: resp,
+                })
+            }
+        }
+    }
+
+    // The access files of `dir` and of its parents up to `root`, the deepest first
+    fn files(&self, root: &Path, dir: &Path) -> Vec<(PathBuf, Arc<AccessFile>)> {
+        let mut files = Vec::new();
+        let mut current = Some(dir);
+        while let Some(dir) = current.filter(|dir| dir.starts_with(root)) {
+            if let Some(file) = self.load(dir) {
+                files.push((dir.to_path_buf(), file));
+            }
+            if dir == root {
+                break;
+            }
+            current = dir.parent();
+        }
+        files
+    }
+
+    fn load(&self, dir: &Path) -> Option<Arc<AccessFile>> {
+        let path = dir.join(ACCESS_FILE);
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((cached_modified, file)) = cache.get(dir) {
+            if *cached_modified == modified {
+                return Some(file.clone());
+            }
+        }
+        let file = Arc::new(AccessFile::parse(&path).unwrap_or_else(|e| AccessFile {
+            error: Some(e.0),
+            ..AccessFile::default()
+        }));
+        cache.insert(dir.to_path_buf(), (modified, file.clone()));
+        Some(file)
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use acme_lib::persist::FilePersist;
+use acme_lib::{create_p384_key, Certificate, Directory, DirectoryUrl};
+use iron::headers::ContentType;
+use iron::modifiers::Redirect;
+use iron::status;
+use iron::{Handler, Iron, IronError, IronResult, Listening, Request, Response, Url};
+use openssl::x509::X509;
+
+use crate::tls::{AcmeOptions, Identity, OpensslServer};
+use crate::util::StringError;
+
+const CHALLENGE_PATH: &str = ".well-known/acme-challenge";
+// Let's Encrypt certificates last for 90 days
+const RENEW_DAYS_LEFT: i64 = 30;
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const POLL_DELAY_MILLIS: u64 = 5000;
+
+// token => key authorization
+type Challenges = Arc<RwLock<HashMap<String, String>>>;
+
+/// Obtains certificates through HTTP-01 challenges and keeps them renewed
+pub struct AcmeClient {
+    opts: AcmeOptions,
+    challenges: Challenges,
+    // PEM of the certificate in use
+    installed: String,
+    listenings: Vec<Listening>,
+}
+
+impl AcmeClient {
+    /// Start answering challenges and return the current certificate (requested if necessary)
+    pub fn start(opts: &AcmeOptions) -> Result<(AcmeClient, Identity), StringError> {
+        fs::create_dir_all(&opts.cache_dir).map_err(|e| {
+            StringError(format!(
+                "Create ACME directory {} failed: {}",
+                opts.cache_dir.display(),
+                e
+            ))
+        })?;
+        let challenges = Challenges::default();
+        let handler = ChallengeHandler {
+            challenges: challenges.clone(),
+            https_port: opts.https_port,
+        };
+        let mut client = AcmeClient {
+            opts: opts.clone(),
+            challenges,
+            installed: String::new(),
+            listenings: Vec::new(),
+        };
+        let rv = opts.http_addrs.iter().try_for_each(|addr| {
+            let listening = Iron::new(handler.clone()).http(addr).map_err(|e| {
+                StringError(format!(
+                    "Can not bind ACME challenge listener on {}, {}",
+                    addr, e
+                ))
+            })?;
+            client.listenings.push(listening);
+            Ok(())
+        });
+        let cert = rv.and_then(|_| client.certificate()).inspect_err(|_| {
+            // Dropping a `Listening` waits for it forever
+            for listening in &mut client.listenings {
+                let _ = listening.close();
+            }
+        })?;
+        client.installed = cert.certificate().to_owned();
+        Ok((client, identity(&cert)))
+    }
+
+    /// Check the certificate periodically, renew it and install it into `server`
+    pub fn spawn_renewal(mut self, server: OpensslServer) {
+        thread::spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+            match self.certificate() {
+                Ok(cert) => {
+                    if cert.certificate() == self.installed {
+                        continue;
+                    }
+                    match server.set_identity(identity(&cert)) {
+                        Ok(()) => self.installed = cert.certificate().to_owned(),
+                        Err(e) => eprintln!("ACME: install certificate failed: {}", e),
+                    }
+                }
+                Err(e) => eprintln!("ACME: {}", e),
+            }
+        });
+    }
+
+    /// The cached certificate, or a new one when it's expiring or doesn't cover all the domains
+    fn certificate(&self) -> Result<Certificate, StringError> {
+        let map_err = |e: acme_lib::Error| StringError(format!("ACME: {}", e));
+        let primary = &self.opts.domains[0];
+        let alt_names = self.opts.domains[1..]
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+
+        let persist = FilePersist::new(&self.opts.cache_dir);
+        let dir = Directory::from_url(persist, DirectoryUrl::Other(&self.opts.directory_url))
+            .map_err(map_err)?;
+        let contact = self
+            .opts
+            .email
+            .as_ref()
+            .map(|email| vec![format!("mailto:{}", email)]);
+        let account = dir
+            .account_with_realm("account", contact)
+            .map_err(map_err)?;
+
+        if let Some(cert) = account.certificate(primary).map_err(map_err)? {
+            if cert.valid_days_left() > RENEW_DAYS_LEFT && covers(&cert, &self.opts.domains) {
+                return Ok(cert);
+            }
+        }
+
+        println!("ACME: requesting certificate for {:?}", self.opts.domains);
+        let mut order = account.new_order(primary, &alt_names).map_err(map_err)?;
+        let csr_order = loop {
+            if let Some(csr_order) = order.confirm_validations() {
+                break csr_order;
+            }
+            for auth in order.authorizations().map_err(map_err)? {
+                if !auth.need_challenge() {
+                    continue;
+                }
+                let challenge = auth.http_challenge();
+                let token = challenge.http_token().to_owned();
+                self.challenges
+                    .write()
+                    .unwrap()
+                    .insert(token.clone(), challenge.http_proof());
+                let rv = challenge.validate(POLL_DELAY_MILLIS);
+                self.challenges.write().unwrap().remove(&token);
+                rv.map_err(|e| {
+                    StringError(format!(
+                        "ACME: validate {} failed: {}",
+                        auth.domain_name(),
+                        e
+                    ))
+                })?;
+            }
+            order.refresh().map_err(map_err)?;
+        };
+        let cert = csr_order
+            .finalize_pkey(create_p384_key(), POLL_DELAY_MILLIS)
+            .and_then(|cert_order| cert_order.download_and_save_cert())
+            .map_err(map_err)?;
+        println!(
+            "ACME: certificate issued, valid for {} days",
+            cert.valid_days_left()
+        );
+        Ok(cert)
+    }
+}
+
+fn identity(cert: &Certificate) -> Identity {
+    Identity::Pem {
+        cert: cert.certificate().to_owned(),
+        key: cert.private_key().to_owned(),
+    }
+}
+
+fn covers(cert: &Certificate, domains: &[String]) -> bool {
+    let names = X509::from_pem(cert.certificate().as_bytes())
+        .ok()
+        .and_then(|x509| x509.subject_alt_names())
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| name.dnsname().map(str::to_lowercase))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    domains
+        .iter()
+        .all(|domain| names.contains(&domain.to_lowercase()))
+}
+
+/// Serves the pending challenges, other requests are redirected to https
+#[derive(Clone)]
+struct ChallengeHandler {
+    challenges: Challenges,
+    https_port: u16,
+}
+
+impl Handler for ChallengeHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = req.url.path().join("/");
+        if let Some(token) = path
+            .strip_prefix(CHALLENGE_PATH)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            return match self.challenges.read().unwrap().get(token) {
+                Some(proof) => Ok(Response::with((
+                    status::Ok,
+                    ContentType::plaintext().0,
+                    proof.clone(),
+                ))),
+                None => Err(IronError::new(
+                    StringError("Unknown ACME challenge".to_owned()),
+                    status::NotFound,
+                )),
+            };
+        }
+
+        let mut url: hyper::Url = req.url.clone().into();
+        url.set_scheme("https").unwrap();
+        url.set_port(if self.https_port == 443 {
+            None
+        } else {
+            Some(self.https_port)
+        })
+        .unwrap();
+        Ok(Response::with((
+            status::MovedPermanently,
+            Redirect(Url::from_generic_url(url).unwrap()),
+        )))
+    }
+}
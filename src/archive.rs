@@ -0,0 +1,151 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use iron::response::WriteBody;
+
+/// Query of the archive downloads (`POST /dir/?archive` with `path=a.txt&path=sub`)
+pub const ARCHIVE_QUERY: &str = "archive";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// `tar` or `tar.gz` (`?download=tar.gz`)
+    pub fn parse(s: &str) -> Option<ArchiveFormat> {
+        match s {
+            "tar" => Some(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" => Some(ArchiveFormat::TarGz),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// Streams files and directories as a tar archive, while it's written to the client
+pub struct ArchiveBody {
+    // (path on disk, path in the archive)
+    entries: Vec<(PathBuf, PathBuf)>,
+    format: ArchiveFormat,
+}
+
+impl ArchiveBody {
+    /// `paths` are relative to `dir` and keep this layout in the archive, `skip` filters out
+    /// the entries (ignore rules) of the directories walked through
+    pub fn new<F>(
+        dir: &Path,
+        paths: &[PathBuf],
+        format: ArchiveFormat,
+        skip: F,
+    ) -> io::Result<ArchiveBody>
+    where
+        F: Fn(&Path, bool) -> bool,
+    {
+        let mut entries = Vec::new();
+        for path in paths {
+            let fs_path = dir.join(path);
+            let metadata = fs::metadata(&fs_path)?;
+            if skip(&fs_path, metadata.is_dir()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Not Found: {}", path.display()),
+                ));
+            }
+            collect(&fs_path, path, metadata.is_dir(), &skip, &mut entries)?;
+        }
+        Ok(ArchiveBody { entries, format })
+    }
+
+    /// The whole directory, as the `name` folder of the archive
+    pub fn directory<F>(
+        dir: &Path,
+        name: &Path,
+        format: ArchiveFormat,
+        skip: F,
+    ) -> io::Result<ArchiveBody>
+    where
+        F: Fn(&Path, bool) -> bool,
+    {
+        let mut entries = Vec::new();
+        collect(dir, name, true, &skip, &mut entries)?;
+        Ok(ArchiveBody { entries, format })
+    }
+}
+
+fn collect<F>(
+    fs_path: &Path,
+    name: &Path,
+    is_dir: bool,
+    skip: &F,
+    entries: &mut Vec<(PathBuf, PathBuf)>,
+) -> io::Result<()>
+where
+    F: Fn(&Path, bool) -> bool,
+{
+    entries.push((fs_path.to_path_buf(), name.to_path_buf()));
+    if !is_dir {
+        return Ok(());
+    }
+    let mut children = fs::read_dir(fs_path)?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(|entry| entry.file_name());
+    for child in children {
+        let path = child.path();
+        let is_dir = match fs::metadata(&path) {
+            Ok(metadata) => metadata.is_dir(),
+            // Broken link
+            Err(_) => continue,
+        };
+        // Linked directories could loop
+        if is_dir && child.file_type()?.is_symlink() {
+            continue;
+        }
+        if !skip(&path, is_dir) {
+            collect(&path, &name.join(child.file_name()), is_dir, skip, entries)?;
+        }
+    }
+    Ok(())
+}
+
+impl ArchiveBody {
+    fn write_tar<W: Write>(&self, writer: W) -> io::Result<W> {
+        let mut builder = tar::Builder::new(writer);
+        for (fs_path, name) in &self.entries {
+            if fs_path.is_dir() {
+                builder.append_dir(name, fs_path)?;
+            } else {
+                builder.append_path_with_name(fs_path, name)?;
+            }
+        }
+        builder.into_inner()
+    }
+}
+
+impl WriteBody for ArchiveBody {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        match self.format {
+            ArchiveFormat::Tar => self.write_tar(res).map(|_| ()),
+            ArchiveFormat::TarGz => self
+                .write_tar(GzEncoder::new(res, Compression::default()))?
+                .finish()
+                .map(|_| ()),
+        }
+    }
+}
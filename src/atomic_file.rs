@@ -0,0 +1,62 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::util::random_token;
+
+// Uploads are written to `.<name>.<token>.upload-tmp` next to the target
+const TEMP_SUFFIX: &str = ".upload-tmp";
+
+/// Whether `name` is the temporary file of an unfinished upload
+pub fn is_temp(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.') && name.ends_with(TEMP_SUFFIX)
+}
+
+/// Writes `data` to `target` so that it either has the whole data or is unchanged
+pub fn save<R: Read>(data: &mut R, target: &Path) -> io::Result<u64> {
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let tmp = target.with_file_name(format!(
+        ".{}.{}{}",
+        name.to_string_lossy(),
+        random_token(8),
+        TEMP_SUFFIX
+    ));
+    let rv = fs::File::create(&tmp).and_then(|mut file| {
+        let size = io::copy(data, &mut file)?;
+        file.sync_all()?;
+        fs::rename(&tmp, target)?;
+        Ok(size)
+    });
+    if rv.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    rv
+}
+
+/// Removes the temporary files left by a crash under `dir`, returns how many were removed
+pub fn remove_stale(dir: &Path) -> usize {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    let mut removed = 0;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            removed += remove_stale(&entry.path());
+        } else if file_type.is_file()
+            && is_temp(&entry.file_name())
+            && fs::remove_file(entry.path()).is_ok()
+        {
+            removed += 1;
+        }
+    }
+    removed
+}
@@ -0,0 +1,71 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use iron::status;
+use serde_json::json;
+
+use crate::util::StringError;
+
+/// The changes of the served files (`--audit-log`), apart from the access log: one JSON line
+/// per upload, delete or directory created, with the user, the client IP and the result.
+/// Only appended to, never rotated nor truncated
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+/// Who changed the files: the authenticated user (or the CN of the client certificate)
+pub struct Actor {
+    pub user: Option<String>,
+    pub client_ip: IpAddr,
+}
+
+impl AuditLog {
+    pub fn new(path: &Path) -> Result<AuditLog, StringError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| StringError(format!("Open audit log {} failed: {}", path.display(), e)))?;
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// `event` of `path` (relative to the root): `upload`, `delete` or `mkdir`, the size of the
+    /// uploads, the status and reason of the failures
+    pub fn record(
+        &self,
+        actor: &Actor,
+        event: &str,
+        path: &str,
+        size: Option<u64>,
+        result: Result<(), (status::Status, &str)>,
+    ) {
+        let mut line = json!({
+            "time": chrono::Local::now().to_rfc3339(),
+            "event": event,
+            "path": path,
+            "user": actor.user,
+            "client_ip": actor.client_ip.to_string(),
+            "result": if result.is_ok() { "ok" } else { "failed" },
+        });
+        if let Some(size) = size {
+            line["size"] = json!(size);
+        }
+        if let Err((status, reason)) = result {
+            line["status"] = json!(status.to_u16());
+            line["error"] = json!(reason);
+        }
+        // One write per line, appended whole
+        let line = format!("{}\n", line);
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            eprintln!("  >> Write audit log {} failed: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use iron::headers::{Authorization, Basic};
+use iron::response::WriteBody;
+use iron::status;
+use iron::{IronError, IronResult, Request, Response};
+
+use crate::util::{decode_path_segment, StringError};
+
+/// Scripts run for the URLs under a prefix (`--cgi-dir /cgi-bin=/path/to/scripts`)
+pub struct CgiDir {
+    prefix: Vec<String>,
+    dir: PathBuf,
+}
+
+/// A script matched by the URL, and the rest of the URL (`PATH_INFO`)
+pub struct Script {
+    pub path: PathBuf,
+    pub name: String,
+    pub path_info: String,
+}
+
+impl CgiDir {
+    pub fn parse(s: &str) -> Result<CgiDir, StringError> {
+        let parts = s.splitn(2, '=').collect::<Vec<&str>>();
+        if parts.len() != 2 {
+            return Err(StringError(format!("not valid format /prefix=dir: {}", s)));
+        }
+        let prefix = parts[0]
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<String>>();
+        if prefix.is_empty() {
+            return Err(StringError(format!("empty prefix: {}", s)));
+        }
+        let dir = PathBuf::from(parts[1]);
+        if !dir.is_dir() {
+            return Err(StringError(format!("not a directory: {}", parts[1])));
+        }
+        Ok(CgiDir { prefix, dir })
+    }
+
+    /// The script of the URL, `None` when the URL is outside the prefix
+    pub fn resolve(&self, url_path: &[&str]) -> Option<IronResult<Script>> {
+        if url_path.len() <= self.prefix.len() || url_path[..self.prefix.len()] != self.prefix[..] {
+            return None;
+        }
+        let not_found = || {
+            IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            )
+        };
+        let mut path = self.dir.clone();
+        let mut name = format!("/{}", self.prefix.join("/"));
+        // The first file on the way is the script, the directories are walked through
+        for (index, segment) in url_path.iter().enumerate().skip(self.prefix.len()) {
+            let decoded = match decode_path_segment(segment) {
+                Ok(decoded) => decoded,
+                Err(e) => return Some(Err(IronError::new(e, status::BadRequest))),
+            };
+            let invalid = decoded.to_string_lossy().contains(['/', '\\']);
+            if decoded.is_empty() || decoded == ".." || decoded == "." || invalid {
+                return Some(Err(not_found()));
+            }
+            path.push(&decoded);
+            name.push('/');
+            name.push_str(segment);
+            if path.is_file() {
+                let rest = &url_path[index + 1..];
+                let path_info = if rest.is_empty() {
+                    String::new()
+                } else {
+                    rest.iter()
+                        .map(|segment| {
+                            decode_path_segment(segment)
+                                .map(|s| format!("/{}", s.to_string_lossy()))
+                                .unwrap_or_default()
+                        })
+                        .collect()
+                };
+                return Some(Ok(Script {
+                    path,
+                    name,
+                    path_info,
+                }));
+            }
+            if !path.is_dir() {
+                break;
+            }
+        }
+        Some(Err(not_found()))
+    }
+}
+
+/// The meta-variables of RFC 3875 (and the usual `SCRIPT_FILENAME`, `DOCUMENT_ROOT`)
+pub fn environment(
+    req: &Request,
+    script_name: &str,
+    path_info: &str,
+    script_filename: &Path,
+    document_root: &Path,
+) -> Vec<(String, String)> {
+    let url = req.url.as_ref();
+    let mut env = vec![
+        ("GATEWAY_INTERFACE", "CGI/1.1".to_owned()),
+        (
+            "SERVER_SOFTWARE",
+            format!("simple-http-server/{}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("SERVER_PROTOCOL", req.version.to_string()),
+        ("SERVER_NAME", url.host_str().unwrap_or("").to_owned()),
+        (
+            "SERVER_PORT",
+            url.port_or_known_default().unwrap_or(80).to_string(),
+        ),
+        ("REQUEST_METHOD", req.method.to_string()),
+        ("REQUEST_SCHEME", url.scheme().to_owned()),
+        ("REQUEST_URI", {
+            let mut uri = url.path().to_owned();
+            if let Some(query) = url.query() {
+                uri.push('?');
+                uri.push_str(query);
+            }
+            uri
+        }),
+        ("SCRIPT_NAME", script_name.to_owned()),
+        (
+            "SCRIPT_FILENAME",
+            script_filename.to_string_lossy().to_string(),
+        ),
+        ("DOCUMENT_ROOT", document_root.to_string_lossy().to_string()),
+        ("PATH_INFO", path_info.to_owned()),
+        ("QUERY_STRING", url.query().unwrap_or("").to_owned()),
+        ("REMOTE_ADDR", req.remote_addr.ip().to_string()),
+        ("REMOTE_PORT", req.remote_addr.port().to_string()),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_owned(), v))
+    .collect::<Vec<(String, String)>>();
+    if !path_info.is_empty() {
+        env.push((
+            "PATH_TRANSLATED".to_owned(),
+            document_root
+                .join(path_info.trim_start_matches('/'))
+                .to_string_lossy()
+                .to_string(),
+        ));
+    }
+    if url.scheme() == "https" {
+        env.push(("HTTPS".to_owned(), "on".to_owned()));
+    }
+    if let Some(Authorization(Basic { username, .. })) = req.headers.get::<Authorization<Basic>>() {
+        env.push(("AUTH_TYPE".to_owned(), "Basic".to_owned()));
+        env.push(("REMOTE_USER".to_owned(), username.clone()));
+    }
+    for header in req.headers.iter() {
+        let name = header.name().to_uppercase().replace('-', "_");
+        let value = header.value_string();
+        match name.as_str() {
+            "CONTENT_TYPE" | "CONTENT_LENGTH" => env.push((name, value)),
+            // Credentials aren't passed to the scripts
+            "AUTHORIZATION" | "PROXY" => {}
+            _ => env.push((format!("HTTP_{}", name), value)),
+        }
+    }
+    env
+}
+
+/// The response of a script: the `Status`, `Location` and other header lines before the body
+pub fn parse_headers<R: BufRead>(reader: &mut R) -> io::Result<Response> {
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid CGI header: {}", line),
+        )
+    };
+    let mut resp = Response::new();
+    let mut has_status = false;
+    let mut has_location = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "CGI script ended before the end of the headers",
+            ));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid(line))?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Status") {
+            let code = value
+                .split_whitespace()
+                .next()
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| invalid(line))?;
+            resp.status = Some(status::Status::from_u16(code));
+            has_status = true;
+        } else {
+            if name.eq_ignore_ascii_case("Location") {
+                has_location = true;
+            }
+            resp.headers
+                .append_raw(name.to_owned(), value.as_bytes().to_vec());
+        }
+    }
+    if !has_status {
+        resp.status = Some(if has_location {
+            status::Found
+        } else {
+            status::Ok
+        });
+    }
+    Ok(resp)
+}
+
+/// Executes the script with the request body as stdin, its stdout is streamed as the response
+pub fn run(req: &mut Request, script: &Script, document_root: &Path) -> IronResult<Response> {
+    let failed = |e: io::Error| {
+        IronError::new(
+            StringError(format!("CGI script {} failed: {}", script.name, e)),
+            status::BadGateway,
+        )
+    };
+    let mut child = Command::new(&script.path)
+        .current_dir(script.path.parent().unwrap_or(document_root))
+        .env_clear()
+        .envs(env::var_os("PATH").map(|path| (OsString::from("PATH"), path)))
+        .envs(environment(
+            req,
+            &script.name,
+            &script.path_info,
+            &script.path,
+            document_root,
+        ))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(failed)?;
+
+    // Written by another thread: the script may answer before it has read the whole body
+    let mut stdin = child.stdin.take().unwrap();
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        for chunk in receiver {
+            if stdin.write_all(&chunk).is_err() {
+                break;
+            }
+        }
+    });
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = req.body.read(&mut buf).map_err(failed)?;
+        if n == 0 || sender.send(buf[..n].to_vec()).is_err() {
+            break;
+        }
+    }
+    drop(sender);
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut resp = match parse_headers(&mut stdout) {
+        Ok(resp) => resp,
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(failed(e));
+        }
+    };
+    resp.body = Some(Box::new(CgiBody { stdout, child }));
+    Ok(resp)
+}
+
+struct CgiBody {
+    stdout: BufReader<ChildStdout>,
+    child: Child,
+}
+
+impl WriteBody for CgiBody {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        io::copy(&mut self.stdout, res).map(|_| ())
+    }
+}
+
+impl Drop for CgiBody {
+    fn drop(&mut self) {
+        // The client went away (or HEAD): don't leave the script running
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn headers(output: &str) -> io::Result<Response> {
+        parse_headers(&mut output.as_bytes())
+    }
+
+    #[test]
+    fn status_and_location() {
+        let resp = headers("Content-Type: text/plain\r\n\r\nbody").unwrap();
+        assert_eq!(resp.status, Some(status::Ok));
+        assert_eq!(
+            resp.headers.get_raw("Content-Type"),
+            Some(&[b"text/plain".to_vec()][..])
+        );
+        let resp = headers("Location: /a\n\n").unwrap();
+        assert_eq!(resp.status, Some(status::Found));
+        let resp = headers("status: 404 Not Found\nLocation: /a\n\n").unwrap();
+        assert_eq!(resp.status, Some(status::NotFound));
+        assert!(resp.headers.get_raw("Status").is_none());
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert_eq!(
+            headers("Content-Type text/plain\n\n").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            headers("Status: ok\n\n").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            headers("Content-Type: text/plain\n").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn resolve_scripts() {
+        let dir = env::temp_dir().join(format!("simple-http-server-cgi-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("run.sh"), "").unwrap();
+        let cgi = CgiDir::parse(&format!("/cgi-bin={}", dir.display())).unwrap();
+        let status = |url_path: &[&str]| match cgi.resolve(url_path) {
+            None => None,
+            Some(Ok(_)) => Some(status::Ok),
+            Some(Err(e)) => e.response.status,
+        };
+
+        assert_eq!(status(&["other", "run.sh"]), None);
+        assert_eq!(status(&["cgi-bin"]), None);
+        let script = cgi
+            .resolve(&["cgi-bin", "sub", "run.sh", "a%20b", "c"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(script.path, dir.join("sub").join("run.sh"));
+        assert_eq!(script.name, "/cgi-bin/sub/run.sh");
+        assert_eq!(script.path_info, "/a b/c");
+        // Never out of the directory, nor through an empty segment
+        assert_eq!(status(&["cgi-bin", "..", "run.sh"]), Some(status::NotFound));
+        assert_eq!(
+            status(&["cgi-bin", "sub", "%2E%2E", "sub"]),
+            Some(status::NotFound)
+        );
+        assert_eq!(
+            status(&["cgi-bin", "", "sub", "run.sh"]),
+            Some(status::NotFound)
+        );
+        assert_eq!(status(&["cgi-bin", "sub%2Frun.sh"]), Some(status::NotFound));
+        assert_eq!(status(&["cgi-bin", "missing"]), Some(status::NotFound));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
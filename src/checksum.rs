@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+/// SHA-256 digests of the served files (`--checksums`)
+pub struct ChecksumCache {
+    // path => (mtime, size, hex digest)
+    cache: Mutex<HashMap<PathBuf, (SystemTime, u64, String)>>,
+}
+
+impl ChecksumCache {
+    pub fn new() -> ChecksumCache {
+        ChecksumCache {
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Hex digest of the file, recomputed only when the file changed
+    pub fn sha256(&self, path: &Path) -> io::Result<String> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        if let Some((cached_modified, cached_len, digest)) = self.cache.lock().unwrap().get(path) {
+            if *cached_modified == modified && *cached_len == metadata.len() {
+                return Ok(digest.clone());
+            }
+        }
+        // Don't hold the lock while reading the (maybe large) file
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        let digest = format!("{:x}", hasher.finalize());
+        self.cache.lock().unwrap().insert(
+            path.to_path_buf(),
+            (modified, metadata.len(), digest.clone()),
+        );
+        Ok(digest)
+    }
+}
@@ -0,0 +1,1836 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{crate_name, crate_version};
+use iron::{Listening, Protocol};
+use termcolor::{Color, ColorSpec};
+
+use crate::cgi::CgiDir;
+use crate::color::{build_spec, Printer};
+use crate::fastcgi::FastCgi;
+use crate::handler::LiveSettings;
+use crate::listener::{QueuedListener, TlsListener};
+use crate::middlewares::{parse_net, Credential, RateLimiter};
+use crate::protect::ProtectedPaths;
+use crate::server::{Server, SimpleHttpServer};
+use crate::tls::{AcmeOptions, Identity, TlsOptions};
+use crate::upload_command::UploadCommand;
+use crate::util::{enable_string, now_string, parse_duration, parse_mime_override, StringError};
+use crate::{
+    atomic_file, color, config_file, daemon, lan, man, mdns, port_mapping, privileges, syslog, tls,
+};
+
+const LETS_ENCRYPT_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// The command line interface, also the source of the shell completions and the man page
+fn build_app() -> clap::App<'static, 'static> {
+    clap::App::new("Simple HTTP(s) Server")
+        .setting(clap::AppSettings::ColoredHelp)
+        .version(crate_version!())
+        .arg(clap::Arg::with_name("root")
+             .index(1)
+             .validator(|s| {
+                 match fs::metadata(s) {
+                     Ok(metadata) => {
+                         if metadata.is_dir() { Ok(()) } else {
+                             Err("Not directory".to_owned())
+                         }
+                     },
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Root directory"))
+        .arg(clap::Arg::with_name("index")
+             .short("i")
+             .long("index")
+             .help("Enable automatic render index page [index.html, index.htm]"))
+        .arg(clap::Arg::with_name("upload")
+             .short("u")
+             .long("upload")
+             .help("Enable upload files (multiple select)"))
+        .arg(clap::Arg::with_name("upload-only")
+             .long("upload-only")
+             .requires("upload")
+             .help("Drop box: the directories only show the upload form, the files can't be downloaded, deleted nor replaced"))
+        .arg(clap::Arg::with_name("delete")
+             .long("delete")
+             .visible_alias("allow-delete")
+             .requires("upload")
+             .conflicts_with("upload-only")
+             .help("Allow deleting files and directories (DELETE requests, the delete links of the listings)"))
+        .arg(clap::Arg::with_name("nocsrf")
+             .long("nocsrf")
+             .help("Disable Origin and CSRF token checks of uploads (allows uploading by scripts)"))
+        .arg(clap::Arg::with_name("redirect").long("redirect")
+             .takes_value(true)
+             .validator(|url_string| iron::Url::parse(url_string.as_str()).map(|_| ()))
+             .help("takes a URL to redirect to using HTTP 301 Moved Permanently"))
+        .arg(clap::Arg::with_name("nolisting")
+             .long("nolisting")
+             .visible_alias("index-only")
+             .help("Disable directory listing, directories without an index page return 403"))
+        .arg(clap::Arg::with_name("ignore")
+             .long("ignore")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("PATTERN")
+             .help("Hide matched paths from listing and reply 404 (repeatable, gitignore syntax)\n    Example: --ignore '*.log' --ignore node_modules/"))
+        .arg(clap::Arg::with_name("gitignore")
+             .long("gitignore")
+             .help("Also respect the .gitignore files (and hide .git directories)"))
+        .arg(clap::Arg::with_name("access-files")
+             .long("access-files")
+             .help("Apply the .shs-access files to their directory and below, reread once changed:\n    auth = user:pass[:r|w|rw] (repeatable), ignore = <pattern> (repeatable), listing = false"))
+        .arg(clap::Arg::with_name("protect-path")
+             .long("protect-path")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("/path=password")
+             .validator(|s| ProtectedPaths::new(&[&s]).map(|_| ()).map_err(|e| e.0))
+             .help("A password of its own for this directory (or file) and below, apart from --auth: asked by a form, or ?pw=password\n    Example: --protect-path /secret=pw"))
+        .arg(clap::Arg::with_name("nosort")
+             .long("nosort")
+             .help("Disable directory entries sort (by: name, modified, size)"))
+        .arg(clap::Arg::with_name("no-canonical-redirect")
+             .long("no-canonical-redirect")
+             .help("Disable the 301 redirects adding the trailing slash of directories and removing duplicate slashes"))
+        .arg(clap::Arg::with_name("nocache")
+             .long("nocache")
+             .help("Disable http cache"))
+        .arg(clap::Arg::with_name("norange")
+             .long("norange")
+             .help("Disable header::Range support (partial request)"))
+        .arg(clap::Arg::with_name("cert")
+             .long("cert")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("[DOMAIN=]CERT")
+             .validator(|s| parse_cert(&s, "").map(|_| ()))
+             .help("TLS/SSL certificate: pkcs#12 file, or `cert.pem,key.pem` files (repeatable)\n    With DOMAIN, presented to the clients asking for this name by SNI (`*.example.com` matches one label), the one without is the default\n    Example: --cert site.p12 --cert example.com=example.pem,example.key"))
+        .arg(clap::Arg::with_name("acme-domain")
+             .long("acme-domain")
+             .takes_value(true)
+             .value_name("DOMAIN")
+             .multiple(true)
+             .number_of_values(1)
+             .help("Obtain (and renew) the TLS certificate from an ACME server (Let's Encrypt by default) for this domain (repeatable)\n    Note: agrees to the CA's terms of service, the domain must reach --acme-http-port"))
+        .arg(clap::Arg::with_name("acme-email")
+             .long("acme-email")
+             .takes_value(true)
+             .value_name("EMAIL")
+             .requires("acme-domain")
+             .help("Contact email of the ACME account"))
+        .arg(clap::Arg::with_name("acme-directory")
+             .long("acme-directory")
+             .takes_value(true)
+             .value_name("URL")
+             .default_value(LETS_ENCRYPT_DIRECTORY)
+             .help("ACME directory URL\n    Staging: https://acme-staging-v02.api.letsencrypt.org/directory"))
+        .arg(clap::Arg::with_name("acme-cache")
+             .long("acme-cache")
+             .takes_value(true)
+             .value_name("DIR")
+             .help("Directory keeping the ACME account key and certificates [default: ~/.simple-http-server/acme]"))
+        .arg(clap::Arg::with_name("page-size")
+             .long("page-size")
+             .takes_value(true)
+             .value_name("N")
+             .default_value("0")
+             .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+             .help("Entries per page of directory listing (next pages are loaded while scrolling), 0 for all; `?per_page=` overrides it"))
+        .arg(clap::Arg::with_name("listing-cache")
+             .long("listing-cache")
+             .takes_value(true)
+             .value_name("COUNT")
+             .conflicts_with("expire")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(0) => Err("Must be at least 1".to_owned()),
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Keep this many rendered listings (by directory, sort and page), rendered again once the directory changes (an entry added, removed or renamed)"))
+        .arg(clap::Arg::with_name("stream-listings")
+             .long("stream-listings")
+             .conflicts_with("listing-cache")
+             .help("Write the listings (chunked) while the directory is read, for huge directories: only their entries are kept to be sorted, not the rows (without --page-size)"))
+        .arg(clap::Arg::with_name("color")
+             .long("color")
+             .takes_value(true)
+             .value_name("WHEN")
+             .possible_values(&["auto", "always", "never"])
+             .default_value("auto")
+             .help("Colored output: `auto` only for terminals and without the NO_COLOR environment variable"))
+        .arg(clap::Arg::with_name("theme")
+             .long("theme")
+             .takes_value(true)
+             .possible_values(&["light", "dark", "auto"])
+             .default_value("auto")
+             .help("Color theme of the directory listing, `auto` follows the system (prefers-color-scheme)"))
+        .arg(clap::Arg::with_name("highlight")
+             .long("highlight")
+             .help("Render text/code files with syntax highlighting and line numbers on `?view=1`"))
+        .arg(clap::Arg::with_name("thumbnails")
+             .long("thumbnails")
+             .help("Show thumbnails of the images in directory listing (rendered on demand by /__thumb?path=...)"))
+        .arg(clap::Arg::with_name("thumbnail-cache")
+             .long("thumbnail-cache")
+             .takes_value(true)
+             .value_name("DIR")
+             .requires("thumbnails")
+             .help("Directory keeping the rendered thumbnails [default: ~/.simple-http-server/thumbnails]"))
+        .arg(clap::Arg::with_name("acme-http-port")
+             .long("acme-http-port")
+             .takes_value(true)
+             .value_name("PORT")
+             .default_value("80")
+             .validator(|s| {
+                 match s.parse::<u16>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Port of the http listener answering ACME challenges (other requests are redirected to https)"))
+        .arg(clap::Arg::with_name("cors")
+             .long("cors")
+             .help("Enable CORS via the \"Access-Control-Allow-Origin\" header"))
+        .arg(clap::Arg::with_name("tls-client-ca")
+             .long("tls-client-ca")
+             .takes_value(true)
+             .value_name("PATH")
+             .requires("tls")
+             .validator(|s| {
+                 match fs::metadata(s) {
+                     Ok(metadata) => {
+                         if metadata.is_file() { Ok(()) } else {
+                             Err("Not a regular file".to_owned())
+                         }
+                     },
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Require TLS client certificates signed by this CA (PEM format)"))
+        .arg(clap::Arg::with_name("tls-client-auth")
+             .long("tls-client-auth")
+             .requires_all(&["tls-client-ca", "auth"])
+             .help("Client certificate is optional and authenticates the request (by its CN) in place of HTTP Basic Auth"))
+        .arg(clap::Arg::with_name("tls-min-version")
+             .long("tls-min-version")
+             .takes_value(true)
+             .value_name("VERSION")
+             .requires("tls")
+             .possible_values(&["1.0", "1.1", "1.2", "1.3"])
+             .help("Refuse the older TLS versions, also enables TLS 1.3 [default: 1.0, up to 1.2]\n    Example: --tls-min-version 1.2"))
+        .arg(clap::Arg::with_name("tls-ciphers")
+             .long("tls-ciphers")
+             .takes_value(true)
+             .value_name("LIST")
+             .requires("tls")
+             .help("Allowed cipher suites, in OpenSSL format (TLS_* names are the TLS 1.3 suites) [default: Mozilla intermediate]\n    Example: --tls-ciphers ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:TLS_AES_128_GCM_SHA256"))
+        .arg(clap::Arg::with_name("certpass").
+             long("certpass")
+             .takes_value(true)
+             .help("TLS/SSL certificate password"))
+        .arg(clap::Arg::with_name("upload_size_limit")
+             .short("l")
+             .long("upload-size-limit")
+             .takes_value(true)
+             .default_value("8000000")
+             .value_name("NUM")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Upload file size limit [bytes]"))
+        .arg(clap::Arg::with_name("upload-limit-rate")
+             .long("upload-limit-rate")
+             .takes_value(true)
+             .value_name("BYTES")
+             .requires("upload")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(0) => Err("Must be at least 1".to_owned()),
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Limit the upload bandwidth of each client (user, or IP when anonymous) [bytes per second], downloads are not limited"))
+        .arg(clap::Arg::with_name("upload-max-per-hour")
+             .long("upload-max-per-hour")
+             .takes_value(true)
+             .value_name("COUNT")
+             .requires("upload")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(0) => Err("Must be at least 1".to_owned()),
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Limit the files uploaded by each client (user, or IP when anonymous) in an hour, others get 429 with Retry-After"))
+        .arg(clap::Arg::with_name("upload-quota")
+             .long("upload-quota")
+             .takes_value(true)
+             .value_name("NUM")
+             .requires("upload")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Limit of the total size of the root directory [bytes], uploads over it are refused (507)"))
+        .arg(clap::Arg::with_name("upload-allow")
+             .long("upload-allow")
+             .multiple(true)
+             .value_delimiter(",")
+             .takes_value(true)
+             .value_name("EXTS")
+             .requires("upload")
+             .help("Only accept uploads with these extensions (415 otherwise)\n    Example: --upload-allow jpg,png,tar.gz"))
+        .arg(clap::Arg::with_name("upload-deny")
+             .long("upload-deny")
+             .multiple(true)
+             .value_delimiter(",")
+             .takes_value(true)
+             .value_name("EXTS")
+             .requires("upload")
+             .help("Refuse uploads with these extensions (415)\n    Example: --upload-deny exe,bat,sh,elf,macho"))
+        .arg(clap::Arg::with_name("trash-dir")
+             .long("trash-dir")
+             .takes_value(true)
+             .value_name("PATH")
+             .requires("delete")
+             .help("Move deleted files into this directory (with a timestamp suffix) instead of removing them"))
+        .arg(clap::Arg::with_name("preserve-mtime")
+             .long("preserve-mtime")
+             .requires("upload")
+             .help("Keep the modification time of the uploaded files (sent by the uploader in the page)"))
+        .arg(clap::Arg::with_name("expire")
+             .long("expire")
+             .takes_value(true)
+             .value_name("DURATION")
+             .conflicts_with("preserve-mtime")
+             .validator(|s| parse_duration(&s).map(|_| ()).map_err(|e| e.0))
+             .help("Delete the files this long after their upload (modification time), or after the start for the older ones, the time left is shown in the listing\n    Example: --expire 24h (units: s, m, h, d)"))
+        .arg(clap::Arg::with_name("upload-sniff")
+             .long("upload-sniff")
+             .help("Also check executables and scripts by their content, as the extensions exe, elf, macho or sh"))
+        .arg(clap::Arg::with_name("ip")
+             .long("ip")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .default_value("0.0.0.0")
+             .validator(|s| {
+                 match IpAddr::from_str(&s) {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("IP address to bind (repeatable)\n    Example: --ip 127.0.0.1 --ip ::1"))
+        .arg(clap::Arg::with_name("qr")
+             .long("qr")
+             .help("Print a QR code of the network URL at startup (for phones on the same network)"))
+        .arg(clap::Arg::with_name("mdns")
+             .long("mdns")
+             .takes_value(true)
+             .min_values(0)
+             .value_name("NAME")
+             .help("Announce the server on the local network by mDNS/DNS-SD (Finder, Avahi) [default name: simple-http-server on <hostname>]"))
+        .arg(clap::Arg::with_name("public")
+             .long("public")
+             .requires("auth")
+             .help("Ask the router to forward the port (UPnP/NAT-PMP) and print the public URL\n    Warning: everyone on the internet can reach the server, requires --auth"))
+        .arg(clap::Arg::with_name("health-path")
+             .long("health-path")
+             .takes_value(true)
+             .value_name("PATH")
+             .validator(|s| {
+                 if s.starts_with('/') && s.len() > 1 {
+                     Ok(())
+                 } else {
+                     Err("must start with '/', example: /healthz".to_owned())
+                 }
+             })
+             .help("Answer GET <PATH> with 200 and a JSON body (uptime, version), without auth nor logging\n    Example: --health-path /healthz"))
+        .arg(clap::Arg::with_name("status")
+             .long("status")
+             .requires("auth")
+             .help("Serve a status page at /__status (uptime, connections, transfers, recent errors), requires --auth"))
+        .arg(clap::Arg::with_name("port")
+             .short("p")
+             .long("port")
+             .takes_value(true)
+             .default_value("8000")
+             .validator(|s| {
+                 match s.parse::<u16>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Port number"))
+        .arg(clap::Arg::with_name("trusted-proxy")
+             .long("trusted-proxy")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("CIDR")
+             .validator(|s| parse_net(&s).map(|_| ()))
+             .help("Take the client address and scheme from X-Forwarded-For/X-Forwarded-Proto (or Forwarded) headers of requests from these addresses (repeatable)\n    Example: --trusted-proxy 127.0.0.1 --trusted-proxy 10.0.0.0/8"))
+        .arg(clap::Arg::with_name("no-robots")
+             .long("no-robots")
+             .help("Keep search engines away: serve a robots.txt disallowing everything (unless the root has one) and send `X-Robots-Tag: noindex`"))
+        .arg(clap::Arg::with_name("security-headers")
+             .long("security-headers")
+             .help("Send the hardening headers: Strict-Transport-Security (over HTTPS), X-Content-Type-Options: nosniff, X-Frame-Options and Referrer-Policy, see the options below to change them"))
+        .arg(clap::Arg::with_name("hsts")
+             .long("hsts")
+             .takes_value(true)
+             .value_name("VALUE")
+             .help("Strict-Transport-Security of the HTTPS responses, `off` for none [default with --security-headers: max-age=31536000]\n    Example: --hsts 'max-age=63072000; includeSubDomains'"))
+        .arg(clap::Arg::with_name("frame-options")
+             .long("frame-options")
+             .takes_value(true)
+             .value_name("VALUE")
+             .help("X-Frame-Options, `off` for none [default with --security-headers: SAMEORIGIN]"))
+        .arg(clap::Arg::with_name("referrer-policy")
+             .long("referrer-policy")
+             .takes_value(true)
+             .value_name("VALUE")
+             .help("Referrer-Policy, `off` for none [default with --security-headers: no-referrer]"))
+        .arg(clap::Arg::with_name("max-connections")
+             .long("max-connections")
+             .takes_value(true)
+             .value_name("N")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Answer 503 to new connections when N are open already\n    Note: a connection is served by a worker thread, at most `--threads` are handled at once per address"))
+        .arg(clap::Arg::with_name("max-connections-per-ip")
+             .long("max-connections-per-ip")
+             .takes_value(true)
+             .value_name("N")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Answer 503 to new connections of a client IP with N open already"))
+        .arg(clap::Arg::with_name("queue")
+             .long("queue")
+             .takes_value(true)
+             .value_name("N")
+             .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+             .help("Keep at most N accepted connections waiting for a free worker thread, answer 503 with Retry-After to the others (HTTPS: close them) [default: unbounded]\n    Example: --threads 2 --queue 8"))
+        .arg(clap::Arg::with_name("backlog")
+             .long("backlog")
+             .takes_value(true)
+             .value_name("N")
+             .default_value("128")
+             .validator(|s| {
+                 match s.parse::<i32>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Size of the listen backlog, the connections the system accepts before the server (capped by net.core.somaxconn)"))
+        .arg(clap::Arg::with_name("keepalive")
+             .long("keepalive")
+             .takes_value(true)
+             .value_name("on|off")
+             .possible_values(&["on", "off"])
+             .default_value("on")
+             .help("Keep the connections open between requests (closed after 5 idle seconds), `off` closes them after every response"))
+        .arg(clap::Arg::with_name("keepalive-requests")
+             .long("keepalive-requests")
+             .takes_value(true)
+             .value_name("N")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Close a kept alive connection once it served N requests [default: unlimited]"))
+        .arg(clap::Arg::with_name("max-url-length")
+             .long("max-url-length")
+             .takes_value(true)
+             .value_name("BYTES")
+             .default_value("8192")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Answer 414 to the requests with a longer request line (method, URL and version)"))
+        .arg(clap::Arg::with_name("max-header-size")
+             .long("max-header-size")
+             .takes_value(true)
+             .value_name("BYTES")
+             .default_value("32768")
+             .validator(|s| {
+                 match s.parse::<usize>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Answer 431 to the requests with larger headers (all the fields together)\n    Note: hyper also refuses more than 100 header fields"))
+        .arg(clap::Arg::with_name("so-reuseport")
+             .long("so-reuseport")
+             .help("Set SO_REUSEPORT on the listening sockets: several servers can listen on the same port, the system spreads the connections"))
+        .arg(clap::Arg::with_name("tcp-nodelay")
+             .long("tcp-nodelay")
+             .help("Set TCP_NODELAY on the connections (no Nagle's algorithm), for latency sensitive clients"))
+        .arg(clap::Arg::with_name("rate-limit")
+             .long("rate-limit")
+             .takes_value(true)
+             .value_name("N/UNIT")
+             .validator(|s| RateLimiter::new(&s).map(|_| ()).map_err(|e| e.to_string()))
+             .help("Limit the requests per client IP (token bucket), others get 429 with Retry-After\n    Example: --rate-limit 60/min (units: s, min, h)"))
+        .arg(clap::Arg::with_name("auth")
+             .short("a")
+             .long("auth")
+             .takes_value(true)
+             .validator(|s| {
+                 let parts = s.splitn(2, ':').collect::<Vec<&str>>();
+                 if parts.len() < 2 || parts.len() >= 2 && parts[1].is_empty() {
+                     Err("no password found".to_owned())
+                 } else if parts[0].is_empty() {
+                     Err("no username found".to_owned())
+                 } else {
+                     Ok(())
+                 }
+             })
+             .value_name("user:pass[:role]")
+             .help("HTTP Basic Auth (username:password)\n    Role suffix: ro (download only), rw (also upload and delete), admin (also the status page, default)"))
+        .arg(clap::Arg::with_name("auth-upload")
+             .long("auth-upload")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("user:pass[:role]")
+             .requires("upload")
+             .validator(|s| {
+                 let parts = s.splitn(2, ':').collect::<Vec<&str>>();
+                 if parts.len() < 2 || parts[1].is_empty() {
+                     Err("no password found".to_owned())
+                 } else if parts[0].is_empty() {
+                     Err("no username found".to_owned())
+                 } else {
+                     Ok(())
+                 }
+             })
+             .help("HTTP Basic Auth for uploads only (repeatable), downloads stay anonymous without --auth\n    Role suffix: w (upload only, default), rw (also download when --auth is set), admin (also the status page)\n    Example: --auth-upload alice:secret --auth-upload bob:secret:rw"))
+        .arg(clap::Arg::with_name("auth-token")
+             .long("auth-token")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("token[:role]")
+             .validator(|s| Credential::token(&s).map(|_| ()).map_err(|e| e.0))
+             .help("API key for the scripts (repeatable), by an 'Authorization: Bearer <token>' header or the ?access_token=<token> query, gates everything as --auth\n    Role suffix: ro, rw (default), admin\n    Example: --auth-token \"$CI_TOKEN\""))
+        .arg(clap::Arg::with_name("auth-jwt-secret")
+             .long("auth-jwt-secret")
+             .takes_value(true)
+             .value_name("SECRET")
+             .help("Accept the Bearer JWTs signed with this HMAC key (HS256/384/512), gates everything as --auth"))
+        .arg(clap::Arg::with_name("auth-jwt-jwks-url")
+             .long("auth-jwt-jwks-url")
+             .takes_value(true)
+             .value_name("URL")
+             .validator(|s| hyper::Url::parse(&s).map(|_| ()).map_err(|e| e.to_string()))
+             .help("Accept the Bearer JWTs signed with a key of this JWK set (RS256/384/512, ES256/384), fetched again hourly or for an unknown key\n    Example: --auth-jwt-jwks-url https://sso.example.com/.well-known/jwks.json"))
+        .arg(clap::Arg::with_name("auth-jwt-audience")
+             .long("auth-jwt-audience")
+             .takes_value(true)
+             .value_name("AUD")
+             .requires("auth-jwt")
+             .help("The aud claim the JWTs must have"))
+        .arg(clap::Arg::with_name("auth-jwt-issuer")
+             .long("auth-jwt-issuer")
+             .takes_value(true)
+             .value_name("ISS")
+             .requires("auth-jwt")
+             .help("The iss claim the JWTs must have"))
+        .arg(clap::Arg::with_name("auth-max-failures")
+             .long("auth-max-failures")
+             .takes_value(true)
+             .value_name("COUNT")
+             .default_value("10")
+             .validator(|s| {
+                 match s.parse::<u32>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Ban a client IP after this many failed logins in a row (0: never), it waits 1s, 2s, 4s... after each one from the 3rd"))
+        .arg(clap::Arg::with_name("auth-ban-time")
+             .long("auth-ban-time")
+             .takes_value(true)
+             .value_name("SECONDS")
+             .default_value("900")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(0) => Err("must be positive".to_owned()),
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("How long the clients of --auth-max-failures are banned, and their failures remembered"))
+        .arg(clap::Arg::with_name("auth-session")
+             .long("auth-session")
+             .requires("auth")
+             .help("Log the browsers in with a form at /__login and a signed session cookie (log out at /__logout), Basic Auth still works for the other clients"))
+        .arg(clap::Arg::with_name("auth-session-max-age")
+             .long("auth-session-max-age")
+             .takes_value(true)
+             .value_name("SECONDS")
+             .default_value("86400")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(0) => Err("must be positive".to_owned()),
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Lifetime of the --auth-session cookies"))
+        .arg(clap::Arg::with_name("compress")
+             .short("c")
+             .long("compress")
+             .multiple(true)
+             .value_delimiter(",")
+             .takes_value(true)
+             .help("Enable file compression: gzip/deflate, by extension, MIME type (text/*) or auto (the text formats)\n    Example: -c=js,d.ts or -c=auto,application/x-ndjson\n    Note: disabled on partial request, and for the already compressed formats (jpg, zip, mp4...)!"))
+        .arg(clap::Arg::with_name("compress-min-size")
+             .long("compress-min-size")
+             .takes_value(true)
+             .value_name("BYTES")
+             .default_value("256")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }})
+             .help("Smaller responses are not compressed"))
+        .arg(clap::Arg::with_name("threads")
+             .short("t")
+             .long("threads")
+             .takes_value(true)
+             .default_value("3")
+             .validator(|s| {
+                 match s.parse::<u8>() {
+                     Ok(v) => {
+                         if v > 0 { Ok(()) } else {
+                             Err("Not positive number".to_owned())
+                         }
+                     }
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("How many worker threads"))
+        .arg(clap::Arg::with_name("try-file-404")
+             .long("try-file")
+             .visible_alias("try-file-404")
+             .takes_value(true)
+             .value_name("PATH")
+             .validator(|s| {
+                 match fs::metadata(s) {
+                     Ok(metadata) => {
+                         if metadata.is_file() { Ok(()) } else {
+                             Err("Not a file".to_owned())
+                         }
+                     },
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("serve this file (server root relative) in place of missing files (useful for single page apps)"))
+        .arg(clap::Arg::with_name("header-file")
+             .long("header-file")
+             .takes_value(true)
+             .value_name("PATH")
+             .help("HTML inserted at the top of the listing pages (banners, instructions)"))
+        .arg(clap::Arg::with_name("footer-file")
+             .long("footer-file")
+             .takes_value(true)
+             .value_name("PATH")
+             .help("HTML inserted at the bottom of the listing pages (analytics snippets)"))
+        .arg(clap::Arg::with_name("inject-html")
+             .long("inject-html")
+             .help("Also insert --header-file after <body> and --footer-file before </body> of the served HTML files"))
+        .arg(clap::Arg::with_name("pretty-urls")
+             .long("pretty-urls")
+             .help("Serve /about.html for /about and /about/index.html for /about/ when the path doesn't exist (static sites)"))
+        .arg(clap::Arg::with_name("checksums")
+             .long("checksums")
+             .help("Serve SHA-256 digests of files by `?hash=sha256` and `<file>.sha256`, and link them in the listing"))
+        .arg(clap::Arg::with_name("etag")
+             .long("etag")
+             .takes_value(true)
+             .value_name("MODE")
+             .possible_values(&["strong", "weak", "strong-hash", "off"])
+             .default_value("strong")
+             .help("ETag of the files: `strong` of the size and the modification time, `weak` the same but never matching If-Range/If-Match,\n    `strong-hash` SHA-256 of the content (read once per modification, kept by a touch), `off` none"))
+        .arg(clap::Arg::with_name("cgi-dir")
+             .long("cgi-dir")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("PREFIX=DIR")
+             .validator(|s| CgiDir::parse(&s).map(|_| ()).map_err(|e| e.0))
+             .help("Execute the scripts of DIR as CGI (RFC 3875) for the URLs under PREFIX (repeatable)\n    Example: --cgi-dir /cgi-bin=./scripts"))
+        .arg(clap::Arg::with_name("fastcgi")
+             .long("fastcgi")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("PATTERN=ADDR")
+             .validator(|s| FastCgi::parse(&s).map(|_| ()).map_err(|e| e.0))
+             .help("Run the files matching PATTERN with a FastCGI backend at ADDR (host:port or unix:/path) (repeatable)\n    Example: --fastcgi \"*.php=127.0.0.1:9000\""))
+        .arg(clap::Arg::with_name("s3")
+             .long("s3")
+             .takes_value(true)
+             .value_name("BUCKET")
+             .validator(|s| {
+                 if s.is_empty() || s.contains('/') {
+                     Err("Not a valid bucket name".to_owned())
+                 } else {
+                     Ok(())
+                 }
+             })
+             .help("Serve the root directory as an S3 bucket at /<BUCKET> (ListObjectsV2, GET/PUT/DELETE objects, PUT and DELETE require --upload), the --auth and --auth-upload users are the access keys"))
+        .arg(clap::Arg::with_name("manifest")
+             .long("manifest")
+             .help("Serve recursive JSON trees of the directories at /__manifest?path=<dir>&depth=<n>[&hashes=1] (hashes require --checksums)"))
+        .arg(clap::Arg::with_name("mime")
+             .long("mime")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("EXT=TYPE")
+             .validator(|s| parse_mime_override(&s).map(|_| ()).map_err(|e| e.0))
+             .help("Override the mime type of an extension (repeatable)\n    Example: --mime wasm=application/wasm"))
+        .arg(clap::Arg::with_name("mime-file")
+             .long("mime-file")
+             .takes_value(true)
+             .value_name("PATH")
+             .validator(|s| {
+                 match fs::metadata(s) {
+                     Ok(metadata) => {
+                         if metadata.is_file() { Ok(()) } else {
+                             Err("Not a regular file".to_owned())
+                         }
+                     },
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Load mime type mappings from a mime.types style file (lines of: type ext1 ext2 ...)"))
+        .arg(clap::Arg::with_name("charset")
+             .long("charset")
+             .takes_value(true)
+             .default_value("utf-8")
+             .help("Charset parameter for text responses (text/*, application/javascript)"))
+        .arg(clap::Arg::with_name("force-download")
+             .long("force-download")
+             .help("Send all files as attachments (Content-Disposition), same as adding `?download` to every link"))
+        .arg(clap::Arg::with_name("vhost")
+             .long("vhost")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .value_name("HOST=ROOT")
+             .validator(|s| {
+                 let parts = s.splitn(2, '=').collect::<Vec<&str>>();
+                 if parts.len() != 2 || parts[0].is_empty() {
+                     return Err("not valid format host=root".to_owned());
+                 }
+                 match fs::metadata(parts[1]) {
+                     Ok(metadata) => {
+                         if metadata.is_dir() { Ok(()) } else {
+                             Err("Not directory".to_owned())
+                         }
+                     },
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Serve another root directory for requests by the Host header (repeatable)\n    Example: --vhost example.com=/srv/example"))
+        .arg(clap::Arg::with_name("webhook-url")
+             .long("webhook-url")
+             .takes_value(true)
+             .value_name("URL")
+             .validator(|url_string| iron::Url::parse(url_string.as_str()).map(|_| ()))
+             .help("POST a JSON payload (event, path, size, client_ip, timestamp) to this URL after each upload"))
+        .arg(clap::Arg::with_name("webhook-downloads")
+             .long("webhook-downloads")
+             .requires("webhook-url")
+             .help("Also call the webhook after each download"))
+        .arg(clap::Arg::with_name("on-upload")
+             .long("on-upload")
+             .takes_value(true)
+             .value_name("COMMAND")
+             .validator(|s| UploadCommand::new(&s, Duration::from_secs(0)).map(|_| ()).map_err(|e| e.0))
+             .help("Run this command after each upload, `{}` is replaced by the saved file path (not run through a shell)\n    Example: --on-upload 'clamscan --remove {}'"))
+        .arg(clap::Arg::with_name("on-upload-timeout")
+             .long("on-upload-timeout")
+             .takes_value(true)
+             .value_name("SECONDS")
+             .default_value("60")
+             .validator(|s| {
+                 match s.parse::<u64>() {
+                     Ok(_) => Ok(()),
+                     Err(e) => Err(e.to_string())
+                 }
+             })
+             .help("Kill the --on-upload command after this long"))
+        .arg(clap::Arg::with_name("user")
+             .long("user")
+             .takes_value(true)
+             .value_name("USER")
+             .help("Switch to this user (name or uid) after binding the port, e.g. when started as root for port 80/443"))
+        .arg(clap::Arg::with_name("group")
+             .long("group")
+             .takes_value(true)
+             .value_name("GROUP")
+             .help("Switch to this group (name or gid) after binding the port [default: primary group of --user]"))
+        .arg(clap::Arg::with_name("log-syslog")
+             .long("log-syslog")
+             .takes_value(true)
+             .min_values(0)
+             .require_equals(true)
+             .value_name("FACILITY")
+             .validator(|s| syslog::parse_facility(&s).map(|_| ()).map_err(|e| e.0))
+             .help("Send the access and error logs to the local syslog/journald (facility: user, daemon, local0-7..., default: user)\n    Example: --log-syslog=local0"))
+        .arg(clap::Arg::with_name("log-file")
+             .long("log-file")
+             .takes_value(true)
+             .value_name("PATH")
+             .help("Append the access log to this file (without colors), reopened on SIGUSR1 for logrotate"))
+        .arg(clap::Arg::with_name("audit-log")
+             .long("audit-log")
+             .takes_value(true)
+             .value_name("PATH")
+             .help("Append each upload, delete and directory created (user, client IP, result) to this file as JSON lines"))
+        .arg(clap::Arg::with_name("one-time-links")
+             .long("one-time-links")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Allow creating links downloading a file only once, without credentials, saved to this file (kept outside of the root)"))
+        .arg(clap::Arg::with_name("log-rotate-size")
+             .long("log-rotate-size")
+             .takes_value(true)
+             .value_name("BYTES")
+             .requires("log-file")
+             .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+             .help("Rotate the log file into <PATH>.1, <PATH>.2... when it's larger than this"))
+        .arg(clap::Arg::with_name("log-rotate-keep")
+             .long("log-rotate-keep")
+             .takes_value(true)
+             .value_name("N")
+             .requires("log-rotate-size")
+             .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+             .help("How many rotated log files to keep [default: 5]"))
+        .arg(clap::Arg::with_name("config")
+             .long("config")
+             .takes_value(true)
+             .value_name("FILE")
+             .validator(|s| config_file::parse(Path::new(&s)).map(|_| ()).map_err(|e| e.0))
+             .help("Read the options from a file of `name = value` lines (the command line and SHS_* win), \
+                    users, compression, cache and ignore patterns are reloaded on SIGHUP or when it changes"))
+        .arg(clap::Arg::with_name("daemon")
+             .long("daemon")
+             .help("Run in the background, detached from the terminal once the server is started (unix only), \
+                    use --log-file or --log-syslog for the logs"))
+        .arg(clap::Arg::with_name("pid-file")
+             .long("pid-file")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Write the process id to a file, removed on SIGTERM/SIGINT. Refused while the process it names runs"))
+        .arg(clap::Arg::with_name("print-config-json")
+             .long("print-config-json")
+             .help("Print the effective configuration and the bound addresses as one JSON line at startup, instead of the banner"))
+        .arg(clap::Arg::with_name("silent")
+             .long("silent")
+             .short("s")
+             .takes_value(false)
+             .help("Disable all outputs"))
+        .arg(clap::Arg::with_name("gen-man")
+             .long("gen-man")
+             .help("Print the man page (roff) and exit"))
+        .subcommand(clap::SubCommand::with_name("completions")
+                    .about("Print the completion script of a shell and exit")
+                    .arg(clap::Arg::with_name("shell")
+                         .required(true)
+                         .possible_values(&clap::Shell::variants())))
+        .group(clap::ArgGroup::with_name("tls")
+               .args(&["cert", "acme-domain"]))
+        .group(clap::ArgGroup::with_name("auth-jwt")
+               .args(&["auth-jwt-secret", "auth-jwt-jwks-url"])
+               .multiple(true))
+}
+
+pub fn run() {
+    let app = build_app();
+    let (app, base_args) = env_config(app);
+    let args = config_args(&app, &base_args).unwrap_or_else(|e| {
+        clap::Error::with_description(&e.0, clap::ErrorKind::InvalidValue).exit()
+    });
+    let matches = app.clone().get_matches_from(args);
+    if let Some(completions) = matches.subcommand_matches("completions") {
+        // Validated by clap
+        let shell = completions.value_of("shell").unwrap().parse().unwrap();
+        build_app().gen_completions_to(crate_name!(), shell, &mut io::stdout());
+        return;
+    }
+    if matches.is_present("gen-man") {
+        print!("{}", man::render(&app));
+        return;
+    }
+
+    let root = matches
+        .value_of("root")
+        .map(|s| PathBuf::from(s).canonicalize().unwrap())
+        .unwrap_or_else(|| env::current_dir().unwrap());
+    let index = matches.is_present("index");
+    let upload = matches.is_present("upload");
+    let upload_only = matches.is_present("upload-only");
+    let csrf = !matches.is_present("nocsrf");
+    let sort = !matches.is_present("nosort");
+    let listing = !matches.is_present("nolisting");
+    let cache = !matches.is_present("nocache");
+    let range = !matches.is_present("norange");
+    let certpass = matches.value_of("certpass");
+    // Validated by clap
+    let mut certs = matches
+        .values_of_lossy("cert")
+        .unwrap_or_default()
+        .iter()
+        .map(|s| parse_cert(s, certpass.unwrap_or("")).unwrap())
+        .collect::<Vec<(Option<String>, Identity)>>();
+    if certs.iter().filter(|(domain, _)| domain.is_none()).count() > 1 {
+        clap::Error::with_description(
+            "Only one --cert may be given without domain",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    let tls_client_ca = matches.value_of("tls-client-ca");
+    let tls_client_auth = matches.is_present("tls-client-auth");
+    let acme_domains = matches.values_of_lossy("acme-domain");
+    let acme_email = matches.value_of("acme-email");
+    let acme_directory = matches.value_of("acme-directory").unwrap();
+    let acme_cache = matches
+        .value_of("acme-cache")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_default()
+                .join(".simple-http-server")
+                .join("acme")
+        });
+    let acme_http_port = matches
+        .value_of("acme-http-port")
+        .unwrap()
+        .parse::<u16>()
+        .unwrap();
+    let https = !certs.is_empty() || acme_domains.is_some();
+    let cors = matches.is_present("cors");
+    let ips = matches
+        .values_of("ip")
+        .unwrap()
+        .map(|s| IpAddr::from_str(s).unwrap())
+        .collect::<Vec<IpAddr>>();
+    let port = matches.value_of("port").unwrap().parse::<u16>().unwrap();
+    let auth = matches.value_of("auth");
+    let auth_upload = matches
+        .values_of("auth-upload")
+        .map(|values| values.collect::<Vec<&str>>())
+        .unwrap_or_default();
+    let compress = matches.values_of_lossy("compress");
+    let threads = matches.value_of("threads").unwrap().parse::<u8>().unwrap();
+    let try_file_404 = matches.value_of("try-file-404");
+    let user = matches.value_of("user");
+    let group = matches.value_of("group");
+    let vhosts = matches
+        .values_of_lossy("vhost")
+        .unwrap_or_default()
+        .iter()
+        .map(|s| {
+            let parts = s.splitn(2, '=').collect::<Vec<&str>>();
+            (
+                parts[0].to_lowercase(),
+                PathBuf::from(parts[1]).canonicalize().unwrap(),
+            )
+        })
+        .collect::<HashMap<String, PathBuf>>();
+
+    color::set_color(matches.value_of("color").unwrap());
+    let printer = Printer::new();
+    let color_blue = Some(build_spec(Some(Color::Blue), false));
+    let color_red = Some(build_spec(Some(Color::Red), false));
+    // Before any thread is started, they don't survive the fork
+    let daemon = if matches.is_present("daemon") {
+        match daemon::daemonize() {
+            Ok(daemon) => Some(daemon),
+            Err(e) => {
+                printer
+                    .println_err("{}: {}", &[("ERROR", &color_red), (&*e, &None)])
+                    .unwrap();
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(path) = matches.value_of("pid-file") {
+        if let Err(e) = daemon::write_pid_file(Path::new(path)) {
+            printer
+                .println_err("{}: {}", &[("ERROR", &color_red), (&*e, &None)])
+                .unwrap();
+            std::process::exit(1);
+        }
+    }
+    let addrs = ips
+        .iter()
+        .map(|ip| SocketAddr::new(*ip, port))
+        .collect::<Vec<SocketAddr>>();
+    let compression_exts = compress
+        .clone()
+        .unwrap_or_default()
+        .iter()
+        .map(|s| {
+            if s == "auto" || s.contains('/') {
+                s.to_owned()
+            } else {
+                format!("*.{}", s)
+            }
+        })
+        .collect::<Vec<String>>();
+    let compression_string = if compression_exts.is_empty() {
+        "disabled".to_owned()
+    } else {
+        format!("{:?}", compression_exts)
+    };
+    let silent = matches.is_present("silent");
+    let qr = matches.is_present("qr");
+    let mdns = matches.is_present("mdns");
+    let mdns_name = matches.value_of("mdns");
+    let public = matches.is_present("public");
+    let mut startup_json = if matches.is_present("print-config-json") {
+        Some(config_json(&matches, &root, https))
+    } else {
+        None
+    };
+
+    if !silent && startup_json.is_none() {
+        let lan_urls = lan::lan_urls(if https { "https" } else { "http" }, &addrs);
+        printer
+            .println_out(
+                r#"     Index: {}, Upload: {}, Cache: {}, Cors: {}, Range: {}, Sort: {}, Threads: {}
+          Auth: {}, Compression: {}
+         https: {}, Cert: {}, Cert-Password: {}
+          Root: {},
+    TryFile404: {}
+       Address: {}
+       Network: {}
+    ======== [{}] ========"#,
+                &vec![
+                    enable_string(index),
+                    enable_string(upload),
+                    enable_string(cache),
+                    enable_string(cors),
+                    enable_string(range),
+                    enable_string(sort),
+                    threads.to_string(),
+                    auth.unwrap_or(if auth_upload.is_empty() {
+                        "disabled"
+                    } else {
+                        "uploads only"
+                    })
+                    .to_string(),
+                    compression_string.clone(),
+                    (if https { "enabled" } else { "disabled" }).to_string(),
+                    match acme_domains {
+                        Some(ref domains) => format!("ACME {:?}", domains),
+                        None => matches
+                            .values_of_lossy("cert")
+                            .unwrap_or_default()
+                            .join(", "),
+                    },
+                    certpass.unwrap_or("").to_owned(),
+                    root.to_str().unwrap().to_owned(),
+                    try_file_404.unwrap_or("").to_owned(),
+                    addrs
+                        .iter()
+                        .map(|addr| format!("{}://{}", if https { "https" } else { "http" }, addr))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    if lan_urls.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        lan_urls.join(", ")
+                    },
+                    now_string(),
+                ]
+                .iter()
+                .map(|s| (s.as_str(), &color_blue))
+                .collect::<Vec<(&str, &Option<ColorSpec>)>>(),
+            )
+            .unwrap();
+        if qr {
+            let url = lan_urls.first().cloned().unwrap_or_else(|| {
+                format!("{}://{}/", if https { "https" } else { "http" }, addrs[0])
+            });
+            printer
+                .println_out(
+                    "{}\n{}",
+                    &[(&lan::qr_code(&url), &None), (&url, &color_blue)],
+                )
+                .unwrap();
+        }
+    }
+
+    if upload {
+        // Left by uploads interrupted by a crash or a kill
+        let removed = std::iter::once(&root)
+            .chain(vhosts.values())
+            .map(|dir| atomic_file::remove_stale(dir))
+            .sum::<usize>();
+        if removed > 0 && !silent {
+            printer
+                .println_out(
+                    "Removed {} unfinished upload(s)",
+                    &[(&removed.to_string(), &color_blue)],
+                )
+                .unwrap();
+        }
+    }
+
+    let config_watcher = matches
+        .value_of("config")
+        .map(|path| config_file::Watcher::new(Path::new(path)));
+
+    // Validated by clap
+    let mut server = SimpleHttpServer::new(&root)
+        .index(index)
+        .upload(upload)
+        .upload_only(upload_only)
+        .delete(matches.is_present("delete"))
+        .csrf(csrf)
+        .cache(cache)
+        .etag(matches.value_of("etag").unwrap())
+        .range(range)
+        .listing(listing)
+        .sort(sort)
+        .stream_listings(matches.is_present("stream-listings"))
+        .pretty_urls(matches.is_present("pretty-urls"))
+        .canonical_redirect(!matches.is_present("no-canonical-redirect"))
+        .force_download(matches.is_present("force-download"))
+        .inject_html(matches.is_present("inject-html"))
+        .theme(matches.value_of("theme").unwrap())
+        .page_size(matches.value_of("page-size").unwrap().parse().unwrap())
+        .upload_size_limit(
+            matches
+                .value_of("upload_size_limit")
+                .unwrap()
+                .parse()
+                .unwrap(),
+        )
+        .upload_sniff(matches.is_present("upload-sniff"))
+        .preserve_mtime(matches.is_present("preserve-mtime"))
+        .charset(matches.value_of("charset").unwrap())
+        .checksums(matches.is_present("checksums"))
+        .manifest(matches.is_present("manifest"))
+        .highlight(matches.is_present("highlight"))
+        .compress_min_size(
+            matches
+                .value_of("compress-min-size")
+                .unwrap()
+                .parse()
+                .unwrap(),
+        )
+        .gitignore(matches.is_present("gitignore"))
+        .access_files(matches.is_present("access-files"))
+        .auth_max_failures(
+            matches
+                .value_of("auth-max-failures")
+                .unwrap()
+                .parse()
+                .unwrap(),
+        )
+        .auth_ban_time(matches.value_of("auth-ban-time").unwrap().parse().unwrap())
+        .cors(cors)
+        .no_robots(matches.is_present("no-robots"))
+        .security_headers(matches.is_present("security-headers"))
+        .status(matches.is_present("status"))
+        .status_info(
+            "Address",
+            addrs
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join(", "),
+        )
+        .status_info("https", enable_string(https))
+        .log(!silent)
+        .threads(threads as usize)
+        .keepalive(matches.value_of("keepalive") != Some("off"))
+        .max_url_length(matches.value_of("max-url-length").unwrap().parse().unwrap())
+        .max_header_size(
+            matches
+                .value_of("max-header-size")
+                .unwrap()
+                .parse()
+                .unwrap(),
+        )
+        .backlog(matches.value_of("backlog").unwrap().parse().unwrap())
+        .reuse_port(matches.is_present("so-reuseport"))
+        .tcp_nodelay(matches.is_present("tcp-nodelay"))
+        .client_certs(
+            matches.is_present("tls-client-ca"),
+            matches.is_present("tls-client-auth"),
+        )
+        .reloadable(config_watcher.is_some());
+    // Options given by a value
+    let value = |name: &str| matches.value_of(name);
+    let number = |name: &str| matches.value_of(name).map(|s| s.parse::<u64>().unwrap());
+    let values = |name: &str| matches.values_of_lossy(name).unwrap_or_default();
+    if let Some(url) = value("redirect") {
+        server = server.redirect(url);
+    }
+    if let Some(path) = value("header-file") {
+        server = server.header_file(path);
+    }
+    if let Some(path) = value("footer-file") {
+        server = server.footer_file(path);
+    }
+    if let Some(count) = number("listing-cache") {
+        server = server.listing_cache(count as usize);
+    }
+    if let Some(bytes) = number("upload-quota") {
+        server = server.upload_quota(bytes);
+    }
+    if let Some(bytes) = number("upload-limit-rate") {
+        server = server.upload_limit_rate(bytes);
+    }
+    if let Some(count) = number("upload-max-per-hour") {
+        server = server.upload_max_per_hour(count as usize);
+    }
+    for extension in values("upload-allow") {
+        server = server.upload_allow(&extension);
+    }
+    for extension in values("upload-deny") {
+        server = server.upload_deny(&extension);
+    }
+    if let Some(dir) = value("trash-dir") {
+        server = server.trash_dir(dir);
+    }
+    if let Some(command) = value("on-upload") {
+        let timeout = number("on-upload-timeout").unwrap();
+        server = server.on_upload(command, Duration::from_secs(timeout));
+    }
+    if let Some(url) = value("webhook-url") {
+        server = server.webhook(url, matches.is_present("webhook-downloads"));
+    }
+    if let Some(after) = value("expire") {
+        server = server.expire(parse_duration(after).unwrap());
+    }
+    if let Some(path) = try_file_404 {
+        server = server.try_file_404(path);
+    }
+    for spec in values("mime") {
+        let (extension, mime) = spec.split_once('=').unwrap();
+        server = server.mime(extension, mime);
+    }
+    if let Some(path) = value("mime-file") {
+        server = server.mime_file(path);
+    }
+    for (host, dir) in &vhosts {
+        server = server.vhost(host, dir);
+    }
+    if matches.is_present("thumbnails") {
+        let cache_dir = value("thumbnail-cache")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                env::var_os("HOME")
+                    .map(PathBuf::from)
+                    .unwrap_or_default()
+                    .join(".simple-http-server")
+                    .join("thumbnails")
+            });
+        server = server.thumbnails(cache_dir);
+    }
+    if let Some(bucket) = value("s3") {
+        server = server.s3(bucket);
+    }
+    for spec in values("cgi-dir") {
+        server = server.cgi_dir(&spec);
+    }
+    for spec in values("fastcgi") {
+        server = server.fastcgi(&spec);
+    }
+    for rule in compress.clone().unwrap_or_default() {
+        server = server.compress(&rule);
+    }
+    for pattern in values("ignore") {
+        server = server.ignore(&pattern);
+    }
+    for spec in values("protect-path") {
+        let (path, password) = spec.split_once('=').unwrap();
+        server = server.protect_path(path, password);
+    }
+    if let Some(credential) = auth {
+        server = server.auth(credential);
+    }
+    for credential in &auth_upload {
+        server = server.auth_upload(credential);
+    }
+    for token in values("auth-token") {
+        server = server.auth_token(&token);
+    }
+    if let Some(secret) = value("auth-jwt-secret") {
+        server = server.auth_jwt_secret(secret);
+    }
+    if let Some(url) = value("auth-jwt-jwks-url") {
+        server = server.auth_jwt_jwks_url(url);
+    }
+    if let Some(audience) = value("auth-jwt-audience") {
+        server = server.auth_jwt_audience(audience);
+    }
+    if let Some(issuer) = value("auth-jwt-issuer") {
+        server = server.auth_jwt_issuer(issuer);
+    }
+    if matches.is_present("auth-session") {
+        server = server.auth_session(number("auth-session-max-age").unwrap());
+    }
+    if let Some(path) = value("audit-log") {
+        server = server.audit_log(path);
+    }
+    if let Some(path) = value("one-time-links") {
+        server = server.one_time_links(path);
+    }
+    for net in values("trusted-proxy") {
+        server = server.trusted_proxy(&net);
+    }
+    if let Some(rate) = value("rate-limit") {
+        server = server.rate_limit(rate);
+    }
+    if let Some(hsts) = value("hsts") {
+        server = server.hsts(hsts);
+    }
+    if let Some(frame_options) = value("frame-options") {
+        server = server.frame_options(frame_options);
+    }
+    if let Some(referrer_policy) = value("referrer-policy") {
+        server = server.referrer_policy(referrer_policy);
+    }
+    if let Some(path) = value("health-path") {
+        server = server.health_path(path);
+    }
+    if matches.is_present("log-syslog") {
+        server = server.log_syslog(value("log-syslog").unwrap_or("user"));
+    }
+    if let Some(path) = value("log-file") {
+        let keep = value("log-rotate-keep").unwrap_or("5").parse().unwrap();
+        server = server.log_file(path, number("log-rotate-size"), keep);
+    }
+    if let Some(count) = number("keepalive-requests") {
+        server = server.keepalive_requests(count as usize);
+    }
+    if let Some(count) = number("max-connections") {
+        server = server.max_connections(count as usize);
+    }
+    if let Some(count) = number("max-connections-per-ip") {
+        server = server.max_connections_per_ip(count as usize);
+    }
+    if let Some(size) = number("queue") {
+        server = server.queue(size as usize);
+    }
+    let mut server = match server.build() {
+        Ok(server) => server,
+        Err(e) => {
+            printer.println_err("{}", &[(&*e, &color_red)]).unwrap();
+            return;
+        }
+    };
+    // Called once the listeners are bound, before serving any request
+    let switch_user = || {
+        if user.is_none() && group.is_none() {
+            return;
+        }
+        if let Err(e) = privileges::drop_privileges(user, group) {
+            printer
+                .println_err("{}: {}", &[("ERROR", &color_red), (&*e, &None)])
+                .unwrap();
+            std::process::exit(1);
+        }
+    };
+    let listenings = if https {
+        let identity = match acme_domains {
+            Some(domains) => Identity::Acme(AcmeOptions {
+                domains,
+                email: acme_email.map(ToOwned::to_owned),
+                directory_url: acme_directory.to_owned(),
+                cache_dir: acme_cache,
+                http_addrs: ips
+                    .iter()
+                    .map(|ip| SocketAddr::new(*ip, acme_http_port))
+                    .collect(),
+                https_port: port,
+            }),
+            None => {
+                let default = certs
+                    .iter()
+                    .position(|(domain, _)| domain.is_none())
+                    .unwrap_or(0);
+                certs.remove(default).1
+            }
+        };
+        let sni = certs
+            .into_iter()
+            .filter_map(|(domain, identity)| Some((domain?, identity)))
+            .collect();
+        let opts = TlsOptions {
+            identity,
+            client_ca: tls_client_ca.map(PathBuf::from),
+            client_cert_optional: tls_client_auth,
+            sni,
+            min_version: matches.value_of("tls-min-version").map(ToOwned::to_owned),
+            ciphers: matches.value_of("tls-ciphers").map(ToOwned::to_owned),
+        };
+        let ssl = match tls::build_server(&opts) {
+            Ok(ssl) => ssl,
+            Err(e) => {
+                printer
+                    .println_err("{}: {}", &[("ERROR", &color_red), (&*e, &None)])
+                    .unwrap();
+                std::process::exit(1);
+            }
+        };
+        tls::spawn_reload(&ssl, silent);
+        let listeners = bind(&server, &addrs, true, &printer);
+        switch_user();
+        listeners
+            .into_iter()
+            .zip(&addrs)
+            .map(|(listener, addr)| {
+                server
+                    .serve_on(TlsListener::new(listener, ssl.clone()), Protocol::https())
+                    .unwrap_or_else(|e| exit_bind_error(&printer, addr, e))
+            })
+            .collect::<Vec<Listening>>()
+    } else {
+        let listeners = bind(&server, &addrs, false, &printer);
+        switch_user();
+        listeners
+            .into_iter()
+            .zip(&addrs)
+            .map(|(listener, addr)| {
+                server
+                    .serve_on(listener, Protocol::http())
+                    .unwrap_or_else(|e| exit_bind_error(&printer, addr, e))
+            })
+            .collect::<Vec<Listening>>()
+    };
+    // Deleting as the user switched to
+    server.spawn_sweeper();
+    if let Some(ref mut json) = startup_json {
+        // The ports chosen by the system for `--port 0`
+        let bound = listenings
+            .iter()
+            .map(|listening| listening.socket)
+            .collect::<Vec<SocketAddr>>();
+        json["addresses"] = serde_json::json!(bound
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<String>>());
+        json["urls"] = serde_json::json!(bound
+            .iter()
+            .map(|addr| format!("{}://{}/", if https { "https" } else { "http" }, addr))
+            .collect::<Vec<String>>());
+        println!("{}", json);
+    }
+    let mdns_daemon = if mdns {
+        match mdns::announce(mdns_name, https, &addrs) {
+            Ok(daemon) => Some(daemon),
+            Err(e) => {
+                printer
+                    .println_err("{}: {}", &[("ERROR", &color_red), (&*e, &None)])
+                    .unwrap();
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if public {
+        match port_mapping::start(port) {
+            Ok(external) => {
+                printer
+                    .println_out(
+                        "{}: The server is reachable from the internet at {}",
+                        &[
+                            ("WARNING", &Some(build_spec(Some(Color::Yellow), true))),
+                            (
+                                &format!(
+                                    "{}://{}/",
+                                    if https { "https" } else { "http" },
+                                    external
+                                ),
+                                &color_blue,
+                            ),
+                        ],
+                    )
+                    .unwrap();
+            }
+            Err(e) => {
+                printer
+                    .println_err("{}: {}", &[("ERROR", &color_red), (&*e, &None)])
+                    .unwrap();
+            }
+        }
+    }
+    if let Some(daemon) = daemon {
+        daemon.detach();
+    }
+    if let Some(mut watcher) = config_watcher {
+        // The servers run in their own threads
+        loop {
+            watcher.wait();
+            let reloaded = config_args(&app, &base_args).and_then(|args| {
+                let matches = app
+                    .clone()
+                    .get_matches_from_safe(args)
+                    .map_err(|e| StringError(e.message))?;
+                let auth_upload = matches
+                    .values_of("auth-upload")
+                    .map(|values| values.collect::<Vec<&str>>())
+                    .unwrap_or_default();
+                let auth_tokens = matches
+                    .values_of("auth-token")
+                    .map(|values| values.collect::<Vec<&str>>())
+                    .unwrap_or_default();
+                let settings = LiveSettings::new(&matches)?;
+                if let Some(ref auth_checker) = server.auth_checker {
+                    auth_checker.set_credentials(
+                        matches.value_of("auth"),
+                        &auth_upload,
+                        &auth_tokens,
+                    )?;
+                }
+                server.live.replace(settings);
+                Ok(())
+            });
+            let path = watcher.path().display().to_string();
+            match reloaded {
+                Ok(()) if silent => {}
+                Ok(()) => printer
+                    .println_out("Reloaded {}", &[(&path, &color_blue)])
+                    .unwrap(),
+                Err(e) => printer
+                    .println_err(
+                        "{}: reload {} failed, the previous configuration stays: {}",
+                        &[("ERROR", &color_red), (&path, &None), (&*e, &None)],
+                    )
+                    .unwrap(),
+            }
+        }
+    }
+    // Dropping them waits for the servers
+    drop(listenings);
+    drop(mdns_daemon);
+}
+
+/// Bind every address, exits on the first failure
+fn bind(
+    server: &Server,
+    addrs: &[SocketAddr],
+    https: bool,
+    printer: &Printer,
+) -> Vec<QueuedListener> {
+    addrs
+        .iter()
+        .map(|addr| {
+            server
+                .bind(addr, https)
+                .unwrap_or_else(|e| exit_bind_error(printer, addr, e.into()))
+        })
+        .collect()
+}
+
+/// Every option can also be given by `SHS_<NAME>` (`SHS_PORT`, `SHS_AUTH`, `SHS_ROOT`...), the
+/// command line wins. Flags are enabled by `1`, `true`, `yes` or `on`.
+fn env_config(
+    mut app: clap::App<'static, 'static>,
+) -> (clap::App<'static, 'static>, Vec<OsString>) {
+    let env_name =
+        |name: &str| -> &'static OsStr { OsStr::new(Box::leak(env_name(name).into_boxed_str())) };
+    let mut args = env::args_os().collect::<Vec<OsString>>();
+    // Parsed once without the environment to know what the command line already gives
+    let given = given_args(&app, &args);
+    let is_given = |name: &str| given.iter().any(|given| given == name);
+
+    for opt in app.p.opts.iter_mut() {
+        let name = env_name(opt.s.long.unwrap_or(opt.b.name));
+        let value = env::var_os(name).filter(|_| !is_given(opt.b.name));
+        opt.v.env = Some((name, value));
+        opt.b.settings.set(clap::ArgSettings::HideEnvValues);
+    }
+    for pos in app.p.positionals.values_mut() {
+        let name = env_name(pos.b.name);
+        let value = env::var_os(name).filter(|_| !is_given(pos.b.name));
+        pos.v.env = Some((name, value));
+        pos.b.settings.set(clap::ArgSettings::HideEnvValues);
+    }
+    // Flags don't take values, the environment turns into their long form
+    let mut flags = Vec::new();
+    for flag in app.p.flags.iter() {
+        let long = match flag.s.long {
+            Some(long) if !matches!(long, "help" | "version") => long,
+            _ => continue,
+        };
+        let enabled = env::var(env_name(long)).is_ok_and(|value| is_enabled(&value));
+        if enabled && !is_given(flag.b.name) {
+            flags.push(OsString::from(format!("--{}", long)));
+        }
+    }
+    let at = args.len().min(1);
+    args.splice(at..at, flags);
+    let app = app.after_help(
+        "Every option and flag can also be set by a SHS_<NAME> environment variable \
+         (SHS_PORT=8080, SHS_UPLOAD=1), the command line wins",
+    );
+    (app, args)
+}
+
+/// The environment variable of an option (`SHS_` and its name in upper case)
+fn env_name(name: &str) -> String {
+    format!("SHS_{}", name.to_uppercase().replace('-', "_"))
+}
+
+fn is_enabled(value: &str) -> bool {
+    matches!(value.to_lowercase().as_str(), "1" | "true" | "yes" | "on")
+}
+
+/// The arguments given by the user, without the default values
+fn given_args(app: &clap::App<'static, 'static>, args: &[OsString]) -> Vec<String> {
+    app.clone()
+        .get_matches_from_safe(args)
+        .map(|matches| {
+            matches
+                .args
+                .iter()
+                .filter(|(_, arg)| arg.occurs > 0)
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default()
+}
+
+/// `args` with the options of the `--config` file that neither the command line nor the
+/// environment give
+fn config_args(
+    app: &clap::App<'static, 'static>,
+    args: &[OsString],
+) -> Result<Vec<OsString>, StringError> {
+    let given = given_args(app, args);
+    let path = match app.clone().get_matches_from_safe(args) {
+        Ok(matches) => match matches.value_of_os("config") {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(args.to_vec()),
+        },
+        // Reported by the real parsing
+        Err(_) => return Ok(args.to_vec()),
+    };
+    let mut options = Vec::new();
+    let mut positionals = Vec::new();
+    for (name, value) in config_file::parse(&path)? {
+        let unknown = || StringError(format!("Unknown option in {}: {}", path.display(), name));
+        let missing = || StringError(format!("Missing value in {}: {}", path.display(), name));
+        if name == "config" {
+            return Err(StringError(format!(
+                "{}: config files can't be nested",
+                path.display()
+            )));
+        }
+        if given.contains(&name) || env::var_os(env_name(&name)).is_some() {
+            continue;
+        }
+        if let Some(opt) = app.p.opts.iter().find(|opt| opt.s.long == Some(&name)) {
+            let value = value.ok_or_else(missing)?;
+            options.push(OsString::from(format!("--{}={}", opt.b.name, value)));
+        } else if let Some(flag) =
+            app.p.flags.iter().find(|flag| {
+                flag.s.long == Some(&name) && !matches!(flag.b.name, "help" | "version")
+            })
+        {
+            if value.as_deref().is_none_or(is_enabled) {
+                options.push(OsString::from(format!("--{}", flag.b.name)));
+            }
+        } else if app.p.positionals.values().any(|pos| pos.b.name == name) {
+            positionals.push(OsString::from(value.ok_or_else(missing)?));
+        } else {
+            return Err(unknown());
+        }
+    }
+    let mut args = args.to_vec();
+    let at = args.len().min(1);
+    args.splice(at..at, options);
+    args.extend(positionals);
+    Ok(args)
+}
+
+/// `--print-config-json`: every option with its resolved value (defaults included),
+/// passwords masked
+fn config_json(matches: &clap::ArgMatches, root: &Path, https: bool) -> serde_json::Value {
+    use serde_json::{json, Map, Value};
+
+    let mask = |name: &str, value: String| match name {
+        "auth" | "auth-upload" => match value.split_once(':') {
+            Some((user, rest)) => match rest.rsplit_once(':') {
+                Some((_, role)) if matches!(role, "r" | "ro" | "w" | "wo" | "rw" | "admin") => {
+                    format!("{}:***:{}", user, role)
+                }
+                _ => format!("{}:***", user),
+            },
+            None => "***".to_owned(),
+        },
+        "auth-token" => match value.rsplit_once(':') {
+            Some((_, role)) if matches!(role, "r" | "ro" | "w" | "wo" | "rw" | "admin") => {
+                format!("***:{}", role)
+            }
+            _ => "***".to_owned(),
+        },
+        "protect-path" => match value.split_once('=') {
+            Some((path, _)) => format!("{}=***", path),
+            None => "***".to_owned(),
+        },
+        "certpass" | "auth-jwt-secret" => "***".to_owned(),
+        _ => value,
+    };
+    let mut names = matches.args.keys().cloned().collect::<Vec<&str>>();
+    names.sort_unstable();
+    let mut options = Map::new();
+    for name in names {
+        let values = matches
+            .values_of_lossy(name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|value| mask(name, value))
+            .collect::<Vec<String>>();
+        let value = match values.len() {
+            // Flags
+            0 => Value::Bool(true),
+            1 if matches.occurrences_of(name) <= 1 && !is_list_option(name) => {
+                json!(values[0])
+            }
+            _ => json!(values),
+        };
+        options.insert(name.to_owned(), value);
+    }
+    json!({
+        "version": crate_version!(),
+        "root": root.to_string_lossy(),
+        "https": https,
+        "options": options,
+    })
+}
+
+/// A `--cert` value: `cert.p12` or `cert.pem,key.pem`, with the SNI name before a `=`
+fn parse_cert(s: &str, password: &str) -> Result<(Option<String>, Identity), String> {
+    let (domain, files) = match s.split_once('=') {
+        Some(("", _)) => return Err("no domain found".to_owned()),
+        Some((domain, files)) => (Some(domain.to_lowercase()), files),
+        None => (None, s),
+    };
+    let files = files.split(',').collect::<Vec<&str>>();
+    for file in &files {
+        match fs::metadata(file) {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => return Err(format!("{}: Not a regular file", file)),
+            Err(e) => return Err(format!("{}: {}", file, e)),
+        }
+    }
+    let identity = match files[..] {
+        [path] => Identity::Pkcs12 {
+            path: PathBuf::from(path),
+            password: password.to_owned(),
+        },
+        [cert, key] => Identity::PemFiles {
+            cert: PathBuf::from(cert),
+            key: PathBuf::from(key),
+        },
+        _ => return Err("expected cert.p12 or cert.pem,key.pem".to_owned()),
+    };
+    Ok((domain, identity))
+}
+
+// Options which are lists, even with one value
+fn is_list_option(name: &str) -> bool {
+    matches!(
+        name,
+        "auth-upload"
+            | "auth-token"
+            | "cert"
+            | "compress"
+            | "ip"
+            | "ignore"
+            | "mime"
+            | "vhost"
+            | "upload-allow"
+            | "upload-deny"
+            | "protect-path"
+            | "cgi-dir"
+            | "fastcgi"
+            | "acme-domain"
+            | "trusted-proxy"
+    )
+}
+
+fn exit_bind_error(printer: &Printer, addr: &SocketAddr, e: hyper::Error) -> ! {
+    printer
+        .println_err(
+            "{}: Can not bind on {}, {}",
+            &[
+                ("ERROR", &Some(build_spec(Some(Color::Red), true))),
+                (addr.to_string().as_str(), &None),
+                (e.to_string().as_str(), &None),
+            ],
+        )
+        .unwrap();
+    std::process::exit(1);
+}
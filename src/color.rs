@@ -1,9 +1,40 @@
-use std::io::Write;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
 use crate::util::StringError;
 
+const AUTO: u8 = 0;
+const ALWAYS: u8 = 1;
+const NEVER: u8 = 2;
+
+// `--color`, read by every new Printer
+static COLOR: AtomicU8 = AtomicU8::new(AUTO);
+
+/// `auto` (the default) colors terminals only, and never when `NO_COLOR` is set
+pub fn set_color(when: &str) {
+    let mode = match when {
+        "always" => ALWAYS,
+        "never" => NEVER,
+        _ => AUTO,
+    };
+    COLOR.store(mode, Ordering::Relaxed);
+}
+
+fn color_choice(is_terminal: bool) -> ColorChoice {
+    match COLOR.load(Ordering::Relaxed) {
+        ALWAYS => ColorChoice::Always,
+        NEVER => ColorChoice::Never,
+        _ if !is_terminal || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => {
+            ColorChoice::Never
+        }
+        // Still off for TERM=dumb
+        _ => ColorChoice::Auto,
+    }
+}
+
 pub struct Printer {
     outwriter: BufferWriter,
     errwriter: BufferWriter,
@@ -20,8 +51,8 @@ pub fn build_spec(fg: Option<Color>, bold: bool) -> ColorSpec {
 impl Printer {
     pub fn new() -> Printer {
         Printer {
-            outwriter: BufferWriter::stdout(ColorChoice::Always),
-            errwriter: BufferWriter::stderr(ColorChoice::Always),
+            outwriter: BufferWriter::stdout(color_choice(io::stdout().is_terminal())),
+            errwriter: BufferWriter::stderr(color_choice(io::stderr().is_terminal())),
         }
     }
 
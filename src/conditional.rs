@@ -0,0 +1,201 @@
+use iron::headers::{
+    EntityTag, Headers, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, IfRange, IfUnmodifiedSince,
+};
+use iron::method::Method;
+
+/// What the preconditions of a request on an existing file ask for (RFC 7232 6)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precondition {
+    /// Answered as if there were none
+    Proceed,
+    /// 304, for `GET` and `HEAD` only
+    NotModified,
+    /// 412
+    Failed,
+}
+
+/// The validators of a file: the `ETag`s of its representations (the encoded ones included,
+/// none without ETags) and its modification time, in seconds as the HTTP dates are
+pub struct Validators<'a> {
+    pub etags: &'a [EntityTag],
+    pub modified: i64,
+}
+
+/// The preconditions of a request in the order of RFC 7232 6: `If-Match`, otherwise
+/// `If-Unmodified-Since`, then `If-None-Match`, otherwise `If-Modified-Since` (of `GET` and
+/// `HEAD` only). `If-Range` is left to the range requests
+pub fn evaluate(method: &Method, headers: &Headers, validators: &Validators) -> Precondition {
+    let safe = matches!(*method, Method::Get | Method::Head);
+    match headers.get::<IfMatch>() {
+        Some(IfMatch::Any) => {}
+        Some(IfMatch::Items(tags)) => {
+            if !any_matches(tags, validators.etags, EntityTag::strong_eq) {
+                return Precondition::Failed;
+            }
+        }
+        None => {
+            if let Some(&IfUnmodifiedSince(HttpDate(ref date))) = headers.get() {
+                if validators.modified > date.to_timespec().sec {
+                    return Precondition::Failed;
+                }
+            }
+        }
+    }
+    let unchanged = match headers.get::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => any_matches(tags, validators.etags, EntityTag::weak_eq),
+        None => match headers.get::<IfModifiedSince>() {
+            Some(&IfModifiedSince(HttpDate(ref date))) if safe => {
+                validators.modified <= date.to_timespec().sec
+            }
+            _ => false,
+        },
+    };
+    match unchanged {
+        false => Precondition::Proceed,
+        true if safe => Precondition::NotModified,
+        true => Precondition::Failed,
+    }
+}
+
+/// Whether the `If-Range` of the request (if any) matches `etag`, that of the identity: strong
+/// validators only, so a date is one once the file is a second old (RFC 7232 2.2.2)
+pub fn if_range(headers: &Headers, etag: Option<&EntityTag>, modified: i64) -> bool {
+    match headers.get::<IfRange>() {
+        Some(IfRange::EntityTag(tag)) => etag.is_some_and(|etag| etag.strong_eq(tag)),
+        Some(&IfRange::Date(HttpDate(ref date))) => {
+            date.to_timespec().sec == modified && time::get_time().sec > modified
+        }
+        None => true,
+    }
+}
+
+fn any_matches(
+    tags: &[EntityTag],
+    current: &[EntityTag],
+    eq: fn(&EntityTag, &EntityTag) -> bool,
+) -> bool {
+    tags.iter()
+        .any(|tag| current.iter().any(|current| eq(tag, current)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIED: i64 = 1_000_000_000;
+
+    fn headers(raw: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+        for (name, value) in raw {
+            headers.set_raw(name.to_string(), vec![value.as_bytes().to_vec()]);
+        }
+        headers
+    }
+
+    fn evaluate_get(raw: &[(&str, &str)]) -> Precondition {
+        let etags = [EntityTag::strong("abc".to_owned())];
+        let validators = Validators {
+            etags: &etags,
+            modified: MODIFIED,
+        };
+        evaluate(&Method::Get, &headers(raw), &validators)
+    }
+
+    #[test]
+    fn if_none_match() {
+        assert_eq!(evaluate_get(&[]), Precondition::Proceed);
+        assert_eq!(
+            evaluate_get(&[("If-None-Match", "\"abc\"")]),
+            Precondition::NotModified
+        );
+        // Weak comparison
+        assert_eq!(
+            evaluate_get(&[("If-None-Match", "W/\"abc\"")]),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            evaluate_get(&[("If-None-Match", "\"xyz\"")]),
+            Precondition::Proceed
+        );
+        let etags = [EntityTag::strong("abc".to_owned())];
+        let validators = Validators {
+            etags: &etags,
+            modified: MODIFIED,
+        };
+        let put = headers(&[("If-None-Match", "*")]);
+        assert_eq!(
+            evaluate(&Method::Put, &put, &validators),
+            Precondition::Failed
+        );
+    }
+
+    #[test]
+    fn if_match_before_the_dates() {
+        assert_eq!(
+            evaluate_get(&[("If-Match", "\"xyz\"")]),
+            Precondition::Failed
+        );
+        // Strong comparison
+        assert_eq!(
+            evaluate_get(&[("If-Match", "W/\"abc\"")]),
+            Precondition::Failed
+        );
+        assert_eq!(
+            evaluate_get(&[
+                ("If-Match", "\"abc\""),
+                ("If-Unmodified-Since", "Sat, 01 Jan 2000 00:00:00 GMT"),
+            ]),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            evaluate_get(&[("If-Unmodified-Since", "Sat, 01 Jan 2000 00:00:00 GMT")]),
+            Precondition::Failed
+        );
+    }
+
+    #[test]
+    fn if_modified_since() {
+        // 2001-09-09T01:46:40Z is MODIFIED
+        assert_eq!(
+            evaluate_get(&[("If-Modified-Since", "Sun, 09 Sep 2001 01:46:40 GMT")]),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            evaluate_get(&[("If-Modified-Since", "Sat, 01 Jan 2000 00:00:00 GMT")]),
+            Precondition::Proceed
+        );
+        // Ignored with an If-None-Match
+        assert_eq!(
+            evaluate_get(&[
+                ("If-None-Match", "\"xyz\""),
+                ("If-Modified-Since", "Sun, 09 Sep 2001 01:46:40 GMT"),
+            ]),
+            Precondition::Proceed
+        );
+    }
+
+    #[test]
+    fn if_range_strong_only() {
+        let etag = EntityTag::strong("abc".to_owned());
+        assert!(if_range(&headers(&[]), Some(&etag), MODIFIED));
+        assert!(if_range(
+            &headers(&[("If-Range", "\"abc\"")]),
+            Some(&etag),
+            MODIFIED
+        ));
+        assert!(!if_range(
+            &headers(&[("If-Range", "W/\"abc\"")]),
+            Some(&etag),
+            MODIFIED
+        ));
+        assert!(!if_range(
+            &headers(&[("If-Range", "\"abc\"")]),
+            None,
+            MODIFIED
+        ));
+        let date = headers(&[("If-Range", "Sun, 09 Sep 2001 01:46:40 GMT")]);
+        assert!(if_range(&date, Some(&etag), MODIFIED));
+        assert!(!if_range(&date, Some(&etag), MODIFIED + 1));
+    }
+}
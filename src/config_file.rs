@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::util::StringError;
+
+// Counted by the SIGHUP handler, every watcher reloads when it changes
+static HANGUPS: AtomicUsize = AtomicUsize::new(0);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The options of a `--config` file: `name = value` lines with the long option names
+/// (`port = 8080`, `auth = user:pass`), repeated for the repeatable options, `upload = true`
+/// or only `upload` for the flags, `#` for comments
+pub fn parse(path: &Path) -> Result<Vec<(String, Option<String>)>, StringError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| StringError(format!("Read config file {} failed: {}", path.display(), e)))?;
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(unquote(value.trim()).to_owned())),
+            None => (line, None),
+        };
+        if name.is_empty() {
+            return Err(StringError(format!(
+                "{}:{}: missing option name",
+                path.display(),
+                index + 1
+            )));
+        }
+        entries.push((name.trim_start_matches("--").to_owned(), value));
+    }
+    Ok(entries)
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Notices the changes of the config file (or other files): SIGHUP, or a new modification
+/// time
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+    hangups: usize,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Watcher {
+        Watcher::with_files(vec![path.to_path_buf()])
+    }
+
+    pub fn with_files(paths: Vec<PathBuf>) -> Watcher {
+        #[cfg(unix)]
+        {
+            extern "C" fn on_sighup(_: libc::c_int) {
+                HANGUPS.fetch_add(1, Ordering::Relaxed);
+            }
+            unsafe {
+                libc::signal(
+                    libc::SIGHUP,
+                    on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+        Watcher {
+            modified: paths.iter().map(|path| modified(path)).collect(),
+            paths,
+            hangups: HANGUPS.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The first file
+    pub fn path(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    /// Blocks until the files should be read again
+    pub fn wait(&mut self) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let modified = self
+                .paths
+                .iter()
+                .map(|path| modified(path))
+                .collect::<Vec<Option<SystemTime>>>();
+            // A file being replaced may be missing for a moment
+            let changed = modified
+                .iter()
+                .zip(&self.modified)
+                .any(|(now, before)| now.is_some() && now != before);
+            let hangups = HANGUPS.load(Ordering::Relaxed);
+            if hangups != self.hangups || changed {
+                self.hangups = hangups;
+                // The missing ones are noticed once they are back
+                for (before, now) in self.modified.iter_mut().zip(modified) {
+                    if now.is_some() {
+                        *before = now;
+                    }
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
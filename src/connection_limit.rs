@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::net::{NetworkListener, NetworkStream};
+
+// How long a rejected client may take to send its request, read before closing so the
+// response isn't reset
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Limits of the open connections, shared by all the listeners
+pub struct ConnectionLimiter {
+    max_total: Option<usize>,
+    max_per_ip: Option<usize>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_total: Option<usize>, max_per_ip: Option<usize>) -> ConnectionLimiter {
+        ConnectionLimiter {
+            max_total,
+            max_per_ip,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn acquire(&self, ip: IpAddr) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let of_ip = state.per_ip.get(&ip).cloned().unwrap_or(0);
+        if self.max_total.is_some_and(|max| state.total >= max)
+            || self.max_per_ip.is_some_and(|max| of_ip >= max)
+        {
+            return false;
+        }
+        state.total += 1;
+        state.per_ip.insert(ip, of_ip + 1);
+        true
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut state = self.state.lock().unwrap();
+        state.total -= 1;
+        if let Some(count) = state.per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                state.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Answers 503 to the connections over the limits
+#[derive(Clone)]
+pub struct LimitedListener<L> {
+    inner: L,
+    limiter: Option<Arc<ConnectionLimiter>>,
+}
+
+impl<L> LimitedListener<L> {
+    pub fn new(inner: L, limiter: Option<Arc<ConnectionLimiter>>) -> LimitedListener<L> {
+        LimitedListener { inner, limiter }
+    }
+}
+
+impl<L: NetworkListener> NetworkListener for LimitedListener<L> {
+    type Stream = LimitedStream<L::Stream>;
+
+    fn accept(&mut self) -> hyper::Result<Self::Stream> {
+        loop {
+            let mut stream = self.inner.accept()?;
+            let limiter = match self.limiter {
+                Some(ref limiter) => limiter.clone(),
+                None => {
+                    return Ok(LimitedStream {
+                        inner: stream,
+                        _guard: None,
+                    })
+                }
+            };
+            let ip = stream.peer_addr()?.ip();
+            if limiter.acquire(ip) {
+                return Ok(LimitedStream {
+                    inner: stream,
+                    _guard: Some(Arc::new(ConnectionGuard { limiter, ip })),
+                });
+            }
+            reject(&mut stream, "Too many connections");
+        }
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_write_timeout(dur)
+    }
+}
+
+/// Answers 503 with `Retry-After` to a connection refused before being served, and closes it
+pub fn reject<S: NetworkStream>(stream: &mut S, message: &str) {
+    let response = format!(
+        "HTTP/1.1 503 Service Unavailable\r\n\
+         Content-Type: text/plain\r\n\
+         Content-Length: {}\r\n\
+         Retry-After: 1\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}\n",
+        message.len() + 1,
+        message
+    );
+    let _ = stream.set_read_timeout(Some(DRAIN_TIMEOUT));
+    let mut buf = [0; 4096];
+    let _ = stream.read(&mut buf);
+    let _ = stream
+        .write_all(response.as_bytes())
+        .and_then(|_| stream.flush());
+    let _ = stream.close(Shutdown::Both);
+}
+
+// Released once all the clones of the stream are dropped
+struct ConnectionGuard {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.limiter.release(self.ip);
+    }
+}
+
+#[derive(Clone)]
+pub struct LimitedStream<S> {
+    inner: S,
+    _guard: Option<Arc<ConnectionGuard>>,
+}
+
+impl<S: Read> Read for LimitedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for LimitedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: NetworkStream> NetworkStream for LimitedStream<S> {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.inner.close(how)
+    }
+}
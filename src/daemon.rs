@@ -0,0 +1,148 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+#[cfg(unix)]
+use std::{ffi::CString, sync::OnceLock};
+
+use crate::util::StringError;
+
+// Removed by the SIGTERM/SIGINT handler
+#[cfg(unix)]
+static PID_FILE: OnceLock<CString> = OnceLock::new();
+
+/// The background process of `--daemon`, still attached to the terminal until `detach()` so
+/// the startup messages and errors are seen
+pub struct Daemon {
+    #[cfg(unix)]
+    ready: File,
+}
+
+/// Forks into the background: the foreground process waits until the server is started
+/// (exit 0), or until it failed (exit 1)
+#[cfg(unix)]
+pub fn daemonize() -> Result<Daemon, StringError> {
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(last_error("pipe"));
+    }
+    let (mut waiting, ready) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    match unsafe { libc::fork() } {
+        -1 => return Err(last_error("fork")),
+        0 => {}
+        _ => {
+            drop(ready);
+            let mut buf = [0; 1];
+            // EOF: the daemon exited before being ready
+            let started = matches!(waiting.read(&mut buf), Ok(1));
+            process::exit(if started { 0 } else { 1 });
+        }
+    }
+    drop(waiting);
+    if unsafe { libc::setsid() } == -1 {
+        return Err(last_error("setsid"));
+    }
+    // Not a session leader anymore, no terminal can be acquired again
+    match unsafe { libc::fork() } {
+        -1 => return Err(last_error("fork")),
+        0 => {}
+        _ => unsafe { libc::_exit(0) },
+    }
+    unsafe { libc::umask(0o022) };
+    Ok(Daemon { ready })
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> Result<Daemon, StringError> {
+    Err(StringError(
+        "--daemon is not supported on this platform".to_owned(),
+    ))
+}
+
+impl Daemon {
+    /// Lets the foreground process exit, the outputs go to /dev/null from now on
+    #[cfg(unix)]
+    pub fn detach(mut self) {
+        use std::os::unix::io::AsRawFd;
+
+        if let Ok(null) = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/null")
+        {
+            for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                unsafe { libc::dup2(null.as_raw_fd(), fd) };
+            }
+        }
+        let _ = self.ready.write_all(b"1");
+    }
+
+    #[cfg(not(unix))]
+    pub fn detach(self) {}
+}
+
+/// `--pid-file`: refused while the process it names is still running, removed on SIGTERM/SIGINT
+pub fn write_pid_file(path: &Path) -> Result<(), StringError> {
+    if let Some(pid) = fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+    {
+        if pid != process::id() && is_running(pid) {
+            return Err(StringError(format!(
+                "Already running with pid {} ({})",
+                pid,
+                path.display()
+            )));
+        }
+    }
+    let mut file = File::create(path)
+        .map_err(|e| StringError(format!("Write pid file {} failed: {}", path.display(), e)))?;
+    writeln!(file, "{}", process::id())
+        .map_err(|e| StringError(format!("Write pid file {} failed: {}", path.display(), e)))?;
+    #[cfg(unix)]
+    {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Ok(path) = CString::new(path.to_string_lossy().as_bytes()) {
+            let _ = PID_FILE.set(path);
+        }
+        extern "C" fn on_exit_signal(_: libc::c_int) {
+            if let Some(path) = PID_FILE.get() {
+                unsafe { libc::unlink(path.as_ptr()) };
+            }
+            unsafe { libc::_exit(0) };
+        }
+        for signal in [libc::SIGTERM, libc::SIGINT] {
+            unsafe {
+                libc::signal(
+                    signal,
+                    on_exit_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // EPERM: running as another user
+    (unsafe { libc::kill(pid as libc::pid_t, 0) } == 0)
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn last_error(call: &str) -> StringError {
+    StringError(format!(
+        "{} failed: {}",
+        call,
+        std::io::Error::last_os_error()
+    ))
+}
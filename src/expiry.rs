@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::access::ACCESS_FILE;
+use crate::atomic_file;
+use crate::util::root_relative_path;
+
+// How often the sweeper looks for the expired files, at most
+const MAX_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `--expire`: the files are deleted this long after their upload (their modification time),
+/// or after the start of the server for the older ones
+pub struct Expiry {
+    after: Duration,
+    started: SystemTime,
+}
+
+impl Expiry {
+    pub fn new(after: Duration) -> Expiry {
+        Expiry {
+            after,
+            started: SystemTime::now(),
+        }
+    }
+
+    /// When the file of `metadata` expires, `None` for the directories
+    pub fn expires(&self, metadata: &fs::Metadata) -> Option<SystemTime> {
+        if metadata.is_dir() {
+            return None;
+        }
+        let modified = metadata.modified().ok()?.max(self.started);
+        Some(modified + self.after)
+    }
+
+    /// The time left before the expiry, roughly: `3d`, `5h`, `12m` or `30s`
+    pub fn time_left(&self, metadata: &fs::Metadata) -> Option<String> {
+        let left = self
+            .expires(metadata)?
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .as_secs();
+        Some(match left {
+            86400.. => format!("{}d", left / 86400),
+            3600.. => format!("{}h", left / 3600),
+            60.. => format!("{}m", left / 60),
+            _ => format!("{}s", left),
+        })
+    }
+
+    pub fn is_expired(&self, metadata: &fs::Metadata) -> bool {
+        self.expires(metadata)
+            .is_some_and(|expires| expires <= SystemTime::now())
+    }
+
+    /// Deletes the expired files under the `roots` periodically, apart from those of `keep`
+    /// (the trash directory), and the directories they leave empty
+    pub fn spawn_sweeper(self: Arc<Self>, roots: Vec<PathBuf>, keep: Option<PathBuf>) {
+        let interval = (self.after / 10).clamp(Duration::from_secs(1), MAX_SWEEP_INTERVAL);
+        thread::spawn(move || loop {
+            for root in &roots {
+                self.sweep(root, root, keep.as_deref());
+            }
+            thread::sleep(interval);
+        });
+    }
+
+    // Whether `dir` is left empty, and whether anything was removed
+    fn sweep(&self, root: &Path, dir: &Path, keep: Option<&Path>) -> (bool, bool) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return (false, false),
+        };
+        let (mut empty, mut removed) = (true, false);
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = entry.file_name();
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let kept = keep.is_some_and(|keep| path.starts_with(keep))
+                || name == ACCESS_FILE
+                || atomic_file::is_temp(&name);
+            if kept {
+                empty = false;
+            } else if metadata.is_dir() {
+                // Only the directories emptied by the expiry, not the new ones
+                match self.sweep(root, &path, keep) {
+                    (true, true) if fs::remove_dir(&path).is_ok() => removed = true,
+                    _ => empty = false,
+                }
+            } else if self.is_expired(&metadata) && fs::remove_file(&path).is_ok() {
+                println!("  >> Expired: {}", root_relative_path(root, &path));
+                removed = true;
+            } else {
+                empty = false;
+            }
+        }
+        (empty, removed)
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+use globset::{Glob, GlobMatcher};
+use iron::response::WriteBody;
+use iron::status;
+use iron::{IronError, IronResult, Request, Response};
+
+use crate::cgi;
+use crate::util::StringError;
+
+const VERSION: u8 = 1;
+const BEGIN_REQUEST: u8 = 1;
+const END_REQUEST: u8 = 3;
+const PARAMS: u8 = 4;
+const STDIN: u8 = 5;
+const STDOUT: u8 = 6;
+const STDERR: u8 = 7;
+const RESPONDER: u16 = 1;
+// Only one request per connection
+const REQUEST_ID: u16 = 1;
+const MAX_RECORD: usize = 65535;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Files matching a pattern are run by a FastCGI backend (`--fastcgi "*.php=127.0.0.1:9000"`)
+pub struct FastCgi {
+    matcher: GlobMatcher,
+    /// `host:port`, or `unix:/path/to/socket`
+    address: String,
+}
+
+impl FastCgi {
+    pub fn parse(s: &str) -> Result<FastCgi, StringError> {
+        let parts = s.splitn(2, '=').collect::<Vec<&str>>();
+        if parts.len() != 2 || parts[0].is_empty() || parts[1].is_empty() {
+            return Err(StringError(format!(
+                "not valid format pattern=address: {}",
+                s
+            )));
+        }
+        let matcher = Glob::new(parts[0])
+            .map(|glob| glob.compile_matcher())
+            .map_err(|e| StringError(format!("Invalid pattern {}: {}", parts[0], e)))?;
+        Ok(FastCgi {
+            matcher,
+            address: parts[1].to_owned(),
+        })
+    }
+
+    /// Patterns are matched against the file name
+    pub fn matches(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| self.matcher.is_match(name))
+    }
+
+    fn connect(&self) -> io::Result<Box<dyn Stream>> {
+        #[cfg(unix)]
+        {
+            if let Some(path) = self.address.strip_prefix("unix:") {
+                return Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?));
+            }
+        }
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address");
+        for addr in std::net::ToSocketAddrs::to_socket_addrs(&self.address)? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => return Ok(Box::new(stream)),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Sends the request to the backend, its output is streamed as the response
+    pub fn run(
+        &self,
+        req: &mut Request,
+        script: &cgi::Script,
+        document_root: &Path,
+    ) -> IronResult<Response> {
+        let failed = |e: io::Error| {
+            IronError::new(
+                StringError(format!("FastCGI backend {} failed: {}", self.address, e)),
+                status::BadGateway,
+            )
+        };
+        let mut stream = self.connect().map_err(failed)?;
+
+        let mut begin = Vec::with_capacity(8);
+        begin.extend_from_slice(&RESPONDER.to_be_bytes());
+        // flags = 0: the backend closes the connection at the end
+        begin.extend_from_slice(&[0; 6]);
+        write_record(&mut stream, BEGIN_REQUEST, &begin).map_err(failed)?;
+
+        let mut params = Vec::new();
+        let mut env = cgi::environment(
+            req,
+            &script.name,
+            &script.path_info,
+            &script.path,
+            document_root,
+        );
+        // php-cgi refuses to run without it (`cgi.force_redirect`)
+        env.push(("REDIRECT_STATUS".to_owned(), "200".to_owned()));
+        for (name, value) in &env {
+            encode_length(&mut params, name.len());
+            encode_length(&mut params, value.len());
+            params.extend_from_slice(name.as_bytes());
+            params.extend_from_slice(value.as_bytes());
+        }
+        write_stream(&mut stream, PARAMS, &params).map_err(failed)?;
+        write_record(&mut stream, PARAMS, &[]).map_err(failed)?;
+
+        let mut buf = vec![0; MAX_RECORD];
+        loop {
+            let n = req.body.read(&mut buf).map_err(failed)?;
+            if n == 0 {
+                break;
+            }
+            write_record(&mut stream, STDIN, &buf[..n]).map_err(failed)?;
+        }
+        write_record(&mut stream, STDIN, &[]).map_err(failed)?;
+        stream.flush().map_err(failed)?;
+
+        let mut stdout = BufReader::new(Records {
+            stream,
+            remaining: 0,
+            padding: 0,
+            done: false,
+        });
+        let mut resp = cgi::parse_headers(&mut stdout).map_err(failed)?;
+        resp.body = Some(Box::new(FastCgiBody(stdout)));
+        Ok(resp)
+    }
+}
+
+fn encode_length(buf: &mut Vec<u8>, len: usize) {
+    if len < 128 {
+        buf.push(len as u8);
+    } else {
+        buf.extend_from_slice(&(len as u32 | 0x8000_0000).to_be_bytes());
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, kind: u8, content: &[u8]) -> io::Result<()> {
+    let len = content.len() as u16;
+    let mut header = [VERSION, kind, 0, 0, 0, 0, 0, 0];
+    header[2..4].copy_from_slice(&REQUEST_ID.to_be_bytes());
+    header[4..6].copy_from_slice(&len.to_be_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(content)
+}
+
+// Split in records of at most 64KiB
+fn write_stream<W: Write>(writer: &mut W, kind: u8, content: &[u8]) -> io::Result<()> {
+    for chunk in content.chunks(MAX_RECORD) {
+        write_record(writer, kind, chunk)?;
+    }
+    Ok(())
+}
+
+/// The `STDOUT` stream of the backend, `STDERR` is printed
+struct Records {
+    stream: Box<dyn Stream>,
+    remaining: usize,
+    padding: usize,
+    done: bool,
+}
+
+impl Records {
+    fn skip(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut content = vec![0; len];
+        self.stream.read_exact(&mut content)?;
+        Ok(content)
+    }
+}
+
+impl Read for Records {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.done {
+                return Ok(0);
+            }
+            self.skip(self.padding)?;
+            self.padding = 0;
+            let mut header = [0; 8];
+            if let Err(e) = self.stream.read_exact(&mut header) {
+                // Closed without END_REQUEST
+                if e.kind() == io::ErrorKind::UnexpectedEof {
+                    self.done = true;
+                    return Ok(0);
+                }
+                return Err(e);
+            }
+            let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+            let padding = header[6] as usize;
+            match header[1] {
+                STDOUT => {
+                    self.remaining = len;
+                    self.padding = padding;
+                }
+                STDERR => {
+                    let message = self.skip(len)?;
+                    self.skip(padding)?;
+                    eprintln!(
+                        "  >> FastCGI: {}",
+                        String::from_utf8_lossy(&message).trim_end()
+                    );
+                }
+                END_REQUEST => {
+                    self.skip(len + padding)?;
+                    self.done = true;
+                }
+                _ => {
+                    self.skip(len + padding)?;
+                }
+            }
+        }
+        let len = buf.len().min(self.remaining);
+        let n = self.stream.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "FastCGI record truncated",
+            ));
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+struct FastCgiBody(BufReader<Records>);
+
+impl WriteBody for FastCgiBody {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        io::copy(&mut self.0, res).map(|_| ())
+    }
+}
@@ -0,0 +1,3357 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, RwLock};
+
+use htmlescape::{encode_attribute, encode_minimal};
+use iron::headers;
+use iron::headers::ContentEncoding;
+use iron::method;
+use iron::mime::Mime;
+use iron::modifiers::Redirect;
+use iron::status;
+use iron::{Chain, Handler, IronError, IronResult, Request, Response, Set};
+use lazy_static::lazy_static;
+use mime_guess as mime_types;
+use multipart::server::save::{PartialReason, SavedData};
+use multipart::server::{Multipart, SaveResult};
+use path_dedot::ParseDot;
+use pretty_bytes::converter::convert;
+
+use crate::access::{AccessRules, ACCESS_FILE};
+use crate::archive::{ArchiveBody, ArchiveFormat, ARCHIVE_QUERY};
+use crate::audit::{Actor, AuditLog};
+use crate::cgi::CgiDir;
+use crate::checksum::ChecksumCache;
+use crate::conditional::{self, Precondition, Validators};
+use crate::expiry::Expiry;
+use crate::fastcgi::FastCgi;
+use crate::health::HealthCheck;
+use crate::highlight::{Highlighter, MAX_HIGHLIGHT_SIZE, PAGE_CSP};
+use crate::hooks::Hooks;
+use crate::ignore_rules::IgnoreRules;
+use crate::listing::{self, Entry, ListingEntries, StreamedListing, Summary};
+use crate::listing_cache::{ListingCache, ListingRows};
+use crate::manifest::{Manifest, MANIFEST_PATH};
+use crate::middlewares::{ClientCert, Login, Sessions, User, LOGIN_PATH, LOGOUT_PATH};
+use crate::one_time::{OneTimeLinks, ONE_TIME_PATH, ONE_TIME_QUERY};
+use crate::protect::ProtectedPaths;
+use crate::range::{self, RangeSelection};
+use crate::s3::{S3Error, S3Gateway, S3Route};
+use crate::stats::{ServerStats, STATUS_PATH};
+use crate::thumbnail::{Thumbnailer, THUMBNAIL_PATH};
+use crate::trash::Trash;
+use crate::upload_command::UploadCommand;
+use crate::upload_filter::UploadFilter;
+use crate::upload_limit::{Client, UploadLimiter};
+use crate::util::{
+    client_relative_path, content_disposition_attachment, decode_path_segment, encode_link_path,
+    encode_query_path, error_io2iron, error_resp, get_cookie, name_filter, query_with,
+    random_token, root_relative_path, secure_attribute, set_page_csp, system_time_to_date_time,
+    tree_size, with_nonce, StringError, ROOT_LINK,
+};
+use crate::webhook::Webhook;
+use crate::{atomic_file, cgi, manifest, middlewares, s3, thumbnail, upload_filter};
+
+const ORDER_ASC: &str = "asc";
+const ORDER_DESC: &str = "desc";
+const DEFAULT_ORDER: &str = ORDER_DESC;
+// Where the rows and the summary of the footer go in the streamed listings
+const ROWS_MARK: &str = "\u{0}rows\u{0}";
+const SUMMARY_MARK: &str = "\u{0}summary\u{0}";
+/// Name of both the upload form field and the cookie carrying the CSRF token
+const CSRF_TOKEN_NAME: &str = "csrf_token";
+// Largest urlencoded form body (selected entries of archive downloads)
+const MAX_FORM_SIZE: u64 = 1024 * 1024;
+// The CSRF token of the requests sent by scripts (DELETE)
+const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+const UPLOAD_FIELD: &str = "files";
+// Paths of the files inside uploaded directories (`photos/2020/a.jpg`), one per file
+const RELATIVE_PATH_FIELD: &str = "relative_path";
+// Optional subfolder (of the current directory) to upload into
+const DESTINATION_FIELD: &str = "destination";
+// Modification times of the files (milliseconds since the epoch), one per file
+const LAST_MODIFIED_FIELD: &str = "last-modified";
+const LISTING_STYLE: &str = include_str!("listing.css");
+// Uploads the files one by one with progress bars, the plain form works without it.
+// Files can be dropped anywhere on the page.
+const UPLOAD_SCRIPT: &str = r#"
+(function () {
+  var form = document.getElementById('upload-form');
+  var list = document.getElementById('upload-progress');
+  if (!window.FormData || !window.XMLHttpRequest) return;
+  form.querySelector('.drop-hint').hidden = false;
+
+  function errorMessage(xhr) {
+    var doc = new DOMParser().parseFromString(xhr.responseText || '', 'text/html');
+    var divs = doc.querySelectorAll('div');
+    var text = divs.length ? divs[divs.length - 1].textContent : '';
+    return (text || xhr.status + ' ' + xhr.statusText).trim();
+  }
+
+  // Files with their paths inside the chosen or dropped folders
+  function withPath(file, path) {
+    return { file: file, path: path || file.webkitRelativePath || file.name };
+  }
+
+  function readEntry(entry, files, done) {
+    if (entry.isFile) {
+      entry.file(function (file) {
+        files.push(withPath(file, entry.fullPath.replace(/^\//, '')));
+        done();
+      }, done);
+      return;
+    }
+    var reader = entry.createReader();
+    (function readBatch() {
+      reader.readEntries(function (entries) {
+        if (!entries.length) return done();
+        var pending = entries.length;
+        entries.forEach(function (child) {
+          readEntry(child, files, function () {
+            if (--pending === 0) readBatch();
+          });
+        });
+      }, done);
+    })();
+  }
+
+  function uploadOne(entry, done) {
+    var file = entry.file;
+    var item = document.createElement('li');
+    var label = document.createElement('span');
+    var bar = document.createElement('progress');
+    var status = document.createElement('span');
+    label.textContent = entry.path + ' ';
+    bar.max = 100;
+    bar.value = 0;
+    status.className = 'upload-status';
+    item.appendChild(label);
+    item.appendChild(bar);
+    item.appendChild(status);
+    list.appendChild(item);
+
+    var data = new FormData();
+    form.querySelectorAll('input[type=hidden], input[name=destination]').forEach(function (input) {
+      data.append(input.name, input.value);
+    });
+    data.append('relative_path', entry.path);
+    data.append('last-modified', file.lastModified);
+    data.append('files', file, file.name);
+    var xhr = new XMLHttpRequest();
+    xhr.open('POST', form.action);
+    xhr.upload.onprogress = function (e) {
+      if (e.lengthComputable) {
+        bar.value = e.loaded * 100 / e.total;
+        status.textContent = ' ' + Math.floor(bar.value) + '%';
+      }
+    };
+    xhr.onload = function () {
+      var ok = xhr.status < 400;
+      if (ok) bar.value = 100;
+      status.textContent = ok ? ' done' : ' ' + errorMessage(xhr);
+      item.className = ok ? 'upload-done' : 'upload-error';
+      done(ok);
+    };
+    xhr.onerror = function () {
+      status.textContent = ' network error';
+      item.className = 'upload-error';
+      done(false);
+    };
+    xhr.send(data);
+  }
+
+  function uploadAll(files) {
+    var index = 0;
+    var failed = false;
+    (function next() {
+      if (index >= files.length) {
+        if (!failed) location.reload();
+        return;
+      }
+      uploadOne(files[index++], function (ok) {
+        failed = failed || !ok;
+        next();
+      });
+    })();
+  }
+
+  form.addEventListener('submit', function (e) {
+    var files = [];
+    form.querySelectorAll('input[type=file]').forEach(function (input) {
+      Array.prototype.forEach.call(input.files, function (file) {
+        files.push(withPath(file));
+      });
+    });
+    if (!files.length) return;
+    e.preventDefault();
+    uploadAll(files);
+  });
+  document.addEventListener('dragover', function (e) {
+    e.preventDefault();
+    document.body.classList.add('dragging');
+  });
+  document.addEventListener('dragleave', function (e) {
+    if (!e.relatedTarget) document.body.classList.remove('dragging');
+  });
+  document.addEventListener('drop', function (e) {
+    e.preventDefault();
+    document.body.classList.remove('dragging');
+    var items = Array.prototype.slice.call(e.dataTransfer.items || []);
+    var entries = items.map(function (item) {
+      return item.webkitGetAsEntry && item.webkitGetAsEntry();
+    }).filter(Boolean);
+    if (!entries.length) {
+      uploadAll(Array.prototype.map.call(e.dataTransfer.files, function (file) {
+        return withPath(file);
+      }));
+      return;
+    }
+    var files = [];
+    var pending = entries.length;
+    entries.forEach(function (entry) {
+      readEntry(entry, files, function () {
+        if (--pending === 0 && files.length) uploadAll(files);
+      });
+    });
+  });
+})();
+"#;
+// Delete links of the entries, sent as DELETE requests
+const DELETE_SCRIPT: &str = r#"
+(function () {
+  var token = document.querySelector('#upload-form input[name=csrf_token]');
+  document.addEventListener('click', function (e) {
+    var link = e.target.closest && e.target.closest('a.delete');
+    if (!link) return;
+    e.preventDefault();
+    var row = link.closest('tr');
+    if (!confirm('Delete ' + row.getAttribute('data-name') + '?')) return;
+    var xhr = new XMLHttpRequest();
+    xhr.open('DELETE', link.getAttribute('href'));
+    if (token) xhr.setRequestHeader('X-CSRF-Token', token.value);
+    xhr.onload = function () {
+      if (xhr.status < 400) {
+        row.remove();
+      } else {
+        var doc = new DOMParser().parseFromString(xhr.responseText || '', 'text/html');
+        var divs = doc.querySelectorAll('div');
+        alert(divs.length ? divs[divs.length - 1].textContent : xhr.status + ' ' + xhr.statusText);
+      }
+    };
+    xhr.send();
+  });
+})();
+"#;
+// Creates the one-time links of the listed files, shown to be copied
+const ONE_TIME_SCRIPT: &str = r#"
+(function () {
+  document.addEventListener('click', function (e) {
+    var link = e.target.closest && e.target.closest('a.one-time');
+    if (!link) return;
+    e.preventDefault();
+    var xhr = new XMLHttpRequest();
+    xhr.open('POST', link.getAttribute('href'));
+    xhr.onload = function () {
+      if (xhr.status < 400) {
+        prompt('One-time download link', location.origin + JSON.parse(xhr.responseText).link);
+      } else {
+        alert(xhr.status + ' ' + xhr.statusText);
+      }
+    };
+    xhr.send();
+  });
+})();
+"#;
+// Narrows the listed entries while typing in the filter box (same pattern as the server)
+const FILTER_SCRIPT: &str = r#"
+(function () {
+  var input = document.querySelector('.filter-form input[name=filter]');
+  function toRegExp(pattern) {
+    if (pattern.indexOf('[') >= 0) return null;
+    if (!/[*?]/.test(pattern)) pattern = '*' + pattern + '*';
+    var source = pattern.replace(/[.+^${}()|\\]/g, '\\$&').replace(/\*/g, '.*').replace(/\?/g, '.');
+    return new RegExp('^' + source + '$', 'i');
+  }
+  input.addEventListener('input', function () {
+    var re = input.value ? toRegExp(input.value) : /^/;
+    if (!re) return;
+    document.querySelectorAll('tr.entry').forEach(function (row) {
+      row.hidden = !re.test(row.getAttribute('data-name'));
+    });
+    document.querySelectorAll('.gallery-item').forEach(function (item) {
+      item.hidden = !re.test(item.title);
+    });
+  });
+})();
+"#;
+// Loads the next page of the listing when the pager scrolls into view
+const LAZY_LOAD_SCRIPT: &str = r#"
+(function () {
+  var pager = document.querySelector('.pager');
+  if (!pager || !('IntersectionObserver' in window) || !window.fetch) return;
+  var table = document.querySelector('table.listing');
+  var loading = false;
+  var observer = new IntersectionObserver(function (entries) {
+    var next = document.getElementById('next-page');
+    if (!next) { observer.disconnect(); return; }
+    if (!entries[0].isIntersecting || loading) return;
+    loading = true;
+    fetch(next.href, { credentials: 'same-origin' })
+      .then(function (resp) { return resp.text(); })
+      .then(function (html) {
+        var doc = new DOMParser().parseFromString(html, 'text/html');
+        var body = table.tBodies[table.tBodies.length - 1];
+        doc.querySelectorAll('table.listing tr.entry').forEach(function (row) {
+          body.appendChild(document.importNode(row, true));
+        });
+        var loaded = doc.querySelector('.pager');
+        pager.innerHTML = loaded ? loaded.innerHTML : '';
+        loading = false;
+      });
+  });
+  observer.observe(pager);
+})();
+"#;
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "iso", "dmg", "deb", "rpm",
+];
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "c", "h", "cc", "cpp", "hpp", "go", "py", "rb", "js", "ts", "jsx", "tsx", "java", "kt",
+    "swift", "cs", "php", "sh", "bash", "zsh", "pl", "lua", "sql", "toml", "yaml", "yml", "json",
+    "xml", "html", "css", "scss", "vue", "ipynb",
+];
+// Thumbnails change with the image, but without a validator of their own
+const THUMBNAIL_MAX_AGE: u32 = 300;
+
+lazy_static! {
+    static ref SORT_FIELDS: Vec<&'static str> = vec!["name", "modified", "size"];
+}
+
+/// The same handler chain behind all the listeners
+#[derive(Clone)]
+pub(crate) struct SharedHandler {
+    pub(crate) chain: Arc<Chain>,
+    // Probes skip the chain (auth, logging)
+    pub(crate) health: Option<Arc<HealthCheck>>,
+}
+
+impl Handler for SharedHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        if let Some(resp) = self.health.as_ref().and_then(|health| health.probe(req)) {
+            return Ok(resp);
+        }
+        self.chain.handle(req)
+    }
+}
+
+/// The settings applied again when the `--config` file is reloaded, the others need a restart
+pub(crate) struct LiveSettings {
+    pub(crate) cache: AtomicBool,
+    pub(crate) compress: RwLock<Option<Vec<String>>>,
+    pub(crate) ignore_rules: RwLock<Option<Arc<IgnoreRules>>>,
+    // Counts the reloads, the listings cached before are stale
+    generation: AtomicUsize,
+}
+
+impl LiveSettings {
+    pub(crate) fn new(matches: &clap::ArgMatches) -> Result<LiveSettings, StringError> {
+        LiveSettings::with(
+            !matches.is_present("nocache"),
+            matches.values_of_lossy("compress"),
+            &matches.values_of_lossy("ignore").unwrap_or_default(),
+            matches.is_present("gitignore"),
+        )
+    }
+
+    /// `compress` are extensions without the dot, MIME types or `auto`, see
+    /// `middlewares::is_compressible()`
+    pub(crate) fn with(
+        cache: bool,
+        compress: Option<Vec<String>>,
+        ignore_patterns: &[String],
+        gitignore: bool,
+    ) -> Result<LiveSettings, StringError> {
+        let ignore_rules = if !ignore_patterns.is_empty() || gitignore {
+            Some(Arc::new(IgnoreRules::new(ignore_patterns, gitignore)?))
+        } else {
+            None
+        };
+        Ok(LiveSettings {
+            cache: AtomicBool::new(cache),
+            compress: RwLock::new(compress.map(|rules| {
+                rules
+                    .iter()
+                    .map(|rule| {
+                        if rule == "auto" || rule.contains('/') {
+                            rule.to_owned()
+                        } else {
+                            format!(".{}", rule)
+                        }
+                    })
+                    .collect()
+            })),
+            ignore_rules: RwLock::new(ignore_rules),
+            generation: AtomicUsize::new(0),
+        })
+    }
+
+    pub(crate) fn replace(&self, other: LiveSettings) {
+        self.cache
+            .store(other.cache.into_inner(), AtomicOrdering::Relaxed);
+        *self.compress.write().unwrap() = other.compress.into_inner().unwrap();
+        *self.ignore_rules.write().unwrap() = other.ignore_rules.into_inner().unwrap();
+        self.generation.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+
+    fn cache(&self) -> bool {
+        self.cache.load(AtomicOrdering::Relaxed)
+    }
+
+    fn compress(&self) -> Option<Vec<String>> {
+        self.compress.read().unwrap().clone()
+    }
+
+    fn ignore_rules(&self) -> Option<Arc<IgnoreRules>> {
+        self.ignore_rules.read().unwrap().clone()
+    }
+
+    fn generation(&self) -> usize {
+        self.generation.load(AtomicOrdering::Relaxed)
+    }
+}
+
+/// The `ETag` of the files (`--etag`)
+pub(crate) enum EtagMode {
+    /// Of the size and the modification time
+    Strong,
+    /// The same, weak: never matches `If-Range` nor `If-Match`
+    Weak,
+    /// SHA-256 of the content, kept by a touch
+    ContentHash(ChecksumCache),
+    Off,
+}
+
+pub(crate) struct MainHandler {
+    pub(crate) root: PathBuf,
+    pub(crate) index: bool,
+    pub(crate) upload: bool,
+    /// `--upload-only`: the upload form in place of the listings and files, nothing replaced
+    pub(crate) upload_only: bool,
+    /// `--delete`: the `DELETE` requests and the delete links of the listings
+    pub(crate) delete: bool,
+    pub(crate) csrf: bool,
+    pub(crate) live: Arc<LiveSettings>,
+    pub(crate) range: bool,
+    pub(crate) redirect_to: Option<iron::Url>,
+    pub(crate) listing: bool,
+    pub(crate) sort: bool,
+    pub(crate) try_file_404: Option<PathBuf>,
+    pub(crate) pretty_urls: bool,
+    pub(crate) canonical_redirect: bool,
+    pub(crate) html_header: Option<String>,
+    pub(crate) html_footer: Option<String>,
+    pub(crate) inject_html: bool,
+    pub(crate) upload_size_limit: u64,
+    pub(crate) upload_quota: Option<u64>,
+    pub(crate) upload_filter: Option<UploadFilter>,
+    pub(crate) preserve_mtime: bool,
+    pub(crate) trash: Option<Trash>,
+    pub(crate) mime_overrides: HashMap<String, Mime>,
+    pub(crate) charset: String,
+    pub(crate) force_download: bool,
+    pub(crate) vhosts: HashMap<String, PathBuf>,
+    pub(crate) checksums: Option<ChecksumCache>,
+    pub(crate) etag: EtagMode,
+    /// `--access-files`
+    pub(crate) access: Option<Arc<AccessRules>>,
+    pub(crate) manifest: bool,
+    pub(crate) s3: Option<S3Gateway>,
+    pub(crate) cgi_dirs: Vec<CgiDir>,
+    pub(crate) fastcgi: Vec<FastCgi>,
+    pub(crate) webhook: Option<Webhook>,
+    pub(crate) on_upload: Option<UploadCommand>,
+    pub(crate) hooks: Hooks,
+    pub(crate) stats: Option<Arc<ServerStats>>,
+    pub(crate) no_robots: bool,
+    pub(crate) thumbnailer: Option<Thumbnailer>,
+    pub(crate) highlighter: Option<Highlighter>,
+    pub(crate) theme: String,
+    pub(crate) page_size: usize,
+    pub(crate) sessions: Option<Arc<Sessions>>,
+    pub(crate) audit: Option<AuditLog>,
+    pub(crate) upload_limit: Option<UploadLimiter>,
+    pub(crate) expiry: Option<Arc<Expiry>>,
+    pub(crate) one_time_links: Option<OneTimeLinks>,
+    /// `--protect-path`
+    pub(crate) protected: Option<ProtectedPaths>,
+    /// `--listing-cache`, not used with `--expire` (the time left changes)
+    pub(crate) listing_cache: Option<ListingCache>,
+    /// `--stream-listings`
+    pub(crate) stream_listings: bool,
+}
+
+impl Handler for MainHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        // An echo of the request would give its cookies and credentials to the scripts
+        // (cross-site tracing)
+        if req.method == method::Trace {
+            return Ok(self.method_not_allowed(None));
+        }
+        if let Some(ref sessions) = self.sessions {
+            if req.url.path() == [LOGIN_PATH] || req.url.path() == [LOGOUT_PATH] {
+                return Ok(self.send_login(req, sessions));
+            }
+        }
+        if let Some(ref stats) = self.stats {
+            if req.url.path() == [STATUS_PATH] {
+                return Ok(stats.page());
+            }
+        }
+        if let Some(ref links) = self.one_time_links {
+            if let [ONE_TIME_PATH, token] = req.url.path()[..] {
+                let token = token.to_owned();
+                return self.send_one_time(req, links, &token);
+            }
+        }
+        if let Some(ref gateway) = self.s3 {
+            if let Some(route) = s3::route(req, &gateway.bucket) {
+                // The failed changes, the others are recorded once done
+                let change = match (&req.method, &route) {
+                    (method::Put, S3Route::Object(key)) if key.ends_with('/') => {
+                        Some(("mkdir", format!("/{}", key.trim_end_matches('/'))))
+                    }
+                    (method::Put, S3Route::Object(key)) => Some(("upload", format!("/{}", key))),
+                    (method::Delete, S3Route::Object(key)) => Some(("delete", format!("/{}", key))),
+                    _ => None,
+                };
+                let actor = self.actor(req);
+                return Ok(self.handle_s3(req, gateway, route).unwrap_or_else(|e| {
+                    if let (Some(audit), Some((event, path))) = (&self.audit, change) {
+                        audit.record(&actor, event, &path, None, Err((e.status, &e.message)));
+                    }
+                    e.response()
+                }));
+            }
+        }
+        let root = self.root_for(req);
+        if self.thumbnailer.is_some() && !self.upload_only && req.url.path() == [THUMBNAIL_PATH] {
+            return self.send_thumbnail(req, root);
+        }
+        if self.manifest && !self.upload_only && req.url.path() == [MANIFEST_PATH] {
+            return self.send_manifest(req, root);
+        }
+        let mut fs_path = root.to_path_buf();
+        if let Some(url) = &self.redirect_to {
+            return Ok(Response::with((
+                status::PermanentRedirect,
+                Redirect(url.clone()),
+            )));
+        }
+        let path_prefix = req
+            .url
+            .path()
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .map(decode_path_segment)
+            .collect::<Result<PathBuf, _>>()
+            .map_err(|e| IronError::new(e, status::BadRequest))?;
+        fs_path.push(&path_prefix);
+        let fs_path = fs_path.parse_dot().map_err(error_io2iron)?;
+
+        if !fs_path.starts_with(root) {
+            return Err(IronError::new(
+                io::Error::new(io::ErrorKind::PermissionDenied, "Permission Denied"),
+                status::Forbidden,
+            ));
+        }
+
+        if let Some(resp) = self.check_or_unlock(req, root, &fs_path)? {
+            return Ok(resp);
+        }
+        // The rules of the URL path, as if the scripts were files of the root
+        for dir in &self.cgi_dirs {
+            if let Some(script) = dir.resolve(&req.url.path()) {
+                if self.is_ignored(root, &fs_path, false) {
+                    return Err(IronError::new(
+                        io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                        status::NotFound,
+                    ));
+                }
+                return cgi::run(req, &script?, root);
+            }
+        }
+        if req.method == method::Options {
+            return self.send_options(root, &fs_path);
+        }
+        if let Some((backend, script)) = self.fastcgi_script(root, &fs_path) {
+            return backend.run(req, &script, root);
+        }
+        if req.method == method::Post
+            && !self.upload_only
+            && req
+                .url
+                .as_ref()
+                .query_pairs()
+                .any(|(k, _)| k == ARCHIVE_QUERY)
+        {
+            return self.send_archive(req, root, &fs_path);
+        }
+        if let Some(ref links) = self.one_time_links {
+            let one_time = req
+                .url
+                .as_ref()
+                .query_pairs()
+                .any(|(k, _)| k == ONE_TIME_QUERY);
+            if req.method == method::Post && one_time && !self.upload_only {
+                return self.create_one_time(req, links, root, &fs_path);
+            }
+        }
+        if self.upload && req.method == method::Post {
+            // Refused before reading the body
+            let retry_after = self
+                .upload_limit
+                .as_ref()
+                .and_then(|limiter| limiter.retry_after(&self.upload_client(req)));
+            if let Some(retry_after) = retry_after {
+                let mut resp = error_resp(
+                    status::TooManyRequests,
+                    &format!("Too many uploads, retry in {}s", retry_after),
+                );
+                resp.headers
+                    .set_raw("Retry-After", vec![retry_after.to_string().into_bytes()]);
+                return Ok(resp);
+            }
+            if let Err((s, msg)) = self.save_files(req, &fs_path) {
+                if let Some(ref audit) = self.audit {
+                    let path = root_relative_path(root, &fs_path);
+                    audit.record(&self.actor(req), "upload", &path, None, Err((s, &msg)));
+                }
+                return Ok(error_resp(s, &msg));
+            } else {
+                return Ok(Response::with((status::Found, Redirect(req.url.clone()))));
+            }
+        }
+        if self.upload_only {
+            return self.send_upload_page(req, root, &fs_path, &path_prefix);
+        }
+        if self.delete && req.method == method::Delete {
+            if let Err((s, msg)) = self.delete_path(req, root, &fs_path) {
+                if let Some(ref audit) = self.audit {
+                    let path = root_relative_path(root, &fs_path);
+                    audit.record(&self.actor(req), "delete", &path, None, Err((s, &msg)));
+                }
+                return Ok(error_resp(s, &msg));
+            } else {
+                return Ok(Response::with(status::NoContent));
+            }
+        }
+
+        let path_metadata = match fs::metadata(&fs_path) {
+            Ok(value) => value,
+            Err(err) => {
+                let status = match err.kind() {
+                    io::ErrorKind::PermissionDenied => status::Forbidden,
+                    io::ErrorKind::NotFound => {
+                        if let Some(target) = self.checksum_sidecar_target(&fs_path) {
+                            return self.send_checksum(req, root, &target);
+                        }
+                        if self.no_robots && path_prefix == Path::new("robots.txt") {
+                            return Ok(Response::with((
+                                status::Ok,
+                                headers::ContentType::plaintext().0,
+                                "User-agent: *\nDisallow: /\n",
+                            )));
+                        }
+                        if self.pretty_urls {
+                            let mut html = fs_path.clone().into_os_string();
+                            html.push(".html");
+                            let html = PathBuf::from(html);
+                            if html.is_file() && !self.is_ignored(root, &html, false) {
+                                if let Some(resp) = self.check_or_unlock(req, root, &html)? {
+                                    return Ok(resp);
+                                }
+                                return self.send_file(req, &html);
+                            }
+                        }
+                        if let Some(ref p) = self.try_file_404 {
+                            if Some(true) == fs::metadata(p).ok().map(|meta| meta.is_file()) {
+                                // Protected as the file of the root it may be
+                                let target = p.canonicalize().unwrap_or_else(|_| p.clone());
+                                if let Some(resp) = self.check_or_unlock(req, root, &target)? {
+                                    return Ok(resp);
+                                }
+                                return self.send_file(req, p);
+                            }
+                        }
+                        status::NotFound
+                    }
+                    _ => status::InternalServerError,
+                };
+                return Err(IronError::new(err, status));
+            }
+        };
+
+        // Until the sweeper deletes it
+        let expired = self
+            .expiry
+            .as_ref()
+            .is_some_and(|expiry| expiry.is_expired(&path_metadata));
+        if expired || self.is_ignored(root, &fs_path, path_metadata.is_dir()) {
+            return Err(IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            ));
+        }
+
+        if !self
+            .allow(Some(path_metadata.is_dir()))
+            .contains(&req.method)
+        {
+            return Ok(self.method_not_allowed(Some(path_metadata.is_dir())));
+        }
+
+        if self.canonical_redirect && matches!(req.method, method::Get | method::Head) {
+            if let Some(location) = canonical_location(req, path_metadata.is_dir()) {
+                let mut resp = Response::with(status::MovedPermanently);
+                resp.headers
+                    .set_raw("Location", vec![location.into_bytes()]);
+                return Ok(resp);
+            }
+        }
+
+        if self.checksums.is_some() && path_metadata.is_file() {
+            if let Some((_, algorithm)) = req.url.as_ref().query_pairs().find(|(k, _)| k == "hash")
+            {
+                if algorithm != "sha256" {
+                    return Err(IronError::new(
+                        StringError(format!("Unsupported hash algorithm: {}", algorithm)),
+                        status::BadRequest,
+                    ));
+                }
+                return self.send_checksum(req, root, &fs_path);
+            }
+        }
+
+        if path_metadata.is_file()
+            && req
+                .url
+                .as_ref()
+                .query_pairs()
+                .any(|(k, v)| k == "play" && v == "1")
+        {
+            if let Some(element) = media_element(&self.mime_overrides, &fs_path) {
+                return Ok(self.send_player(req, &fs_path, element));
+            }
+        }
+
+        if self.highlighter.is_some()
+            && path_metadata.is_file()
+            && path_metadata.len() <= MAX_HIGHLIGHT_SIZE
+            && req
+                .url
+                .as_ref()
+                .query_pairs()
+                .any(|(k, v)| k == "view" && v == "1")
+        {
+            if let Some(resp) = self.send_highlighted(req, &fs_path)? {
+                return Ok(resp);
+            }
+        }
+
+        if path_metadata.is_dir() {
+            let archive_format = req
+                .url
+                .as_ref()
+                .query_pairs()
+                .find(|(k, _)| k == "download")
+                .and_then(|(_, v)| ArchiveFormat::parse(&v));
+            if let Some(format) = archive_format {
+                return self.send_directory_archive(req, root, &fs_path, format);
+            }
+            let listing = self.listing
+                && self
+                    .access
+                    .as_ref()
+                    .is_none_or(|access| access.listing(root, &fs_path));
+            if self.index || !listing || self.pretty_urls {
+                for fname in &["index.html", "index.htm"] {
+                    let index_path = fs_path.join(fname);
+                    if index_path.is_file() {
+                        // Automatic render index page
+                        return self.send_file(req, &index_path);
+                    }
+                }
+            }
+            if !listing {
+                return Err(IronError::new(
+                    StringError("Directory listing is disabled".to_owned()),
+                    status::Forbidden,
+                ));
+            }
+            let path_prefix: Vec<OsString> = path_prefix.iter().map(|s| s.to_os_string()).collect();
+            self.list_directory(req, root, &fs_path, &path_prefix)
+        } else {
+            self.send_file(req, &fs_path)
+        }
+    }
+}
+
+/// `header` after the `<body>` tag and `footer` before `</body>` (or around the whole page)
+fn inject_html(html: &str, header: &str, footer: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let body_start = lower
+        .find("<body")
+        .and_then(|start| lower[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+    let body_end = lower
+        .rfind("</body>")
+        .filter(|end| *end >= body_start)
+        .unwrap_or(html.len());
+    let mut rv = String::with_capacity(html.len() + header.len() + footer.len());
+    rv.push_str(&html[..body_start]);
+    rv.push_str(header);
+    rv.push_str(&html[body_start..body_end]);
+    rv.push_str(footer);
+    rv.push_str(&html[body_end..]);
+    rv
+}
+
+/// Where to redirect when the path isn't canonical: directories end with a slash, no
+/// empty segments. Relative links of the listings are resolved against it
+fn canonical_location(req: &Request, is_dir: bool) -> Option<String> {
+    let path = req.url.path();
+    let segments = path
+        .iter()
+        .filter(|segment| !segment.is_empty())
+        .cloned()
+        .collect::<Vec<&str>>();
+    let mut location = format!("/{}", segments.join("/"));
+    if is_dir && !segments.is_empty() {
+        location.push('/');
+    }
+    if location == format!("/{}", path.join("/")) {
+        return None;
+    }
+    if let Some(query) = req.url.query() {
+        location.push('?');
+        location.push_str(query);
+    }
+    Some(location)
+}
+
+// Named after the directory
+fn archive_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".to_owned())
+}
+
+fn archive_response(dir: &Path, archive: ArchiveBody, format: ArchiveFormat) -> Response {
+    let mut resp = Response::with(status::Ok);
+    resp.headers.set_raw(
+        "Content-Type",
+        vec![format.content_type().as_bytes().to_vec()],
+    );
+    resp.headers.set_raw(
+        "Content-Disposition",
+        vec![content_disposition_attachment(&format!(
+            "{}.{}",
+            archive_name(dir),
+            format.extension()
+        ))
+        .into_bytes()],
+    );
+    resp.body = Some(Box::new(archive));
+    resp
+}
+
+// The hidden field of the CSRF token: that of the cookie, or a new one set on `resp`
+fn csrf_field(req: &Request, resp: &mut Response) -> String {
+    let prefix = format!("{}=", CSRF_TOKEN_NAME);
+    let token = get_cookie(req, CSRF_TOKEN_NAME)
+        // Already set by another form of the page
+        .or_else(|| {
+            resp.headers
+                .get::<headers::SetCookie>()
+                .and_then(|cookies| {
+                    cookies.iter().find_map(|cookie| {
+                        Some(cookie.strip_prefix(&prefix)?.split(';').next()?.to_owned())
+                    })
+                })
+        })
+        .unwrap_or_else(|| {
+            let token = random_token(32);
+            resp.headers.set(headers::SetCookie(vec![format!(
+                "{}{}; Path=/; HttpOnly; SameSite=Strict{}",
+                prefix,
+                token,
+                secure_attribute(req)
+            )]));
+            token
+        });
+    format!(
+        r#"<input type="hidden" name="{name}" value="{token}" />"#,
+        name = CSRF_TOKEN_NAME,
+        token = encode_minimal(&token)
+    )
+}
+
+// `&filter=` of the sort links
+fn filter_query(filter: Option<&str>) -> String {
+    filter
+        .map(|filter| {
+            format!(
+                "&filter={}",
+                url::form_urlencoded::byte_serialize(filter.as_bytes()).collect::<String>()
+            )
+        })
+        .unwrap_or_default()
+}
+
+// Goto parent directory link, the first row
+fn up_row(path_prefix: &[OsString]) -> String {
+    if !path_prefix.is_empty() {
+        let mut link = path_prefix.to_owned();
+        link.pop();
+        if !link.is_empty() {
+            link.push(OsString::new());
+        }
+        format!(
+            r#"
+<tr>
+  <td><a href="/{link}"><strong>[Up]</strong></a></td>
+  <td></td>
+  <td></td>
+</tr>
+"#,
+            link = encode_link_path(&link)
+        )
+    } else {
+        r#"<tr><td>&nbsp;</td></tr>"#.to_owned()
+    }
+}
+
+// By `--ignore`/`--gitignore` and the `.shs-access` files
+fn ignored(
+    live: &LiveSettings,
+    access: Option<&AccessRules>,
+    root: &Path,
+    path: &Path,
+    is_dir: bool,
+) -> bool {
+    live.ignore_rules()
+        .is_some_and(|rules| rules.is_ignored(root, path, is_dir))
+        || access.is_some_and(|access| access.is_ignored(root, path, is_dir))
+}
+
+// Emoji of the entry category (folder, image, video, audio, archive, code, text)
+fn entry_icon(mime_overrides: &HashMap<String, Mime>, path: &Path, is_dir: bool) -> &'static str {
+    use iron::mime::{SubLevel, TopLevel};
+
+    if is_dir {
+        return "&#x1F4C1;";
+    }
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    if ARCHIVE_EXTENSIONS.contains(&ext.as_str()) {
+        return "&#x1F4E6;";
+    }
+    if CODE_EXTENSIONS.contains(&ext.as_str()) {
+        return "&#x1F4DD;";
+    }
+    match guess_mime(mime_overrides, path) {
+        Mime(TopLevel::Image, _, _) => "&#x1F5BC;&#xFE0F;",
+        Mime(TopLevel::Video, _, _) => "&#x1F3AC;",
+        Mime(TopLevel::Audio, _, _) => "&#x1F3B5;",
+        Mime(TopLevel::Text, _, _) => "&#x1F4C4;",
+        Mime(TopLevel::Application, SubLevel::Ext(ref sub), _) if sub == "pdf" => "&#x1F4D5;",
+        _ => "&#x1F4CE;",
+    }
+}
+
+// `video` or `audio` by the mime type, `None` for other files
+fn media_element(mime_overrides: &HashMap<String, Mime>, path: &Path) -> Option<&'static str> {
+    use iron::mime::TopLevel;
+
+    match guess_mime(mime_overrides, path) {
+        Mime(TopLevel::Video, _, _) => Some("video"),
+        Mime(TopLevel::Audio, _, _) => Some("audio"),
+        _ => None,
+    }
+}
+
+// By `--mime-file`/`--mime` first, without a charset
+fn guess_mime(mime_overrides: &HashMap<String, Mime>, path: &Path) -> Mime {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| mime_overrides.get(&ext.to_lowercase()))
+        .cloned()
+        .unwrap_or_else(|| mime_types::guess_mime_type(path))
+}
+
+// Renders the rows of the listings, owning what it needs: the streamed listings render them
+// while written
+struct RowRenderer {
+    path_prefix: Vec<OsString>,
+    mime_overrides: HashMap<String, Mime>,
+    expiry: Option<Arc<Expiry>>,
+    thumbnails: bool,
+    checksums: bool,
+    one_time_links: bool,
+    delete: bool,
+}
+
+impl RowRenderer {
+    // The row of `entry`, or its item of the gallery for the images
+    fn render(&self, entry: &Entry, gallery: bool) -> String {
+        let Entry {
+            filename,
+            raw_name,
+            metadata,
+        } = entry;
+        // * Entry.modified
+        let mut file_modified = system_time_to_date_time(metadata.modified().unwrap())
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        // * Entry.expires (`--expire`)
+        if let Some(left) = self.expiry.as_ref().and_then(|e| e.time_left(metadata)) {
+            file_modified.push_str(&format!(
+                r#" <span class="expires">expires in {}</span>"#,
+                left
+            ));
+        }
+        // * Entry.filesize
+        let file_size = if metadata.is_dir() {
+            "-".to_owned()
+        } else {
+            convert(metadata.len() as f64)
+        };
+        // * Entry.linkstyle
+        let link_style = if metadata.is_dir() {
+            "class=\"dir\"".to_owned()
+        } else {
+            "".to_owned()
+        };
+        // * Entry.link
+        let mut link = self.path_prefix.to_owned();
+        link.push(raw_name.clone());
+        if metadata.is_dir() {
+            link.push(OsString::new());
+        }
+        // * Entry.label
+        let file_name_label = if metadata.is_dir() {
+            format!("{}/", &filename)
+        } else {
+            filename.clone()
+        };
+        let is_image = entry.is_image();
+        // * Entry.thumbnail
+        let thumbnail_src = if self.thumbnails && is_image {
+            Some(format!(
+                "/{route}?path={path}",
+                route = THUMBNAIL_PATH,
+                path = encode_query_path(&link)
+            ))
+        } else {
+            None
+        };
+        if gallery && is_image {
+            return format!(
+                r#"<a class="gallery-item" href="/{link}" title="{label}"><img src="{src}" alt="{label}" loading="lazy" /></a>"#,
+                link = encode_link_path(&link),
+                label = encode_attribute(&file_name_label),
+                src = thumbnail_src.unwrap_or_else(|| format!("/{}", encode_link_path(&link)))
+            );
+        }
+        // * Entry.thumbnail, or the icon of its file type
+        let thumbnail = match thumbnail_src {
+            Some(src) => format!(
+                r#"<img class="thumbnail" src="{src}" alt="" loading="lazy" />"#,
+                src = src
+            ),
+            None => format!(
+                r#"<span class="icon">{}</span>"#,
+                entry_icon(
+                    &self.mime_overrides,
+                    Path::new(&filename),
+                    metadata.is_dir()
+                )
+            ),
+        };
+        // * Entry.download
+        let download_link = {
+            let link = encode_link_path(&link);
+            let mut links = Vec::new();
+            if metadata.is_dir() {
+                links.push(format!(
+                    r#"<a href="/{link}?download=tar.gz" title="Download as tar.gz">&#x2B73;</a>"#,
+                    link = link
+                ));
+            } else {
+                links.push(format!(
+                    r#"<a href="/{link}?download" title="Download">&#x2B73;</a>"#,
+                    link = link
+                ));
+            }
+            if media_element(&self.mime_overrides, Path::new(&filename)).is_some() {
+                links.push(format!(
+                    r#"<a href="/{link}?play=1" title="Play">&#x25B6;</a>"#,
+                    link = link
+                ));
+            }
+            if self.checksums && !metadata.is_dir() {
+                links.push(format!(
+                    r#"<a href="/{link}?hash=sha256" title="SHA-256">#</a>"#,
+                    link = link
+                ));
+            }
+            if self.one_time_links && !metadata.is_dir() {
+                links.push(format!(
+                    r#"<a class="one-time" href="/{link}?{query}" title="One-time link">&#x1F517;</a>"#,
+                    link = link,
+                    query = ONE_TIME_QUERY
+                ));
+            }
+            if self.delete {
+                links.push(format!(
+                    r#"<a class="delete" href="/{link}" title="Delete">&#x2715;</a>"#,
+                    link = link
+                ));
+            }
+            links.join(" ")
+        };
+
+        // Render one directory entry
+        format!(
+            r#"
+<tr class="entry" data-name="{name}">
+  <td><input type="checkbox" class="select" form="selection-form" name="path" value="{name}" /><a {linkstyle} href="/{link}">{thumbnail}{label}</a></td>
+  <td class="modified">{modified}</td>
+  <td class="size">{filesize}</td>
+  <td class="actions">{download}</td>
+</tr>
+"#,
+            name = encode_attribute(filename),
+            linkstyle = link_style,
+            link = encode_link_path(&link),
+            label = encode_minimal(&file_name_label),
+            thumbnail = thumbnail,
+            modified = file_modified,
+            filesize = file_size,
+            download = download_link
+        )
+    }
+}
+
+impl MainHandler {
+    /// The defaults of the command line
+    pub(crate) fn new(root: PathBuf, live: Arc<LiveSettings>) -> MainHandler {
+        MainHandler {
+            root,
+            index: false,
+            upload: false,
+            upload_only: false,
+            delete: false,
+            csrf: true,
+            live,
+            range: true,
+            redirect_to: None,
+            listing: true,
+            sort: true,
+            try_file_404: None,
+            pretty_urls: false,
+            canonical_redirect: true,
+            html_header: None,
+            html_footer: None,
+            inject_html: false,
+            upload_size_limit: 8_000_000,
+            upload_quota: None,
+            upload_filter: None,
+            preserve_mtime: false,
+            trash: None,
+            mime_overrides: HashMap::new(),
+            charset: "utf-8".to_owned(),
+            force_download: false,
+            vhosts: HashMap::new(),
+            checksums: None,
+            etag: EtagMode::Strong,
+            access: None,
+            manifest: false,
+            s3: None,
+            cgi_dirs: Vec::new(),
+            fastcgi: Vec::new(),
+            webhook: None,
+            on_upload: None,
+            hooks: Hooks::default(),
+            stats: None,
+            no_robots: false,
+            thumbnailer: None,
+            highlighter: None,
+            theme: "auto".to_owned(),
+            page_size: 0,
+            sessions: None,
+            audit: None,
+            upload_limit: None,
+            expiry: None,
+            one_time_links: None,
+            protected: None,
+            listing_cache: None,
+            stream_listings: false,
+        }
+    }
+
+    /// The user and IP of `req` for the audit log
+    fn actor(&self, req: &Request) -> Actor {
+        Actor {
+            user: req
+                .extensions
+                .get::<User>()
+                .or_else(|| req.extensions.get::<ClientCert>())
+                .cloned(),
+            client_ip: req.remote_addr.ip(),
+        }
+    }
+
+    /// Whose uploads `--upload-limit-rate` and `--upload-max-per-hour` count together
+    fn upload_client(&self, req: &Request) -> Client {
+        match self.actor(req).user {
+            Some(user) => Client::User(user),
+            None => Client::Ip(req.remote_addr.ip()),
+        }
+    }
+
+    /// The root directory of the virtual host (by `Host` header), or the default root
+    fn root_for(&self, req: &Request) -> &Path {
+        req.headers
+            .get::<headers::Host>()
+            .and_then(|host| self.vhosts.get(&host.hostname.to_lowercase()))
+            .unwrap_or(&self.root)
+    }
+
+    /// `<file>` when `path` is a missing `<file>.sha256`
+    fn checksum_sidecar_target(&self, path: &Path) -> Option<PathBuf> {
+        self.checksums.as_ref()?;
+        if path.extension()? != "sha256" {
+            return None;
+        }
+        let target = path.with_extension("");
+        if target.is_file() {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    /// The methods of `Allow`: those of a file, of a directory, or of the whole server
+    /// (`OPTIONS *`) with `None`
+    pub(crate) fn allow(&self, is_dir: Option<bool>) -> Vec<method::Method> {
+        let mut methods = vec![method::Get, method::Head, method::Options];
+        // The selected entries of the listings are downloaded by a form
+        if is_dir != Some(false) {
+            methods.push(method::Post);
+        }
+        if self.delete {
+            methods.push(method::Delete);
+        }
+        if self.s3.is_some() && is_dir.is_none() {
+            methods.push(method::Put);
+        }
+        methods
+    }
+
+    /// 405 with the methods of `allow()`
+    fn method_not_allowed(&self, is_dir: Option<bool>) -> Response {
+        let mut resp = error_resp(status::MethodNotAllowed, "Method Not Allowed");
+        resp.headers.set(headers::Allow(self.allow(is_dir)));
+        resp
+    }
+
+    fn send_options(&self, root: &Path, path: &Path) -> IronResult<Response> {
+        let metadata = fs::metadata(path)
+            .ok()
+            .filter(|metadata| !self.is_ignored(root, path, metadata.is_dir()))
+            .ok_or_else(|| {
+                IronError::new(
+                    io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                    status::NotFound,
+                )
+            })?;
+        let mut resp = Response::with(status::Ok);
+        resp.headers
+            .set(headers::Allow(self.allow(Some(metadata.is_dir()))));
+        Ok(resp)
+    }
+
+    // A new one-time link of the file, posted by a user allowed to upload
+    fn create_one_time(
+        &self,
+        req: &Request,
+        links: &OneTimeLinks,
+        root: &Path,
+        path: &Path,
+    ) -> IronResult<Response> {
+        if self.csrf {
+            self.check_same_origin(req)
+                .map_err(|(s, msg)| IronError::new(StringError(msg), s))?;
+        }
+        let is_file = fs::metadata(path).is_ok_and(|metadata| metadata.is_file());
+        if !is_file || self.is_ignored(root, path, false) {
+            return Err(IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            ));
+        }
+        let token = links
+            .create(path)
+            .map_err(|e| IronError::new(e, status::InternalServerError))?;
+        let link = format!("/{}/{}", ONE_TIME_PATH, token);
+        println!(
+            "  >> One-time link of {}: {}",
+            root_relative_path(root, path),
+            link
+        );
+        let json = serde_json::json!({ "link": link, "path": root_relative_path(root, path) });
+        let mut resp = Response::with((
+            status::Created,
+            "application/json".parse::<Mime>().unwrap(),
+            json.to_string(),
+        ));
+        resp.headers.set(headers::Location(link));
+        Ok(resp)
+    }
+
+    // The file of a one-time link as an attachment, 410 Gone once downloaded
+    fn send_one_time(
+        &self,
+        req: &mut Request,
+        links: &OneTimeLinks,
+        token: &str,
+    ) -> IronResult<Response> {
+        if !matches!(req.method, method::Get | method::Head) {
+            return Ok(self.method_not_allowed(Some(false)));
+        }
+        // Not used by HEAD requests
+        let path = match req.method {
+            method::Head => links.peek(token),
+            _ => links.take(token),
+        }
+        .map_err(|s| {
+            let msg = if s == status::Gone {
+                "This link was used already"
+            } else {
+                "Not Found"
+            };
+            IronError::new(StringError(msg.to_owned()), s)
+        })?;
+        if let Some(ref expiry) = self.expiry {
+            if fs::metadata(&path).is_ok_and(|metadata| expiry.is_expired(&metadata)) {
+                return Err(IronError::new(
+                    io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                    status::NotFound,
+                ));
+            }
+        }
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // The whole file, the link can't be used again for the rest
+        for name in ["Range", "If-Range", "If-None-Match", "If-Modified-Since"] {
+            req.headers.remove_raw(name);
+        }
+        let mut resp = self.send_file(req, &path)?;
+        resp.headers.set_raw(
+            "Content-Disposition",
+            vec![content_disposition_attachment(&filename).into_bytes()],
+        );
+        resp.headers.set(headers::CacheControl(vec![
+            headers::CacheDirective::NoStore,
+        ]));
+        Ok(resp)
+    }
+
+    // The login form (`--auth-session`), the form checked by `AuthChecker` sets the cookie
+    fn send_login(&self, req: &mut Request, sessions: &Sessions) -> Response {
+        let see_other = |location: &str| {
+            let mut resp = Response::with(status::SeeOther);
+            resp.headers.set(headers::Location(location.to_owned()));
+            resp
+        };
+        if req.url.path() == [LOGOUT_PATH] {
+            // Not by a link nor a form of another site
+            if req.method != method::Post {
+                let mut resp = error_resp(status::MethodNotAllowed, "Method Not Allowed");
+                resp.headers.set(headers::Allow(vec![method::Post]));
+                return resp;
+            }
+            let mut body = String::new();
+            let _ = req
+                .body
+                .by_ref()
+                .take(MAX_FORM_SIZE)
+                .read_to_string(&mut body);
+            let token = url::form_urlencoded::parse(body.as_bytes())
+                .find(|(k, _)| k == CSRF_TOKEN_NAME)
+                .map(|(_, v)| v.into_owned());
+            if let Err((s, msg)) = self.check_same_origin(req) {
+                return error_resp(s, &msg);
+            }
+            if token.is_none() || token != get_cookie(req, CSRF_TOKEN_NAME) {
+                return error_resp(status::Forbidden, "CSRF token missing or not matched");
+            }
+            let mut resp = see_other("/");
+            sessions.end(req, &mut resp);
+            return resp;
+        }
+        match req.extensions.remove::<Login>() {
+            Some(Login {
+                username: Some(username),
+                next,
+            }) => {
+                let mut resp = see_other(middlewares::next_path(&next));
+                sessions.start(req, &mut resp, &username);
+                resp
+            }
+            Some(Login {
+                username: None,
+                next,
+            }) => middlewares::login_page(
+                status::Unauthorized,
+                middlewares::next_path(&next),
+                Some("Wrong username or password."),
+            ),
+            None if req.method == method::Post => self.method_not_allowed(None),
+            None => {
+                let next = req
+                    .url
+                    .as_ref()
+                    .query_pairs()
+                    .find(|(k, _)| k == "next")
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap_or_default();
+                middlewares::login_page(status::Ok, middlewares::next_path(&next), None)
+            }
+        }
+    }
+
+    /// Streams the entries chosen in the listing (`path` fields) as a tar archive
+    fn send_archive(&self, req: &mut Request, root: &Path, dir: &Path) -> IronResult<Response> {
+        let mut body = String::new();
+        req.body
+            .by_ref()
+            .take(MAX_FORM_SIZE)
+            .read_to_string(&mut body)
+            .map_err(|e| IronError::new(e, status::BadRequest))?;
+        let format = url::form_urlencoded::parse(body.as_bytes())
+            .find(|(k, _)| k == "format")
+            .map(|(_, v)| {
+                ArchiveFormat::parse(&v).ok_or_else(|| {
+                    IronError::new(
+                        StringError(format!("Unknown archive format: {}", v)),
+                        status::BadRequest,
+                    )
+                })
+            })
+            .transpose()?
+            .unwrap_or(ArchiveFormat::Tar);
+        let paths = url::form_urlencoded::parse(body.as_bytes())
+            .filter(|(k, _)| k == "path")
+            .map(|(_, v)| {
+                client_relative_path(&v).ok_or_else(|| {
+                    IronError::new(
+                        StringError(format!("Invalid path: {}", v)),
+                        status::BadRequest,
+                    )
+                })
+            })
+            .collect::<IronResult<Vec<PathBuf>>>()?;
+        if paths.is_empty() {
+            return Err(IronError::new(
+                StringError("No file selected".to_owned()),
+                status::BadRequest,
+            ));
+        }
+        let archive = ArchiveBody::new(dir, &paths, format, |path, is_dir| {
+            self.is_ignored(root, path, is_dir) || self.is_denied(req, root, path)
+        })
+        .map_err(error_io2iron)?;
+        Ok(archive_response(dir, archive, format))
+    }
+
+    /// The whole directory as an archive (`?download=tar.gz`)
+    fn send_directory_archive(
+        &self,
+        req: &Request,
+        root: &Path,
+        dir: &Path,
+        format: ArchiveFormat,
+    ) -> IronResult<Response> {
+        let name = archive_name(dir);
+        let archive = ArchiveBody::directory(dir, Path::new(&name), format, |path, is_dir| {
+            self.is_ignored(root, path, is_dir) || self.is_denied(req, root, path)
+        })
+        .map_err(error_io2iron)?;
+        Ok(archive_response(dir, archive, format))
+    }
+
+    /// The file run by FastCGI for this path: the first file on the way (the rest is
+    /// `PATH_INFO`), or the `index.*` of a directory
+    fn fastcgi_script(&self, root: &Path, fs_path: &Path) -> Option<(&FastCgi, cgi::Script)> {
+        if self.fastcgi.is_empty() {
+            return None;
+        }
+        let script = if fs_path.is_dir() {
+            if !self.index && self.listing {
+                return None;
+            }
+            let mut names = fs::read_dir(fs_path)
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name())
+                .filter(|name| name.to_string_lossy().starts_with("index."))
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+                .into_iter()
+                .map(|name| fs_path.join(name))
+                .find(|path| path.is_file() && self.fastcgi.iter().any(|b| b.matches(path)))?
+        } else {
+            fs_path
+                .ancestors()
+                .take_while(|path| *path != root && path.starts_with(root))
+                .find(|path| path.is_file())?
+                .to_path_buf()
+        };
+        let backend = self.fastcgi.iter().find(|b| b.matches(&script))?;
+        if self.is_ignored(root, &script, false) {
+            return None;
+        }
+        let path_info = match fs_path.strip_prefix(&script) {
+            Ok(rest) if rest.components().next().is_some() => {
+                root_relative_path(Path::new(""), rest)
+            }
+            _ => String::new(),
+        };
+        Some((
+            backend,
+            cgi::Script {
+                name: root_relative_path(root, &script),
+                path: script,
+                path_info,
+            },
+        ))
+    }
+
+    fn is_ignored(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        ignored(&self.live, self.access.as_deref(), root, path, is_dir)
+    }
+
+    /// The passwords of `--protect-path` and the users of the `.shs-access` files of `path` only
+    fn check_access(&self, req: &Request, root: &Path, path: &Path) -> IronResult<()> {
+        if let Some(ref protected) = self.protected {
+            protected.check(req, root, path)?;
+        }
+        match self.access {
+            Some(ref access) => access.check(&req.headers, middlewares::is_write(req), root, path),
+            None => Ok(()),
+        }
+    }
+
+    /// `check_access`, then the redirect of a browser giving the password of a protected path
+    fn check_or_unlock(
+        &self,
+        req: &Request,
+        root: &Path,
+        path: &Path,
+    ) -> IronResult<Option<Response>> {
+        self.check_access(req, root, path)?;
+        Ok(self
+            .protected
+            .as_ref()
+            .and_then(|protected| protected.unlock(req, root, path)))
+    }
+
+    // Skipped by the walks of the archives and manifests
+    fn is_denied(&self, req: &Request, root: &Path, path: &Path) -> bool {
+        self.check_access(req, root, path).is_err()
+    }
+
+    /// Digest of the file in `sha256sum` format
+    fn send_checksum(&self, req: &Request, root: &Path, path: &Path) -> IronResult<Response> {
+        if self.is_ignored(root, path, false) {
+            return Err(IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            ));
+        }
+        // The `.sha256` of a file is as protected as the file
+        self.check_access(req, root, path)?;
+        let digest = self
+            .checksums
+            .as_ref()
+            .unwrap()
+            .sha256(path)
+            .map_err(error_io2iron)?;
+        let filename = path.file_name().unwrap().to_string_lossy();
+        Ok(Response::with((
+            status::Ok,
+            self.guess_mime_type(Path::new("checksum.txt")),
+            format!("{}  {}\n", digest, filename),
+        )))
+    }
+
+    /// HTML5 player page of the media file, seeking is done by range requests
+    fn send_player(&self, req: &Request, path: &Path, element: &str) -> Response {
+        let filename = path.file_name().unwrap().to_string_lossy();
+        let nonce = random_token(16);
+        let mut resp = Response::with((
+            status::Ok,
+            format!(
+                r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width,initial-scale=1.0"/>
+  <title>{filename}</title>
+  <style nonce="{nonce}">
+    body {{ margin:0; background:#111; color:#EEE; font-family:sans-serif; text-align:center; }}
+    a {{ color:#9CF; text-decoration:none; margin:0 0.5em; }}
+    video {{ max-width:100%; max-height:85vh; }}
+    audio {{ width:80%; margin-top:3em; }}
+  </style>
+</head>
+<body>
+  <p>{filename} <a href="{src}?download">Download</a></p>
+  <{element} src="{src}" controls autoplay preload="metadata"></{element}>
+</body>
+</html>
+"#,
+                filename = encode_minimal(&filename),
+                nonce = nonce,
+                src = format!("/{}", req.url.path().join("/")),
+                element = element
+            ),
+        ));
+        resp.headers.set(headers::ContentType::html());
+        set_page_csp(&mut resp, &nonce);
+        resp
+    }
+
+    /// Highlighted source page, `None` for binary files (sent as they are)
+    fn send_highlighted(&self, req: &Request, path: &Path) -> IronResult<Option<Response>> {
+        let content = fs::read(path).map_err(error_io2iron)?;
+        let content = match String::from_utf8(content) {
+            Ok(content) if !content.contains('\0') => content,
+            _ => return Ok(None),
+        };
+        let raw_link = format!("/{}", req.url.path().join("/"));
+        let html = self
+            .highlighter
+            .as_ref()
+            .unwrap()
+            .render(path, &content, &raw_link)
+            .map_err(|e| IronError::new(e, status::InternalServerError))?;
+        let mut resp = Response::with((status::Ok, html));
+        resp.headers.set(headers::ContentType::html());
+        resp.headers.set_raw(
+            "Content-Security-Policy",
+            vec![PAGE_CSP.as_bytes().to_vec()],
+        );
+        Ok(Some(resp))
+    }
+
+    /// The file of the `path=` query (`/__thumb?path=/photos/a.jpg`), `None` without it
+    fn query_path(&self, req: &Request, root: &Path) -> IronResult<Option<PathBuf>> {
+        let path = match req.url.as_ref().query_pairs().find(|(k, _)| k == "path") {
+            Some((_, path)) => path.into_owned(),
+            None => return Ok(None),
+        };
+        let mut fs_path = root.to_path_buf();
+        for component in Path::new(&path).components() {
+            match component {
+                Component::Normal(name) => fs_path.push(name),
+                Component::RootDir | Component::CurDir => {}
+                _ => {
+                    return Err(IronError::new(
+                        StringError("Invalid path".to_owned()),
+                        status::BadRequest,
+                    ))
+                }
+            }
+        }
+        Ok(Some(fs_path))
+    }
+
+    /// Thumbnail of the image `?path=` (relative to the root)
+    fn send_thumbnail(&self, req: &Request, root: &Path) -> IronResult<Response> {
+        let fs_path = self.query_path(req, root)?.ok_or_else(|| {
+            IronError::new(StringError("Missing path".to_owned()), status::BadRequest)
+        })?;
+        let not_found = || {
+            IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            )
+        };
+        if !thumbnail::is_image(&fs_path) || !fs_path.is_file() {
+            return Err(not_found());
+        }
+        if self.is_ignored(root, &fs_path, false) {
+            return Err(not_found());
+        }
+        self.check_access(req, root, &fs_path)?;
+        let thumbnail = self
+            .thumbnailer
+            .as_ref()
+            .unwrap()
+            .thumbnail(&fs_path)
+            .map_err(|e| IronError::new(e, status::InternalServerError))?;
+        let mut resp =
+            Response::with((status::Ok, "image/jpeg".parse::<Mime>().unwrap(), thumbnail));
+        resp.headers.set(headers::CacheControl(vec![
+            headers::CacheDirective::Public,
+            headers::CacheDirective::MaxAge(THUMBNAIL_MAX_AGE),
+        ]));
+        Ok(resp)
+    }
+
+    /// Recursive JSON tree of a directory (`/__manifest?path=/photos&depth=2&hashes=1`)
+    fn send_manifest(&self, req: &Request, root: &Path) -> IronResult<Response> {
+        use iron::headers::{EntityTag, IfNoneMatch};
+        use sha2::{Digest, Sha256};
+
+        let bad_request = |msg: String| IronError::new(StringError(msg), status::BadRequest);
+        let fs_path = self
+            .query_path(req, root)?
+            .unwrap_or_else(|| root.to_path_buf());
+        let mut depth = manifest::MAX_DEPTH;
+        let mut max_entries = manifest::MAX_ENTRIES;
+        let mut hashes = false;
+        let mut since = None;
+        for (k, v) in req.url.as_ref().query_pairs() {
+            let number = || {
+                v.parse::<usize>()
+                    .map_err(|_| bad_request(format!("Invalid {}: {}", k, v)))
+            };
+            match &*k {
+                "depth" => depth = number()?.min(manifest::MAX_DEPTH),
+                "limit" => max_entries = number()?.min(manifest::MAX_ENTRIES),
+                "hashes" => hashes = v == "1",
+                "since" => since = Some(number()? as u64),
+                _ => {}
+            }
+        }
+        if hashes && self.checksums.is_none() {
+            return Err(bad_request("Hashes require --checksums".to_owned()));
+        }
+        let is_dir = fs::metadata(&fs_path).map_err(error_io2iron)?.is_dir();
+        if self.is_ignored(root, &fs_path, is_dir) {
+            return Err(IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            ));
+        }
+        self.check_access(req, root, &fs_path)?;
+        let skip = |path: &Path, is_dir: bool| {
+            self.is_ignored(root, path, is_dir) || self.is_denied(req, root, path)
+        };
+        let hash = |path: &Path| self.checksums.as_ref().unwrap().sha256(path);
+        let manifest = Manifest {
+            depth,
+            max_entries,
+            skip: &skip,
+            hash: if hashes { Some(&hash) } else { None },
+            since,
+        }
+        .build(&fs_path, &root_relative_path(root, &fs_path))
+        .map_err(error_io2iron)?
+        .to_string();
+        // Mirrors poll with `If-None-Match`, 304 while nothing changed
+        let etag = EntityTag::strong(format!("{:x}", Sha256::digest(manifest.as_bytes())));
+        let not_modified = match req.headers.get::<IfNoneMatch>() {
+            Some(IfNoneMatch::Any) => true,
+            Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+            None => false,
+        };
+        let mut resp = if not_modified {
+            Response::with(status::NotModified)
+        } else {
+            Response::with((
+                status::Ok,
+                "application/json".parse::<Mime>().unwrap(),
+                manifest,
+            ))
+        };
+        resp.headers.set(headers::ETag(etag));
+        resp.headers.set(headers::CacheControl(vec![
+            headers::CacheDirective::NoCache,
+        ]));
+        Ok(resp)
+    }
+
+    /// Reject cross-site form submissions by the `Origin` (or `Referer`) header
+    fn check_same_origin(&self, req: &Request) -> Result<(), (status::Status, String)> {
+        let origin = req
+            .headers
+            .get_raw("Origin")
+            .or_else(|| req.headers.get_raw("Referer"))
+            .and_then(|values| values.first())
+            .map(|value| String::from_utf8_lossy(value).to_string());
+        let origin = match origin {
+            Some(origin) => origin,
+            // Not sent by a browser, the CSRF token still applies
+            None => return Ok(()),
+        };
+        let host = req
+            .headers
+            .get::<headers::Host>()
+            .map(|host| match host.port {
+                Some(port) => format!("{}:{}", host.hostname, port),
+                None => host.hostname.clone(),
+            });
+        let origin_host = url::Url::parse(&origin)
+            .ok()
+            .and_then(|url| url.host_str().map(|h| (h.to_owned(), url.port())))
+            .map(|(h, port)| match port {
+                Some(port) => format!("{}:{}", h, port),
+                None => h,
+            });
+        match (origin_host, host) {
+            (Some(ref a), Some(ref b)) if a.eq_ignore_ascii_case(b) => Ok(()),
+            _ => Err((
+                status::Forbidden,
+                format!("Cross-origin upload rejected: {}", origin),
+            )),
+        }
+    }
+
+    /// The S3 API of `--s3` (authenticated by the gateway): listings of the bucket, and objects
+    /// read, uploaded or removed as the files of the root
+    fn handle_s3(
+        &self,
+        req: &mut Request,
+        gateway: &S3Gateway,
+        route: S3Route,
+    ) -> Result<Response, S3Error> {
+        let root = self.root_for(req).to_path_buf();
+        let write = !matches!(req.method, method::Get | method::Head);
+        let payload = gateway.authenticate(req, write)?;
+        if write && !self.upload {
+            return Err(S3Error::new(
+                status::Forbidden,
+                "AccessDenied",
+                "Uploads are disabled (--upload)",
+            ));
+        }
+        if self.upload_only && req.method != method::Put {
+            return Err(S3Error::new(
+                status::Forbidden,
+                "AccessDenied",
+                "Only uploads are allowed (--upload-only)",
+            ));
+        }
+        // Multipart uploads, ACLs, tagging, versions...
+        let subresource = req.url.query().is_some_and(|query| {
+            query.split('&').any(|pair| {
+                let name = pair.split('=').next().unwrap_or("");
+                !matches!(
+                    name,
+                    "list-type"
+                        | "prefix"
+                        | "delimiter"
+                        | "max-keys"
+                        | "continuation-token"
+                        | "start-after"
+                        | "marker"
+                        | "encoding-type"
+                        | "fetch-owner"
+                        | "location"
+                        | "x-id"
+                        | ""
+                )
+            })
+        });
+        if subresource || req.headers.get_raw("x-amz-copy-source").is_some() {
+            return Err(S3Error::not_implemented());
+        }
+        // The objects are the files the other requests would see
+        let expired = |path: &Path| {
+            self.expiry.as_ref().is_some_and(|expiry| {
+                fs::metadata(path).is_ok_and(|metadata| expiry.is_expired(&metadata))
+            })
+        };
+        let skip = |path: &Path, is_dir: bool| {
+            self.is_ignored(&root, path, is_dir)
+                || path.file_name().is_some_and(atomic_file::is_temp)
+                || self.is_denied(req, &root, path)
+                || (!is_dir && expired(path))
+        };
+
+        let key = match route {
+            S3Route::ListBuckets if req.method == method::Get => {
+                return Ok(gateway.list_buckets(&root))
+            }
+            S3Route::Bucket => {
+                return match req.method {
+                    method::Get if req.url.as_ref().query_pairs().any(|(k, _)| k == "location") => {
+                        Ok(gateway.location())
+                    }
+                    method::Get if self.is_denied(req, &root, &root) => {
+                        Err(S3Error::access_denied())
+                    }
+                    method::Get => gateway.list_objects(req, &root, skip),
+                    // The bucket is always there ("created")
+                    method::Head | method::Put => Ok(Response::with(status::Ok)),
+                    _ => Err(S3Error::not_implemented()),
+                };
+            }
+            S3Route::Object(key) => key,
+            _ => return Err(S3Error::not_implemented()),
+        };
+        let relative_path = client_relative_path(&key)
+            .filter(|path| path.components().next().is_some())
+            .ok_or_else(|| S3Error::new(status::BadRequest, "InvalidArgument", "Invalid key"))?;
+        let path = root.join(&relative_path);
+        if self.is_denied(req, &root, &path) {
+            return Err(S3Error::access_denied());
+        }
+        let metadata = fs::metadata(&path).ok();
+        let hidden = skip(&path, metadata.as_ref().is_some_and(|m| m.is_dir()));
+
+        match req.method {
+            method::Get | method::Head => match metadata {
+                Some(ref metadata) if metadata.is_file() && !hidden => {
+                    self.send_file(req, &path).map_err(|e| {
+                        S3Error::new(
+                            e.response.status.unwrap_or(status::InternalServerError),
+                            "InternalError",
+                            &e.error.to_string(),
+                        )
+                    })
+                }
+                _ => Err(S3Error::not_found(&key)),
+            },
+            method::Put => {
+                // Replacing an expired file, as a missing one
+                if hidden && !expired(&path) {
+                    return Err(S3Error::access_denied());
+                }
+                if self.upload_only && metadata.is_some() && !key.ends_with('/') {
+                    return Err(S3Error::new(
+                        status::Forbidden,
+                        "AccessDenied",
+                        "Already uploaded, existing objects are not replaced (--upload-only)",
+                    ));
+                }
+                // Folders of the S3 browsers
+                if key.ends_with('/') {
+                    fs::create_dir_all(&path)?;
+                    println!(
+                        "  >> Directory created: {}",
+                        root_relative_path(&root, &path)
+                    );
+                    if let Some(ref audit) = self.audit {
+                        let path = root_relative_path(&root, &path);
+                        audit.record(&self.actor(req), "mkdir", &path, None, Ok(()));
+                    }
+                    return Ok(Response::with(status::Ok));
+                }
+                let length = ["x-amz-decoded-content-length", "Content-Length"]
+                    .iter()
+                    .find_map(|name| {
+                        req.headers
+                            .get_raw(name)
+                            .and_then(|values| values.first())
+                            .and_then(|value| {
+                                String::from_utf8_lossy(value).trim().parse::<u64>().ok()
+                            })
+                    })
+                    .ok_or_else(|| {
+                        S3Error::new(
+                            status::LengthRequired,
+                            "MissingContentLength",
+                            "You must provide the Content-Length HTTP header",
+                        )
+                    })?;
+                if length > self.upload_size_limit {
+                    return Err(S3Error::new(
+                        status::BadRequest,
+                        "EntityTooLarge",
+                        &format!(
+                            "Larger than the upload size limit ({})",
+                            convert(self.upload_size_limit as f64)
+                        ),
+                    ));
+                }
+                if let Some(quota) = self.upload_quota {
+                    let used = tree_size(&root);
+                    if used + length > quota {
+                        return Err(S3Error::new(
+                            status::InsufficientStorage,
+                            "QuotaExceeded",
+                            &format!(
+                                "Upload quota exceeded, {} left",
+                                convert(quota.saturating_sub(used) as f64)
+                            ),
+                        ));
+                    }
+                }
+                let client = self.upload_client(req);
+                if let Some(ref limiter) = self.upload_limit {
+                    limiter.reserve(&client, 1).map_err(|wait| {
+                        S3Error::new(
+                            status::ServiceUnavailable,
+                            "SlowDown",
+                            &format!("Too many uploads, retry in {}s", wait),
+                        )
+                    })?;
+                }
+                let body: Box<dyn Read> = match self.upload_limit {
+                    Some(ref limiter) => Box::new(limiter.throttle(&mut req.body, client)),
+                    None => Box::new(&mut req.body),
+                };
+                let mut body = s3::object_body(body, payload).take(length);
+                let mut head = Vec::new();
+                if let Some(ref filter) = self.upload_filter {
+                    (&mut body)
+                        .take(upload_filter::SNIFF_LEN as u64)
+                        .read_to_end(&mut head)?;
+                    filter.check_head(&relative_path, &head).map_err(|e| {
+                        S3Error::new(status::UnsupportedMediaType, "InvalidArgument", &e)
+                    })?;
+                }
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let size = atomic_file::save(&mut io::Cursor::new(head).chain(body), &path)
+                    .map_err(|e| {
+                        if e.kind() == io::ErrorKind::InvalidData {
+                            S3Error::new(
+                                status::BadRequest,
+                                "XAmzContentSHA256Mismatch",
+                                &e.to_string(),
+                            )
+                        } else {
+                            S3Error::from(e)
+                        }
+                    })?;
+                println!("  >> File saved: {}", root_relative_path(&root, &path));
+                if let Some(ref audit) = self.audit {
+                    let path = root_relative_path(&root, &path);
+                    audit.record(&self.actor(req), "upload", &path, Some(size), Ok(()));
+                }
+                if let Some(ref webhook) = self.webhook {
+                    webhook.notify(
+                        "upload",
+                        &root_relative_path(&root, &path),
+                        size,
+                        req.remote_addr.ip(),
+                    );
+                }
+                if let Some(ref command) = self.on_upload {
+                    command.run(&path);
+                }
+                self.hooks.upload(&path, size, req.remote_addr.ip());
+                let modified = fs::metadata(&path)?.modified()?;
+                let mut resp = Response::with(status::Ok);
+                resp.headers
+                    .set_raw("ETag", vec![s3::etag(size, modified).into_bytes()]);
+                Ok(resp)
+            }
+            method::Delete if !self.delete => Err(S3Error::new(
+                status::Forbidden,
+                "AccessDenied",
+                "Deletes are disabled (--delete)",
+            )),
+            method::Delete => {
+                // Deleting a missing object succeeds
+                if hidden || metadata.is_none() {
+                    return Ok(Response::with(status::NoContent));
+                }
+                // Only empty directories, as S3 folders are
+                let is_dir = metadata.is_some_and(|m| m.is_dir());
+                if is_dir && fs::read_dir(&path)?.next().is_some() {
+                    return Ok(Response::with(status::NoContent));
+                }
+                match self.trash {
+                    Some(ref trash) if !trash.contains(&path) => trash.move_into(&path).map(|_| ()),
+                    _ if is_dir => fs::remove_dir(&path),
+                    _ => fs::remove_file(&path),
+                }?;
+                println!("  >> Deleted: {}", root_relative_path(&root, &path));
+                if let Some(ref audit) = self.audit {
+                    let path = root_relative_path(&root, &path);
+                    audit.record(&self.actor(req), "delete", &path, None, Ok(()));
+                }
+                if let Some(ref webhook) = self.webhook {
+                    webhook.notify(
+                        "delete",
+                        &root_relative_path(&root, &path),
+                        0,
+                        req.remote_addr.ip(),
+                    );
+                }
+                Ok(Response::with(status::NoContent))
+            }
+            _ => Err(S3Error::not_implemented()),
+        }
+    }
+
+    /// Removes a file or directory (`DELETE`), into the trash directory with `--trash-dir`
+    fn delete_path(
+        &self,
+        req: &Request,
+        root: &Path,
+        path: &Path,
+    ) -> Result<(), (status::Status, String)> {
+        if self.csrf {
+            self.check_same_origin(req)?;
+            let token = req
+                .headers
+                .get_raw(CSRF_TOKEN_HEADER)
+                .and_then(|values| values.first())
+                .map(|value| String::from_utf8_lossy(value).to_string());
+            if token.is_none() || token != get_cookie(req, CSRF_TOKEN_NAME) {
+                return Err((
+                    status::Forbidden,
+                    "CSRF token missing or not matched".to_owned(),
+                ));
+            }
+        }
+        if path == root {
+            return Err((
+                status::Forbidden,
+                "The root directory can't be deleted".to_owned(),
+            ));
+        }
+        // The link itself, not its target
+        let metadata =
+            fs::symlink_metadata(path).map_err(|_| (status::NotFound, "Not Found".to_owned()))?;
+        if self.is_ignored(root, path, metadata.is_dir()) {
+            return Err((status::NotFound, "Not Found".to_owned()));
+        }
+        // The directories have no ETag, only their modification time
+        let etags = match metadata.is_file() {
+            true => self
+                .file_etag(path, &metadata)
+                .map_err(|e| (status::InternalServerError, e.error.to_string()))?
+                .into_iter()
+                .collect(),
+            false => Vec::new(),
+        };
+        let validators = Validators {
+            etags: &etags,
+            modified: filetime::FileTime::from_last_modification_time(&metadata).seconds(),
+        };
+        if conditional::evaluate(&req.method, &req.headers, &validators) != Precondition::Proceed {
+            return Err((status::PreconditionFailed, "Precondition failed".to_owned()));
+        }
+        let rv = match self.trash {
+            Some(ref trash) if !trash.contains(path) => trash.move_into(path).map(|_| ()),
+            _ if metadata.is_dir() => fs::remove_dir_all(path),
+            _ => fs::remove_file(path),
+        };
+        rv.map_err(|e| (status::InternalServerError, format!("Delete failed: {}", e)))?;
+        println!("  >> Deleted: {}", root_relative_path(root, path));
+        if let Some(ref audit) = self.audit {
+            let path = root_relative_path(root, path);
+            audit.record(&self.actor(req), "delete", &path, None, Ok(()));
+        }
+        if let Some(ref webhook) = self.webhook {
+            webhook.notify(
+                "delete",
+                &root_relative_path(root, path),
+                0,
+                req.remote_addr.ip(),
+            );
+        }
+        Ok(())
+    }
+
+    fn save_files(&self, req: &mut Request, path: &Path) -> Result<(), (status::Status, String)> {
+        use filetime::FileTime;
+        use iron::mime::{SubLevel, TopLevel};
+
+        let root = self.root_for(req);
+        let client_ip = req.remote_addr.ip();
+        let actor = self.actor(req);
+        // The destination may be another directory, of other access rules
+        let (url, headers) = (req.url.clone(), req.headers.clone());
+        let csrf_cookie = if self.csrf {
+            self.check_same_origin(req)?;
+            Some(get_cookie(req, CSRF_TOKEN_NAME))
+        } else {
+            None
+        };
+        // The token of the scripts, otherwise the first field of the form
+        let mut csrf_token = req
+            .headers
+            .get_raw(CSRF_TOKEN_HEADER)
+            .and_then(|values| values.first())
+            .map(|value| String::from_utf8_lossy(value).to_string())
+            .or_else(|| {
+                req.url
+                    .as_ref()
+                    .query_pairs()
+                    .find(|(k, _)| k == CSRF_TOKEN_NAME)
+                    .map(|(_, v)| v.into_owned())
+            });
+        let client = self.upload_client(req);
+        let boundary = req
+            .headers
+            .get::<headers::ContentType>()
+            .filter(|content_type| {
+                matches!(
+                    ***content_type,
+                    Mime(TopLevel::Multipart, SubLevel::FormData, _)
+                )
+            })
+            .and_then(|content_type| content_type.get_param("boundary"))
+            .map(|boundary| boundary.to_string());
+        let body: Box<dyn Read> = match self.upload_limit {
+            Some(ref limiter) => Box::new(limiter.throttle(&mut req.body, client.clone())),
+            None => Box::new(&mut req.body),
+        };
+        match boundary.map(|boundary| Multipart::with_body(body, boundary)) {
+            Some(mut multipart) => {
+                // Checked before any file is read
+                if let Some(cookie) = csrf_cookie {
+                    if csrf_token.is_none() {
+                        if let Ok(Some(mut field)) = multipart.read_entry() {
+                            if &*field.headers.name == CSRF_TOKEN_NAME && field.is_text() {
+                                let mut text = String::new();
+                                let _ = field.data.by_ref().take(1024).read_to_string(&mut text);
+                                csrf_token = Some(text);
+                            }
+                        }
+                    }
+                    if csrf_token.is_none() || csrf_token != cookie {
+                        return Err((
+                            status::Forbidden,
+                            "CSRF token missing or not matched".to_owned(),
+                        ));
+                    }
+                }
+                // Fetching all data and processing it.
+                // save().temp() reads the request fully, parsing all fields and saving all files
+                // in a new temporary directory under the OS temporary directory.
+                match multipart.save().size_limit(self.upload_size_limit).temp() {
+                    SaveResult::Full(entries) => {
+                        if let Some(quota) = self.upload_quota {
+                            let incoming = entries
+                                .fields
+                                .values()
+                                .flatten()
+                                .filter(|field| field.headers.filename.is_some())
+                                .map(|field| field.data.size())
+                                .sum::<u64>();
+                            let used = tree_size(root);
+                            if used + incoming > quota {
+                                return Err((
+                                    status::InsufficientStorage,
+                                    format!(
+                                        "Upload quota exceeded, {} left",
+                                        convert(quota.saturating_sub(used) as f64)
+                                    ),
+                                ));
+                            }
+                        }
+                        let text_fields = |name: &str| {
+                            entries
+                                .fields
+                                .get(name)
+                                .map(|fields| {
+                                    fields
+                                        .iter()
+                                        .map(|field| match field.data {
+                                            SavedData::Text(ref text) => text.clone(),
+                                            _ => String::new(),
+                                        })
+                                        .collect::<Vec<String>>()
+                                })
+                                .unwrap_or_default()
+                        };
+                        // Paths inside uploaded directories, in the order of the files
+                        let relative_paths = text_fields(RELATIVE_PATH_FIELD);
+                        let destination = text_fields(DESTINATION_FIELD)
+                            .into_iter()
+                            .next()
+                            .filter(|s| !s.trim().is_empty());
+                        let path = match destination {
+                            Some(destination) => {
+                                let subdir =
+                                    client_relative_path(destination.trim()).ok_or_else(|| {
+                                        (
+                                            status::BadRequest,
+                                            format!("Invalid destination: {}", destination),
+                                        )
+                                    })?;
+                                path.join(subdir)
+                            }
+                            None => path.to_path_buf(),
+                        };
+                        let last_modified = text_fields(LAST_MODIFIED_FIELD);
+                        // All the files are checked before saving any of them
+                        let mut uploads = Vec::new();
+                        for (name, fields) in entries.fields.iter() {
+                            let mut file_index = 0;
+                            for field in fields {
+                                // Skip the non-file fields (csrf token)
+                                let filename = match field.headers.filename {
+                                    Some(ref filename) => filename,
+                                    None => continue,
+                                };
+                                let relative_path = relative_paths
+                                    .get(file_index)
+                                    .filter(|s| &**name == UPLOAD_FIELD && !s.is_empty())
+                                    .unwrap_or(filename);
+                                let modified = last_modified
+                                    .get(file_index)
+                                    .filter(|_| self.preserve_mtime && &**name == UPLOAD_FIELD)
+                                    .and_then(|s| s.trim().parse::<i64>().ok())
+                                    .map(|ms| {
+                                        FileTime::from_unix_time(
+                                            ms.div_euclid(1000),
+                                            (ms.rem_euclid(1000) * 1_000_000) as u32,
+                                        )
+                                    });
+                                file_index += 1;
+                                let relative_path = client_relative_path(relative_path)
+                                    .ok_or_else(|| {
+                                        (
+                                            status::BadRequest,
+                                            format!("Invalid file name: {}", relative_path),
+                                        )
+                                    })?;
+                                // Nor replaced by the uploaders who can't see them
+                                if self.upload_only
+                                    && fs::symlink_metadata(path.join(&relative_path)).is_ok()
+                                {
+                                    return Err((
+                                        status::Conflict,
+                                        format!("Already uploaded: {}", relative_path.display()),
+                                    ));
+                                }
+                                if let Some(ref filter) = self.upload_filter {
+                                    filter
+                                        .check(&relative_path, &field.data)
+                                        .map_err(|e| (status::UnsupportedMediaType, e))?;
+                                }
+                                if let Some(ref access) = self.access {
+                                    if relative_path.file_name() == Some(OsStr::new(ACCESS_FILE)) {
+                                        return Err((
+                                            status::Forbidden,
+                                            format!(
+                                                "Reserved file name: {}",
+                                                relative_path.display()
+                                            ),
+                                        ));
+                                    }
+                                    access
+                                        .check(&headers, true, root, &path.join(&relative_path))
+                                        .map_err(|e| {
+                                            (
+                                                e.response.status.unwrap_or(status::Forbidden),
+                                                "No permission to upload.".to_owned(),
+                                            )
+                                        })?;
+                                }
+                                let target_path = path.join(&relative_path);
+                                if let Some(ref protected) = self.protected {
+                                    protected
+                                        .check_parts(&url, &headers, root, &target_path)
+                                        .map_err(|_| {
+                                            (
+                                                status::Forbidden,
+                                                "No permission to upload.".to_owned(),
+                                            )
+                                        })?;
+                                }
+                                if self.is_ignored(root, &target_path, false) {
+                                    return Err((
+                                        status::Forbidden,
+                                        format!("Ignored file name: {}", relative_path.display()),
+                                    ));
+                                }
+                                uploads.push((relative_path, modified, field));
+                            }
+                        }
+                        if let Some(ref limiter) = self.upload_limit {
+                            limiter.reserve(&client, uploads.len()).map_err(|wait| {
+                                (
+                                    status::TooManyRequests,
+                                    format!("Too many uploads, retry in {}s", wait),
+                                )
+                            })?;
+                        }
+                        for (relative_path, modified, field) in uploads {
+                            let mut data = field.data.readable().unwrap();
+                            let target_path = path.join(&relative_path);
+
+                            match target_path
+                                .parent()
+                                .map_or(Ok(()), fs::create_dir_all)
+                                .and_then(|_| atomic_file::save(&mut data, &target_path))
+                            {
+                                Err(errno) => {
+                                    return Err((
+                                        status::InternalServerError,
+                                        format!("Copy file failed: {}", errno),
+                                    ));
+                                }
+                                Ok(size) => {
+                                    println!("  >> File saved: {}", relative_path.display());
+                                    if let Some(modified) = modified {
+                                        if let Err(e) =
+                                            filetime::set_file_mtime(&target_path, modified)
+                                        {
+                                            println!("  >> Set modification time failed: {}", e);
+                                        }
+                                    }
+                                    let saved = root_relative_path(root, &target_path);
+                                    if let Some(ref audit) = self.audit {
+                                        audit.record(&actor, "upload", &saved, Some(size), Ok(()));
+                                    }
+                                    if let Some(ref webhook) = self.webhook {
+                                        webhook.notify("upload", &saved, size, client_ip);
+                                    }
+                                    if let Some(ref command) = self.on_upload {
+                                        command.run(&target_path);
+                                    }
+                                    self.hooks.upload(&target_path, size, client_ip);
+                                }
+                            }
+                        }
+                        Ok(())
+                    }
+                    SaveResult::Partial(_entries, PartialReason::SizeLimit) => Err((
+                        status::PayloadTooLarge,
+                        format!(
+                            "Larger than the upload size limit ({})",
+                            convert(self.upload_size_limit as f64)
+                        ),
+                    )),
+                    SaveResult::Partial(_entries, reason) => {
+                        Err((status::InternalServerError, reason.unwrap_err().to_string()))
+                    }
+                    SaveResult::Error(error) => {
+                        Err((status::InternalServerError, error.to_string()))
+                    }
+                }
+            }
+            None => Err((
+                status::BadRequest,
+                "The request is not multipart".to_owned(),
+            )),
+        }
+    }
+
+    /// The log out button of a session user, posting the CSRF token
+    fn logout_form(&self, req: &Request, resp: &mut Response) -> String {
+        match self.sessions {
+            Some(ref sessions) if sessions.user(req).is_some() => format!(
+                r#"<form class="logout" method="POST" action="/{}">{}<button type="submit">Log out</button></form>"#,
+                LOGOUT_PATH,
+                csrf_field(req, resp)
+            ),
+            _ => "".to_owned(),
+        }
+    }
+
+    /// The upload form of the directory `path_prefix`, with the CSRF cookie set on `resp`
+    fn upload_form(
+        &self,
+        req: &Request,
+        resp: &mut Response,
+        root: &Path,
+        path_prefix: &[OsString],
+        nonce: &str,
+    ) -> String {
+        let csrf_field = if self.csrf {
+            csrf_field(req, resp)
+        } else {
+            "".to_owned()
+        };
+        let quota = match self.upload_quota {
+            Some(quota) => format!(
+                r#"<span class="quota">{} of {} left</span>"#,
+                convert(quota.saturating_sub(tree_size(root)) as f64),
+                convert(quota as f64)
+            ),
+            None => "".to_owned(),
+        };
+        format!(
+            r#"
+<form class="upload-form" id="upload-form" action="/{path}" method="POST" enctype="multipart/form-data">
+  {csrf_field}
+  <input type="file" name="files" accept="*" multiple title="Files" />
+  <input type="file" name="files" webkitdirectory multiple title="A folder" />
+  <input type="text" name="destination" placeholder="into subfolder (optional)" />
+  <input type="submit" value="Upload" />
+  <span class="drop-hint" hidden>or drop files here</span>
+  {quota}
+</form>
+<ul id="upload-progress"></ul>
+<script nonce="{nonce}">{script}</script>
+<script nonce="{nonce}">{delete_script}</script>
+"#,
+            path = encode_link_path(path_prefix),
+            csrf_field = csrf_field,
+            quota = quota,
+            nonce = nonce,
+            script = UPLOAD_SCRIPT,
+            delete_script = DELETE_SCRIPT
+        )
+    }
+
+    /// The page of a directory with `--upload-only`: the upload form, without the entries
+    fn send_upload_page(
+        &self,
+        req: &mut Request,
+        root: &Path,
+        fs_path: &Path,
+        path_prefix: &Path,
+    ) -> IronResult<Response> {
+        let not_found = || {
+            IronError::new(
+                io::Error::new(io::ErrorKind::NotFound, "Not Found"),
+                status::NotFound,
+            )
+        };
+        let metadata = fs::metadata(fs_path).map_err(|_| not_found())?;
+        if !metadata.is_dir() || self.is_ignored(root, fs_path, true) {
+            return Err(not_found());
+        }
+        if !matches!(req.method, method::Get | method::Head) {
+            return Ok(self.method_not_allowed(Some(true)));
+        }
+        if self.canonical_redirect {
+            if let Some(location) = canonical_location(req, true) {
+                let mut resp = Response::with(status::MovedPermanently);
+                resp.headers
+                    .set_raw("Location", vec![location.into_bytes()]);
+                return Ok(resp);
+            }
+        }
+        let path_prefix: Vec<OsString> = path_prefix.iter().map(|s| s.to_os_string()).collect();
+        let mut resp = Response::with(status::Ok);
+        let nonce = random_token(16);
+        let upload_form = self.upload_form(req, &mut resp, root, &path_prefix, &nonce);
+        let logout = self.logout_form(req, &mut resp);
+        resp.set_mut(format!(
+            r#"<!DOCTYPE html>
+<html data-theme="{theme}">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width,initial-scale=1.0, minimum-scale=1.0, maximum-scale=1.0, user-scalable=no"/>
+  <style nonce="{nonce}">
+{style}
+  </style>
+</head>
+<body>
+  {html_header}
+  {upload_form}
+  <div>{logout}<p>The uploaded files are not listed here, nor can be downloaded.</p></div>
+  {html_footer}
+</body>
+</html>
+"#,
+            theme = self.theme,
+            nonce = nonce,
+            html_header = with_nonce(self.html_header.as_deref().unwrap_or(""), &nonce),
+            html_footer = with_nonce(self.html_footer.as_deref().unwrap_or(""), &nonce),
+            style = LISTING_STYLE,
+            upload_form = upload_form,
+            logout = logout
+        ));
+        resp.headers.set(headers::ContentType::html());
+        set_page_csp(&mut resp, &nonce);
+        Ok(resp)
+    }
+
+    fn list_directory(
+        &self,
+        req: &mut Request,
+        root: &Path,
+        fs_path: &Path,
+        path_prefix: &[OsString],
+    ) -> IronResult<Response> {
+        let mut resp = Response::with(status::Ok);
+        // Of the scripts and styles of the page, see `set_page_csp()`
+        let nonce = random_token(16);
+        // Images of the gallery view, other entries stay in the table
+        let gallery = req
+            .url
+            .as_ref()
+            .query_pairs()
+            .any(|(k, v)| k == "view" && v == "gallery");
+
+        // Name filter (`?filter=*.jpg`), kept by the sort links
+        let filter = req
+            .url
+            .as_ref()
+            .query_pairs()
+            .find(|(k, v)| k == "filter" && !v.is_empty())
+            .map(|(_, v)| v.to_string());
+
+        // Breadcrumb navigation
+        let breadcrumb = if !path_prefix.is_empty() {
+            let mut breadcrumb = path_prefix.to_owned();
+            let mut bread_links: Vec<String> = Vec::new();
+            bread_links.push(encode_minimal(&breadcrumb.pop().unwrap().to_string_lossy()));
+            while !breadcrumb.is_empty() {
+                bread_links.push(format!(
+                    r#"<a href="/{link}/"><strong>{label}</strong></a>"#,
+                    link = encode_link_path(&breadcrumb),
+                    label = encode_minimal(&breadcrumb.pop().unwrap().to_string_lossy()),
+                ));
+            }
+            bread_links.push(ROOT_LINK.to_owned());
+            bread_links.reverse();
+            bread_links.join(" / ")
+        } else {
+            ROOT_LINK.to_owned()
+        };
+
+        // Written while the directory is read (`--stream-listings`), the pages are small already
+        let paginated = self.page_size > 0
+            || req
+                .url
+                .as_ref()
+                .query_pairs()
+                .any(|(k, _)| k == "page" || k == "per_page");
+        let mut streamed = None;
+        // Sort links, entries and pager, of the cache until the directory changes
+        let rows = match self.listing_cache {
+            _ if self.stream_listings && !gallery && !paginated => {
+                let (sort_links, sort) =
+                    self.sort_links(req, path_prefix, &filter_query(filter.as_deref()))?;
+                let entries =
+                    ListingEntries::new(fs_path, self.listing_skip(root, filter.as_deref())?)
+                        .map_err(error_io2iron)?
+                        // Too late for an error response once written
+                        .filter_map(Result::ok);
+                let entries: Box<dyn Iterator<Item = Entry> + Send> = match sort {
+                    // The entries of the whole directory first, not their rows
+                    Some((field, reverse)) => {
+                        let mut entries = entries.collect::<Vec<Entry>>();
+                        listing::sort(&mut entries, &field, reverse);
+                        Box::new(entries.into_iter())
+                    }
+                    None => Box::new(entries),
+                };
+                streamed = Some(entries);
+                Arc::new(ListingRows {
+                    sort_links,
+                    rows: vec![up_row(path_prefix), ROWS_MARK.to_owned()],
+                    gallery_items: Vec::new(),
+                    // Unknown before the rows are written, the toggle follows the summary
+                    has_images: false,
+                    pager: "".to_owned(),
+                    summary: SUMMARY_MARK.to_owned(),
+                })
+            }
+            Some(ref cache) if self.expiry.is_none() => {
+                let query = req.url.query().unwrap_or("").to_owned();
+                let access_files = match self.access {
+                    Some(_) => fs_path
+                        .ancestors()
+                        .take_while(|dir| dir.starts_with(root))
+                        .map(|dir| dir.join(ACCESS_FILE))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let generation = self.live.generation();
+                cache.get_or_render(fs_path, &access_files, query, generation, || {
+                    self.listing_rows(req, root, fs_path, path_prefix, gallery, filter.as_deref())
+                })?
+            }
+            _ => Arc::new(self.listing_rows(
+                req,
+                root,
+                fs_path,
+                path_prefix,
+                gallery,
+                filter.as_deref(),
+            )?),
+        };
+        let disk_space = match (fs2::available_space(fs_path), fs2::total_space(fs_path)) {
+            (Ok(available), Ok(total)) => format!(
+                " &middot; {} free of {}",
+                convert(available as f64),
+                convert(total as f64)
+            ),
+            _ => "".to_owned(),
+        };
+        let footer = format!(
+            r#"<footer>{summary}{disk}</footer>"#,
+            summary = rows.summary,
+            disk = disk_space
+        );
+
+        // Optinal upload form
+        let upload_form = if self.upload {
+            self.upload_form(req, &mut resp, root, path_prefix, &nonce)
+        } else {
+            "".to_owned()
+        };
+
+        // Gallery view toggle, and the grid with a lightbox
+        let mut current_link = path_prefix.to_owned();
+        current_link.push(OsString::new());
+        let gallery_toggle = format!(
+            r#"<a class="view-toggle" href="/{link}?view=gallery">Gallery view</a>"#,
+            link = encode_link_path(&current_link)
+        );
+        let view_toggle = if gallery {
+            format!(
+                r#"<a class="view-toggle" href="/{link}">List view</a>"#,
+                link = encode_link_path(&current_link)
+            )
+        } else if rows.has_images {
+            gallery_toggle.clone()
+        } else {
+            "".to_owned()
+        };
+        let gallery_html = if gallery {
+            format!(
+                r#"
+<div id="gallery">
+  {items}
+</div>
+<div id="lightbox" hidden>
+  <button id="lightbox-prev" title="Previous (&larr;)">&lsaquo;</button>
+  <img id="lightbox-image" alt="" />
+  <button id="lightbox-next" title="Next (&rarr;)">&rsaquo;</button>
+  <button id="lightbox-close" title="Close (Esc)">&times;</button>
+</div>
+<script nonce="{nonce}">
+(function () {{
+  var items = Array.prototype.slice.call(document.querySelectorAll('.gallery-item'));
+  var box = document.getElementById('lightbox');
+  var image = document.getElementById('lightbox-image');
+  var current = -1;
+  function show(index) {{
+    current = (index + items.length) % items.length;
+    image.src = items[current].getAttribute('href');
+    image.alt = items[current].title;
+    box.hidden = false;
+  }}
+  function close() {{ box.hidden = true; image.src = ''; current = -1; }}
+  items.forEach(function (item, index) {{
+    item.addEventListener('click', function (e) {{ e.preventDefault(); show(index); }});
+  }});
+  document.getElementById('lightbox-prev').onclick = function () {{ show(current - 1); }};
+  document.getElementById('lightbox-next').onclick = function () {{ show(current + 1); }};
+  document.getElementById('lightbox-close').onclick = close;
+  box.addEventListener('click', function (e) {{ if (e.target === box) close(); }});
+  document.addEventListener('keydown', function (e) {{
+    if (current < 0) return;
+    if (e.key === 'ArrowLeft') show(current - 1);
+    else if (e.key === 'ArrowRight') show(current + 1);
+    else if (e.key === 'Escape') close();
+  }});
+}})();
+</script>
+"#,
+                items = rows.gallery_items.join("\n  "),
+                nonce = nonce
+            )
+        } else {
+            "".to_owned()
+        };
+        let logout = self.logout_form(req, &mut resp);
+
+        // Filter box: narrows the rows while typing, the server filters on submit
+        let hidden_fields = req
+            .url
+            .as_ref()
+            .query_pairs()
+            .filter(|(k, _)| ["sort", "order", "view", "per_page"].contains(&k.as_ref()))
+            .map(|(k, v)| {
+                format!(
+                    r#"<input type="hidden" name="{}" value="{}" />"#,
+                    encode_attribute(&k),
+                    encode_attribute(&v)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("");
+        let filter_form = format!(
+            r#"<form class="filter-form" method="GET">{hidden}<input type="search" name="filter" value="{filter}" placeholder="Filter (e.g. *.jpg)" /></form>
+  <script nonce="{nonce}">{script}</script>"#,
+            hidden = hidden_fields,
+            nonce = nonce,
+            filter = encode_attribute(filter.as_deref().unwrap_or("")),
+            script = FILTER_SCRIPT
+        );
+        // Posts the checked entries (`form` attribute of the checkboxes)
+        let selection_form = if gallery {
+            "".to_owned()
+        } else {
+            format!(
+                r#"<form class="selection-form" id="selection-form" method="POST" action="/{path}?{query}"><select name="format"><option value="tar">tar</option><option value="tar.gz">tar.gz</option></select> <input type="submit" value="Download selected" /></form>"#,
+                path = encode_link_path(path_prefix),
+                query = ARCHIVE_QUERY
+            )
+        };
+
+        // Put all parts together
+        let page = format!(
+            r#"<!DOCTYPE html>
+<html data-theme="{theme}">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width,initial-scale=1.0, minimum-scale=1.0, maximum-scale=1.0, user-scalable=no"/>
+  <style nonce="{nonce}">
+{style}
+  </style>
+</head>
+<body>
+  {html_header}
+  {upload_form}
+  <div>{breadcrumb}{logout}{view_toggle}</div>
+  {filter_form}
+  {selection_form}
+  {one_time_script}
+  <hr />
+  <table class="listing">
+    {sort_links}
+    {rows}
+  </table>
+  {gallery}
+  {pager}
+  <hr />
+  {footer}
+  {html_footer}
+</body>
+</html>
+"#,
+            theme = self.theme,
+            nonce = nonce,
+            html_header = with_nonce(self.html_header.as_deref().unwrap_or(""), &nonce),
+            html_footer = with_nonce(self.html_footer.as_deref().unwrap_or(""), &nonce),
+            style = LISTING_STYLE,
+            upload_form = upload_form,
+            breadcrumb = breadcrumb,
+            view_toggle = view_toggle,
+            logout = logout,
+            filter_form = filter_form,
+            selection_form = selection_form,
+            one_time_script = match self.one_time_links {
+                Some(_) => format!("<script nonce=\"{}\">{}</script>", nonce, ONE_TIME_SCRIPT),
+                None => "".to_owned(),
+            },
+            footer = footer,
+            gallery = gallery_html,
+            pager = if rows.pager.is_empty() || gallery {
+                rows.pager.clone()
+            } else {
+                format!(
+                    "{}\n  <script nonce=\"{}\">{}</script>",
+                    rows.pager, nonce, LAZY_LOAD_SCRIPT
+                )
+            },
+            sort_links = rows.sort_links,
+            rows = rows.rows.join("\n")
+        );
+        match streamed {
+            Some(entries) => {
+                let (head, rest) = page.split_once(ROWS_MARK).unwrap();
+                let (middle, tail) = rest.split_once(SUMMARY_MARK).unwrap();
+                let renderer = self.row_renderer(path_prefix);
+                resp.body = Some(Box::new(StreamedListing {
+                    head: head.to_owned(),
+                    entries,
+                    row: Box::new(move |entry| renderer.render(entry, false)),
+                    middle: middle.to_owned(),
+                    gallery_toggle,
+                    tail: tail.to_owned(),
+                }));
+            }
+            None => {
+                resp.set_mut(page);
+            }
+        }
+
+        resp.headers.set(headers::ContentType::html());
+        set_page_csp(&mut resp, &nonce);
+        if self.live.compress().is_some() {
+            middlewares::set_vary(&mut resp);
+        }
+        let encoding = middlewares::negotiate(req, self.live.compress().is_some())
+            .map_err(|e| IronError::new(e, status::NotAcceptable))?;
+        if let Some(encoding) = encoding {
+            resp.headers.set(ContentEncoding(vec![encoding]));
+        }
+        Ok(resp)
+    }
+
+    // The part of the listing read from the directory: sorted, filtered and paginated as the
+    // query asks, cached by `--listing-cache`
+    fn listing_rows(
+        &self,
+        req: &Request,
+        root: &Path,
+        fs_path: &Path,
+        path_prefix: &[OsString],
+        gallery: bool,
+        filter: Option<&str>,
+    ) -> IronResult<ListingRows> {
+        let mut entries = ListingEntries::new(fs_path, self.listing_skip(root, filter)?)
+            .and_then(|entries| entries.collect::<io::Result<Vec<Entry>>>())
+            .map_err(error_io2iron)?;
+        let (sort_links, sort) = self.sort_links(req, path_prefix, &filter_query(filter))?;
+        if let Some((field, reverse)) = sort {
+            listing::sort(&mut entries, &field, reverse);
+        }
+        let mut summary = Summary::default();
+        for entry in &entries {
+            summary.add(&entry.metadata);
+        }
+
+        // Pagination, after sorting
+        let mut page = 1;
+        let mut per_page = self.page_size;
+        for (k, v) in req.url.as_ref().query_pairs() {
+            let parse = |v: &str| {
+                v.parse::<usize>().ok().filter(|n| *n > 0).ok_or_else(|| {
+                    IronError::new(
+                        StringError(format!("Invalid {}: {}", k, v)),
+                        status::BadRequest,
+                    )
+                })
+            };
+            if k == "page" {
+                page = parse(&v)?;
+            } else if k == "per_page" {
+                per_page = parse(&v)?;
+            }
+        }
+        let pager = if per_page > 0 && (entries.len() > per_page || page > 1) {
+            let pages = entries.len().div_ceil(per_page).max(1);
+            entries = entries
+                .into_iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .collect();
+            let mut current_link = path_prefix.to_owned();
+            current_link.push(OsString::new());
+            let page_link = |page: usize| {
+                encode_attribute(&format!(
+                    "/{}?{}",
+                    encode_link_path(&current_link),
+                    query_with(req, &[("page", page.to_string())])
+                ))
+            };
+            let prev = if page > 1 {
+                format!(
+                    r#"<a href="{}">&laquo; Previous</a>"#,
+                    page_link((page - 1).min(pages))
+                )
+            } else {
+                "".to_owned()
+            };
+            let next = if page < pages {
+                format!(
+                    r#"<a id="next-page" href="{}">Next &raquo;</a>"#,
+                    page_link(page + 1)
+                )
+            } else {
+                "".to_owned()
+            };
+            format!(
+                r#"<div class="pager">{prev} <span>Page {page} of {pages}</span> {next}</div>"#,
+                prev = prev,
+                page = page,
+                pages = pages,
+                next = next
+            )
+        } else {
+            "".to_owned()
+        };
+
+        let mut rows = vec![up_row(path_prefix)];
+        let mut gallery_items = Vec::new();
+        let mut has_images = false;
+        let renderer = self.row_renderer(path_prefix);
+        for entry in entries {
+            let is_image = entry.is_image();
+            has_images |= is_image;
+            let html = renderer.render(&entry, gallery);
+            if gallery && is_image {
+                gallery_items.push(html);
+            } else {
+                rows.push(html);
+            }
+        }
+
+        Ok(ListingRows {
+            sort_links,
+            rows,
+            gallery_items,
+            has_images,
+            pager,
+            summary: summary.to_string(),
+        })
+    }
+
+    // The header of the table, and the field and direction (reversed or not) of `?sort=` and
+    // `?order=`, by name by default: `None` without `--nosort`
+    fn sort_links(
+        &self,
+        req: &Request,
+        path_prefix: &[OsString],
+        filter_query: &str,
+    ) -> IronResult<(String, Option<(String, bool)>)> {
+        if !self.sort {
+            return Ok(("".to_owned(), None));
+        }
+        let mut field = String::from("name");
+        let mut order = None;
+        for (k, v) in req.url.as_ref().query_pairs() {
+            if k == "sort" {
+                field = v.to_string();
+            } else if k == "order" {
+                order = Some(v.to_string());
+            }
+        }
+        let order = order.unwrap_or_else(|| DEFAULT_ORDER.to_owned());
+        let mut order_labels = BTreeMap::new();
+        for sort_field in SORT_FIELDS.iter() {
+            if field == *sort_field && order == ORDER_DESC {
+                // reverse the order of the field
+                order_labels.insert(sort_field.to_owned(), ORDER_ASC);
+            }
+        }
+        if !SORT_FIELDS.contains(&field.as_str()) {
+            return Err(IronError::new(
+                StringError(format!("Unknown sort field: {}", field)),
+                status::BadRequest,
+            ));
+        }
+        if ![ORDER_ASC, ORDER_DESC].contains(&order.as_str()) {
+            return Err(IronError::new(
+                StringError(format!("Unknown sort order: {}", order)),
+                status::BadRequest,
+            ));
+        }
+
+        let mut current_link = path_prefix.to_owned();
+        current_link.push(OsString::new());
+        let links = format!(
+            r#"
+<tr>
+  <th><a href="/{link}?sort=name&order={name_order}{filter}">Name</a></th>
+  <th><a href="/{link}?sort=modified&order={modified_order}{filter}">Last modified</a></th>
+  <th class="size"><a href="/{link}?sort=size&order={size_order}{filter}">Size</a></th>
+  <th></th>
+</tr>
+"#,
+            link = encode_link_path(&current_link),
+            filter = filter_query,
+            name_order = order_labels.get("name").unwrap_or(&DEFAULT_ORDER),
+            modified_order = order_labels.get("modified").unwrap_or(&DEFAULT_ORDER),
+            size_order = order_labels.get("size").unwrap_or(&DEFAULT_ORDER)
+        );
+        Ok((links, Some((field, order == ORDER_DESC))))
+    }
+
+    // Leaves out the ignored and expired entries, and those not matching `filter`: owned by the
+    // streamed listings, read after the handler returned
+    fn listing_skip(&self, root: &Path, filter: Option<&str>) -> IronResult<listing::Skip> {
+        let matcher = match filter {
+            Some(filter) => {
+                Some(name_filter(filter).map_err(|e| IronError::new(e, status::BadRequest))?)
+            }
+            None => None,
+        };
+        let root = root.to_path_buf();
+        let (live, access, expiry) = (self.live.clone(), self.access.clone(), self.expiry.clone());
+        Ok(Box::new(move |path, metadata| {
+            ignored(&live, access.as_deref(), &root, path, metadata.is_dir())
+                || expiry
+                    .as_ref()
+                    .is_some_and(|expiry| expiry.is_expired(metadata))
+                || matcher.as_ref().is_some_and(|matcher| {
+                    path.file_name().is_some_and(|name| !matcher.is_match(name))
+                })
+        }))
+    }
+
+    fn row_renderer(&self, path_prefix: &[OsString]) -> RowRenderer {
+        RowRenderer {
+            path_prefix: path_prefix.to_owned(),
+            mime_overrides: self.mime_overrides.clone(),
+            expiry: self.expiry.clone(),
+            thumbnails: self.thumbnailer.is_some(),
+            checksums: self.checksums.is_some(),
+            one_time_links: self.one_time_links.is_some(),
+            delete: self.delete,
+        }
+    }
+
+    fn guess_mime_type(&self, path: &Path) -> Mime {
+        use iron::mime::{Attr, SubLevel, TopLevel, Value};
+
+        let mut mime = guess_mime(&self.mime_overrides, path);
+        // Text content is served with a charset, browsers may mis-render it otherwise
+        let is_text = matches!(
+            mime,
+            Mime(TopLevel::Text, _, _) | Mime(TopLevel::Application, SubLevel::Javascript, _)
+        );
+        if is_text && mime.get_param(Attr::Charset).is_none() {
+            mime.2
+                .push((Attr::Charset, Value::from_str(&self.charset).unwrap()));
+        }
+        mime
+    }
+
+    /// The `ETag` of the identity of a file, by `--etag`
+    fn file_etag(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+    ) -> IronResult<Option<iron::headers::EntityTag>> {
+        use filetime::FileTime;
+        use iron::headers::EntityTag;
+
+        let time = FileTime::from_last_modification_time(metadata);
+        // Strong ones for `If-Range` and `If-Match`: a change of the content changes the size or
+        // the modification time
+        Ok(match self.etag {
+            EtagMode::Strong | EtagMode::Weak => Some(EntityTag::new(
+                matches!(self.etag, EtagMode::Weak),
+                format!(
+                    "{0:x}-{1:x}.{2:x}",
+                    metadata.len(),
+                    time.seconds(),
+                    time.nanoseconds()
+                ),
+            )),
+            EtagMode::ContentHash(ref hashes) => Some(EntityTag::strong(
+                hashes.sha256(path).map_err(error_io2iron)?,
+            )),
+            EtagMode::Off => None,
+        })
+    }
+
+    fn send_file<P: AsRef<Path>>(&self, req: &Request, path: P) -> IronResult<Response> {
+        use filetime::FileTime;
+        use iron::headers::{
+            AcceptRanges, ContentLength, ContentRange, ContentRangeSpec, ContentType, ETag,
+            Encoding, EntityTag, Range, RangeUnit,
+        };
+        use iron::headers::{CacheControl, CacheDirective, HttpDate, LastModified};
+        use iron::method::Method;
+        use iron::mime::{SubLevel, TopLevel};
+
+        let path = path.as_ref();
+        let metadata = fs::metadata(path).map_err(error_io2iron)?;
+
+        let time = FileTime::from_last_modification_time(&metadata);
+        // In seconds as `Last-Modified` is, the ETag has the nanoseconds
+        let modified = time::Timespec::new(time.seconds() as i64, 0);
+        let etag = self.file_etag(path, &metadata)?;
+        static SECONDS: u32 = 7 * 24 * 3600; // max-age: 7.days()
+        let cache_control = CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(SECONDS),
+        ]);
+
+        // Ranges are of the identity, the full responses may be compressed
+        let negotiable = self.live.compress().is_some_and(|rules| {
+            middlewares::is_compressible(&rules, path, &self.guess_mime_type(path))
+        });
+        let mut etags = etag.iter().cloned().collect::<Vec<EntityTag>>();
+        if let Some(etag) = etag.as_ref().filter(|_| negotiable) {
+            for encoding in [Encoding::Gzip, Encoding::Deflate] {
+                etags.push(middlewares::encoded_etag(etag, &encoding));
+            }
+        }
+        let validators = Validators {
+            etags: &etags,
+            modified: modified.sec,
+        };
+        match conditional::evaluate(&req.method, &req.headers, &validators) {
+            Precondition::Failed => {
+                return Err(IronError::new(
+                    StringError("Precondition failed".to_owned()),
+                    status::PreconditionFailed,
+                ));
+            }
+            // Only once the validators are sent
+            Precondition::NotModified if self.live.cache() => {
+                let mut resp = Response::with(status::NotModified);
+                if negotiable {
+                    middlewares::set_vary(&mut resp);
+                }
+                resp.headers.set(cache_control);
+                if let Some(ref etag) = etag {
+                    // That of the representation the client would get
+                    let encoding = middlewares::negotiate(req, negotiable)
+                        .ok()
+                        .flatten()
+                        .filter(|_| req.method != Method::Head);
+                    resp.headers.set(ETag(match encoding {
+                        Some(ref encoding) => middlewares::encoded_etag(etag, encoding),
+                        None => etag.clone(),
+                    }));
+                }
+                return Ok(resp);
+            }
+            _ => {}
+        }
+
+        let mut resp = Response::with(status::Ok);
+        if self.range {
+            resp.headers.set(AcceptRanges(vec![RangeUnit::Bytes]));
+        }
+        if self.force_download || req.url.as_ref().query_pairs().any(|(k, _)| k == "download") {
+            let filename = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            resp.headers.set_raw(
+                "Content-Disposition",
+                vec![content_disposition_attachment(&filename).into_bytes()],
+            );
+        }
+        match req.method {
+            Method::Head => {
+                let content_type = req
+                    .headers
+                    .get::<ContentType>()
+                    .cloned()
+                    .unwrap_or_else(|| ContentType(Mime(TopLevel::Text, SubLevel::Plain, vec![])));
+                resp.headers.set(content_type);
+                resp.headers.set(ContentLength(metadata.len()));
+                // Without a body Iron sends `Content-Length: 0`
+                resp.body = Some(Box::new(Vec::<u8>::new()));
+            }
+            Method::Get => {
+                // Set mime type
+                let mime = self.guess_mime_type(path);
+                let is_html = matches!(mime, Mime(TopLevel::Text, SubLevel::Html, _));
+                resp.set_mut(mime);
+
+                if self.inject_html
+                    && is_html
+                    && (self.html_header.is_some() || self.html_footer.is_some())
+                {
+                    // Ranges of the original file would be wrong
+                    resp.headers.remove::<AcceptRanges>();
+                    let html = fs::read_to_string(path).map_err(error_io2iron)?;
+                    let html = inject_html(
+                        &html,
+                        self.html_header.as_deref().unwrap_or(""),
+                        self.html_footer.as_deref().unwrap_or(""),
+                    );
+                    resp.headers.set(ContentLength(html.len() as u64));
+                    resp.body = Some(Box::new(html));
+                } else if self.range {
+                    let mut range = req.headers.get::<Range>();
+
+                    // With the size, for the clients to retry
+                    let unsatisfiable = |message: String| {
+                        let mut err =
+                            IronError::new(StringError(message), status::RangeNotSatisfiable);
+                        err.response
+                            .headers
+                            .set(ContentRange(ContentRangeSpec::Bytes {
+                                range: None,
+                                instance_length: Some(metadata.len()),
+                            }));
+                        err
+                    };
+                    // [Reference]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/If-Range
+                    // Of the identity: the ranges are never compressed
+                    if !conditional::if_range(&req.headers, etag.as_ref(), modified.sec) {
+                        range = None;
+                    }
+
+                    let selection = match range {
+                        Some(range) => range::select(range, metadata.len()),
+                        None => RangeSelection::Full,
+                    };
+                    match selection {
+                        RangeSelection::Partial { offset, length } => {
+                            let mut file = fs::File::open(path).map_err(error_io2iron)?;
+                            file.seek(SeekFrom::Start(offset)).map_err(error_io2iron)?;
+                            let take = file.take(length);
+
+                            resp.headers.set(ContentLength(length));
+                            resp.headers.set(ContentRange(ContentRangeSpec::Bytes {
+                                range: Some((offset, offset + length - 1)),
+                                instance_length: Some(metadata.len()),
+                            }));
+                            resp.body = Some(Box::new(Box::new(take) as Box<dyn Read + Send>));
+                            resp.set_mut(status::PartialContent);
+                        }
+                        RangeSelection::Unsatisfiable => {
+                            return Err(unsatisfiable(format!(
+                                "No range within the {} bytes",
+                                metadata.len()
+                            )));
+                        }
+                        RangeSelection::Full => {
+                            resp.headers.set(ContentLength(metadata.len()));
+                            let file = fs::File::open(path).map_err(error_io2iron)?;
+                            resp.body = Some(Box::new(file));
+                        }
+                    }
+                } else {
+                    resp.headers.set(ContentLength(metadata.len()));
+                    let file = fs::File::open(path).map_err(error_io2iron)?;
+                    resp.body = Some(Box::new(file));
+                }
+            }
+            _ => {
+                return Ok(self.method_not_allowed(Some(false)));
+            }
+        }
+
+        if negotiable {
+            middlewares::set_vary(&mut resp);
+        }
+        let compressible = negotiable && resp.status != Some(status::PartialContent);
+        let encoding = middlewares::negotiate(req, compressible)
+            .map_err(|e| IronError::new(e, status::NotAcceptable))?;
+        // HEAD: the length of the encoded body is unknown, the identity one is sent instead
+        if let Some(encoding) = encoding.filter(|_| req.method != Method::Head) {
+            resp.headers.set(ContentEncoding(vec![encoding]));
+        }
+
+        if self.live.cache() {
+            resp.headers.set(cache_control);
+            resp.headers.set(LastModified(HttpDate(time::at(modified))));
+            if let Some(etag) = etag {
+                resp.headers.set(ETag(etag));
+            }
+        }
+        if let Some(ref webhook) = self.webhook {
+            if webhook.downloads && req.method == Method::Get {
+                let size = resp
+                    .headers
+                    .get::<ContentLength>()
+                    .map(|len| len.0)
+                    .unwrap_or_else(|| metadata.len());
+                webhook.notify(
+                    "download",
+                    &root_relative_path(self.root_for(req), path),
+                    size,
+                    req.remote_addr.ip(),
+                );
+            }
+        }
+        Ok(resp)
+    }
+}
@@ -0,0 +1,193 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::net::{NetworkListener, NetworkStream};
+
+/// The size limits of the request heads (`--max-url-length`, `--max-header-size`), checked
+/// before hyper parses them
+#[derive(Clone, Copy)]
+pub struct HeadLimits {
+    /// Of the request line, `GET <url> HTTP/1.1`
+    pub request_line: usize,
+    /// Of the header fields together
+    pub headers: usize,
+}
+
+/// Answers 414 to the requests with a too long request line, 431 to those with too large
+/// headers, and closes their connection. So is `OPTIONS *` answered (with the `Allow` of the
+/// whole server), Iron can't route it
+#[derive(Clone)]
+pub struct HeadLimitListener<L> {
+    inner: L,
+    limits: HeadLimits,
+    server_allow: Arc<String>,
+}
+
+impl<L> HeadLimitListener<L> {
+    pub fn new(inner: L, limits: HeadLimits, server_allow: Arc<String>) -> HeadLimitListener<L> {
+        HeadLimitListener {
+            inner,
+            limits,
+            server_allow,
+        }
+    }
+}
+
+impl<L: NetworkListener> NetworkListener for HeadLimitListener<L> {
+    type Stream = HeadLimitStream<L::Stream>;
+
+    fn accept(&mut self) -> hyper::Result<Self::Stream> {
+        Ok(HeadLimitStream {
+            inner: self.inner.accept()?,
+            limits: self.limits,
+            server_allow: self.server_allow.clone(),
+            head: Arc::new(Mutex::new(Head::default())),
+        })
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_write_timeout(dur)
+    }
+}
+
+// The head being read, shared by the reading and the writing clones of the stream
+#[derive(Default)]
+struct Head {
+    done: bool,
+    request_line: usize,
+    in_headers: bool,
+    headers: usize,
+    // Without the line endings, an empty one ends the head
+    line: usize,
+    // Enough of the request line to know an `OPTIONS *`
+    start: Vec<u8>,
+}
+
+const OPTIONS_STAR: &[u8] = b"OPTIONS * ";
+// Its answer, the others are errors
+const OPTIONS_STATUS: &[u8] = b"200 OK";
+
+impl Head {
+    /// The status of the answer, if the request isn't left to hyper
+    fn scan(&mut self, bytes: &[u8], limits: &HeadLimits) -> Result<(), &'static [u8]> {
+        for &byte in bytes {
+            if self.done {
+                break;
+            }
+            if self.in_headers {
+                self.headers += 1;
+            } else {
+                self.request_line += 1;
+                if !matches!(byte, b'\r' | b'\n') && self.start.len() < OPTIONS_STAR.len() {
+                    self.start.push(byte);
+                }
+            }
+            match byte {
+                b'\n' if self.in_headers && self.line == 0 => {
+                    self.done = true;
+                    if self.start == OPTIONS_STAR {
+                        return Err(OPTIONS_STATUS);
+                    }
+                }
+                // Empty lines before the request line are ignored, like httparse does
+                b'\n' if !self.in_headers && self.line > 0 => {
+                    self.in_headers = true;
+                    self.line = 0;
+                }
+                b'\n' => self.line = 0,
+                b'\r' => {}
+                _ => self.line += 1,
+            }
+            if self.request_line > limits.request_line {
+                return Err(b"414 URI Too Long");
+            }
+            if self.headers > limits.headers {
+                return Err(b"431 Request Header Fields Too Large");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct HeadLimitStream<S> {
+    inner: S,
+    limits: HeadLimits,
+    server_allow: Arc<String>,
+    head: Arc<Mutex<Head>>,
+}
+
+impl<S: NetworkStream> HeadLimitStream<S> {
+    fn reject(&mut self, status: &[u8]) -> io::Error {
+        let mut response = b"HTTP/1.1 ".to_vec();
+        response.extend_from_slice(status);
+        if status == OPTIONS_STATUS {
+            response.extend_from_slice(format!("\r\nAllow: {}", self.server_allow).as_bytes());
+        }
+        response.extend_from_slice(
+            b"\r\nContent-Type: text/plain\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let _ = self
+            .inner
+            .write_all(&response)
+            .and_then(|_| self.inner.flush());
+        let _ = self.inner.close(Shutdown::Both);
+        io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "Request answered before hyper",
+        )
+    }
+}
+
+impl<S: NetworkStream> Read for HeadLimitStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let scanned = self.head.lock().unwrap().scan(&buf[..read], &self.limits);
+        match scanned {
+            Ok(()) => Ok(read),
+            Err(status) => Err(self.reject(status)),
+        }
+    }
+}
+
+impl<S: Write> Write for HeadLimitStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A new request follows the response, not the `100 Continue` before the body
+        if !buf.starts_with(b"HTTP/1.1 100 ") {
+            *self.head.lock().unwrap() = Head::default();
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: NetworkStream> NetworkStream for HeadLimitStream<S> {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.inner.close(how)
+    }
+}
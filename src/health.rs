@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use clap::crate_version;
+use iron::headers::{CacheControl, CacheDirective, ContentType};
+use iron::method;
+use iron::status;
+use iron::{Request, Response};
+use serde_json::json;
+
+/// Answers probes of load balancers and uptime monitors, ahead of auth and logging
+pub struct HealthCheck {
+    // Without the leading '/'
+    path: String,
+    started: Instant,
+}
+
+impl HealthCheck {
+    pub fn new(path: &str) -> HealthCheck {
+        HealthCheck {
+            path: path.trim_start_matches('/').to_owned(),
+            started: Instant::now(),
+        }
+    }
+
+    /// The health response if `req` is a probe
+    pub fn probe(&self, req: &Request) -> Option<Response> {
+        if !(req.method == method::Get || req.method == method::Head)
+            || req.url.path().join("/") != self.path
+        {
+            return None;
+        }
+        let body = json!({
+            "status": "ok",
+            "version": crate_version!(),
+            "uptime": self.started.elapsed().as_secs(),
+        });
+        let mut resp = Response::with((status::Ok, body.to_string()));
+        resp.headers.set(ContentType::json());
+        resp.headers
+            .set(CacheControl(vec![CacheDirective::NoStore]));
+        Some(resp)
+    }
+}
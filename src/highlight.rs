@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use htmlescape::encode_minimal;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::util::StringError;
+
+/// Larger files are sent as they are
+pub const MAX_HIGHLIGHT_SIZE: u64 = 1024 * 1024;
+const THEME_NAME: &str = "InspiredGitHub";
+/// `Content-Security-Policy` of the pages: the highlighted spans have `style` attributes, and
+/// no scripts run
+pub const PAGE_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; base-uri 'none'; \
+                            form-action 'none'; frame-ancestors 'self'";
+
+/// Renders source files as HTML pages with syntax highlighting (`?view=1`)
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Highlighter {
+        let mut themes = ThemeSet::load_defaults();
+        Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes.themes.remove(THEME_NAME).unwrap(),
+        }
+    }
+
+    /// By extension (or file name like `Makefile`), then by the first line (shebang)
+    fn find_syntax(&self, path: &Path, content: &str) -> &SyntaxReference {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntaxes.find_syntax_by_extension(ext))
+            .or_else(|| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| self.syntaxes.find_syntax_by_extension(name))
+            })
+            .or_else(|| self.syntaxes.find_syntax_by_first_line(content))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+
+    /// The whole page, `raw_link` points to the file itself
+    pub fn render(
+        &self,
+        path: &Path,
+        content: &str,
+        raw_link: &str,
+    ) -> Result<String, StringError> {
+        let syntax = self.find_syntax(path, content);
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut rows = Vec::new();
+        for (index, line) in LinesWithEndings::from(content).enumerate() {
+            let regions = highlighter
+                .highlight_line(line, &self.syntaxes)
+                .map_err(|e| StringError(e.to_string()))?;
+            // Rows break the lines already
+            let regions = regions
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches(['\r', '\n'])))
+                .collect::<Vec<_>>();
+            let html = styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                .map_err(|e| StringError(e.to_string()))?;
+            rows.push(format!(
+                r##"<tr><td class="ln" id="L{n}"><a href="#L{n}">{n}</a></td><td class="code">{html}</td></tr>"##,
+                n = index + 1,
+                html = html
+            ));
+        }
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let background = self.theme.settings.background.unwrap_or(Color::WHITE);
+        Ok(format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width,initial-scale=1.0"/>
+  <title>{filename}</title>
+  <style>
+    body {{ margin:0; font-family:sans-serif; }}
+    .header {{ padding:0.5em 1em; border-bottom:1px solid #DDD; }}
+    .header a {{ margin-left:1em; text-decoration:none; }}
+    table {{ border-collapse:collapse; background:rgb({r},{g},{b}); width:100%; }}
+    td {{ padding:0 0.5em; vertical-align:top; font-family:monospace; white-space:pre; }}
+    td.ln {{ text-align:right; color:#999; user-select:none; border-right:1px solid #DDD; width:1%; }}
+    td.ln a {{ color:inherit; text-decoration:none; }}
+    tr:target {{ background:#FFFBDD; }}
+  </style>
+</head>
+<body>
+  <div class="header"><strong>{filename}</strong> <small>({syntax})</small><a href="{raw}">Raw</a><a href="{raw}?download">Download</a></div>
+  <table>
+{rows}
+  </table>
+</body>
+</html>
+"#,
+            filename = encode_minimal(&filename),
+            syntax = encode_minimal(&syntax.name),
+            raw = raw_link,
+            r = background.r,
+            g = background.g,
+            b = background.b,
+            rows = rows.join("\n")
+        ))
+    }
+}
@@ -0,0 +1,74 @@
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use iron::{AfterMiddleware, BeforeMiddleware, Chain, IronError, IronResult, Request, Response};
+
+/// Custom behavior of the programs embedding the server (auth backends, logging sinks...),
+/// registered by `SimpleHttpServer::hook()`. The methods do nothing by default
+pub trait Hook: Send + Sync {
+    /// Before the file server, once the request is authenticated. An `Err` is answered instead
+    fn on_request(&self, _req: &mut Request) -> IronResult<()> {
+        Ok(())
+    }
+
+    /// The response (the error ones too) before compression and logging
+    fn on_response(&self, _req: &mut Request, resp: Response) -> IronResult<Response> {
+        Ok(resp)
+    }
+
+    /// A file saved by an upload (form or S3 `PUT`)
+    fn on_upload(&self, _path: &Path, _size: u64, _client_ip: IpAddr) {}
+}
+
+/// The registered hooks, in order
+#[derive(Clone, Default)]
+pub struct Hooks(Vec<Arc<dyn Hook>>);
+
+impl Hooks {
+    pub fn push(&mut self, hook: Arc<dyn Hook>) {
+        self.0.push(hook);
+    }
+
+    pub fn upload(&self, path: &Path, size: u64, client_ip: IpAddr) {
+        for hook in &self.0 {
+            hook.on_upload(path, size, client_ip);
+        }
+    }
+
+    /// Registers `on_request` after the authentication and `on_response` before the other
+    /// after middlewares: to be called once the before middlewares are linked, before the
+    /// after ones
+    pub fn link(&self, chain: &mut Chain) {
+        if !self.0.is_empty() {
+            chain.link_before(self.clone());
+            chain.link_after(self.clone());
+        }
+    }
+}
+
+impl BeforeMiddleware for Hooks {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        for hook in &self.0 {
+            hook.on_request(req)?;
+        }
+        Ok(())
+    }
+}
+
+impl AfterMiddleware for Hooks {
+    fn after(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        for hook in &self.0 {
+            resp = hook.on_response(req, resp)?;
+        }
+        Ok(resp)
+    }
+
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        // Still an error for the logger
+        for hook in &self.0 {
+            err.response = hook.on_response(req, err.response)?;
+        }
+        Err(err)
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+use crate::util::StringError;
+
+/// Paths hidden from listings and answered with 404 (`--ignore`, `--gitignore`)
+pub struct IgnoreRules {
+    // Matched against paths relative to the served root
+    patterns: Gitignore,
+    gitignore: bool,
+    // directory => (mtime of its .gitignore, parsed rules)
+    gitignore_cache: Mutex<HashMap<PathBuf, (SystemTime, Arc<Gitignore>)>>,
+}
+
+impl IgnoreRules {
+    pub fn new(patterns: &[String], gitignore: bool) -> Result<IgnoreRules, StringError> {
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern)
+                .map_err(|e| StringError(format!("Invalid ignore pattern {}: {}", pattern, e)))?;
+        }
+        let patterns = builder
+            .build()
+            .map_err(|e| StringError(format!("Invalid ignore patterns: {}", e)))?;
+        Ok(IgnoreRules {
+            patterns,
+            gitignore,
+            gitignore_cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// `path` must be inside the `root` directory it's served from
+    pub fn is_ignored(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) if relative != Path::new("") => relative,
+            _ => return false,
+        };
+        if self
+            .patterns
+            .matched_path_or_any_parents(relative, is_dir)
+            .is_ignore()
+        {
+            return true;
+        }
+        if self.gitignore {
+            if relative.components().any(|c| c.as_os_str() == ".git") {
+                return true;
+            }
+            // The deepest .gitignore which has an opinion wins, like git does
+            let mut dir = path.parent();
+            while let Some(current) = dir {
+                if let Some(rules) = self.load_gitignore(current) {
+                    match rules.matched_path_or_any_parents(path, is_dir) {
+                        Match::Ignore(_) => return true,
+                        Match::Whitelist(_) => return false,
+                        Match::None => {}
+                    }
+                }
+                if current == root {
+                    break;
+                }
+                dir = current.parent();
+            }
+        }
+        false
+    }
+
+    fn load_gitignore(&self, dir: &Path) -> Option<Arc<Gitignore>> {
+        let path = dir.join(".gitignore");
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let mut cache = self.gitignore_cache.lock().unwrap();
+        if let Some((cached_modified, rules)) = cache.get(dir) {
+            if *cached_modified == modified {
+                return Some(rules.clone());
+            }
+        }
+        let (rules, _) = Gitignore::new(&path);
+        let rules = Arc::new(rules);
+        cache.insert(dir.to_path_buf(), (modified, rules.clone()));
+        Some(rules)
+    }
+}
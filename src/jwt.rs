@@ -0,0 +1,335 @@
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use hyper::client::Client;
+use hyper::net::HttpsConnector;
+use hyper::Url;
+use hyper_native_tls::NativeTlsClient;
+use serde_json::Value;
+use sha2::{Sha256, Sha384, Sha512};
+
+use crate::middlewares::Credential;
+use crate::util::StringError;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// The keys are fetched again after this, or for an unknown `kid` (once a minute at most)
+const JWKS_MAX_AGE: Duration = Duration::from_secs(3600);
+const JWKS_MIN_AGE: Duration = Duration::from_secs(60);
+// Of the clocks of the issuer and this server, for `exp` and `nbf`
+const LEEWAY: u64 = 60;
+
+/// Validates the JWTs of `--auth-jwt-secret` (HS256/384/512) or `--auth-jwt-jwks-url`
+/// (RS256/384/512, ES256/384): the signature, `exp` (required), `nbf`, and `aud`/`iss` when set
+pub struct JwtValidator {
+    secret: Option<Vec<u8>>,
+    jwks: Option<Jwks>,
+    /// `--auth-jwt-audience`, one of the `aud` claim
+    pub audience: Option<String>,
+    /// `--auth-jwt-issuer`, the `iss` claim
+    pub issuer: Option<String>,
+    /// The permissions of the valid tokens: downloads, uploads and deletes
+    pub credential: Credential,
+}
+
+struct Jwks {
+    url: Url,
+    client: Client,
+    // The keys and when they were fetched
+    keys: Mutex<Option<(Instant, Vec<Value>)>>,
+}
+
+impl JwtValidator {
+    pub fn new(secret: Option<&str>, jwks_url: Option<&str>) -> Result<JwtValidator, StringError> {
+        let jwks = match jwks_url {
+            Some(url) => {
+                let url =
+                    Url::parse(url).map_err(|e| StringError(format!("Invalid JWKS url: {}", e)))?;
+                let tls = NativeTlsClient::new().map_err(|e| StringError(e.to_string()))?;
+                let mut client = Client::with_connector(HttpsConnector::new(tls));
+                client.set_read_timeout(Some(TIMEOUT));
+                client.set_write_timeout(Some(TIMEOUT));
+                Some(Jwks {
+                    url,
+                    client,
+                    keys: Mutex::new(None),
+                })
+            }
+            None => None,
+        };
+        Ok(JwtValidator {
+            secret: secret.map(|secret| secret.as_bytes().to_vec()),
+            jwks,
+            audience: None,
+            issuer: None,
+            credential: Credential {
+                username: "jwt".to_owned(),
+                password: String::new(),
+                read: true,
+                write: true,
+                admin: false,
+            },
+        })
+    }
+
+    /// The claims of a valid `token`, the reason otherwise
+    pub fn validate(&self, token: &str) -> Result<Value, StringError> {
+        let invalid = |reason: &str| StringError(reason.to_owned());
+        let mut parts = token.split('.');
+        let (header, payload, signature) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(payload), Some(signature)) if parts.next().is_none() => {
+                (header, payload, signature)
+            }
+            _ => return Err(invalid("not a JWT")),
+        };
+        let decode_json = |part: &str| {
+            base64url_decode(part)
+                .and_then(|json| serde_json::from_slice::<Value>(&json).ok())
+                .filter(Value::is_object)
+                .ok_or_else(|| invalid("malformed JWT"))
+        };
+        let header = decode_json(header)?;
+        let claims = decode_json(payload)?;
+        let signature = base64url_decode(signature).ok_or_else(|| invalid("malformed JWT"))?;
+        let message = &token[..token.rfind('.').unwrap()];
+
+        let alg = header["alg"].as_str().unwrap_or("");
+        let verified = match (alg, &self.secret) {
+            ("HS256", Some(secret)) => verify_hmac::<Hmac<Sha256>>(secret, message, &signature),
+            ("HS384", Some(secret)) => verify_hmac::<Hmac<Sha384>>(secret, message, &signature),
+            ("HS512", Some(secret)) => verify_hmac::<Hmac<Sha512>>(secret, message, &signature),
+            (_, _) if alg.starts_with("RS") || alg.starts_with("ES") => match self.jwks {
+                Some(ref jwks) => {
+                    let key = jwks.key(header["kid"].as_str(), alg)?;
+                    imp::verify(&key, alg, message.as_bytes(), &signature)?
+                }
+                None => false,
+            },
+            _ => false,
+        };
+        if !verified {
+            return Err(invalid("invalid signature"));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        match claims["exp"].as_f64() {
+            Some(exp) if exp as u64 + LEEWAY > now => {}
+            Some(_) => return Err(invalid("expired")),
+            None => return Err(invalid("no exp claim")),
+        }
+        if claims["nbf"]
+            .as_f64()
+            .is_some_and(|nbf| nbf as u64 > now + LEEWAY)
+        {
+            return Err(invalid("not valid yet"));
+        }
+        if let Some(ref audience) = self.audience {
+            let matches = match claims["aud"] {
+                Value::String(ref aud) => aud == audience,
+                Value::Array(ref auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(invalid("wrong audience"));
+            }
+        }
+        if let Some(ref issuer) = self.issuer {
+            if claims["iss"].as_str() != Some(issuer) {
+                return Err(invalid("wrong issuer"));
+            }
+        }
+        Ok(claims)
+    }
+}
+
+impl Jwks {
+    // The key of `kid` (the only one of `alg` without), fetched again if unknown
+    fn key(&self, kid: Option<&str>, alg: &str) -> Result<Value, StringError> {
+        let find = |keys: &[Value]| {
+            let mut candidates = keys.iter().filter(|key| {
+                key["alg"].as_str().is_none_or(|key_alg| key_alg == alg)
+                    && key["use"].as_str().is_none_or(|key_use| key_use == "sig")
+                    && key["kty"].as_str() == Some(if alg.starts_with("RS") { "RSA" } else { "EC" })
+            });
+            match kid {
+                Some(kid) => candidates.find(|key| key["kid"].as_str() == Some(kid)),
+                None => candidates.next().filter(|_| candidates.next().is_none()),
+            }
+            .cloned()
+        };
+        let mut cached = self.keys.lock().unwrap();
+        if let Some((fetched, ref keys)) = *cached {
+            let age = fetched.elapsed();
+            if age < JWKS_MAX_AGE {
+                if let Some(key) = find(keys) {
+                    return Ok(key);
+                }
+            }
+            if age < JWKS_MIN_AGE {
+                return Err(StringError("unknown key".to_owned()));
+            }
+        }
+        let keys = match self.fetch() {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("JWKS {} failed: {}", self.url, e);
+                // The previous keys, not fetched again before a minute
+                cached.take().map(|(_, keys)| keys).unwrap_or_default()
+            }
+        };
+        let key = find(&keys);
+        *cached = Some((Instant::now(), keys));
+        key.ok_or_else(|| StringError("unknown key".to_owned()))
+    }
+
+    fn fetch(&self) -> Result<Vec<Value>, StringError> {
+        let mut resp = self
+            .client
+            .get(self.url.clone())
+            .send()
+            .map_err(|e| StringError(e.to_string()))?;
+        let mut body = String::new();
+        resp.read_to_string(&mut body)
+            .map_err(|e| StringError(e.to_string()))?;
+        if !resp.status.is_success() {
+            return Err(StringError(format!("responded {}", resp.status)));
+        }
+        match serde_json::from_str::<Value>(&body) {
+            Ok(Value::Object(mut jwks)) => match jwks.remove("keys") {
+                Some(Value::Array(keys)) => Ok(keys),
+                _ => Err(StringError("no keys".to_owned())),
+            },
+            Ok(_) => Err(StringError("not a JWK set".to_owned())),
+            Err(e) => Err(StringError(e.to_string())),
+        }
+    }
+}
+
+fn verify_hmac<M: Mac + hmac::digest::KeyInit>(
+    secret: &[u8],
+    message: &str,
+    signature: &[u8],
+) -> bool {
+    match <M as Mac>::new_from_slice(secret) {
+        Ok(mut mac) => {
+            mac.update(message.as_bytes());
+            mac.verify_slice(signature).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+// Unpadded base64url (RFC 7515 2)
+fn base64url_decode(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    // A single character left is no byte
+    if bits >= 6 {
+        return None;
+    }
+    Some(bytes)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+mod imp {
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::ecdsa::EcdsaSig;
+    use openssl::hash::{hash, MessageDigest};
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::sign::Verifier;
+    use serde_json::Value;
+
+    use super::base64url_decode;
+    use crate::util::StringError;
+
+    fn map_err(e: openssl::error::ErrorStack) -> StringError {
+        StringError(e.to_string())
+    }
+
+    /// The `signature` of `message` by `key`, a JWK of `alg`
+    pub fn verify(
+        key: &Value,
+        alg: &str,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, StringError> {
+        let number = |name: &str| {
+            key[name]
+                .as_str()
+                .and_then(base64url_decode)
+                .ok_or_else(|| StringError(format!("invalid key: no {}", name)))
+                .and_then(|bytes| BigNum::from_slice(&bytes).map_err(map_err))
+        };
+        let digest = match &alg[2..] {
+            "256" => MessageDigest::sha256(),
+            "384" => MessageDigest::sha384(),
+            "512" => MessageDigest::sha512(),
+            _ => return Ok(false),
+        };
+        if alg.starts_with("RS") {
+            let rsa = Rsa::from_public_components(number("n")?, number("e")?).map_err(map_err)?;
+            let pkey = PKey::from_rsa(rsa).map_err(map_err)?;
+            let mut verifier = Verifier::new(digest, &pkey).map_err(map_err)?;
+            verifier.update(message).map_err(map_err)?;
+            return Ok(verifier.verify(signature).unwrap_or(false));
+        }
+        let (nid, size) = match (alg, key["crv"].as_str()) {
+            ("ES256", Some("P-256")) => (Nid::X9_62_PRIME256V1, 32),
+            ("ES384", Some("P-384")) => (Nid::SECP384R1, 48),
+            _ => return Ok(false),
+        };
+        if signature.len() != 2 * size {
+            return Ok(false);
+        }
+        let group = EcGroup::from_curve_name(nid).map_err(map_err)?;
+        let (x, y) = (number("x")?, number("y")?);
+        let ec_key = EcKey::from_public_key_affine_coordinates(&group, &x, &y).map_err(map_err)?;
+        let r = BigNum::from_slice(&signature[..size]).map_err(map_err)?;
+        let s = BigNum::from_slice(&signature[size..]).map_err(map_err)?;
+        let signature = EcdsaSig::from_private_components(r, s).map_err(map_err)?;
+        let digest = hash(digest, message).map_err(map_err)?;
+        Ok(signature.verify(&digest, &ec_key).unwrap_or(false))
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "ios"))]
+mod imp {
+    use serde_json::Value;
+
+    use crate::util::StringError;
+
+    pub fn verify(
+        _key: &Value,
+        _alg: &str,
+        _message: &[u8],
+        _signature: &[u8],
+    ) -> Result<bool, StringError> {
+        Err(StringError(
+            "RSA and EC keys are not supported on this platform".to_owned(),
+        ))
+    }
+}
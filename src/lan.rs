@@ -0,0 +1,46 @@
+use std::net::{IpAddr, SocketAddr};
+
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+
+/// URLs for other machines on the network, the wildcard addresses are expanded to the
+/// addresses of all the interfaces
+pub fn lan_urls(scheme: &str, addrs: &[SocketAddr]) -> Vec<String> {
+    let interfaces = if_addrs::get_if_addrs().unwrap_or_default();
+    let mut urls = Vec::new();
+    for addr in addrs {
+        let ips = if addr.ip().is_unspecified() {
+            interfaces
+                .iter()
+                // Link-local addresses would need the zone of the client's interface
+                .filter(|iface| !iface.is_loopback() && !iface.is_link_local())
+                // `::` usually accepts IPv4 too, `0.0.0.0` doesn't accept IPv6
+                .filter(|iface| addr.is_ipv6() || iface.ip().is_ipv4())
+                .map(|iface| iface.ip())
+                .collect::<Vec<IpAddr>>()
+        } else if addr.ip().is_loopback() {
+            Vec::new()
+        } else {
+            vec![addr.ip()]
+        };
+        for ip in ips {
+            let url = format!("{}://{}/", scheme, SocketAddr::new(ip, addr.port()));
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// QR code of `text` drawn with unicode blocks (light on dark, for usual terminals)
+pub fn qr_code(text: &str) -> String {
+    match QrCode::new(text) {
+        Ok(code) => code
+            .render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build(),
+        Err(e) => format!("QR code failed: {}", e),
+    }
+}
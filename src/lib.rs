@@ -0,0 +1,68 @@
+//! A simple HTTP(s) file server: directory listings, uploads, ranges, compression, basic auth.
+//!
+//! The `simple-http-server` binary is built on it, [`SimpleHttpServer`] embeds the same server
+//! in other programs:
+//!
+//! ```no_run
+//! let listening = simple_http_server::SimpleHttpServer::new(".")
+//!     .index(true)
+//!     .serve("127.0.0.1:0")
+//!     .unwrap();
+//! println!("http://{}/", listening.socket);
+//! ```
+
+mod access;
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+mod acme;
+mod archive;
+mod atomic_file;
+mod audit;
+mod cgi;
+mod checksum;
+#[doc(hidden)]
+pub mod cli;
+mod color;
+mod conditional;
+mod config_file;
+mod connection_limit;
+mod daemon;
+mod expiry;
+mod fastcgi;
+mod handler;
+mod head_limit;
+mod health;
+mod highlight;
+mod hooks;
+mod ignore_rules;
+mod jwt;
+mod lan;
+mod listener;
+mod listing;
+mod listing_cache;
+mod log_file;
+mod man;
+mod manifest;
+mod mdns;
+mod middlewares;
+mod one_time;
+mod port_mapping;
+mod privileges;
+mod protect;
+mod range;
+mod s3;
+mod server;
+mod stats;
+mod syslog;
+mod thumbnail;
+mod tls;
+mod trash;
+mod upload_command;
+mod upload_filter;
+mod upload_limit;
+mod util;
+mod webhook;
+
+pub use hooks::Hook;
+pub use iron;
+pub use server::SimpleHttpServer;
+pub use util::StringError;
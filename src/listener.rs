@@ -0,0 +1,202 @@
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use hyper::net::{HttpListener, HttpStream, NetworkListener, NetworkStream, SslServer};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::connection_limit;
+use crate::middlewares;
+
+const ACCEPT_RETRY: Duration = Duration::from_millis(10);
+
+/// The options of the listening sockets
+#[derive(Clone, Copy)]
+pub struct SocketOptions {
+    /// `--backlog`
+    pub backlog: i32,
+    /// `--so-reuseport`: other processes may listen on the same port, the system balances
+    /// the connections between them
+    pub reuse_port: bool,
+    /// `--tcp-nodelay`: disables Nagle's algorithm on the connections
+    pub nodelay: bool,
+}
+
+/// Binds like `TcpListener::bind()`, with the given options
+pub fn bind(addr: &SocketAddr, opts: &SocketOptions) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(*addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    // As `std` does, a restarted server doesn't wait for the old connections to time out
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    if opts.reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    socket.bind(&(*addr).into())?;
+    socket.listen(opts.backlog)?;
+    Ok(socket.into())
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+)))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--so-reuseport is not supported on this platform",
+    ))
+}
+
+/// The connections waiting for a free worker thread (`--queue`): accepted by a thread of
+/// their own, those over the size of the queue get 503 with `Retry-After`
+#[derive(Clone)]
+pub struct QueuedListener {
+    queue: Queue,
+    local_addr: SocketAddr,
+    nodelay: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Queue {
+    // The workers accept by themselves, the system backlog is the only queue
+    Unbounded(HttpListener),
+    Bounded(Arc<Mutex<Receiver<HttpStream>>>),
+}
+
+impl QueuedListener {
+    /// `reject` answers the rejected connections, otherwise they are only closed (TLS: the
+    /// handshake is made by the workers)
+    pub fn new(
+        listener: TcpListener,
+        size: Option<usize>,
+        reject: bool,
+        nodelay: bool,
+    ) -> io::Result<QueuedListener> {
+        let local_addr = listener.local_addr()?;
+        let mut listener = HttpListener::from(listener);
+        let queue = match size {
+            None => Queue::Unbounded(listener),
+            Some(size) => {
+                let (sender, receiver) = mpsc::sync_channel(size);
+                thread::spawn(move || loop {
+                    let stream = match listener.accept() {
+                        Ok(stream) => stream,
+                        Err(_) => {
+                            // Out of file descriptors most likely, let the workers close some
+                            thread::sleep(ACCEPT_RETRY);
+                            continue;
+                        }
+                    };
+                    match sender.try_send(stream) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(mut stream)) => {
+                            if reject {
+                                connection_limit::reject(&mut stream, "Server busy");
+                            } else {
+                                let _ = stream.close(Shutdown::Both);
+                            }
+                        }
+                        Err(TrySendError::Disconnected(_)) => return,
+                    }
+                });
+                Queue::Bounded(Arc::new(Mutex::new(receiver)))
+            }
+        };
+        Ok(QueuedListener {
+            queue,
+            local_addr,
+            nodelay,
+            read_timeout: None,
+            write_timeout: None,
+        })
+    }
+}
+
+impl NetworkListener for QueuedListener {
+    type Stream = HttpStream;
+
+    fn accept(&mut self) -> hyper::Result<HttpStream> {
+        let stream = match self.queue {
+            Queue::Unbounded(ref mut listener) => listener.accept()?,
+            Queue::Bounded(ref receiver) => receiver.lock().unwrap().recv().map_err(|_| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "Accepting thread stopped")
+            })?,
+        };
+        middlewares::connection_accepted();
+        if self.nodelay {
+            stream.0.set_nodelay(true)?;
+        }
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        Ok(stream)
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) {
+        self.read_timeout = dur;
+    }
+
+    fn set_write_timeout(&mut self, dur: Option<Duration>) {
+        self.write_timeout = dur;
+    }
+}
+
+/// `HttpsListener` over any listener
+#[derive(Clone)]
+pub struct TlsListener<L, S> {
+    inner: L,
+    ssl: S,
+}
+
+impl<L, S> TlsListener<L, S> {
+    pub fn new(inner: L, ssl: S) -> TlsListener<L, S> {
+        TlsListener { inner, ssl }
+    }
+}
+
+impl<L, S> NetworkListener for TlsListener<L, S>
+where
+    L: NetworkListener,
+    S: SslServer<L::Stream> + Clone,
+{
+    type Stream = S::Stream;
+
+    fn accept(&mut self) -> hyper::Result<S::Stream> {
+        // In the worker thread, which the client certificate checks rely on
+        self.inner
+            .accept()
+            .and_then(|stream| self.ssl.wrap_server(stream))
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_write_timeout(dur)
+    }
+}
@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use iron::response::WriteBody;
+use pretty_bytes::converter::convert;
+
+use crate::atomic_file;
+use crate::thumbnail;
+
+/// An entry of a listed directory
+pub struct Entry {
+    /// Lossy display name, `raw_name` is used for links and paths
+    pub filename: String,
+    pub raw_name: OsString,
+    pub metadata: fs::Metadata,
+}
+
+impl Entry {
+    /// A file shown by the gallery view
+    pub fn is_image(&self) -> bool {
+        self.metadata.is_file() && thumbnail::is_image(Path::new(&self.filename))
+    }
+}
+
+/// Whether an entry is left out of the listing, by its path
+pub type Skip = Box<dyn Fn(&Path, &fs::Metadata) -> bool + Send>;
+
+/// The entries of a directory, read while iterated: those of `skip` (ignore rules, expired...)
+/// and the temporary files of the uploads are left out
+pub struct ListingEntries {
+    read_dir: fs::ReadDir,
+    skip: Skip,
+}
+
+impl ListingEntries {
+    pub fn new(dir: &Path, skip: Skip) -> io::Result<ListingEntries> {
+        Ok(ListingEntries {
+            read_dir: fs::read_dir(dir)?,
+            skip,
+        })
+    }
+}
+
+impl Iterator for ListingEntries {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        loop {
+            let entry = match self.read_dir.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => return Some(Err(e)),
+            };
+            if atomic_file::is_temp(&entry.file_name()) || (self.skip)(&entry.path(), &metadata) {
+                continue;
+            }
+            return Some(Ok(Entry {
+                filename: entry.file_name().to_string_lossy().to_string(),
+                raw_name: entry.file_name(),
+                metadata,
+            }));
+        }
+    }
+}
+
+/// By `name`, `modified` or `size` (the directories first)
+pub fn sort(entries: &mut [Entry], field: &str, reverse: bool) {
+    entries.sort_by(|a, b| {
+        let rv = match field {
+            "name" => a.filename.cmp(&b.filename),
+            "modified" => {
+                let a = a.metadata.modified().unwrap();
+                let b = b.metadata.modified().unwrap();
+                a.cmp(&b)
+            }
+            "size" => {
+                if a.metadata.is_dir() == b.metadata.is_dir()
+                    || a.metadata.is_file() == b.metadata.is_file()
+                {
+                    a.metadata.len().cmp(&b.metadata.len())
+                } else if a.metadata.is_dir() {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            }
+            _ => unreachable!(),
+        };
+        if reverse {
+            rv.reverse()
+        } else {
+            rv
+        }
+    });
+}
+
+/// The counts and total size of the footer, of all the pages
+#[derive(Default)]
+pub struct Summary {
+    dirs: usize,
+    files: usize,
+    size: u64,
+}
+
+impl Summary {
+    pub fn add(&mut self, metadata: &fs::Metadata) {
+        if metadata.is_dir() {
+            self.dirs += 1;
+        } else {
+            self.files += 1;
+            self.size += metadata.len();
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}, {} {}, {}",
+            self.dirs,
+            if self.dirs == 1 {
+                "directory"
+            } else {
+                "directories"
+            },
+            self.files,
+            if self.files == 1 { "file" } else { "files" },
+            convert(self.size as f64)
+        )
+    }
+}
+
+/// A listing written while its directory is read (`--stream-listings`), chunked: the rows of
+/// huge directories are never held in memory, only their entries when sorted
+pub struct StreamedListing {
+    /// The page up to the rows
+    pub head: String,
+    pub entries: Box<dyn Iterator<Item = Entry> + Send>,
+    pub row: Box<dyn Fn(&Entry) -> String + Send>,
+    /// Between the rows and the summary of the footer
+    pub middle: String,
+    /// The link to the gallery view, unknown before the rows: written after the summary once
+    /// one of the entries is an image
+    pub gallery_toggle: String,
+    pub tail: String,
+}
+
+impl WriteBody for StreamedListing {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        let mut res = BufWriter::new(res);
+        res.write_all(self.head.as_bytes())?;
+        let mut summary = Summary::default();
+        let mut has_images = false;
+        for entry in &mut self.entries {
+            summary.add(&entry.metadata);
+            has_images |= entry.is_image();
+            res.write_all((self.row)(&entry).as_bytes())?;
+            res.write_all(b"\n")?;
+        }
+        res.write_all(self.middle.as_bytes())?;
+        write!(res, "{}", summary)?;
+        if has_images {
+            res.write_all(self.gallery_toggle.as_bytes())?;
+        }
+        res.write_all(self.tail.as_bytes())?;
+        res.flush()
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use iron::IronResult;
+
+/// The part of a listing read from the directory, the rest (nonces, forms) is rendered for each
+/// request
+pub struct ListingRows {
+    pub sort_links: String,
+    pub rows: Vec<String>,
+    pub gallery_items: Vec<String>,
+    pub has_images: bool,
+    pub pager: String,
+    /// The counts and total size of the footer
+    pub summary: String,
+}
+
+/// The rendered listings (`--listing-cache`), by directory and query: rendered again once the
+/// directory or one of its entries changed (added, removed, renamed or rewritten), one of the
+/// access files of the directory changed, or the `--config` file reloaded
+pub struct ListingCache {
+    max_entries: usize,
+    // (directory, query) => (fingerprint of its entries, generation of the settings, last used,
+    // rows)
+    cache: Mutex<HashMap<(PathBuf, String), Cached>>,
+}
+
+struct Cached {
+    fingerprint: u64,
+    generation: usize,
+    used: Instant,
+    rows: Arc<ListingRows>,
+}
+
+impl ListingCache {
+    pub fn new(max_entries: usize) -> ListingCache {
+        ListingCache {
+            max_entries,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached rows of `dir`, or those of `render` kept for the next requests, also rendered
+    /// again when one of the `watched` files (the access files of `dir`) changed
+    pub fn get_or_render<F>(
+        &self,
+        dir: &Path,
+        watched: &[PathBuf],
+        query: String,
+        generation: usize,
+        render: F,
+    ) -> IronResult<Arc<ListingRows>>
+    where
+        F: FnOnce() -> IronResult<ListingRows>,
+    {
+        // Before reading the entries: a change meanwhile is seen by the next request
+        let fingerprint = match fingerprint(dir, watched) {
+            Ok(Some(fingerprint)) => fingerprint,
+            _ => return render().map(Arc::new),
+        };
+        let key = (dir.to_path_buf(), query);
+        if let Some(cached) = self.cache.lock().unwrap().get_mut(&key) {
+            if cached.fingerprint == fingerprint && cached.generation == generation {
+                cached.used = Instant::now();
+                return Ok(cached.rows.clone());
+            }
+        }
+        // Without the lock, large directories take a while
+        let rows = Arc::new(render()?);
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= self.max_entries && !cache.contains_key(&key) {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(
+            key,
+            Cached {
+                fingerprint,
+                generation,
+                used: Instant::now(),
+                rows: rows.clone(),
+            },
+        );
+        Ok(rows)
+    }
+}
+
+// The modification times and sizes of `dir`, its entries and the `watched` files, `None` while
+// one of them was modified during the current second: a change within the same second would
+// keep its modification time, on the file systems of one second precision
+fn fingerprint(dir: &Path, watched: &[PathBuf]) -> io::Result<Option<u64>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut hasher = DefaultHasher::new();
+    let add = |hasher: &mut DefaultHasher, metadata: &fs::Metadata| -> io::Result<bool> {
+        let modified = metadata.modified()?;
+        modified.hash(hasher);
+        metadata.len().hash(hasher);
+        let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(modified.as_secs() < now.as_secs())
+    };
+    let mut settled = add(&mut hasher, &fs::metadata(dir)?)?;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        entry.file_name().hash(&mut hasher);
+        if let Ok(metadata) = fs::metadata(entry.path()) {
+            settled &= add(&mut hasher, &metadata)?;
+        }
+    }
+    for path in watched {
+        path.hash(&mut hasher);
+        if let Ok(metadata) = fs::metadata(path) {
+            settled &= add(&mut hasher, &metadata)?;
+        }
+    }
+    Ok(Some(hasher.finish()).filter(|_| settled))
+}
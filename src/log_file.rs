@@ -0,0 +1,93 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::util::StringError;
+
+// Set by SIGUSR1, the file is reopened before the next line
+static REOPEN: AtomicBool = AtomicBool::new(false);
+
+/// Access log file (`--log-file`), rotated by size (`--log-rotate-size`) into `<path>.1`,
+/// `<path>.2`... or reopened on SIGUSR1 for logrotate
+pub struct LogFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    keep: usize,
+    // The file and its size
+    file: Mutex<(File, u64)>,
+}
+
+fn open(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+impl LogFile {
+    pub fn new(path: &Path, max_size: Option<u64>, keep: usize) -> Result<LogFile, StringError> {
+        let file = open(path)
+            .map_err(|e| StringError(format!("Open log file {} failed: {}", path.display(), e)))?;
+        #[cfg(unix)]
+        {
+            extern "C" fn on_sigusr1(_: libc::c_int) {
+                REOPEN.store(true, Ordering::Relaxed);
+            }
+            unsafe {
+                libc::signal(
+                    libc::SIGUSR1,
+                    on_sigusr1 as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            max_size,
+            keep,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Failures are printed, logging must not break the requests
+    pub fn write_line(&self, line: &str) {
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = self.write_locked(&mut file, line) {
+            eprintln!("  >> Write log file {} failed: {}", self.path.display(), e);
+        }
+    }
+
+    fn write_locked(&self, file: &mut (File, u64), line: &str) -> io::Result<()> {
+        if REOPEN.swap(false, Ordering::Relaxed) {
+            *file = open(&self.path)?;
+        }
+        let len = line.len() as u64 + 1;
+        if self
+            .max_size
+            .is_some_and(|max_size| file.1 > 0 && file.1 + len > max_size)
+        {
+            self.rotate()?;
+            *file = open(&self.path)?;
+        }
+        writeln!(file.0, "{}", line)?;
+        file.1 += len;
+        Ok(())
+    }
+
+    // <path>.1 is the most recent, <path>.<keep> is removed
+    fn rotate(&self) -> io::Result<()> {
+        let numbered = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", n));
+            PathBuf::from(path)
+        };
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_file(numbered(self.keep));
+        for n in (1..self.keep).rev() {
+            let _ = fs::rename(numbered(n), numbered(n + 1));
+        }
+        fs::rename(&self.path, numbered(1))
+    }
+}
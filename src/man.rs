@@ -0,0 +1,173 @@
+use std::fmt::Write;
+
+/// The man page of the whole command line (`--gen-man`), in roff
+pub fn render(app: &clap::App) -> String {
+    let name = env!("CARGO_PKG_NAME");
+    let mut page = String::new();
+    let _ = writeln!(
+        page,
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"",
+        name.to_uppercase(),
+        name,
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(page, ".SH NAME");
+    let _ = writeln!(
+        page,
+        "{} \\- {}",
+        name,
+        escape(env!("CARGO_PKG_DESCRIPTION"))
+    );
+
+    let _ = writeln!(page, ".SH SYNOPSIS");
+    let positionals = app
+        .p
+        .positionals
+        .values()
+        .map(|pos| format!("[\\fI{}\\fR]", escape(pos.b.name)))
+        .collect::<Vec<String>>();
+    let _ = writeln!(page, "\\fB{}\\fR [OPTIONS] {}", name, positionals.join(" "));
+    for subcommand in &app.p.subcommands {
+        let args = subcommand
+            .p
+            .positionals
+            .values()
+            .map(|pos| format!("\\fI{}\\fR", escape(pos.b.name)))
+            .collect::<Vec<String>>();
+        let _ = writeln!(page, ".br");
+        let _ = writeln!(
+            page,
+            "\\fB{} {}\\fR {}",
+            name,
+            escape(&subcommand.p.meta.name),
+            args.join(" ")
+        );
+    }
+
+    let _ = writeln!(page, ".SH ARGUMENTS");
+    for pos in app.p.positionals.values() {
+        let _ = writeln!(page, ".TP\n\\fI{}\\fR", escape(pos.b.name));
+        paragraph(&mut page, pos.b.help);
+        if let Some((env, _)) = pos.v.env {
+            let _ = writeln!(page, ".br\nEnvironment: {}", escape(&env.to_string_lossy()));
+        }
+    }
+
+    let _ = writeln!(page, ".SH OPTIONS");
+    // Sorted like `--help`
+    let mut options = Vec::new();
+    for flag in app.p.flags.iter() {
+        if flag.b.is_set(clap::ArgSettings::Hidden) {
+            continue;
+        }
+        let header = switches(flag.s.short, flag.s.long);
+        let mut lines = Vec::new();
+        if let Some(long) = flag
+            .s
+            .long
+            .filter(|long| !matches!(*long, "help" | "version"))
+        {
+            lines.push(format!(
+                "Environment: SHS_{}=1",
+                long.to_uppercase().replace('-', "_")
+            ));
+        }
+        options.push((
+            flag.s.long.unwrap_or(flag.b.name),
+            header,
+            flag.b.help,
+            lines,
+        ));
+    }
+    for opt in app.p.opts.iter() {
+        if opt.b.is_set(clap::ArgSettings::Hidden) {
+            continue;
+        }
+        let value = opt
+            .v
+            .val_names
+            .as_ref()
+            .and_then(|names| names.values().next().copied())
+            .unwrap_or(opt.b.name);
+        let header = format!(
+            "{} \\fI{}\\fR",
+            switches(opt.s.short, opt.s.long),
+            escape(value)
+        );
+        let mut lines = Vec::new();
+        if let Some(ref values) = opt.v.possible_vals {
+            lines.push(format!("Possible values: {}", values.join(", ")));
+        }
+        if let Some(default) = opt.v.default_val {
+            lines.push(format!("Default: {}", default.to_string_lossy()));
+        }
+        if let Some((env, _)) = opt.v.env {
+            lines.push(format!("Environment: {}", env.to_string_lossy()));
+        }
+        options.push((opt.s.long.unwrap_or(opt.b.name), header, opt.b.help, lines));
+    }
+    options.sort_by(|a, b| a.0.cmp(b.0));
+    for (_, header, help, lines) in options {
+        let _ = writeln!(page, ".TP\n{}", header);
+        paragraph(&mut page, help);
+        for line in lines {
+            let _ = writeln!(page, ".br\n{}", escape(&line));
+        }
+    }
+
+    if !app.p.subcommands.is_empty() {
+        let _ = writeln!(page, ".SH COMMANDS");
+        for subcommand in &app.p.subcommands {
+            let _ = writeln!(page, ".TP\n\\fB{}\\fR", escape(&subcommand.p.meta.name));
+            paragraph(&mut page, subcommand.p.meta.about);
+        }
+    }
+
+    let _ = writeln!(page, ".SH ENVIRONMENT");
+    paragraph(&mut page, app.p.meta.more_help);
+    let _ = writeln!(page, ".SH SIGNALS");
+    for (signal, effect) in [
+        (
+            "SIGHUP",
+            "Reload the --config file and the TLS certificates",
+        ),
+        ("SIGUSR1", "Reopen the --log-file, for logrotate"),
+        ("SIGTERM, SIGINT", "Exit, removing the --pid-file"),
+    ] {
+        let _ = writeln!(page, ".TP\n\\fB{}\\fR\n{}", signal, escape(effect));
+    }
+    page
+}
+
+fn switches(short: Option<char>, long: Option<&str>) -> String {
+    let mut names = Vec::new();
+    if let Some(short) = short {
+        names.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = long {
+        names.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    names.join(", ")
+}
+
+// The `Example:` lines of the help stay on their own lines
+fn paragraph(page: &mut String, text: Option<&str>) {
+    if let Some(text) = text {
+        let lines = text
+            .lines()
+            .map(|line| escape(line.trim()))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<String>>();
+        let _ = writeln!(page, "{}", lines.join("\n.br\n"));
+    }
+}
+
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    // A control line otherwise
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use serde_json::{json, Map, Value};
+
+/// Route of the directory tree API (`/__manifest?path=/photos&depth=2`)
+pub const MANIFEST_PATH: &str = "__manifest";
+pub const MAX_DEPTH: usize = 32;
+/// Larger trees are cut (`"truncated": true`)
+pub const MAX_ENTRIES: usize = 10_000;
+
+type HashFn<'a> = &'a dyn Fn(&Path) -> io::Result<String>;
+
+/// Walks a directory into a JSON tree, at most `depth` levels and `max_entries` entries
+pub struct Manifest<'a> {
+    pub depth: usize,
+    pub max_entries: usize,
+    /// Ignore rules
+    pub skip: &'a dyn Fn(&Path, bool) -> bool,
+    /// SHA-256 digests of the files (`hashes=1`)
+    pub hash: Option<HashFn<'a>>,
+    /// Only the entries modified after this time (`since=<mtime>`), for incremental mirroring
+    pub since: Option<u64>,
+}
+
+impl Manifest<'_> {
+    pub fn build(&self, dir: &Path, url_path: &str) -> io::Result<Value> {
+        let mut count = 0;
+        let mut truncated = false;
+        let tree = self.node(dir, Path::new(""), 0, &mut count, &mut truncated)?;
+        let tree = tree.unwrap_or(Value::Null);
+        Ok(json!({
+            "path": url_path,
+            "entries": count,
+            "truncated": truncated,
+            "tree": tree,
+        }))
+    }
+
+    fn node(
+        &self,
+        path: &Path,
+        name: &Path,
+        level: usize,
+        count: &mut usize,
+        truncated: &mut bool,
+    ) -> io::Result<Option<Value>> {
+        let metadata = fs::metadata(path)?;
+        // Linked directories are listed but not walked, they could loop
+        let link = fs::symlink_metadata(path)?.file_type().is_symlink();
+        let mut node = Map::new();
+        node.insert("name".to_owned(), json!(name.to_string_lossy()));
+        node.insert(
+            "type".to_owned(),
+            json!(if metadata.is_dir() { "dir" } else { "file" }),
+        );
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|mtime| mtime.as_secs());
+        if let Some(mtime) = mtime {
+            node.insert("mtime".to_owned(), json!(mtime));
+        }
+        // Directories are kept when something inside changed
+        let unchanged = level > 0
+            && self
+                .since
+                .is_some_and(|since| mtime.is_none_or(|mtime| mtime <= since));
+        if unchanged && !metadata.is_dir() {
+            return Ok(None);
+        }
+        if metadata.is_file() {
+            node.insert("size".to_owned(), json!(metadata.len()));
+            if let Some(hash) = self.hash {
+                node.insert("sha256".to_owned(), json!(hash(path)?));
+            }
+        }
+        if link {
+            node.insert("link".to_owned(), json!(true));
+        }
+        // No `children` past the depth limit (and for links), an empty array for empty directories
+        if metadata.is_dir() && !link && level < self.depth {
+            let mut names = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name())
+                .collect::<Vec<_>>();
+            names.sort();
+            let mut children = Vec::new();
+            for child_name in names {
+                let child = path.join(&child_name);
+                let is_dir = match fs::metadata(&child) {
+                    Ok(metadata) => metadata.is_dir(),
+                    // Broken link
+                    Err(_) => continue,
+                };
+                if (self.skip)(&child, is_dir) {
+                    continue;
+                }
+                if *count >= self.max_entries {
+                    *truncated = true;
+                    break;
+                }
+                if let Some(node) =
+                    self.node(&child, Path::new(&child_name), level + 1, count, truncated)?
+                {
+                    *count += 1;
+                    children.push(node);
+                }
+            }
+            if unchanged && children.is_empty() {
+                return Ok(None);
+            }
+            node.insert("children".to_owned(), Value::Array(children));
+        } else if unchanged {
+            return Ok(None);
+        }
+        Ok(Some(Value::Object(node)))
+    }
+}
@@ -0,0 +1,51 @@
+use std::net::{IpAddr, SocketAddr};
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+use crate::util::StringError;
+
+/// Announce the server as `_http._tcp` (or `_https._tcp`) on the local network (`--mdns`),
+/// for as long as the returned daemon is alive
+pub fn announce(
+    name: Option<&str>,
+    https: bool,
+    addrs: &[SocketAddr],
+) -> Result<ServiceDaemon, StringError> {
+    let map_err = |e: mdns_sd::Error| StringError(format!("mDNS: {}", e));
+    let hostname = hostname::get()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "localhost".to_owned());
+    let instance = match name {
+        Some(name) => name.to_owned(),
+        None => format!("simple-http-server on {}", hostname),
+    };
+    let service_type = if https {
+        "_https._tcp.local."
+    } else {
+        "_http._tcp.local."
+    };
+    let ips = addrs
+        .iter()
+        .map(SocketAddr::ip)
+        .filter(|ip| !ip.is_unspecified() && !ip.is_loopback())
+        .collect::<Vec<IpAddr>>();
+    let info = ServiceInfo::new(
+        service_type,
+        &instance,
+        &format!("{}.local.", hostname),
+        &ips[..],
+        addrs[0].port(),
+        &[("path", "/")][..],
+    )
+    .map_err(map_err)?;
+    // Bound to a wildcard address: announce the addresses of all the interfaces
+    let info = if addrs.iter().any(|addr| addr.ip().is_unspecified()) {
+        info.enable_addr_auto()
+    } else {
+        info
+    };
+
+    let daemon = ServiceDaemon::new().map_err(map_err)?;
+    daemon.register(info).map_err(map_err)?;
+    Ok(daemon)
+}
@@ -1,24 +1,295 @@
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use iron::method::Method;
 use iron::status;
+use iron::typemap::Key;
 use iron::{BeforeMiddleware, IronError, IronResult, Request, Response};
 
-use crate::util::StringError;
+use crate::archive::ARCHIVE_QUERY;
+use crate::jwt::JwtValidator;
+use crate::middlewares::session::{self, Login, Sessions, LOGIN_PATH, LOGOUT_PATH};
+use crate::middlewares::ClientCert;
+use crate::one_time::ONE_TIME_PATH;
+use crate::s3;
+use crate::stats::STATUS_PATH;
+use crate::util::{same_secret, StringError};
+
+/// A user of `--auth` or `--auth-upload`
+pub struct Credential {
+    pub username: String,
+    pub password: String,
+    pub read: bool,
+    /// Uploads and deletes
+    pub write: bool,
+    /// The status page too
+    pub admin: bool,
+}
+
+impl Credential {
+    /// `user:pass`, or `user:pass:ro|rw|admin` to choose the permissions (`default` otherwise,
+    /// as `(read, write, admin)`)
+    pub fn parse(s: &str, default: (bool, bool, bool)) -> Result<Credential, StringError> {
+        let parts = s.splitn(2, ':').collect::<Vec<&str>>();
+        if parts.len() != 2 {
+            return Err(StringError("not valid format user & password".to_owned()));
+        }
+        let (password, (read, write, admin)) = match parts[1].rsplit_once(':') {
+            Some((password, role)) => match parse_role(role) {
+                Some(role) => (password, role),
+                None => (parts[1], default),
+            },
+            None => (parts[1], default),
+        };
+        Ok(Credential {
+            username: parts[0].to_owned(),
+            password: password.to_owned(),
+            read,
+            write,
+            admin,
+        })
+    }
+
+    /// An API key of `--auth-token`: `token`, or `token:ro|rw|admin` (`rw` otherwise), kept as
+    /// the password of a `token` user
+    pub fn token(s: &str) -> Result<Credential, StringError> {
+        let (token, (read, write, admin)) = match s.rsplit_once(':') {
+            Some((token, role)) => match parse_role(role) {
+                Some(role) => (token, role),
+                None => (s, (true, true, false)),
+            },
+            None => (s, (true, true, false)),
+        };
+        if token.is_empty() {
+            return Err(StringError("no token found".to_owned()));
+        }
+        Ok(Credential {
+            username: "token".to_owned(),
+            password: token.to_owned(),
+            read,
+            write,
+            admin,
+        })
+    }
+}
+
+/// The user permitted by `AuthChecker`: of `--auth`, `token` for `--auth-token`, the `sub`
+/// claim of a JWT (`jwt` without)
+pub struct User;
+
+impl Key for User {
+    type Value = String;
+}
+
+fn parse_role(role: &str) -> Option<(bool, bool, bool)> {
+    match role {
+        "r" | "ro" => Some((true, false, false)),
+        "w" | "wo" => Some((false, true, false)),
+        "rw" => Some((true, true, false)),
+        "admin" => Some((true, true, true)),
+        _ => None,
+    }
+}
+
+/// The query parameter of the `--auth-token` tokens, for the clients without headers
+/// (RFC 6750 2.3)
+pub const TOKEN_QUERY: &str = "access_token";
+
+/// The error of a server built or reloaded with `--status` but no credentials
+pub const STATUS_CREDENTIALS: &str = "The status page requires credentials";
+
+/// Requests changing files (uploads), the others only read
+pub fn is_write(req: &Request) -> bool {
+    // Archives of the selected entries are posted downloads
+    let archive = || {
+        req.url
+            .as_ref()
+            .query_pairs()
+            .any(|(k, _)| k == ARCHIVE_QUERY)
+    };
+    match req.method {
+        Method::Post => !archive(),
+        Method::Put | Method::Delete | Method::Patch => true,
+        _ => false,
+    }
+}
 
 pub struct AuthChecker {
-    username: String,
-    password: String,
+    /// Replaced when the `--config` file is reloaded, everything is allowed without any
+    credentials: RwLock<Vec<Credential>>,
+    /// Of `--auth-token`, by `Authorization: Bearer` or `TOKEN_QUERY`
+    tokens: RwLock<Vec<Credential>>,
+    /// Downloads are anonymous without `--auth` or `--auth-token`
+    protect_read: AtomicBool,
+    /// Requests with a verified TLS client certificate are authenticated already
+    pub accept_client_cert: bool,
+    /// Requests of the S3 API are checked by their signature (`--s3`)
+    pub s3_bucket: Option<String>,
+    /// The login form and its cookies, besides Basic Auth (`--auth-session`)
+    pub sessions: Option<Arc<Sessions>>,
+    /// The Bearer tokens which aren't `--auth-token`s may be JWTs (`--auth-jwt-secret`)
+    pub jwt: Option<JwtValidator>,
+    /// The one-time links are downloaded without credentials (`--one-time-links`)
+    pub one_time_links: bool,
+    /// The status page is served, a reload can't remove all the credentials (`--status`)
+    pub status: bool,
+}
+
+// Of the posted login form
+const MAX_LOGIN_SIZE: u64 = 4096;
+
+/// `auth` gates everything (`--auth`, an admin by default), `upload_auth` only the uploads
+/// (`--auth-upload`)
+pub fn parse_credentials(
+    auth: Option<&str>,
+    upload_auth: &[&str],
+) -> Result<Vec<Credential>, StringError> {
+    let mut credentials = Vec::new();
+    if let Some(auth) = auth {
+        credentials.push(Credential::parse(auth, (true, true, true))?);
+    }
+    for s in upload_auth {
+        credentials.push(Credential::parse(s, (false, true, false))?);
+    }
+    Ok(credentials)
 }
 
 impl AuthChecker {
-    pub fn new(s: &str) -> Result<AuthChecker, StringError> {
-        let parts = s.splitn(2, ':').collect::<Vec<&str>>();
-        if parts.len() == 2 {
-            Ok(AuthChecker {
-                username: parts[0].to_owned(),
-                password: parts[1].to_owned(),
-            })
-        } else {
-            Err(StringError("not valid format user & password".to_owned()))
+    pub fn new(
+        auth: Option<&str>,
+        upload_auth: &[&str],
+        tokens: &[&str],
+    ) -> Result<AuthChecker, StringError> {
+        Ok(AuthChecker {
+            credentials: RwLock::new(parse_credentials(auth, upload_auth)?),
+            tokens: RwLock::new(parse_tokens(tokens)?),
+            protect_read: AtomicBool::new(auth.is_some() || !tokens.is_empty()),
+            accept_client_cert: false,
+            s3_bucket: None,
+            sessions: None,
+            jwt: None,
+            one_time_links: false,
+            status: false,
+        })
+    }
+
+    /// The users and tokens of a reloaded configuration
+    pub fn set_credentials(
+        &self,
+        auth: Option<&str>,
+        upload_auth: &[&str],
+        tokens: &[&str],
+    ) -> Result<(), StringError> {
+        if self.status
+            && auth.is_none()
+            && upload_auth.is_empty()
+            && tokens.is_empty()
+            && self.jwt.is_none()
+        {
+            return Err(StringError(STATUS_CREDENTIALS.to_owned()));
         }
+        let credentials = parse_credentials(auth, upload_auth)?;
+        let parsed_tokens = parse_tokens(tokens)?;
+        *self.credentials.write().unwrap() = credentials;
+        *self.tokens.write().unwrap() = parsed_tokens;
+        self.protect_read
+            .store(auth.is_some() || !tokens.is_empty(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn unauthorized(response: Response) -> IronError {
+    IronError {
+        error: Box::new(StringError("authorization error".to_owned())),
+        response,
+    }
+}
+
+fn no_status_permission() -> IronError {
+    IronError {
+        error: Box::new(StringError("permission denied".to_owned())),
+        response: Response::with((status::Forbidden, "No permission to view the status.")),
+    }
+}
+
+fn parse_tokens(tokens: &[&str]) -> Result<Vec<Credential>, StringError> {
+    tokens.iter().map(|s| Credential::token(s)).collect()
+}
+
+// The token of `Authorization: Bearer`, otherwise of the query
+fn bearer_token(req: &Request) -> Option<String> {
+    use iron::headers::{Authorization, Bearer};
+
+    match req.headers.get::<Authorization<Bearer>>() {
+        Some(Authorization(Bearer { token })) => Some(token.clone()),
+        None => req
+            .url
+            .as_ref()
+            .query_pairs()
+            .find(|(k, _)| k == TOKEN_QUERY)
+            .map(|(_, v)| v.into_owned()),
+    }
+}
+
+impl AuthChecker {
+    // The Basic Auth prompt (and the Bearer one with tokens), or the login form for the
+    // browsers with `--auth-session`
+    fn challenge(&self, req: &Request, basic: bool, bearer: bool) -> IronError {
+        let browser = req
+            .headers
+            .get_raw("Accept")
+            .and_then(|values| values.first())
+            .is_some_and(|value| String::from_utf8_lossy(value).contains("text/html"));
+        if self.sessions.is_some() && browser {
+            let next = match req.url.query() {
+                Some(query) => format!("/{}?{}", req.url.path().join("/"), query),
+                None => format!("/{}", req.url.path().join("/")),
+            };
+            return unauthorized(session::login_page(status::Unauthorized, &next, None));
+        }
+        let mut schemes = Vec::new();
+        if basic {
+            schemes.push(b"Basic realm=\"main\"".to_vec());
+        }
+        if bearer {
+            schemes.push(b"Bearer realm=\"main\"".to_vec());
+        }
+        let mut resp = Response::with(status::Unauthorized);
+        resp.headers.set_raw("WWW-Authenticate", schemes);
+        unauthorized(resp)
+    }
+
+    // The form posted to `LOGIN_PATH`, checked for the handler
+    fn login(&self, req: &mut Request) {
+        let mut body = String::new();
+        let _ = req
+            .body
+            .by_ref()
+            .take(MAX_LOGIN_SIZE)
+            .read_to_string(&mut body);
+        let field = |name: &str| {
+            url::form_urlencoded::parse(body.as_bytes())
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        let (username, password) = (field("username"), field("password"));
+        let logged_in = self
+            .credentials
+            .read()
+            .unwrap()
+            .iter()
+            .find(|c| {
+                username.as_deref() == Some(&c.username)
+                    && password
+                        .as_deref()
+                        .is_some_and(|p| same_secret(p, &c.password))
+            })
+            .map(|c| c.username.clone());
+        req.extensions.insert::<Login>(Login {
+            username: logged_in,
+            next: field("next").unwrap_or_default(),
+        });
     }
 }
 
@@ -26,32 +297,120 @@ impl BeforeMiddleware for AuthChecker {
     fn before(&self, req: &mut Request) -> IronResult<()> {
         use iron::headers::{Authorization, Basic};
 
-        match req.headers.get::<Authorization<Basic>>() {
+        if self.accept_client_cert && req.extensions.contains::<ClientCert>() {
+            return Ok(());
+        }
+        if let Some(ref bucket) = self.s3_bucket {
+            if s3::route(req, bucket).is_some() {
+                return Ok(());
+            }
+        }
+        if self.one_time_links
+            && matches!(req.url.path()[..], [ONE_TIME_PATH, _])
+            && matches!(req.method, Method::Get | Method::Head)
+        {
+            return Ok(());
+        }
+        if self.sessions.is_some() {
+            if req.url.path() == [LOGIN_PATH] && req.method == Method::Post {
+                self.login(req);
+                return Ok(());
+            }
+            if req.url.path() == [LOGIN_PATH] || req.url.path() == [LOGOUT_PATH] {
+                return Ok(());
+            }
+        }
+        let credentials = self.credentials.read().unwrap();
+        let tokens = self.tokens.read().unwrap();
+        let write = is_write(req);
+        let admin = req.url.path() == [STATUS_PATH];
+        let jwt = self.jwt.as_ref();
+        if jwt.is_none() && credentials.is_empty() && tokens.is_empty() {
+            return if admin {
+                Err(no_status_permission())
+            } else {
+                Ok(())
+            };
+        }
+        // The status page is never anonymous
+        if jwt.is_none() && !write && !admin && !self.protect_read.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let (basic, bearer) = (!credentials.is_empty(), !tokens.is_empty() || jwt.is_some());
+        let token = if bearer { bearer_token(req) } else { None };
+        let mut subject = None;
+        let credential = match req.headers.get::<Authorization<Basic>>() {
+            _ if token.is_some() => {
+                let token = token.as_deref().unwrap();
+                let validated = match tokens.iter().find(|c| same_secret(token, &c.password)) {
+                    Some(c) => Ok(c),
+                    None => match jwt {
+                        Some(jwt) => jwt.validate(token).map(|claims| {
+                            subject = claims["sub"].as_str().map(str::to_owned);
+                            &jwt.credential
+                        }),
+                        None => Err(StringError("unknown token".to_owned())),
+                    },
+                };
+                match validated {
+                    Ok(c) => Some(c),
+                    Err(e) => {
+                        let mut resp = Response::with((status::Unauthorized, "Invalid token."));
+                        let challenge = format!(
+                            "Bearer realm=\"main\", error=\"invalid_token\", error_description=\"{}\"",
+                            e.0
+                        );
+                        resp.headers
+                            .set_raw("WWW-Authenticate", vec![challenge.into_bytes()]);
+                        return Err(unauthorized(resp));
+                    }
+                }
+            }
             Some(&Authorization(Basic {
                 ref username,
                 ref password,
-            })) => {
-                if username == self.username.as_str() && password == &Some(self.password.clone()) {
-                    Ok(())
-                } else {
-                    Err(IronError {
-                        error: Box::new(StringError("authorization error".to_owned())),
-                        response: Response::with((
-                            status::Unauthorized,
-                            "Wrong username or password.",
-                        )),
-                    })
-                }
+            })) => credentials.iter().find(|c| {
+                username == c.username.as_str()
+                    && password
+                        .as_deref()
+                        .is_some_and(|p| same_secret(p, &c.password))
+            }),
+            // Still a user of the reloaded configuration
+            None => match self
+                .sessions
+                .as_ref()
+                .and_then(|sessions| sessions.user(req))
+            {
+                Some(username) => credentials.iter().find(|c| c.username == username),
+                None => return Err(self.challenge(req, basic, bearer)),
+            },
+        };
+        match credential {
+            Some(c) if admin && !c.admin => Err(no_status_permission()),
+            Some(c) if admin || (write && c.write) || (!write && c.read) => {
+                let user = subject.unwrap_or_else(|| c.username.clone());
+                req.extensions.insert::<User>(user);
+                Ok(())
             }
-            None => {
-                let mut resp = Response::with(status::Unauthorized);
-                resp.headers
-                    .set_raw("WWW-Authenticate", vec![b"Basic realm=\"main\"".to_vec()]);
-                Err(IronError {
-                    error: Box::new(StringError("authorization error".to_owned())),
-                    response: resp,
-                })
+            Some(_) => Err(IronError {
+                error: Box::new(StringError("permission denied".to_owned())),
+                response: Response::with((
+                    status::Forbidden,
+                    if write {
+                        "No permission to upload."
+                    } else {
+                        "No permission to download."
+                    },
+                )),
+            }),
+            // An expired or removed user of a session too
+            None if req.headers.get::<Authorization<Basic>>().is_none() => {
+                Err(self.challenge(req, basic, bearer))
             }
+            None => Err(unauthorized(Response::with((
+                status::Unauthorized,
+                "Wrong username or password.",
+            )))),
         }
     }
 }
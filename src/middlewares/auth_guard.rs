@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use iron::method::Method;
+use iron::status;
+use iron::{AfterMiddleware, BeforeMiddleware, IronError, IronResult, Request, Response};
+
+use crate::middlewares::{LOGIN_PATH, TOKEN_QUERY};
+use crate::protect::PASSWORD_QUERY;
+use crate::util::StringError;
+
+// The clients wait 1s, then 2s, 4s... after this many failures in a row
+const BACKOFF_AFTER: u32 = 3;
+// Clients without failures for a while are dropped when there are this many
+const PRUNE_THRESHOLD: usize = 10000;
+
+/// Failed logins per client IP: the credentials of a 401. Once `BACKOFF_AFTER` failed in a
+/// row, the client waits longer after each one (429 with Retry-After), and is banned for
+/// `ban` once `max_failures` failed (403). Failures are forgiven after `ban` without any, or
+/// once logged in
+pub struct AuthGuard {
+    max_failures: u32,
+    ban: Duration,
+    clients: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    // Until when the client waits, and whether it's banned
+    blocked: Option<(Instant, bool)>,
+}
+
+impl AuthGuard {
+    /// `--auth-max-failures` (at least 1), `--auth-ban-time`
+    pub fn new(max_failures: u32, ban: Duration) -> AuthGuard {
+        AuthGuard {
+            max_failures,
+            ban,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn failed(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= PRUNE_THRESHOLD {
+            let ban = self.ban;
+            clients.retain(|_, failures| now.duration_since(failures.last) < ban);
+        }
+        let failures = clients.entry(ip).or_insert(Failures {
+            count: 0,
+            last: now,
+            blocked: None,
+        });
+        if now.duration_since(failures.last) >= self.ban {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last = now;
+        if failures.count >= self.max_failures {
+            failures.blocked = Some((now + self.ban, true));
+            eprintln!(
+                "Banned {} for {}s after {} failed logins",
+                ip,
+                self.ban.as_secs(),
+                failures.count
+            );
+        } else if failures.count >= BACKOFF_AFTER {
+            let backoff = Duration::from_secs(1 << (failures.count - BACKOFF_AFTER).min(16));
+            failures.blocked = Some((now + backoff.min(self.ban), false));
+        }
+    }
+}
+
+// Whether `req` came with credentials, the 401 of the others is the challenge
+fn has_credentials(req: &Request) -> bool {
+    req.headers.get_raw("Authorization").is_some()
+        || (req.method == Method::Post && req.url.path() == [LOGIN_PATH])
+        || req
+            .url
+            .as_ref()
+            .query_pairs()
+            .any(|(k, _)| k == TOKEN_QUERY || k == PASSWORD_QUERY)
+}
+
+impl BeforeMiddleware for AuthGuard {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let ip = req.remote_addr.ip();
+        let mut clients = self.clients.lock().unwrap();
+        let (until, banned) = match clients.get(&ip).and_then(|failures| failures.blocked) {
+            Some(blocked) => blocked,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        if until <= now {
+            if let Some(failures) = clients.get_mut(&ip) {
+                failures.blocked = None;
+            }
+            return Ok(());
+        }
+        if banned {
+            return Err(IronError::new(
+                StringError(format!("{} is banned after too many failed logins", ip)),
+                (status::Forbidden, "Too many failed logins."),
+            ));
+        }
+        let retry_after = (until - now).as_secs_f64().ceil() as u64;
+        let mut resp = Response::with(status::TooManyRequests);
+        resp.headers
+            .set_raw("Retry-After", vec![retry_after.to_string().into_bytes()]);
+        Err(IronError {
+            error: Box::new(StringError(format!("{} waits after failed logins", ip))),
+            response: resp,
+        })
+    }
+}
+
+impl AfterMiddleware for AuthGuard {
+    fn after(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
+        // Also the wrong login forms, answered by the handler
+        if has_credentials(req) {
+            if resp.status == Some(status::Unauthorized) {
+                self.failed(req.remote_addr.ip());
+            } else {
+                self.clients.lock().unwrap().remove(&req.remote_addr.ip());
+            }
+        }
+        Ok(resp)
+    }
+
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        if err.response.status == Some(status::Unauthorized) && has_credentials(req) {
+            self.failed(req.remote_addr.ip());
+        }
+        Err(err)
+    }
+}
@@ -0,0 +1,22 @@
+use iron::typemap::Key;
+use iron::{BeforeMiddleware, IronResult, Request};
+
+use crate::tls::peer_common_name;
+
+/// Subject CN of the verified TLS client certificate
+pub struct ClientCert;
+
+impl Key for ClientCert {
+    type Value = String;
+}
+
+pub struct ClientCertChecker;
+
+impl BeforeMiddleware for ClientCertChecker {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        if let Some(common_name) = peer_common_name() {
+            req.extensions.insert::<ClientCert>(common_name);
+        }
+        Ok(())
+    }
+}
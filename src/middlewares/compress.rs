@@ -1,14 +1,142 @@
 use std::io;
+use std::path::Path;
 
 use flate2::{
     write::{DeflateEncoder, GzEncoder},
     Compression,
 };
-use iron::headers::{ContentEncoding, ContentLength, Encoding, TransferEncoding};
+use iron::headers::{
+    AcceptEncoding, ContentEncoding, ContentLength, ETag, Encoding, EntityTag, TransferEncoding,
+};
+use iron::mime::Mime;
 use iron::response::WriteBody;
 use iron::{AfterMiddleware, IronResult, Request, Response};
 
+use crate::util::StringError;
+
+/// Smaller responses are sent as they are, by default (`--compress-min-size`)
+pub const DEFAULT_MIN_SIZE: u64 = 256;
+// The `--compress auto` types
+const TEXT_MIME_TYPES: &[&str] = &[
+    "text/*",
+    "application/json",
+    "application/javascript",
+    "application/xml",
+    "application/xhtml+xml",
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/manifest+json",
+    "application/wasm",
+    "image/svg+xml",
+    "image/bmp",
+    "font/ttf",
+    "font/otf",
+];
+// Already compressed, whatever the rules
+const COMPRESSED_MIME_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/heic",
+    "video/*",
+    "audio/*",
+    "font/woff",
+    "font/woff2",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/zstd",
+    "application/x-7z-compressed",
+    "application/x-rar-compressed",
+    "application/vnd.rar",
+];
+
+/// Whether a file is compressed by the `--compress` rules: `.ext` suffixes, MIME types
+/// (`text/*`) or `auto` for the text formats. The already compressed formats never are
+pub fn is_compressible(rules: &[String], path: &Path, mime: &Mime) -> bool {
+    let essence = format!("{}/{}", mime.0, mime.1).to_ascii_lowercase();
+    if COMPRESSED_MIME_TYPES
+        .iter()
+        .any(|pattern| mime_matches(pattern, &essence))
+    {
+        return false;
+    }
+    let path = path.to_string_lossy();
+    rules.iter().any(|rule| match rule.as_str() {
+        "auto" => TEXT_MIME_TYPES
+            .iter()
+            .any(|pattern| mime_matches(pattern, &essence)),
+        rule if rule.contains('/') => mime_matches(rule, &essence),
+        ext => path.ends_with(ext),
+    })
+}
+
+fn mime_matches(pattern: &str, essence: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => essence.split('/').next() == Some(top_level),
+        None => pattern.eq_ignore_ascii_case(essence),
+    }
+}
+
+/// The content coding of the response by the `Accept-Encoding` of the request, gzip and
+/// deflate only when `compressible`: the highest quality wins, gzip before deflate before
+/// identity on ties. `Ok(None)` for identity, `Err` when even identity is refused (406)
+pub fn negotiate(req: &Request, compressible: bool) -> Result<Option<Encoding>, StringError> {
+    let items = match req.headers.get::<AcceptEncoding>() {
+        Some(AcceptEncoding(items)) => items,
+        // Any coding would do, but not every client decodes them
+        None => return Ok(None),
+    };
+    let any = Encoding::EncodingExt("*".to_owned());
+    let quality = |encoding: &Encoding| {
+        items
+            .iter()
+            .find(|item| item.item == *encoding)
+            .or_else(|| items.iter().find(|item| item.item == any))
+            .map(|item| item.quality.0)
+    };
+
+    let mut best = None;
+    if compressible {
+        for encoding in [Encoding::Gzip, Encoding::Deflate] {
+            match quality(&encoding) {
+                Some(q) if q > 0 && best.as_ref().is_none_or(|(_, best_q)| q > *best_q) => {
+                    best = Some((encoding, q));
+                }
+                _ => {}
+            }
+        }
+    }
+    // Identity is acceptable unless excluded, at the lowest preference if not listed
+    let identity = quality(&Encoding::Identity);
+    match (best, identity) {
+        (Some((_, q)), Some(identity)) if identity > q => Ok(None),
+        (Some((encoding, _)), _) => Ok(Some(encoding)),
+        (None, Some(0)) => Err(StringError(
+            "No acceptable content coding (Accept-Encoding)".to_owned(),
+        )),
+        (None, _) => Ok(None),
+    }
+}
+
+/// Marks a response chosen by `negotiate()`, for the caches
+pub fn set_vary(resp: &mut Response) {
+    resp.headers
+        .set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+}
+
+/// The `ETag` of the `encoding` representation, weak if `etag` is
+pub fn encoded_etag(etag: &EntityTag, encoding: &Encoding) -> EntityTag {
+    EntityTag::new(etag.weak, format!("{}-{}", etag.tag(), encoding))
+}
+
 // [Reference]: https://github.com/iron/iron/issues/548
+// Encoded while the body is written, through the bounded buffers of the encoders: large files
+// are never held in memory
 struct GzipBody(Box<dyn WriteBody>);
 struct DeflateBody(Box<dyn WriteBody>);
 
@@ -28,26 +156,34 @@ impl WriteBody for DeflateBody {
     }
 }
 
-pub struct CompressionHandler;
+pub struct CompressionHandler {
+    /// In bytes, `DEFAULT_MIN_SIZE` by default
+    pub min_size: u64,
+}
 
 impl AfterMiddleware for CompressionHandler {
     fn after(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
         if let Some(&ContentLength(length)) = resp.headers.get::<ContentLength>() {
-            if length <= 256 {
+            if length < self.min_size {
                 resp.headers.remove::<ContentEncoding>();
                 return Ok(resp);
             }
         }
 
         let mut encoding: Option<Encoding> = None;
-        if let Some(&ContentEncoding(ref objs)) = resp.headers.get::<ContentEncoding>() {
+        if let Some(ContentEncoding(objs)) = resp.headers.get::<ContentEncoding>() {
             encoding = objs
                 .iter()
                 .find(|obj| *obj == &Encoding::Deflate || *obj == &Encoding::Gzip)
                 .cloned();
         }
+        if let (Some(encoding), Some(ETag(tag))) = (&encoding, resp.headers.get::<ETag>()) {
+            // Another representation, with a validator of its own
+            let tag = encoded_etag(tag, encoding);
+            resp.headers.set(ETag(tag));
+        }
         if encoding.is_none() {
-            if let Some(&TransferEncoding(ref objs)) = resp.headers.get::<TransferEncoding>() {
+            if let Some(TransferEncoding(objs)) = resp.headers.get::<TransferEncoding>() {
                 encoding = objs
                     .iter()
                     .find(|obj| *obj == &Encoding::Deflate || *obj == &Encoding::Gzip)
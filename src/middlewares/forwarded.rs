@@ -0,0 +1,118 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use ipnet::IpNet;
+use iron::{BeforeMiddleware, IronResult, Request};
+
+/// Takes the client address and scheme from the headers set by trusted reverse proxies,
+/// so that the rest of the chain (logging, auth, redirects) sees the real client
+pub struct ForwardedHeaders {
+    trusted: Vec<IpNet>,
+}
+
+impl ForwardedHeaders {
+    /// `proxies` are CIDRs (`10.0.0.0/8`) or single addresses
+    pub fn new(proxies: &[String]) -> Result<ForwardedHeaders, String> {
+        let trusted = proxies
+            .iter()
+            .map(|s| parse_net(s))
+            .collect::<Result<Vec<IpNet>, String>>()?;
+        Ok(ForwardedHeaders { trusted })
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted.iter().any(|net| net.contains(ip))
+    }
+
+    /// The nearest untrusted hop (or the farthest one if all are trusted)
+    fn client(&self, hops: &[SocketAddr]) -> Option<SocketAddr> {
+        hops.iter()
+            .rev()
+            .find(|addr| !self.is_trusted(&addr.ip()))
+            .or_else(|| hops.first())
+            .cloned()
+    }
+}
+
+pub fn parse_net(s: &str) -> Result<IpNet, String> {
+    IpNet::from_str(s)
+        .or_else(|_| IpAddr::from_str(s).map(IpNet::from))
+        .map_err(|_| format!("Invalid address or CIDR: {}", s))
+}
+
+impl BeforeMiddleware for ForwardedHeaders {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        if !self.is_trusted(&req.remote_addr.ip()) {
+            return Ok(());
+        }
+        let (hops, proto) = match raw_values(req, "Forwarded") {
+            Some(values) => parse_forwarded(&values),
+            None => (
+                raw_values(req, "X-Forwarded-For")
+                    .map(|values| values.iter().filter_map(|s| parse_node(s)).collect())
+                    .unwrap_or_default(),
+                raw_values(req, "X-Forwarded-Proto").and_then(|values| values.last().cloned()),
+            ),
+        };
+        if let Some(client) = self.client(&hops) {
+            req.remote_addr = client;
+        }
+        if let Some(proto) = proto {
+            let proto = proto.to_lowercase();
+            if proto == "http" || proto == "https" {
+                let _ = req.url.as_mut().set_scheme(&proto);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Comma separated values of all the `name` headers
+fn raw_values(req: &Request, name: &str) -> Option<Vec<String>> {
+    let lines = req.headers.get_raw(name)?;
+    Some(
+        lines
+            .iter()
+            .flat_map(|line| {
+                String::from_utf8_lossy(line)
+                    .split(',')
+                    .map(|s| s.trim().to_owned())
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .collect(),
+    )
+}
+
+/// `for` addresses and the last `proto` of RFC 7239 `Forwarded` elements
+fn parse_forwarded(elements: &[String]) -> (Vec<SocketAddr>, Option<String>) {
+    let mut hops = Vec::new();
+    let mut proto = None;
+    for element in elements {
+        for pair in element.split(';') {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().to_lowercase();
+            let value = parts.next().unwrap_or("").trim().trim_matches('"');
+            match key.as_str() {
+                "for" => {
+                    if let Some(addr) = parse_node(value) {
+                        hops.push(addr);
+                    }
+                }
+                "proto" => proto = Some(value.to_owned()),
+                _ => {}
+            }
+        }
+    }
+    (hops, proto)
+}
+
+/// `1.2.3.4`, `1.2.3.4:80`, `::1`, `[::1]` or `[::1]:80` (obfuscated identifiers are skipped)
+fn parse_node(s: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = SocketAddr::from_str(s) {
+        return Some(addr);
+    }
+    IpAddr::from_str(s.trim_start_matches('[').trim_end_matches(']'))
+        .ok()
+        .map(|ip| SocketAddr::new(ip, 0))
+}
@@ -0,0 +1,43 @@
+use std::cell::Cell;
+
+use iron::headers::Connection;
+use iron::{AfterMiddleware, IronError, IronResult, Request, Response};
+
+thread_local! {
+    // hyper serves a connection on the thread which accepted it, until it is closed
+    static SERVED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// To be called by the listener once a connection is accepted, in the thread serving it
+pub fn connection_accepted() {
+    SERVED.with(|served| served.set(0));
+}
+
+/// Closes the connections once they served `max_requests` (`--keepalive-requests`)
+pub struct KeepAlive {
+    pub max_requests: usize,
+}
+
+impl KeepAlive {
+    fn count(&self, resp: &mut Response) {
+        let served = SERVED.with(|served| {
+            served.set(served.get() + 1);
+            served.get()
+        });
+        if served >= self.max_requests {
+            resp.headers.set(Connection::close());
+        }
+    }
+}
+
+impl AfterMiddleware for KeepAlive {
+    fn after(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+        self.count(&mut resp);
+        Ok(resp)
+    }
+
+    fn catch(&self, _: &mut Request, mut err: IronError) -> IronResult<Response> {
+        self.count(&mut err.response);
+        Err(err)
+    }
+}
@@ -1,12 +1,23 @@
+use std::io::{self, Write};
 use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Instant;
 
+use iron::headers::ContentLength;
+use iron::method::Method;
+use iron::response::WriteBody;
 use iron::status;
 use iron::{AfterMiddleware, IronError, IronResult, Request, Response};
 use lazy_static::lazy_static;
 use percent_encoding::percent_decode;
+use pretty_bytes::converter::convert;
 use termcolor::{Color, ColorSpec};
 
 use crate::color::{build_spec, Printer};
+use crate::log_file::LogFile;
+use crate::middlewares::ClientCert;
+use crate::stats::ServerStats;
+use crate::syslog::{Severity, Syslog};
 use crate::util::{error_resp, now_string};
 
 lazy_static! {
@@ -16,60 +27,275 @@ lazy_static! {
 }
 
 pub struct RequestLogger {
-    pub printer: Printer,
+    /// `None` when `--silent`
+    pub printer: Option<Arc<Printer>>,
+    /// Counters of the status page
+    pub stats: Option<Arc<ServerStats>>,
+    /// Access and error events are also sent to syslog (`--log-syslog`)
+    pub syslog: Option<Arc<Syslog>>,
+    /// Access log without colors (`--log-file`)
+    pub log_file: Option<Arc<LogFile>>,
 }
 
 impl RequestLogger {
-    fn log(&self, req: &Request, resp: &Response) {
+    fn record(&self, req: &Request, resp: &mut Response, error: Option<&str>) {
+        let stats = match self.stats {
+            Some(ref stats) => stats,
+            None => return,
+        };
+        stats.record_request();
+        let status = resp.status.unwrap_or(status::InternalServerError);
+        if status.is_client_error() || status.is_server_error() {
+            stats.record_error(format!(
+                "[{}] - {} - {} - {} {}{}",
+                now_string(),
+                req.remote_addr.ip(),
+                status.to_u16(),
+                req.method,
+                decoded_path(req),
+                error.map(|e| format!(" - {}", e)).unwrap_or_default()
+            ));
+        }
+        if let Some(body) = resp.body.take() {
+            let total = resp.headers.get::<ContentLength>().map(|len| len.0);
+            resp.body =
+                Some(stats.track_body(body, req.remote_addr.ip(), decoded_path(req), total));
+        }
+    }
+
+    /// Responses with a body are logged once it's sent, with the bytes written
+    fn log(&self, req: &Request, resp: &mut Response) {
+        if self.printer.is_none() && self.syslog.is_none() && self.log_file.is_none() {
+            return;
+        }
         if let Some(status) = resp.status {
-            let status_color = if status.is_success() {
-                C_BOLD_GREEN.deref()
-            } else if status.is_informational() || status.is_redirection() {
-                C_BOLD_YELLOW.deref()
-            } else {
-                C_BOLD_RED.deref()
+            // The CN of client certificate follows remote ip
+            let remote = match req.extensions.get::<ClientCert>() {
+                Some(common_name) => format!("{} ({})", req.remote_addr.ip(), common_name),
+                None => req.remote_addr.ip().to_string(),
             };
-            self.printer
-                .println_out(
-                    // datetime, remote-ip, status-code, method, url-path
-                    "[{}] - {} - {} - {} {}",
-                    &[
-                        (now_string().as_str(), &None),
-                        (req.remote_addr.ip().to_string().as_str(), &None),
-                        (status.to_u16().to_string().as_str(), status_color),
-                        (req.method.to_string().as_str(), &None),
-                        (
-                            percent_decode(req.url.as_ref().path().as_bytes())
-                                .decode_utf8()
-                                .unwrap()
-                                .to_string()
-                                .as_str(),
-                            &None,
-                        ),
-                    ],
-                )
-                .unwrap();
+            let line = LogLine {
+                printer: self.printer.clone(),
+                syslog: self.syslog.clone(),
+                log_file: self.log_file.clone(),
+                time: now_string(),
+                remote,
+                status,
+                method: req.method.to_string(),
+                path: decoded_path(req),
+            };
+            match resp.body.take() {
+                Some(inner) if req.method != Method::Head => {
+                    let total = resp.headers.get::<ContentLength>().map(|len| len.0);
+                    resp.body = Some(Box::new(LoggedBody {
+                        inner,
+                        line,
+                        total,
+                        started: Instant::now(),
+                        written: None,
+                    }));
+                }
+                body => {
+                    resp.body = body;
+                    line.print(None);
+                }
+            }
         } else {
             println!("ERROR: StatusCode missing");
         }
     }
+
+    /// The reason of the error responses, the access lines only have the status
+    fn log_error(&self, req: &Request, status: status::Status, message: &str) {
+        if let Some(ref syslog) = self.syslog {
+            syslog.send(
+                if status.is_server_error() {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                &format!(
+                    "{} - {} - {} {} - {}",
+                    req.remote_addr.ip(),
+                    status.to_u16(),
+                    req.method,
+                    decoded_path(req),
+                    message
+                ),
+            );
+        }
+    }
+}
+
+struct LogLine {
+    printer: Option<Arc<Printer>>,
+    syslog: Option<Arc<Syslog>>,
+    log_file: Option<Arc<LogFile>>,
+    time: String,
+    remote: String,
+    status: status::Status,
+    method: String,
+    path: String,
+}
+
+impl LogLine {
+    fn print(&self, transfer: Option<(&str, bool)>) {
+        let status_color = if self.status.is_success() {
+            C_BOLD_GREEN.deref()
+        } else if self.status.is_informational() || self.status.is_redirection() {
+            C_BOLD_YELLOW.deref()
+        } else {
+            C_BOLD_RED.deref()
+        };
+        let mut fmtstr = "[{}] - {} - {} - {} {}".to_owned();
+        // datetime, remote-ip, status-code, method, url-path
+        let status = self.status.to_u16().to_string();
+        let mut args = vec![
+            (self.time.as_str(), &None),
+            (self.remote.as_str(), &None),
+            (status.as_str(), status_color),
+            (self.method.as_str(), &None),
+            (self.path.as_str(), &None),
+        ];
+        if let Some((transfer, aborted)) = transfer {
+            fmtstr.push_str(" - {}");
+            args.push((transfer, if aborted { C_BOLD_RED.deref() } else { &None }));
+        }
+        if let Some(ref printer) = self.printer {
+            printer.println_out(&fmtstr, &args).unwrap();
+        }
+        if self.syslog.is_none() && self.log_file.is_none() {
+            return;
+        }
+        let mut message = format!(
+            "{} - {} - {} {}",
+            self.remote, status, self.method, self.path
+        );
+        if let Some((transfer, _)) = transfer {
+            message.push_str(" - ");
+            message.push_str(transfer);
+        }
+        // Syslog has its own timestamps
+        if let Some(ref syslog) = self.syslog {
+            syslog.send(Severity::Info, &message);
+        }
+        if let Some(ref log_file) = self.log_file {
+            log_file.write_line(&format!("[{}] - {}", self.time, message));
+        }
+    }
+}
+
+/// Logs the request when the body is sent: size, duration and speed, or where the client
+/// went away
+struct LoggedBody {
+    inner: Box<dyn WriteBody>,
+    line: LogLine,
+    total: Option<u64>,
+    started: Instant,
+    // Bytes written and whether it's complete, `None` until it's written
+    written: Option<(u64, bool)>,
+}
+
+impl WriteBody for LoggedBody {
+    fn write_body(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.started = Instant::now();
+        let mut w = CountingWriter { inner: w, sent: 0 };
+        let rv = self.inner.write_body(&mut w).and_then(|_| w.flush());
+        self.written = Some((w.sent, rv.is_ok()));
+        rv
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        let (sent, complete) = match self.written {
+            Some(written) => written,
+            // Not sent at all (e.g. the connection was closed before)
+            None => {
+                self.line.print(None);
+                return;
+            }
+        };
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let speed = if elapsed > 0.0 {
+            format!("{}/s", convert(sent as f64 / elapsed))
+        } else {
+            "-".to_owned()
+        };
+        let transfer = if complete {
+            format!("{} in {:.2}s ({})", convert(sent as f64), elapsed, speed)
+        } else {
+            format!(
+                "aborted after {}{} in {:.2}s ({})",
+                convert(sent as f64),
+                self.total
+                    .map(|total| format!(" of {}", convert(total as f64)))
+                    .unwrap_or_default(),
+                elapsed,
+                speed
+            )
+        };
+        self.line.print(Some((&transfer, !complete)));
+    }
+}
+
+struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    sent: u64,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.sent += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 impl AfterMiddleware for RequestLogger {
-    fn after(&self, req: &mut Request, resp: Response) -> IronResult<Response> {
-        self.log(req, &resp);
+    fn after(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        self.log(req, &mut resp);
+        self.record(req, &mut resp, None);
         Ok(resp)
     }
 
-    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
-        self.log(req, &err.response);
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        self.log(req, &mut err.response);
+        let message = err.error.to_string();
+        self.log_error(
+            req,
+            err.response.status.unwrap_or(status::InternalServerError),
+            &message,
+        );
         if err.response.status == Some(status::Unauthorized) {
+            self.record(req, &mut err.response, None);
             Err(err)
         } else {
-            Ok(error_resp(
+            let mut resp = error_resp(
                 err.response.status.unwrap_or(status::InternalServerError),
-                err.error.description(),
-            ))
+                &message,
+            );
+            // Keep the extra headers of the error (e.g. Retry-After)
+            for header in err.response.headers.iter() {
+                if resp.headers.get_raw(header.name()).is_none() {
+                    resp.headers.set_raw(
+                        header.name().to_owned(),
+                        vec![header.value_string().into_bytes()],
+                    );
+                }
+            }
+            self.record(req, &mut resp, Some(&message));
+            Ok(resp)
         }
     }
 }
+
+fn decoded_path(req: &Request) -> String {
+    percent_decode(req.url.as_ref().path().as_bytes())
+        .decode_utf8_lossy()
+        .to_string()
+}
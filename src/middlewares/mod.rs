@@ -1,10 +1,32 @@
 mod auth;
+mod auth_guard;
+mod client_cert;
 mod compress;
+mod forwarded;
+mod keep_alive;
 mod logger;
+mod no_index;
+mod rate_limit;
+mod security_headers;
+mod session;
 
 // BeforeMiddleware
-pub use self::auth::AuthChecker;
+pub use self::auth::{
+    is_write, parse_credentials, AuthChecker, Credential, User, STATUS_CREDENTIALS, TOKEN_QUERY,
+};
+pub use self::auth_guard::AuthGuard;
+pub use self::client_cert::{ClientCert, ClientCertChecker};
+pub use self::forwarded::{parse_net, ForwardedHeaders};
+pub use self::rate_limit::RateLimiter;
+pub use self::session::{login_page, next_path, Login, Sessions, LOGIN_PATH, LOGOUT_PATH};
 
 // AfterMiddleware
-pub use self::compress::CompressionHandler;
+pub use self::compress::{
+    encoded_etag, is_compressible, negotiate, set_vary, CompressionHandler, DEFAULT_MIN_SIZE,
+};
+pub use self::keep_alive::{connection_accepted, KeepAlive};
 pub use self::logger::RequestLogger;
+pub use self::no_index::NoIndex;
+pub use self::security_headers::{
+    SecurityHeaders, DEFAULT_FRAME_OPTIONS, DEFAULT_HSTS, DEFAULT_REFERRER_POLICY,
+};
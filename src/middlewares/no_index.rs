@@ -0,0 +1,21 @@
+use iron::{AfterMiddleware, IronError, IronResult, Request, Response};
+
+/// Ask the search engines not to index any response (`X-Robots-Tag: noindex`)
+pub struct NoIndex;
+
+fn set_header(resp: &mut Response) {
+    resp.headers
+        .set_raw("X-Robots-Tag", vec![b"noindex, nofollow".to_vec()]);
+}
+
+impl AfterMiddleware for NoIndex {
+    fn after(&self, _: &mut Request, mut resp: Response) -> IronResult<Response> {
+        set_header(&mut resp);
+        Ok(resp)
+    }
+
+    fn catch(&self, _: &mut Request, mut err: IronError) -> IronResult<Response> {
+        set_header(&mut err.response);
+        Err(err)
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use iron::status;
+use iron::{BeforeMiddleware, IronError, IronResult, Request, Response};
+
+use crate::util::StringError;
+
+// Buckets refilled completely are dropped when there are this many
+const PRUNE_THRESHOLD: usize = 10000;
+
+/// Token bucket per client IP, `429 Too Many Requests` when it's empty
+pub struct RateLimiter {
+    // Size of the bucket, also the requests allowed per `period`
+    capacity: f64,
+    period: Duration,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// `60/min`, units: `s`, `min`, `h` (also `sec`, `second`, `minute`, `hour`)
+    pub fn new(s: &str) -> Result<RateLimiter, StringError> {
+        let err = || StringError(format!("Invalid rate limit: {}, example: 60/min", s));
+        let mut parts = s.splitn(2, '/');
+        let count = parts
+            .next()
+            .and_then(|n| n.trim().parse::<u32>().ok())
+            .filter(|n| *n > 0)
+            .ok_or_else(err)?;
+        let seconds = match parts.next().map(|unit| unit.trim().to_lowercase()) {
+            Some(unit) => match unit.as_str() {
+                "s" | "sec" | "second" => 1,
+                "m" | "min" | "minute" => 60,
+                "h" | "hour" => 3600,
+                _ => return Err(err()),
+            },
+            None => return Err(err()),
+        };
+        Ok(RateLimiter {
+            capacity: f64::from(count),
+            period: Duration::from_secs(seconds),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Take a token of `ip`, or the seconds to wait for the next one
+    fn acquire(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let rate = self.capacity / self.period.as_secs_f64();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            let capacity = self.capacity;
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < capacity
+            });
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate)
+            .min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+impl BeforeMiddleware for RateLimiter {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        match self.acquire(req.remote_addr.ip()) {
+            Ok(()) => Ok(()),
+            Err(retry_after) => {
+                let mut resp = Response::with(status::TooManyRequests);
+                resp.headers
+                    .set_raw("Retry-After", vec![retry_after.to_string().into_bytes()]);
+                Err(IronError {
+                    error: Box::new(StringError("Too many requests".to_owned())),
+                    response: resp,
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use iron::{AfterMiddleware, IronError, IronResult, Request, Response};
+
+pub const DEFAULT_HSTS: &str = "max-age=31536000";
+pub const DEFAULT_FRAME_OPTIONS: &str = "SAMEORIGIN";
+pub const DEFAULT_REFERRER_POLICY: &str = "no-referrer";
+
+/// Hardening headers (`--security-headers`, `--hsts`...), those already set by the handler
+/// (CGI scripts) are kept
+pub struct SecurityHeaders {
+    /// `Strict-Transport-Security`, only sent over HTTPS
+    pub hsts: Option<String>,
+    /// `X-Content-Type-Options: nosniff`
+    pub nosniff: bool,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+}
+
+impl SecurityHeaders {
+    fn set_headers(&self, req: &Request, resp: &mut Response) {
+        let mut headers = Vec::new();
+        if req.url.scheme() == "https" {
+            headers.push(("Strict-Transport-Security", self.hsts.as_deref()));
+        }
+        if self.nosniff {
+            headers.push(("X-Content-Type-Options", Some("nosniff")));
+        }
+        headers.push(("X-Frame-Options", self.frame_options.as_deref()));
+        headers.push(("Referrer-Policy", self.referrer_policy.as_deref()));
+        for (name, value) in headers {
+            if let Some(value) = value {
+                if resp.headers.get_raw(name).is_none() {
+                    resp.headers.set_raw(name, vec![value.as_bytes().to_vec()]);
+                }
+            }
+        }
+    }
+}
+
+impl AfterMiddleware for SecurityHeaders {
+    fn after(&self, req: &mut Request, mut resp: Response) -> IronResult<Response> {
+        self.set_headers(req, &mut resp);
+        Ok(resp)
+    }
+
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        self.set_headers(req, &mut err.response);
+        Err(err)
+    }
+}
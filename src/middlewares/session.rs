@@ -0,0 +1,200 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use htmlescape::{encode_attribute, encode_minimal};
+use iron::headers::{ContentType, SetCookie};
+use iron::typemap::Key;
+use iron::{status, Request, Response};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use sha2::Sha256;
+
+use crate::util::{get_cookie, random_token, secure_attribute, set_page_csp};
+
+/// The login form (`--auth-session`), and its target
+pub const LOGIN_PATH: &str = "__login";
+pub const LOGOUT_PATH: &str = "__logout";
+const SESSION_COOKIE: &str = "shs_session";
+
+/// The signed session cookies of the login form: `<expiry>.<user>.<HMAC-SHA256>`, with a key
+/// of the process (a restart logs everyone out)
+pub struct Sessions {
+    key: String,
+    /// In seconds, `--auth-session-max-age`
+    pub max_age: u64,
+}
+
+/// The login form posted by the request, checked by `AuthChecker` for the handler to answer
+/// with the cookie
+pub struct Login {
+    /// `None` with wrong credentials
+    pub username: Option<String>,
+    pub next: String,
+}
+
+impl Key for Login {
+    type Value = Login;
+}
+
+impl Sessions {
+    pub fn new(max_age: u64) -> Sessions {
+        Sessions {
+            key: random_token(32),
+            max_age,
+        }
+    }
+
+    /// The `Set-Cookie` of a new session of `username`, answering `req`
+    pub fn start(&self, req: &Request, resp: &mut Response, username: &str) {
+        let payload = format!(
+            "{}.{}",
+            now() + self.max_age,
+            utf8_percent_encode(username, NON_ALPHANUMERIC)
+        );
+        let cookie = format!(
+            "{}={}.{}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+            SESSION_COOKIE,
+            payload,
+            self.sign(&payload),
+            self.max_age,
+            secure_attribute(req)
+        );
+        resp.headers.set(SetCookie(vec![cookie]));
+    }
+
+    /// The `Set-Cookie` removing the session
+    pub fn end(&self, req: &Request, resp: &mut Response) {
+        resp.headers.set(SetCookie(vec![format!(
+            "{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax{}",
+            SESSION_COOKIE,
+            secure_attribute(req)
+        )]));
+    }
+
+    /// The user of the session cookie of `req`, if signed by this process and not expired
+    pub fn user(&self, req: &Request) -> Option<String> {
+        let cookie = get_cookie(req, SESSION_COOKIE)?;
+        let (payload, signature) = cookie.rsplit_once('.')?;
+        let (expiry, username) = payload.split_once('.')?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&hex_decode(signature)?).ok()?;
+        if expiry.parse::<u64>().ok()? <= now() {
+            return None;
+        }
+        percent_decode_str(username)
+            .decode_utf8()
+            .ok()
+            .map(|username| username.into_owned())
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.key.as_bytes()).expect("HMAC takes keys of any size")
+    }
+}
+
+/// The form, posting to `LOGIN_PATH` and going back to `next` once logged in
+pub fn login_page(s: status::Status, next: &str, message: Option<&str>) -> Response {
+    let nonce = random_token(16);
+    let message = message
+        .map(|message| format!(r#"<p class="error">{}</p>"#, encode_minimal(message)))
+        .unwrap_or_default();
+    let mut resp = Response::with((
+        s,
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>Log in</title>
+  <style nonce="{nonce}">
+    body {{ font-family: sans-serif; max-width: 20em; margin: 4em auto; }}
+    input {{ display: block; width: 100%; margin: 0.3em 0 1em; box-sizing: border-box; }}
+    .error {{ color: red; }}
+  </style>
+</head>
+<body>
+  <h1>Log in</h1>
+  {message}
+  <form method="POST" action="/{login}">
+    <input type="hidden" name="next" value="{next}" />
+    <label>Username <input name="username" autocomplete="username" required autofocus /></label>
+    <label>Password <input name="password" type="password" autocomplete="current-password" required /></label>
+    <button type="submit">Log in</button>
+  </form>
+</body>
+</html>
+"#,
+            nonce = nonce,
+            message = message,
+            login = LOGIN_PATH,
+            next = encode_attribute(next)
+        ),
+    ));
+    resp.headers.set(ContentType::html());
+    set_page_csp(&mut resp, &nonce);
+    resp
+}
+
+/// Where to go back after logging in: a path of this server only, neither `//` nor the `\\` or
+/// control characters the browsers turn into it (`/\t/host`), even percent-encoded
+pub fn next_path(next: &str) -> &str {
+    let local = |path: &str| {
+        path.starts_with('/')
+            && !path.starts_with("//")
+            && !path.contains(|c: char| c == '\\' || c.is_control())
+    };
+    if local(next) && local(&percent_decode_str(next).decode_utf8_lossy()) {
+        next
+    } else {
+        "/"
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_path_of_this_server() {
+        assert_eq!(next_path("/a/b.txt?x=1"), "/a/b.txt?x=1");
+        assert_eq!(next_path("/"), "/");
+        assert_eq!(next_path(""), "/");
+        assert_eq!(next_path("a.txt"), "/");
+        assert_eq!(next_path("https://evil.example/"), "/");
+        assert_eq!(next_path("//evil.example/"), "/");
+        assert_eq!(next_path("/\\evil.example/"), "/");
+        assert_eq!(next_path("/\t/evil.example/"), "/");
+        assert_eq!(next_path("/%09/evil.example/"), "/");
+        assert_eq!(next_path("/%2F/evil.example/"), "/");
+        assert_eq!(next_path("/%5Cevil.example/"), "/");
+        assert_eq!(next_path("/a%20b/"), "/a%20b/");
+    }
+}
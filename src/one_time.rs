@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use iron::status;
+use serde_json::{json, Value};
+
+use crate::atomic_file;
+use crate::util::{random_token, StringError};
+
+/// The one-time links (`--one-time-links`): `/__once/<token>`, and `?one_time` posted to a
+/// file to create one
+pub const ONE_TIME_PATH: &str = "__once";
+pub const ONE_TIME_QUERY: &str = "one_time";
+// Used links answer 410 Gone for this long, then 404
+const USED_KEPT_SECS: i64 = 30 * 86400;
+
+/// The links and the files they download once, saved to a JSON file (across restarts)
+pub struct OneTimeLinks {
+    path: PathBuf,
+    links: Mutex<HashMap<String, Link>>,
+}
+
+struct Link {
+    file: PathBuf,
+    created: i64,
+    used: Option<i64>,
+}
+
+impl OneTimeLinks {
+    /// The links saved in `path`, if it exists already
+    pub fn new(path: &Path) -> Result<OneTimeLinks, StringError> {
+        let err = |e: String| {
+            StringError(format!(
+                "Read one-time links {} failed: {}",
+                path.display(),
+                e
+            ))
+        };
+        let mut links = HashMap::new();
+        match fs::read(path) {
+            Ok(content) => {
+                let saved = serde_json::from_slice::<HashMap<String, Value>>(&content)
+                    .map_err(|e| err(e.to_string()))?;
+                for (token, link) in saved {
+                    let file = match link["file"].as_str() {
+                        Some(file) => PathBuf::from(file),
+                        None => return Err(err(format!("no file of {}", token))),
+                    };
+                    let link = Link {
+                        file,
+                        created: link["created"].as_i64().unwrap_or(0),
+                        used: link["used"].as_i64(),
+                    };
+                    links.insert(token, link);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(err(e.to_string())),
+        }
+        Ok(OneTimeLinks {
+            path: path.to_path_buf(),
+            links: Mutex::new(links),
+        })
+    }
+
+    /// A new link of `file`, its token
+    pub fn create(&self, file: &Path) -> Result<String, StringError> {
+        let token = random_token(32);
+        let mut links = self.links.lock().unwrap();
+        links.insert(
+            token.clone(),
+            Link {
+                file: file.to_path_buf(),
+                created: chrono::Utc::now().timestamp(),
+                used: None,
+            },
+        );
+        self.save(&mut links)?;
+        Ok(token)
+    }
+
+    /// The file of `token`, without using the link
+    pub fn peek(&self, token: &str) -> Result<PathBuf, status::Status> {
+        let links = self.links.lock().unwrap();
+        match links.get(token) {
+            Some(link) if link.used.is_some() => Err(status::Gone),
+            Some(link) if link.file.is_file() => Ok(link.file.clone()),
+            _ => Err(status::NotFound),
+        }
+    }
+
+    /// The file of `token`, the link is used from now on: 404 for unknown links (or of missing
+    /// files), 410 for the used ones
+    pub fn take(&self, token: &str) -> Result<PathBuf, status::Status> {
+        let mut links = self.links.lock().unwrap();
+        let link = links.get_mut(token).ok_or(status::NotFound)?;
+        if link.used.is_some() {
+            return Err(status::Gone);
+        }
+        if !link.file.is_file() {
+            return Err(status::NotFound);
+        }
+        link.used = Some(chrono::Utc::now().timestamp());
+        let file = link.file.clone();
+        if let Err(e) = self.save(&mut links) {
+            eprintln!("{}", e);
+        }
+        Ok(file)
+    }
+
+    // Without the links of removed files, nor those used long ago
+    fn save(&self, links: &mut HashMap<String, Link>) -> Result<(), StringError> {
+        let now = chrono::Utc::now().timestamp();
+        links.retain(|_, link| match link.used {
+            Some(used) => now - used < USED_KEPT_SECS,
+            None => link.file.exists(),
+        });
+        let saved = links
+            .iter()
+            .map(|(token, link)| {
+                let link = json!({
+                    "file": link.file.to_string_lossy(),
+                    "created": link.created,
+                    "used": link.used,
+                });
+                (token.clone(), link)
+            })
+            .collect::<serde_json::Map<String, Value>>();
+        let content = Value::Object(saved).to_string();
+        atomic_file::save(&mut content.as_bytes(), &self.path).map_err(|e| {
+            StringError(format!(
+                "Save one-time links {} failed: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use igd_next::{search_gateway, PortMappingProtocol, SearchOptions};
+use natpmp::{Natpmp, Response};
+
+use crate::util::StringError;
+
+// Leased mappings expire by themselves when the server is gone
+const LEASE_SECONDS: u32 = 3600;
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
+const DESCRIPTION: &str = "simple-http-server";
+
+/// Ask the router to forward `port` to this host (`--public`), by UPnP IGD or else NAT-PMP.
+/// Returns the external address, the mapping is renewed in the background.
+pub fn start(port: u16) -> Result<SocketAddr, StringError> {
+    let external = map_port(port)?;
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(u64::from(LEASE_SECONDS) / 2));
+        if let Err(e) = map_port(port) {
+            eprintln!("Renew port mapping failed: {}", e);
+        }
+    });
+    Ok(external)
+}
+
+fn map_port(port: u16) -> Result<SocketAddr, StringError> {
+    map_port_upnp(port).or_else(|upnp_err| {
+        map_port_natpmp(port).map_err(|natpmp_err| {
+            StringError(format!(
+                "Port mapping failed, UPnP: {}, NAT-PMP: {}",
+                upnp_err, natpmp_err
+            ))
+        })
+    })
+}
+
+fn map_port_upnp(port: u16) -> Result<SocketAddr, String> {
+    let gateway = search_gateway(SearchOptions {
+        timeout: Some(SEARCH_TIMEOUT),
+        ..Default::default()
+    })
+    .map_err(|e| e.to_string())?;
+    // The address of the interface facing the router
+    let local_ip = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect(gateway.addr)?;
+            socket.local_addr()
+        })
+        .map_err(|e| e.to_string())?
+        .ip();
+    gateway
+        .add_port(
+            PortMappingProtocol::TCP,
+            port,
+            SocketAddr::new(local_ip, port),
+            LEASE_SECONDS,
+            DESCRIPTION,
+        )
+        .map_err(|e| e.to_string())?;
+    let external_ip = gateway.get_external_ip().map_err(|e| e.to_string())?;
+    Ok(SocketAddr::new(external_ip, port))
+}
+
+fn map_port_natpmp(port: u16) -> Result<SocketAddr, String> {
+    let mut client = Natpmp::new().map_err(|e| format!("{:?}", e))?;
+    client
+        .send_public_address_request()
+        .map_err(|e| format!("{:?}", e))?;
+    let external_ip = match natpmp_response(&mut client)? {
+        Response::Gateway(gateway) => *gateway.public_address(),
+        _ => return Err("unexpected response".to_owned()),
+    };
+    client
+        .send_port_mapping_request(natpmp::Protocol::TCP, port, port, LEASE_SECONDS)
+        .map_err(|e| format!("{:?}", e))?;
+    match natpmp_response(&mut client)? {
+        Response::TCP(mapping) => Ok(SocketAddr::new(
+            IpAddr::V4(external_ip),
+            mapping.public_port(),
+        )),
+        _ => Err("unexpected response".to_owned()),
+    }
+}
+
+fn natpmp_response(client: &mut Natpmp) -> Result<Response, String> {
+    let started = Instant::now();
+    loop {
+        match client.read_response_or_retry() {
+            Err(natpmp::Error::NATPMP_TRYAGAIN) if started.elapsed() < SEARCH_TIMEOUT => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(format!("{:?}", e)),
+            Ok(response) => return Ok(response),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::util::StringError;
+
+/// Switch to an unprivileged account (`--user`, `--group`), after the ports are bound
+#[cfg(unix)]
+pub fn drop_privileges(user: Option<&str>, group: Option<&str>) -> Result<(), StringError> {
+    use std::ffi::CString;
+
+    let user = match user {
+        Some(name) => Some(lookup_user(name)?),
+        None => None,
+    };
+    let gid = match group {
+        Some(name) => Some(lookup_group(name)?),
+        // The primary group of the user
+        None => user.as_ref().map(|&(_, gid, _)| gid),
+    };
+
+    unsafe {
+        if let Some(gid) = gid {
+            let rv = match user {
+                Some((_, _, ref name)) => {
+                    let name = CString::new(name.as_str()).unwrap();
+                    libc::initgroups(name.as_ptr(), gid as _)
+                }
+                None => libc::setgroups(1, &gid),
+            };
+            if rv != 0 {
+                return Err(last_error("setgroups"));
+            }
+            if libc::setgid(gid) != 0 {
+                return Err(last_error("setgid"));
+            }
+        }
+        if let Some((uid, _, _)) = user {
+            if libc::setuid(uid) != 0 {
+                return Err(last_error("setuid"));
+            }
+            if uid != 0 && libc::setuid(0) == 0 {
+                return Err(StringError("Privileges can still be regained".to_owned()));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn drop_privileges(_user: Option<&str>, _group: Option<&str>) -> Result<(), StringError> {
+    Err(StringError(
+        "--user/--group are not supported on this platform".to_owned(),
+    ))
+}
+
+/// (uid, primary gid, name) of a user name or numeric id
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Result<(libc::uid_t, libc::gid_t, String), StringError> {
+    use std::ffi::{CStr, CString};
+
+    let entry = unsafe {
+        match name.parse::<libc::uid_t>() {
+            Ok(uid) => libc::getpwuid(uid),
+            Err(_) => {
+                let c_name = CString::new(name)
+                    .map_err(|_| StringError(format!("Invalid user: {}", name)))?;
+                libc::getpwnam(c_name.as_ptr())
+            }
+        }
+    };
+    if entry.is_null() {
+        return Err(StringError(format!("No such user: {}", name)));
+    }
+    unsafe {
+        let entry = &*entry;
+        let name = CStr::from_ptr(entry.pw_name).to_string_lossy().to_string();
+        Ok((entry.pw_uid, entry.pw_gid, name))
+    }
+}
+
+/// gid of a group name or numeric id
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Result<libc::gid_t, StringError> {
+    use std::ffi::CString;
+
+    if let Ok(gid) = name.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+    let c_name = CString::new(name).map_err(|_| StringError(format!("Invalid group: {}", name)))?;
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(StringError(format!("No such group: {}", name)));
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(unix)]
+fn last_error(call: &str) -> StringError {
+    StringError(format!(
+        "{} failed: {}",
+        call,
+        std::io::Error::last_os_error()
+    ))
+}
@@ -0,0 +1,225 @@
+use std::path::{Component, Path, PathBuf};
+
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
+use iron::headers::{ContentType, Headers, Location, SetCookie};
+use iron::method::Method;
+use iron::{status, IronError, IronResult, Request, Response, Url};
+use sha2::Sha256;
+
+use crate::util::{
+    header_cookie, random_token, same_secret, secure_attribute, set_page_csp, StringError,
+};
+
+/// The password of a protected path in the query, as submitted by the form of `password_page`
+pub const PASSWORD_QUERY: &str = "pw";
+const COOKIE_PREFIX: &str = "shs_pw_";
+
+/// The directories and files with a password of their own (`--protect-path /secret=pw`),
+/// apart from `--auth`: asked by a form, then remembered by a cookie signed with a key of the
+/// process
+pub struct ProtectedPaths {
+    key: String,
+    // Relative to the root, the deepest first: its password only
+    paths: Vec<(PathBuf, String)>,
+}
+
+impl ProtectedPaths {
+    pub fn new(specs: &[&str]) -> Result<ProtectedPaths, StringError> {
+        let mut paths = Vec::new();
+        for spec in specs {
+            let (path, password) = parse(spec).ok_or_else(|| {
+                StringError(format!(
+                    "Invalid protected path {}, expected /path=password",
+                    spec
+                ))
+            })?;
+            paths.push((path, password.to_owned()));
+        }
+        paths.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+        Ok(ProtectedPaths {
+            key: random_token(32),
+            paths,
+        })
+    }
+
+    /// 401 with the password form, unless `req` has the password of the protected path of
+    /// `path` (inside `root`), by the query or the cookie
+    pub fn check(&self, req: &Request, root: &Path, path: &Path) -> IronResult<()> {
+        self.check_parts(&req.url, &req.headers, root, path)
+    }
+
+    /// `check` by the URL and headers of a request, while its body is read
+    pub fn check_parts(
+        &self,
+        url: &Url,
+        headers: &Headers,
+        root: &Path,
+        path: &Path,
+    ) -> IronResult<()> {
+        let index = match self.find(root, path) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let password = query_password(url);
+        if password
+            .as_deref()
+            .is_some_and(|password| self.is_password(index, password))
+            || self.has_cookie(headers, index)
+        {
+            return Ok(());
+        }
+        let message = password.map(|_| "Wrong password.");
+        Err(IronError {
+            error: Box::new(StringError("password required".to_owned())),
+            response: password_page(message),
+        })
+    }
+
+    /// The password checked already in the query of a browser: back to the page without it,
+    /// with the cookie
+    pub fn unlock(&self, req: &Request, root: &Path, path: &Path) -> Option<Response> {
+        let index = self.find(root, path)?;
+        let password = query_password(&req.url);
+        if req.method != Method::Get
+            || !accepts_html(req)
+            || !password
+                .as_deref()
+                .is_some_and(|password| self.is_password(index, password))
+        {
+            return None;
+        }
+        let query = req
+            .url
+            .as_ref()
+            .query_pairs()
+            .filter(|(k, _)| k != PASSWORD_QUERY)
+            .collect::<Vec<_>>();
+        let mut location = format!("/{}", req.url.path().join("/"));
+        if !query.is_empty() {
+            location.push('?');
+            location.push_str(
+                &url::form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(query)
+                    .finish(),
+            );
+        }
+        let cookie = format!(
+            "{}{}={}; Path=/; HttpOnly; SameSite=Lax{}",
+            COOKIE_PREFIX,
+            index,
+            self.sign(index),
+            secure_attribute(req)
+        );
+        let mut resp = Response::with(status::SeeOther);
+        resp.headers.set(Location(location));
+        resp.headers.set(SetCookie(vec![cookie]));
+        Some(resp)
+    }
+
+    // The protected path of `path`, its index
+    fn find(&self, root: &Path, path: &Path) -> Option<usize> {
+        let relative = path.strip_prefix(root).ok()?;
+        self.paths
+            .iter()
+            .position(|(protected, _)| relative.starts_with(protected))
+    }
+
+    fn has_cookie(&self, headers: &Headers, index: usize) -> bool {
+        header_cookie(headers, &format!("{}{}", COOKIE_PREFIX, index))
+            .is_some_and(|cookie| same_secret(&cookie, &self.sign(index)))
+    }
+
+    fn is_password(&self, index: usize, password: &str) -> bool {
+        same_secret(password, &self.paths[index].1)
+    }
+
+    // Of the path and its password, a changed password needs the new one
+    fn sign(&self, index: usize) -> String {
+        let (path, password) = &self.paths[index];
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.as_bytes())
+            .expect("HMAC takes keys of any size");
+        mac.update(path.to_string_lossy().as_bytes());
+        mac.update(b"\0");
+        mac.update(password.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+// `/path=password`, the path without `..`
+fn parse(spec: &str) -> Option<(PathBuf, &str)> {
+    let (path, password) = spec.split_once('=')?;
+    if !path.starts_with('/') || password.is_empty() {
+        return None;
+    }
+    let path = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<PathBuf>();
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some((path, password))
+}
+
+fn query_password(url: &Url) -> Option<String> {
+    url.as_ref()
+        .query_pairs()
+        .find(|(k, _)| k == PASSWORD_QUERY)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn accepts_html(req: &Request) -> bool {
+    req.headers
+        .get_raw("Accept")
+        .and_then(|values| values.first())
+        .is_some_and(|value| String::from_utf8_lossy(value).contains("text/html"))
+}
+
+// Submitted to the same page, with the password in the query
+fn password_page(message: Option<&str>) -> Response {
+    let nonce = random_token(16);
+    let message = message
+        .map(|message| format!(r#"<p class="error">{}</p>"#, encode_minimal(message)))
+        .unwrap_or_default();
+    let mut resp = Response::with((
+        status::Unauthorized,
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>Password required</title>
+  <style nonce="{nonce}">
+    body {{ font-family: sans-serif; max-width: 20em; margin: 4em auto; }}
+    input {{ display: block; width: 100%; margin: 0.3em 0 1em; box-sizing: border-box; }}
+    .error {{ color: red; }}
+  </style>
+</head>
+<body>
+  <h1>Password required</h1>
+  {message}
+  <form method="GET">
+    <label>Password <input name="{query}" type="password" autocomplete="current-password" required autofocus /></label>
+    <button type="submit">Open</button>
+  </form>
+</body>
+</html>
+"#,
+            nonce = nonce,
+            message = message,
+            query = PASSWORD_QUERY
+        ),
+    ));
+    resp.headers.set(ContentType::html());
+    set_page_csp(&mut resp, &nonce);
+    resp
+}
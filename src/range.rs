@@ -0,0 +1,126 @@
+use iron::headers::{ByteRangeSpec, Range};
+
+/// What a `Range` request is answered with (RFC 7233)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeSelection {
+    /// 200 with the whole file: another unit, disjoint ranges, or an empty file
+    Full,
+    /// 206 with `length` bytes from `offset`
+    Partial { offset: u64, length: u64 },
+    /// 416, none of the ranges is within the file
+    Unsatisfiable,
+}
+
+/// The selection of `range` over a file of `size` bytes. The satisfiable ranges are merged
+/// into a single part when they overlap or touch, the disjoint ones get the whole file
+/// (no `multipart/byteranges`)
+pub fn select(range: &Range, size: u64) -> RangeSelection {
+    let specs = match *range {
+        Range::Bytes(ref specs) => specs,
+        // Ignored, as any unknown unit
+        Range::Unregistered(..) => return RangeSelection::Full,
+    };
+    // Nothing to select from, `bytes=0-` of the download managers included: the empty body
+    // answers them better than 416 does
+    if size == 0 {
+        return RangeSelection::Full;
+    }
+    let mut bounds = specs
+        .iter()
+        .filter_map(|spec| spec_bounds(spec, size))
+        .collect::<Vec<(u64, u64)>>();
+    if bounds.is_empty() {
+        return RangeSelection::Unsatisfiable;
+    }
+    bounds.sort_unstable();
+    let (first, mut last) = bounds[0];
+    for &(start, end) in &bounds[1..] {
+        if start > last + 1 {
+            return RangeSelection::Full;
+        }
+        last = last.max(end);
+    }
+    RangeSelection::Partial {
+        offset: first,
+        length: last - first + 1,
+    }
+}
+
+// The first and the last byte of `spec` in the file, `None` when unsatisfiable
+fn spec_bounds(spec: &ByteRangeSpec, size: u64) -> Option<(u64, u64)> {
+    match *spec {
+        // "x-y", the end past the file is clamped
+        ByteRangeSpec::FromTo(first, last) if first < size => Some((first, last.min(size - 1))),
+        // "x-"
+        ByteRangeSpec::AllFrom(first) if first < size => Some((first, size - 1)),
+        // "-x", a longer suffix is the whole file, "-0" is nothing
+        ByteRangeSpec::Last(length) if length > 0 => Some((size - length.min(size), size - 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(specs: &[ByteRangeSpec], size: u64) -> RangeSelection {
+        select(&Range::Bytes(specs.to_vec()), size)
+    }
+
+    #[test]
+    fn single_ranges() {
+        use self::ByteRangeSpec::*;
+        let partial = |offset, length| RangeSelection::Partial { offset, length };
+        assert_eq!(bytes(&[FromTo(0, 9)], 100), partial(0, 10));
+        // Clamped to the end of the file
+        assert_eq!(bytes(&[FromTo(90, 200)], 100), partial(90, 10));
+        assert_eq!(bytes(&[AllFrom(95)], 100), partial(95, 5));
+        assert_eq!(bytes(&[Last(10)], 100), partial(90, 10));
+        assert_eq!(bytes(&[Last(200)], 100), partial(0, 100));
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        use self::ByteRangeSpec::*;
+        assert_eq!(
+            bytes(&[FromTo(100, 200)], 100),
+            RangeSelection::Unsatisfiable
+        );
+        assert_eq!(bytes(&[AllFrom(100)], 100), RangeSelection::Unsatisfiable);
+        assert_eq!(bytes(&[Last(0)], 100), RangeSelection::Unsatisfiable);
+        // But an empty file is sent whole
+        assert_eq!(bytes(&[AllFrom(0)], 0), RangeSelection::Full);
+    }
+
+    #[test]
+    fn several_ranges() {
+        use self::ByteRangeSpec::*;
+        // Overlapping or touching ranges are merged, in any order
+        assert_eq!(
+            bytes(&[FromTo(10, 19), FromTo(0, 9), FromTo(5, 12)], 100),
+            RangeSelection::Partial {
+                offset: 0,
+                length: 20
+            }
+        );
+        // The unsatisfiable ones are skipped
+        assert_eq!(
+            bytes(&[FromTo(200, 300), Last(5)], 100),
+            RangeSelection::Partial {
+                offset: 95,
+                length: 5
+            }
+        );
+        assert_eq!(
+            bytes(&[FromTo(0, 9), FromTo(20, 29)], 100),
+            RangeSelection::Full
+        );
+        assert_eq!(
+            select(
+                &Range::Unregistered("lines".to_owned(), "1-2".to_owned()),
+                100
+            ),
+            RangeSelection::Full
+        );
+    }
+}
@@ -0,0 +1,649 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
+use iron::status;
+use iron::{Request, Response};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+use crate::middlewares::Credential;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+const MAX_KEYS: usize = 1000;
+// Signed requests older (or newer) than this are refused
+const MAX_CLOCK_SKEW: i64 = 15 * 60;
+// URI encoding of the signatures (everything but the unreserved characters)
+const URI_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+const KEY_ENCODE_SET: &AsciiSet = &URI_ENCODE_SET.remove(b'/');
+
+/// What an S3 request is about
+pub enum S3Route {
+    ListBuckets,
+    Bucket,
+    Object(String),
+}
+
+/// `/<bucket>` and `/<bucket>/<key>`, or `/` when it's signed by an S3 client (ListBuckets)
+pub fn route(req: &Request, bucket: &str) -> Option<S3Route> {
+    let path = req.url.path();
+    if path == [""] {
+        let signed = req
+            .headers
+            .get_raw("Authorization")
+            .and_then(|values| values.first())
+            .is_some_and(|value| value.starts_with(ALGORITHM.as_bytes()));
+        return if signed {
+            Some(S3Route::ListBuckets)
+        } else {
+            None
+        };
+    }
+    if path[0] != bucket {
+        return None;
+    }
+    let key = path[1..]
+        .iter()
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if key.is_empty() {
+        Some(S3Route::Bucket)
+    } else {
+        Some(S3Route::Object(key))
+    }
+}
+
+/// Error responses, in the XML format of S3
+pub struct S3Error {
+    pub status: status::Status,
+    code: &'static str,
+    pub message: String,
+}
+
+impl S3Error {
+    pub fn new(status: status::Status, code: &'static str, message: &str) -> S3Error {
+        S3Error {
+            status,
+            code,
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn not_found(key: &str) -> S3Error {
+        S3Error::new(status::NotFound, "NoSuchKey", key)
+    }
+
+    pub fn access_denied() -> S3Error {
+        S3Error::new(status::Forbidden, "AccessDenied", "Access Denied")
+    }
+
+    pub fn not_implemented() -> S3Error {
+        S3Error::new(
+            status::NotImplemented,
+            "NotImplemented",
+            "Not supported by this server",
+        )
+    }
+
+    pub fn response(&self) -> Response {
+        xml_response(
+            self.status,
+            format!(
+                "<Error><Code>{}</Code><Message>{}</Message></Error>",
+                self.code,
+                encode_minimal(&self.message)
+            ),
+        )
+    }
+}
+
+impl From<io::Error> for S3Error {
+    fn from(err: io::Error) -> S3Error {
+        match err.kind() {
+            io::ErrorKind::NotFound => S3Error::new(status::NotFound, "NoSuchKey", "Not Found"),
+            io::ErrorKind::PermissionDenied => {
+                S3Error::new(status::Forbidden, "AccessDenied", "Permission denied")
+            }
+            _ => S3Error::new(
+                status::InternalServerError,
+                "InternalError",
+                &err.to_string(),
+            ),
+        }
+    }
+}
+
+fn xml_response(status: status::Status, body: String) -> Response {
+    let mut resp = Response::with((
+        status,
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?>{}"#, body),
+    ));
+    resp.headers
+        .set_raw("Content-Type", vec![b"application/xml".to_vec()]);
+    resp
+}
+
+/// How the body of a signed request is protected (`x-amz-content-sha256`)
+pub enum Payload {
+    Unsigned,
+    /// `aws-chunked` encoding, the chunk signatures aren't verified
+    Streaming,
+    Sha256(String),
+}
+
+/// The S3 API over the root directory (`--s3 <bucket>`), keyed by the `--auth` users
+pub struct S3Gateway {
+    pub bucket: String,
+    credentials: Vec<Credential>,
+    protect_read: bool,
+}
+
+impl S3Gateway {
+    pub fn new(bucket: String, credentials: Vec<Credential>, protect_read: bool) -> S3Gateway {
+        S3Gateway {
+            bucket,
+            credentials,
+            protect_read,
+        }
+    }
+
+    /// Checks the AWS Signature V4 of the request (access key = username, secret = password)
+    pub fn authenticate(&self, req: &Request, write: bool) -> Result<Payload, S3Error> {
+        let payload = match header(req, "x-amz-content-sha256").as_deref() {
+            Some("UNSIGNED-PAYLOAD") | None => Payload::Unsigned,
+            Some(value) if value.starts_with("STREAMING-") => Payload::Streaming,
+            Some(value) => Payload::Sha256(value.to_lowercase()),
+        };
+        let needs_auth = !self.credentials.is_empty() && (write || self.protect_read);
+        let authorization = match header(req, "Authorization") {
+            Some(value) if value.starts_with(ALGORITHM) => value,
+            _ if !needs_auth => return Ok(payload),
+            Some(_) => {
+                return Err(S3Error::new(
+                    status::BadRequest,
+                    "InvalidArgument",
+                    "Only AWS4-HMAC-SHA256 signatures are supported",
+                ))
+            }
+            None => {
+                return Err(S3Error::new(
+                    status::Forbidden,
+                    "AccessDenied",
+                    "Anonymous access is forbidden",
+                ))
+            }
+        };
+        if self.credentials.is_empty() {
+            return Ok(payload);
+        }
+        let malformed = || {
+            S3Error::new(
+                status::BadRequest,
+                "AuthorizationHeaderMalformed",
+                "Malformed Authorization header",
+            )
+        };
+        let mut credential = None;
+        let mut signed_headers = None;
+        let mut signature = None;
+        for part in authorization[ALGORITHM.len()..].split(',') {
+            match part.trim().split_once('=') {
+                Some(("Credential", value)) => credential = Some(value),
+                Some(("SignedHeaders", value)) => signed_headers = Some(value),
+                Some(("Signature", value)) => signature = Some(value),
+                _ => {}
+            }
+        }
+        let (credential, signed_headers, signature) = match (credential, signed_headers, signature)
+        {
+            (Some(a), Some(b), Some(c)) => (a, b, c),
+            _ => return Err(malformed()),
+        };
+        // <access key>/<date>/<region>/s3/aws4_request
+        let scope = credential.splitn(2, '/').collect::<Vec<&str>>();
+        if scope.len() != 2 || scope[1].split('/').count() != 4 {
+            return Err(malformed());
+        }
+        let (access_key, scope) = (scope[0], scope[1]);
+        let user = self
+            .credentials
+            .iter()
+            .find(|c| c.username == access_key)
+            .ok_or_else(|| {
+                S3Error::new(
+                    status::Forbidden,
+                    "InvalidAccessKeyId",
+                    "The access key does not exist",
+                )
+            })?;
+
+        let amz_date = header(req, "x-amz-date").ok_or_else(malformed)?;
+        let time =
+            NaiveDateTime::parse_from_str(&amz_date, "%Y%m%dT%H%M%SZ").map_err(|_| malformed())?;
+        if (Utc::now().timestamp() - time.timestamp()).abs() > MAX_CLOCK_SKEW {
+            return Err(S3Error::new(
+                status::Forbidden,
+                "RequestTimeTooSkewed",
+                "The difference between the request time and the server's time is too large",
+            ));
+        }
+
+        let mut canonical_headers = String::new();
+        for name in signed_headers.split(';') {
+            let value = req
+                .headers
+                .get_raw(name)
+                .map(|values| {
+                    values
+                        .iter()
+                        .map(|value| {
+                            String::from_utf8_lossy(value)
+                                .split_whitespace()
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .unwrap_or_default();
+            canonical_headers.push_str(&format!("{}:{}\n", name, value));
+        }
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method,
+            canonical_uri(req),
+            canonical_query(req),
+            canonical_headers,
+            signed_headers,
+            header(req, "x-amz-content-sha256").unwrap_or_else(|| "UNSIGNED-PAYLOAD".to_owned())
+        );
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{:x}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            Sha256::digest(canonical_request.as_bytes())
+        );
+        let mut key = format!("AWS4{}", user.password).into_bytes();
+        for part in scope.split('/') {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let expected = hmac_sha256(&key, string_to_sign.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        if expected != signature {
+            return Err(S3Error::new(
+                status::Forbidden,
+                "SignatureDoesNotMatch",
+                "The request signature does not match",
+            ));
+        }
+        // Writes need `w`, reads need `r` when downloads aren't anonymous
+        if (write && !user.write) || (!write && self.protect_read && !user.read) {
+            return Err(S3Error::new(
+                status::Forbidden,
+                "AccessDenied",
+                "Access Denied",
+            ));
+        }
+        Ok(payload)
+    }
+
+    /// ListBuckets, the only bucket is the root directory
+    pub fn list_buckets(&self, root: &Path) -> Response {
+        let created = fs::metadata(root)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(UNIX_EPOCH);
+        xml_response(
+            status::Ok,
+            format!(
+                r#"<ListAllMyBucketsResult xmlns="{}"><Owner><ID>simple-http-server</ID></Owner><Buckets><Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket></Buckets></ListAllMyBucketsResult>"#,
+                NAMESPACE,
+                encode_minimal(&self.bucket),
+                iso8601(created)
+            ),
+        )
+    }
+
+    pub fn location(&self) -> Response {
+        xml_response(
+            status::Ok,
+            format!(
+                r#"<LocationConstraint xmlns="{}"></LocationConstraint>"#,
+                NAMESPACE
+            ),
+        )
+    }
+
+    /// ListObjects (V1) and ListObjectsV2 (`list-type=2`) of the root directory
+    pub fn list_objects<F>(&self, req: &Request, root: &Path, skip: F) -> Result<Response, S3Error>
+    where
+        F: Fn(&Path, bool) -> bool,
+    {
+        let query = req
+            .url
+            .as_ref()
+            .query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        let param = |name: &str| {
+            query
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_owned())
+        };
+        let v2 = param("list-type").as_deref() == Some("2");
+        let prefix = param("prefix").unwrap_or_default();
+        let delimiter = param("delimiter").unwrap_or_default();
+        let url_encoding = param("encoding-type").as_deref() == Some("url");
+        let max_keys = match param("max-keys") {
+            Some(s) => s
+                .parse::<usize>()
+                .map_err(|_| S3Error::new(status::BadRequest, "InvalidArgument", "max-keys"))?
+                .min(MAX_KEYS),
+            None => MAX_KEYS,
+        };
+        let marker = if v2 {
+            param("continuation-token").or_else(|| param("start-after"))
+        } else {
+            param("marker")
+        }
+        .unwrap_or_default();
+
+        // Only the directory of the prefix is walked, one level with the `/` delimiter
+        let prefix_dir = match prefix.rfind('/') {
+            Some(index) => &prefix[..=index],
+            None => "",
+        };
+        let mut items = Vec::new();
+        let dir = root.join(prefix_dir);
+        if !prefix_dir.split('/').any(|segment| segment == "..") && dir.is_dir() {
+            collect_objects(&dir, prefix_dir, delimiter != "/", &skip, &mut items)?;
+        }
+        items.retain(|item| item.key.starts_with(&prefix));
+        if !delimiter.is_empty() && delimiter != "/" {
+            for item in items.iter_mut() {
+                if let Some(index) = item.key[prefix.len()..].find(&delimiter) {
+                    item.key.truncate(prefix.len() + index + delimiter.len());
+                    item.size = None;
+                }
+            }
+        }
+        items.sort_by(|a, b| a.key.cmp(&b.key));
+        items.dedup_by(|a, b| a.key == b.key);
+        items.retain(|item| item.key > marker);
+        let truncated = items.len() > max_keys;
+        items.truncate(max_keys);
+
+        let encode = |s: &str| {
+            if url_encoding {
+                utf8_percent_encode(s, KEY_ENCODE_SET).to_string()
+            } else {
+                encode_minimal(s)
+            }
+        };
+        let mut body = format!(
+            r#"<ListBucketResult xmlns="{}"><Name>{}</Name><Prefix>{}</Prefix><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>"#,
+            NAMESPACE,
+            encode_minimal(&self.bucket),
+            encode(&prefix),
+            max_keys,
+            truncated
+        );
+        if !delimiter.is_empty() {
+            body.push_str(&format!("<Delimiter>{}</Delimiter>", encode(&delimiter)));
+        }
+        if url_encoding {
+            body.push_str("<EncodingType>url</EncodingType>");
+        }
+        if v2 {
+            body.push_str(&format!("<KeyCount>{}</KeyCount>", items.len()));
+            if let Some(token) = param("continuation-token") {
+                body.push_str(&format!(
+                    "<ContinuationToken>{}</ContinuationToken>",
+                    encode_minimal(&token)
+                ));
+            }
+            if let Some(start_after) = param("start-after") {
+                body.push_str(&format!(
+                    "<StartAfter>{}</StartAfter>",
+                    encode(&start_after)
+                ));
+            }
+        } else {
+            body.push_str(&format!("<Marker>{}</Marker>", encode(&marker)));
+        }
+        if truncated {
+            let last = &items.last().unwrap().key;
+            if v2 {
+                body.push_str(&format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    encode_minimal(last)
+                ));
+            } else {
+                body.push_str(&format!("<NextMarker>{}</NextMarker>", encode(last)));
+            }
+        }
+        for item in &items {
+            match item.size {
+                Some(size) => body.push_str(&format!(
+                    "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                    encode(&item.key),
+                    iso8601(item.modified),
+                    encode_minimal(&etag(size, item.modified)),
+                    size
+                )),
+                None => body.push_str(&format!(
+                    "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                    encode(&item.key)
+                )),
+            }
+        }
+        body.push_str("</ListBucketResult>");
+        Ok(xml_response(status::Ok, body))
+    }
+}
+
+// An object, or a common prefix (`size` is `None`)
+struct Item {
+    key: String,
+    size: Option<u64>,
+    modified: SystemTime,
+}
+
+fn collect_objects<F>(
+    dir: &Path,
+    key_prefix: &str,
+    recursive: bool,
+    skip: &F,
+    items: &mut Vec<Item>,
+) -> io::Result<()>
+where
+    F: Fn(&Path, bool) -> bool,
+{
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            // Broken link
+            Err(_) => continue,
+        };
+        if skip(&path, metadata.is_dir()) {
+            continue;
+        }
+        let key = format!("{}{}", key_prefix, entry.file_name().to_string_lossy());
+        if metadata.is_dir() {
+            if recursive {
+                // Linked directories could loop
+                if !entry.file_type()?.is_symlink() {
+                    collect_objects(&path, &format!("{}/", key), true, skip, items)?;
+                }
+            } else {
+                items.push(Item {
+                    key: format!("{}/", key),
+                    size: None,
+                    modified: UNIX_EPOCH,
+                });
+            }
+        } else {
+            items.push(Item {
+                key,
+                size: Some(metadata.len()),
+                modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Same as the `ETag` of the downloads, not an MD5 digest (clients skip the check with the `-`)
+pub fn etag(size: u64, modified: SystemTime) -> String {
+    let secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}.0\"", size, secs)
+}
+
+fn iso8601(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string()
+}
+
+fn header(req: &Request, name: &str) -> Option<String> {
+    req.headers
+        .get_raw(name)
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn canonical_uri(req: &Request) -> String {
+    let segments = req
+        .url
+        .path()
+        .iter()
+        .map(|segment| {
+            let decoded = percent_decode_str(segment).collect::<Vec<u8>>();
+            percent_encoding::percent_encode(&decoded, URI_ENCODE_SET).to_string()
+        })
+        .collect::<Vec<_>>();
+    format!("/{}", segments.join("/"))
+}
+
+fn canonical_query(req: &Request) -> String {
+    let mut pairs = req
+        .url
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            let encode = |s: &str| {
+                let decoded = percent_decode_str(s).collect::<Vec<u8>>();
+                percent_encoding::percent_encode(&decoded, URI_ENCODE_SET).to_string()
+            };
+            (encode(k), encode(v))
+        })
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The body of PutObject, decoded from `aws-chunked` and checked against its digest
+pub fn object_body<'a, R: Read + 'a>(body: R, payload: Payload) -> Box<dyn Read + 'a> {
+    match payload {
+        Payload::Unsigned => Box::new(body),
+        Payload::Streaming => Box::new(ChunkedReader {
+            inner: BufReader::new(body),
+            remaining: 0,
+            done: false,
+        }),
+        Payload::Sha256(expected) => Box::new(VerifiedReader {
+            inner: body,
+            hasher: Sha256::new(),
+            expected,
+        }),
+    }
+}
+
+// `<hex size>;chunk-signature=<signature>\r\n<data>\r\n`, until a chunk of size 0
+struct ChunkedReader<R> {
+    inner: BufReader<R>,
+    remaining: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid aws-chunked body");
+        if self.done {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+            // After the data of the previous chunk
+            if line == "\r\n" {
+                line.clear();
+                self.inner.read_line(&mut line)?;
+            }
+            let size = line.split(';').next().unwrap_or("").trim();
+            self.remaining = usize::from_str_radix(size, 16).map_err(|_| invalid())?;
+            if self.remaining == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(invalid());
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+struct VerifiedReader<R> {
+    inner: R,
+    hasher: Sha256,
+    expected: String,
+}
+
+impl<R: Read> Read for VerifiedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        if n == 0 && format!("{:x}", self.hasher.clone().finalize()) != self.expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "XAmzContentSHA256Mismatch",
+            ));
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,1282 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::net::NetworkListener;
+use iron::{Chain, Iron, Listening, Protocol};
+use iron_cors::CorsMiddleware;
+
+use crate::access::AccessRules;
+use crate::audit::AuditLog;
+use crate::cgi::CgiDir;
+use crate::checksum::ChecksumCache;
+use crate::color::Printer;
+use crate::connection_limit::{ConnectionLimiter, LimitedListener};
+use crate::expiry::Expiry;
+use crate::fastcgi::FastCgi;
+use crate::handler::{EtagMode, LiveSettings, MainHandler, SharedHandler};
+use crate::head_limit::{HeadLimitListener, HeadLimits};
+use crate::health::HealthCheck;
+use crate::highlight::Highlighter;
+use crate::hooks::{Hook, Hooks};
+use crate::jwt::JwtValidator;
+use crate::listener::{self, QueuedListener, SocketOptions};
+use crate::listing_cache::ListingCache;
+use crate::log_file::LogFile;
+use crate::middlewares::{
+    parse_credentials, AuthChecker, AuthGuard, ClientCertChecker, CompressionHandler,
+    ForwardedHeaders, KeepAlive, NoIndex, RateLimiter, RequestLogger, SecurityHeaders, Sessions,
+    DEFAULT_FRAME_OPTIONS, DEFAULT_HSTS, DEFAULT_MIN_SIZE, DEFAULT_REFERRER_POLICY,
+    STATUS_CREDENTIALS,
+};
+use crate::one_time::OneTimeLinks;
+use crate::protect::ProtectedPaths;
+use crate::s3::S3Gateway;
+use crate::stats::{CountingListener, ServerStats};
+use crate::syslog::Syslog;
+use crate::thumbnail::Thumbnailer;
+use crate::trash::Trash;
+use crate::upload_command::UploadCommand;
+use crate::upload_filter::UploadFilter;
+use crate::upload_limit::UploadLimiter;
+use crate::util::{enable_string, parse_mime_override, parse_mime_types_file, StringError};
+use crate::webhook::Webhook;
+
+/// The file server with the defaults of the command line, configured by its methods:
+///
+/// ```no_run
+/// use simple_http_server::SimpleHttpServer;
+///
+/// let listening = SimpleHttpServer::new("/srv/files")
+///     .index(true)
+///     .upload(true)
+///     .auth("admin:secret")
+///     .serve("127.0.0.1:8000")
+///     .unwrap();
+/// println!("Serving on {}", listening.socket);
+/// ```
+pub struct SimpleHttpServer {
+    root: PathBuf,
+    index: bool,
+    upload: bool,
+    upload_only: bool,
+    delete: bool,
+    csrf: bool,
+    cache: bool,
+    etag: String,
+    range: bool,
+    listing: bool,
+    sort: bool,
+    pretty_urls: bool,
+    canonical_redirect: bool,
+    redirect: Option<String>,
+    force_download: bool,
+    header_file: Option<PathBuf>,
+    footer_file: Option<PathBuf>,
+    inject_html: bool,
+    theme: String,
+    page_size: usize,
+    upload_size_limit: u64,
+    upload_quota: Option<u64>,
+    upload_limit_rate: Option<u64>,
+    upload_max_per_hour: Option<usize>,
+    upload_allow: Vec<String>,
+    upload_deny: Vec<String>,
+    upload_sniff: bool,
+    preserve_mtime: bool,
+    trash_dir: Option<PathBuf>,
+    on_upload: Option<(String, Duration)>,
+    webhook: Option<(String, bool)>,
+    listing_cache: Option<usize>,
+    stream_listings: bool,
+    expire: Option<Duration>,
+    try_file_404: Option<PathBuf>,
+    mime: Vec<String>,
+    mime_file: Option<PathBuf>,
+    charset: String,
+    vhosts: Vec<(String, PathBuf)>,
+    checksums: bool,
+    manifest: bool,
+    thumbnails: Option<PathBuf>,
+    highlight: bool,
+    s3: Option<String>,
+    cgi_dirs: Vec<String>,
+    fastcgi: Vec<String>,
+    compress: Vec<String>,
+    compress_min_size: u64,
+    ignore: Vec<String>,
+    gitignore: bool,
+    access_files: bool,
+    protect_paths: Vec<String>,
+    auth: Option<String>,
+    auth_upload: Vec<String>,
+    auth_tokens: Vec<String>,
+    auth_jwt_secret: Option<String>,
+    auth_jwt_jwks_url: Option<String>,
+    auth_jwt_audience: Option<String>,
+    auth_jwt_issuer: Option<String>,
+    auth_session: Option<u64>,
+    auth_max_failures: u32,
+    auth_ban_time: u64,
+    audit_log: Option<PathBuf>,
+    one_time_links: Option<PathBuf>,
+    cors: bool,
+    trusted_proxies: Vec<String>,
+    rate_limit: Option<String>,
+    no_robots: bool,
+    security_headers: bool,
+    hsts: Option<String>,
+    frame_options: Option<String>,
+    referrer_policy: Option<String>,
+    status: bool,
+    health_path: Option<String>,
+    log: bool,
+    log_syslog: Option<String>,
+    log_file: Option<(PathBuf, Option<u64>, usize)>,
+    threads: usize,
+    keepalive: bool,
+    keepalive_requests: Option<usize>,
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    queue: Option<usize>,
+    max_url_length: usize,
+    max_header_size: usize,
+    backlog: i32,
+    reuse_port: bool,
+    nodelay: bool,
+    hooks: Hooks,
+    // Of the command line: the TLS client certificates (`--tls-client-ca`, `--tls-client-auth`),
+    // the configuration of the status page and the reloads of `--config`
+    client_cert_check: bool,
+    client_cert_auth: bool,
+    status_info: Vec<(String, String)>,
+    reloadable: bool,
+}
+
+impl SimpleHttpServer {
+    /// Serves the files of the `root` directory
+    pub fn new<P: AsRef<Path>>(root: P) -> SimpleHttpServer {
+        SimpleHttpServer {
+            root: root.as_ref().to_path_buf(),
+            index: false,
+            upload: false,
+            upload_only: false,
+            delete: false,
+            csrf: true,
+            cache: true,
+            etag: "strong".to_owned(),
+            range: true,
+            listing: true,
+            sort: true,
+            pretty_urls: false,
+            canonical_redirect: true,
+            redirect: None,
+            force_download: false,
+            header_file: None,
+            footer_file: None,
+            inject_html: false,
+            theme: "auto".to_owned(),
+            page_size: 0,
+            upload_size_limit: 8_000_000,
+            upload_quota: None,
+            upload_limit_rate: None,
+            upload_max_per_hour: None,
+            upload_allow: Vec::new(),
+            upload_deny: Vec::new(),
+            upload_sniff: false,
+            preserve_mtime: false,
+            trash_dir: None,
+            on_upload: None,
+            webhook: None,
+            listing_cache: None,
+            stream_listings: false,
+            expire: None,
+            try_file_404: None,
+            mime: Vec::new(),
+            mime_file: None,
+            charset: "utf-8".to_owned(),
+            vhosts: Vec::new(),
+            checksums: false,
+            manifest: false,
+            thumbnails: None,
+            highlight: false,
+            s3: None,
+            cgi_dirs: Vec::new(),
+            fastcgi: Vec::new(),
+            compress: Vec::new(),
+            compress_min_size: DEFAULT_MIN_SIZE,
+            ignore: Vec::new(),
+            gitignore: false,
+            access_files: false,
+            protect_paths: Vec::new(),
+            auth: None,
+            auth_upload: Vec::new(),
+            auth_tokens: Vec::new(),
+            auth_jwt_secret: None,
+            auth_jwt_jwks_url: None,
+            auth_jwt_audience: None,
+            auth_jwt_issuer: None,
+            auth_session: None,
+            auth_max_failures: 10,
+            auth_ban_time: 900,
+            audit_log: None,
+            one_time_links: None,
+            cors: false,
+            trusted_proxies: Vec::new(),
+            rate_limit: None,
+            no_robots: false,
+            security_headers: false,
+            hsts: None,
+            frame_options: None,
+            referrer_policy: None,
+            status: false,
+            health_path: None,
+            log: false,
+            log_syslog: None,
+            log_file: None,
+            threads: 3,
+            keepalive: true,
+            keepalive_requests: None,
+            max_connections: None,
+            max_connections_per_ip: None,
+            queue: None,
+            max_url_length: 8192,
+            max_header_size: 32768,
+            backlog: 128,
+            reuse_port: false,
+            nodelay: false,
+            hooks: Hooks::default(),
+            client_cert_check: false,
+            client_cert_auth: false,
+            status_info: Vec::new(),
+            reloadable: false,
+        }
+    }
+
+    /// Render `index.html`/`index.htm` instead of the listing (`--index`)
+    pub fn index(mut self, enabled: bool) -> Self {
+        self.index = enabled;
+        self
+    }
+
+    /// Accept uploads (`--upload`)
+    pub fn upload(mut self, enabled: bool) -> Self {
+        self.upload = enabled;
+        self
+    }
+
+    /// A drop box: the directories only show the upload form, the files can't be downloaded,
+    /// deleted nor replaced (`--upload-only`, with `upload(true)`)
+    pub fn upload_only(mut self, enabled: bool) -> Self {
+        self.upload_only = enabled;
+        self
+    }
+
+    /// Allow deleting the files and directories, with `upload(true)` (`--delete`)
+    pub fn delete(mut self, enabled: bool) -> Self {
+        self.delete = enabled;
+        self
+    }
+
+    /// Require the CSRF token of the upload form, enabled by default (`--nocsrf`)
+    pub fn csrf(mut self, enabled: bool) -> Self {
+        self.csrf = enabled;
+        self
+    }
+
+    /// `Cache-Control`, `Last-Modified` and `ETag`, enabled by default (`--nocache`)
+    pub fn cache(mut self, enabled: bool) -> Self {
+        self.cache = enabled;
+        self
+    }
+
+    /// `strong` (by default), `weak`, `strong-hash` or `off` (`--etag`)
+    pub fn etag(mut self, mode: &str) -> Self {
+        self.etag = mode.to_owned();
+        self
+    }
+
+    /// Range requests, enabled by default (`--norange`)
+    pub fn range(mut self, enabled: bool) -> Self {
+        self.range = enabled;
+        self
+    }
+
+    /// Directory listings, enabled by default (`--nolisting`)
+    pub fn listing(mut self, enabled: bool) -> Self {
+        self.listing = enabled;
+        self
+    }
+
+    /// Sorting of the listings, enabled by default (`--nosort`)
+    pub fn sort(mut self, enabled: bool) -> Self {
+        self.sort = enabled;
+        self
+    }
+
+    /// Keep this many rendered listings, until their directory changes (`--listing-cache`)
+    pub fn listing_cache(mut self, count: usize) -> Self {
+        self.listing_cache = Some(count);
+        self
+    }
+
+    /// Write the listings while their directory is read, chunked (`--stream-listings`)
+    pub fn stream_listings(mut self, enabled: bool) -> Self {
+        self.stream_listings = enabled;
+        self
+    }
+
+    /// `/about` serves `about.html` (`--pretty-urls`)
+    pub fn pretty_urls(mut self, enabled: bool) -> Self {
+        self.pretty_urls = enabled;
+        self
+    }
+
+    /// Redirect the directories without their trailing slash and the duplicate slashes,
+    /// enabled by default (`--no-canonical-redirect`)
+    pub fn canonical_redirect(mut self, enabled: bool) -> Self {
+        self.canonical_redirect = enabled;
+        self
+    }
+
+    /// Answer every request with a redirect to this URL (`--redirect`)
+    pub fn redirect(mut self, url: &str) -> Self {
+        self.redirect = Some(url.to_owned());
+        self
+    }
+
+    /// Every file as an attachment (`--force-download`)
+    pub fn force_download(mut self, enabled: bool) -> Self {
+        self.force_download = enabled;
+        self
+    }
+
+    /// HTML inserted at the top of the listings (`--header-file`)
+    pub fn header_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.header_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// HTML inserted at the bottom of the listings (`--footer-file`)
+    pub fn footer_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.footer_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also insert the header and footer into the served HTML files (`--inject-html`)
+    pub fn inject_html(mut self, enabled: bool) -> Self {
+        self.inject_html = enabled;
+        self
+    }
+
+    /// `light`, `dark` or `auto` (by default) of the listings (`--theme`)
+    pub fn theme(mut self, theme: &str) -> Self {
+        self.theme = theme.to_owned();
+        self
+    }
+
+    /// Entries per page of the listings, 0 (by default) for all (`--page-size`)
+    pub fn page_size(mut self, entries: usize) -> Self {
+        self.page_size = entries;
+        self
+    }
+
+    /// In bytes, 8 MB by default (`--upload-size-limit`)
+    pub fn upload_size_limit(mut self, bytes: u64) -> Self {
+        self.upload_size_limit = bytes;
+        self
+    }
+
+    /// Refuse the uploads once the root is this large in bytes (`--upload-quota`)
+    pub fn upload_quota(mut self, bytes: u64) -> Self {
+        self.upload_quota = Some(bytes);
+        self
+    }
+
+    /// The upload bandwidth of each client (user, or IP when anonymous) in bytes per second
+    /// (`--upload-limit-rate`)
+    pub fn upload_limit_rate(mut self, bytes: u64) -> Self {
+        self.upload_limit_rate = Some(bytes);
+        self
+    }
+
+    /// The files each client can upload in an hour (`--upload-max-per-hour`)
+    pub fn upload_max_per_hour(mut self, count: usize) -> Self {
+        self.upload_max_per_hour = Some(count);
+        self
+    }
+
+    /// Only accept the uploads with this extension (`--upload-allow`)
+    pub fn upload_allow(mut self, extension: &str) -> Self {
+        self.upload_allow.push(extension.to_owned());
+        self
+    }
+
+    /// Refuse the uploads with this extension (`--upload-deny`)
+    pub fn upload_deny(mut self, extension: &str) -> Self {
+        self.upload_deny.push(extension.to_owned());
+        self
+    }
+
+    /// Also check the executables and scripts by their content (`--upload-sniff`)
+    pub fn upload_sniff(mut self, enabled: bool) -> Self {
+        self.upload_sniff = enabled;
+        self
+    }
+
+    /// Keep the modification time sent by the upload form (`--preserve-mtime`)
+    pub fn preserve_mtime(mut self, enabled: bool) -> Self {
+        self.preserve_mtime = enabled;
+        self
+    }
+
+    /// Move the deleted files into this directory instead of removing them (`--trash-dir`)
+    pub fn trash_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.trash_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Run `command` after each upload, `{}` replaced by the saved file, killed after `timeout`
+    /// (`--on-upload`, `--on-upload-timeout`)
+    pub fn on_upload(mut self, command: &str, timeout: Duration) -> Self {
+        self.on_upload = Some((command.to_owned(), timeout));
+        self
+    }
+
+    /// POST a JSON payload to `url` after each upload, and each download with `downloads`
+    /// (`--webhook-url`, `--webhook-downloads`)
+    pub fn webhook(mut self, url: &str, downloads: bool) -> Self {
+        self.webhook = Some((url.to_owned(), downloads));
+        self
+    }
+
+    /// Delete the files this long after their upload, or after the start for the older ones
+    /// (`--expire`)
+    pub fn expire(mut self, after: Duration) -> Self {
+        self.expire = Some(after);
+        self
+    }
+
+    /// The file answered to the requests matching nothing (`--try-file`)
+    pub fn try_file_404<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.try_file_404 = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The MIME type of an extension, before those of `mime_file` (`--mime`)
+    pub fn mime(mut self, extension: &str, mime: &str) -> Self {
+        self.mime.push(format!("{}={}", extension, mime));
+        self
+    }
+
+    /// The MIME types of a `mime.types` file (`--mime-file`)
+    pub fn mime_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.mime_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Of the text responses, `utf-8` by default (`--charset`)
+    pub fn charset(mut self, charset: &str) -> Self {
+        self.charset = charset.to_lowercase();
+        self
+    }
+
+    /// Serve `root` to the requests for `host` (`--vhost`)
+    pub fn vhost<P: AsRef<Path>>(mut self, host: &str, root: P) -> Self {
+        self.vhosts
+            .push((host.to_lowercase(), root.as_ref().to_path_buf()));
+        self
+    }
+
+    /// SHA-256 digests by `?hash=sha256` and `<file>.sha256` (`--checksums`)
+    pub fn checksums(mut self, enabled: bool) -> Self {
+        self.checksums = enabled;
+        self
+    }
+
+    /// JSON trees of the directories at `/__manifest` (`--manifest`)
+    pub fn manifest(mut self, enabled: bool) -> Self {
+        self.manifest = enabled;
+        self
+    }
+
+    /// Thumbnails of the images in the listings, kept in `cache_dir` (`--thumbnails`,
+    /// `--thumbnail-cache`)
+    pub fn thumbnails<P: AsRef<Path>>(mut self, cache_dir: P) -> Self {
+        self.thumbnails = Some(cache_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Syntax highlighting of the text files on `?view=1` (`--highlight`)
+    pub fn highlight(mut self, enabled: bool) -> Self {
+        self.highlight = enabled;
+        self
+    }
+
+    /// The root as an S3 bucket at `/<bucket>`, the `auth` users are its access keys (`--s3`)
+    pub fn s3(mut self, bucket: &str) -> Self {
+        self.s3 = Some(bucket.to_owned());
+        self
+    }
+
+    /// `PREFIX=DIR`, the scripts of `DIR` run as CGI under `PREFIX` (`--cgi-dir`)
+    pub fn cgi_dir(mut self, spec: &str) -> Self {
+        self.cgi_dirs.push(spec.to_owned());
+        self
+    }
+
+    /// `PATTERN=ADDR`, the matching files run by the FastCGI backend at `ADDR` (`--fastcgi`)
+    pub fn fastcgi(mut self, spec: &str) -> Self {
+        self.fastcgi.push(spec.to_owned());
+        self
+    }
+
+    /// Compress the files with this extension (without the dot), of this MIME type (`text/*`),
+    /// or `auto` for the text formats (`--compress`)
+    pub fn compress(mut self, rule: &str) -> Self {
+        self.compress.push(rule.to_owned());
+        self
+    }
+
+    /// Smaller responses are sent as they are, 256 bytes by default (`--compress-min-size`)
+    pub fn compress_min_size(mut self, bytes: u64) -> Self {
+        self.compress_min_size = bytes;
+        self
+    }
+
+    /// Hide the matching paths, gitignore syntax (`--ignore`)
+    pub fn ignore(mut self, pattern: &str) -> Self {
+        self.ignore.push(pattern.to_owned());
+        self
+    }
+
+    /// Also respect the .gitignore files (`--gitignore`)
+    pub fn gitignore(mut self, enabled: bool) -> Self {
+        self.gitignore = enabled;
+        self
+    }
+
+    /// Apply the `.shs-access` files of the directories (`--access-files`)
+    pub fn access_files(mut self, enabled: bool) -> Self {
+        self.access_files = enabled;
+        self
+    }
+
+    /// `user:pass[:ro|rw|admin]` for everything, an admin by default (`--auth`)
+    pub fn auth(mut self, credential: &str) -> Self {
+        self.auth = Some(credential.to_owned());
+        self
+    }
+
+    /// A password of its own for `path` and below, by a form or `?pw=` (`--protect-path`)
+    pub fn protect_path(mut self, path: &str, password: &str) -> Self {
+        self.protect_paths.push(format!("{}={}", path, password));
+        self
+    }
+
+    /// `user:pass[:role]` for the uploads only (`--auth-upload`)
+    pub fn auth_upload(mut self, credential: &str) -> Self {
+        self.auth_upload.push(credential.to_owned());
+        self
+    }
+
+    /// `token[:ro|rw|admin]` by `Authorization: Bearer` or `?access_token=`, for everything as
+    /// `auth` (`--auth-token`)
+    pub fn auth_token(mut self, token: &str) -> Self {
+        self.auth_tokens.push(token.to_owned());
+        self
+    }
+
+    /// Accept the Bearer JWTs signed with this HMAC key, for everything as `auth`
+    /// (`--auth-jwt-secret`)
+    pub fn auth_jwt_secret(mut self, secret: &str) -> Self {
+        self.auth_jwt_secret = Some(secret.to_owned());
+        self
+    }
+
+    /// Accept the Bearer JWTs signed with a key of this JWK set (`--auth-jwt-jwks-url`)
+    pub fn auth_jwt_jwks_url(mut self, url: &str) -> Self {
+        self.auth_jwt_jwks_url = Some(url.to_owned());
+        self
+    }
+
+    /// The `aud` claim the JWTs must have (`--auth-jwt-audience`)
+    pub fn auth_jwt_audience(mut self, audience: &str) -> Self {
+        self.auth_jwt_audience = Some(audience.to_owned());
+        self
+    }
+
+    /// The `iss` claim the JWTs must have (`--auth-jwt-issuer`)
+    pub fn auth_jwt_issuer(mut self, issuer: &str) -> Self {
+        self.auth_jwt_issuer = Some(issuer.to_owned());
+        self
+    }
+
+    /// The login form and session cookies of this lifetime in seconds, besides Basic Auth
+    /// (`--auth-session`)
+    pub fn auth_session(mut self, max_age: u64) -> Self {
+        self.auth_session = Some(max_age);
+        self
+    }
+
+    /// Ban a client IP after this many failed logins in a row, 10 by default, 0 never
+    /// (`--auth-max-failures`)
+    pub fn auth_max_failures(mut self, count: u32) -> Self {
+        self.auth_max_failures = count;
+        self
+    }
+
+    /// In seconds, 900 by default (`--auth-ban-time`)
+    pub fn auth_ban_time(mut self, seconds: u64) -> Self {
+        self.auth_ban_time = seconds;
+        self
+    }
+
+    /// Append the uploads and deletes to this file as JSON lines (`--audit-log`)
+    pub fn audit_log<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.audit_log = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Allow creating links downloading a file once, saved to this file (`--one-time-links`)
+    pub fn one_time_links<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.one_time_links = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Allow any origin (`--cors`)
+    pub fn cors(mut self, enabled: bool) -> Self {
+        self.cors = enabled;
+        self
+    }
+
+    /// Take the client address and scheme from the forwarded headers of the requests of these
+    /// addresses (`--trusted-proxy`)
+    pub fn trusted_proxy(mut self, net: &str) -> Self {
+        self.trusted_proxies.push(net.to_owned());
+        self
+    }
+
+    /// The requests of each client IP, `60/min` (`--rate-limit`)
+    pub fn rate_limit(mut self, rate: &str) -> Self {
+        self.rate_limit = Some(rate.to_owned());
+        self
+    }
+
+    /// A robots.txt disallowing everything and `X-Robots-Tag: noindex` (`--no-robots`)
+    pub fn no_robots(mut self, enabled: bool) -> Self {
+        self.no_robots = enabled;
+        self
+    }
+
+    /// HSTS, `nosniff`, `X-Frame-Options` and `Referrer-Policy` (`--security-headers`)
+    pub fn security_headers(mut self, enabled: bool) -> Self {
+        self.security_headers = enabled;
+        self
+    }
+
+    /// `Strict-Transport-Security` of the HTTPS responses, `off` for none (`--hsts`)
+    pub fn hsts(mut self, value: &str) -> Self {
+        self.hsts = Some(value.to_owned());
+        self
+    }
+
+    /// `X-Frame-Options`, `off` for none (`--frame-options`)
+    pub fn frame_options(mut self, value: &str) -> Self {
+        self.frame_options = Some(value.to_owned());
+        self
+    }
+
+    /// `Referrer-Policy`, `off` for none (`--referrer-policy`)
+    pub fn referrer_policy(mut self, value: &str) -> Self {
+        self.referrer_policy = Some(value.to_owned());
+        self
+    }
+
+    /// The status page at `/__status`, for the admins: credentials are required (`--status`)
+    pub fn status(mut self, enabled: bool) -> Self {
+        self.status = enabled;
+        self
+    }
+
+    /// Answer `GET path` with 200, without auth nor logging (`--health-path`)
+    pub fn health_path(mut self, path: &str) -> Self {
+        self.health_path = Some(path.to_owned());
+        self
+    }
+
+    /// Print the requests to stdout, disabled by default
+    pub fn log(mut self, enabled: bool) -> Self {
+        self.log = enabled;
+        self
+    }
+
+    /// Send the logs to the local syslog with this facility (`--log-syslog`)
+    pub fn log_syslog(mut self, facility: &str) -> Self {
+        self.log_syslog = Some(facility.to_owned());
+        self
+    }
+
+    /// Append the access log to `path`, rotated once larger than `max_size`, `keep` rotated
+    /// files kept (`--log-file`, `--log-rotate-size`, `--log-rotate-keep`)
+    pub fn log_file<P: AsRef<Path>>(mut self, path: P, max_size: Option<u64>, keep: usize) -> Self {
+        self.log_file = Some((path.as_ref().to_path_buf(), max_size, keep));
+        self
+    }
+
+    /// Worker threads of `serve()`, 3 by default (`--threads`)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Keep the connections open between requests, enabled by default (`--keepalive`)
+    pub fn keepalive(mut self, enabled: bool) -> Self {
+        self.keepalive = enabled;
+        self
+    }
+
+    /// Close a kept alive connection once it served this many requests
+    /// (`--keepalive-requests`)
+    pub fn keepalive_requests(mut self, count: usize) -> Self {
+        self.keepalive_requests = Some(count);
+        self
+    }
+
+    /// Answer 503 to the new connections once this many are open (`--max-connections`)
+    pub fn max_connections(mut self, count: usize) -> Self {
+        self.max_connections = Some(count);
+        self
+    }
+
+    /// The same for each client IP (`--max-connections-per-ip`)
+    pub fn max_connections_per_ip(mut self, count: usize) -> Self {
+        self.max_connections_per_ip = Some(count);
+        self
+    }
+
+    /// The accepted connections waiting for a worker thread, the others get 503 (`--queue`)
+    pub fn queue(mut self, size: usize) -> Self {
+        self.queue = Some(size);
+        self
+    }
+
+    /// Answer 414 to the longer request lines, 8192 bytes by default (`--max-url-length`)
+    pub fn max_url_length(mut self, bytes: usize) -> Self {
+        self.max_url_length = bytes;
+        self
+    }
+
+    /// Answer 431 to the larger headers, 32768 bytes by default (`--max-header-size`)
+    pub fn max_header_size(mut self, bytes: usize) -> Self {
+        self.max_header_size = bytes;
+        self
+    }
+
+    /// The listen backlog, 128 by default (`--backlog`)
+    pub fn backlog(mut self, size: i32) -> Self {
+        self.backlog = size;
+        self
+    }
+
+    /// `SO_REUSEPORT` on the listening sockets (`--so-reuseport`)
+    pub fn reuse_port(mut self, enabled: bool) -> Self {
+        self.reuse_port = enabled;
+        self
+    }
+
+    /// `TCP_NODELAY` on the connections (`--tcp-nodelay`)
+    pub fn tcp_nodelay(mut self, enabled: bool) -> Self {
+        self.nodelay = enabled;
+        self
+    }
+
+    /// Adds custom behavior, the hooks are called in their registration order
+    pub fn hook<H: Hook + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    // The TLS client certificates of the listeners: required by `ClientCertChecker`, accepted as
+    // credentials with `auth`
+    pub(crate) fn client_certs(mut self, check: bool, auth: bool) -> Self {
+        self.client_cert_check = check;
+        self.client_cert_auth = auth;
+        self
+    }
+
+    // Shown by the status page after the root, the listeners are known to the caller only
+    pub(crate) fn status_info(mut self, name: &str, value: String) -> Self {
+        self.status_info.push((name.to_owned(), value));
+        self
+    }
+
+    // The settings may be reloaded by `--config`, with credentials and compression
+    pub(crate) fn reloadable(mut self, enabled: bool) -> Self {
+        self.reloadable = enabled;
+        self
+    }
+
+    /// The handler chain, to be served by the caller's own `Iron` server or mounted
+    pub fn handler(self) -> Result<Chain, StringError> {
+        let mut server = self.build()?;
+        server.spawn_sweeper();
+        Ok(Arc::try_unwrap(server.handler.chain)
+            .unwrap_or_else(|_| unreachable!("the chain is not shared yet")))
+    }
+
+    /// Listens on `addr` (port 0 for any free port, see `Listening::socket`), the requests are
+    /// served by other threads until `Listening::close()`
+    pub fn serve<A: ToSocketAddrs>(self, addr: A) -> Result<Listening, StringError> {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(|e| StringError(format!("Invalid address: {}", e)))?
+            .next()
+            .ok_or_else(|| StringError("No address to listen on".to_owned()))?;
+        let mut server = self.build()?;
+        server.spawn_sweeper();
+        let listener = server
+            .bind(&addr, false)
+            .map_err(|e| StringError(format!("Listen failed: {}", e)))?;
+        server
+            .serve_on(listener, Protocol::http())
+            .map_err(|e| StringError(format!("Listen failed: {}", e)))
+    }
+
+    /// The handler chain and the options of the listeners, the command line binds them itself
+    /// (TLS, privileges dropped once bound)
+    pub(crate) fn build(self) -> Result<Server, StringError> {
+        let root = self
+            .root
+            .canonicalize()
+            .map_err(|e| StringError(format!("Invalid root {}: {}", self.root.display(), e)))?;
+        if !root.is_dir() {
+            return Err(StringError(format!("Not directory: {}", root.display())));
+        }
+        let compress = if self.compress.is_empty() {
+            None
+        } else {
+            Some(self.compress.clone())
+        };
+        let live = Arc::new(LiveSettings::with(
+            self.cache,
+            compress.clone(),
+            &self.ignore,
+            self.gitignore,
+        )?);
+
+        let etag = match self.etag.as_str() {
+            "strong" => EtagMode::Strong,
+            "weak" => EtagMode::Weak,
+            "strong-hash" => EtagMode::ContentHash(ChecksumCache::new()),
+            "off" => EtagMode::Off,
+            mode => return Err(StringError(format!("Unknown ETag mode: {}", mode))),
+        };
+        let read_fragment = |name: &str, path: &Option<PathBuf>| match path {
+            Some(path) => fs::read_to_string(path).map(Some).map_err(|e| {
+                StringError(format!("Read {} {} failed: {}", name, path.display(), e))
+            }),
+            None => Ok(None),
+        };
+        // Mappings from `mime` take precedence over the ones from `mime_file`
+        let mut mime_overrides = HashMap::new();
+        if let Some(ref path) = self.mime_file {
+            let content = fs::read_to_string(path)
+                .map_err(|e| StringError(format!("Read mime file failed: {}", e)))?;
+            mime_overrides.extend(parse_mime_types_file(&content)?);
+        }
+        for spec in &self.mime {
+            let (extension, mime) = parse_mime_override(spec)?;
+            mime_overrides.insert(extension, mime);
+        }
+        let mut vhosts = HashMap::new();
+        for (host, dir) in &self.vhosts {
+            let dir = dir
+                .canonicalize()
+                .map_err(|e| StringError(format!("Invalid root {}: {}", dir.display(), e)))?;
+            vhosts.insert(host.clone(), dir);
+        }
+        let auth_upload = self
+            .auth_upload
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        let auth_tokens = self
+            .auth_tokens
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        let stats = if self.status {
+            let compression = if self.compress.is_empty() {
+                "disabled".to_owned()
+            } else {
+                let rules = self
+                    .compress
+                    .iter()
+                    .map(|s| {
+                        if s == "auto" || s.contains('/') {
+                            s.to_owned()
+                        } else {
+                            format!("*.{}", s)
+                        }
+                    })
+                    .collect::<Vec<String>>();
+                format!("{:?}", rules)
+            };
+            // Credentials are left out
+            let mut config = vec![("Root".to_owned(), root.display().to_string())];
+            config.extend(self.status_info.iter().cloned());
+            config.extend(vec![
+                ("Index".to_owned(), enable_string(self.index)),
+                ("Upload".to_owned(), enable_string(self.upload)),
+                ("Cache".to_owned(), enable_string(self.cache)),
+                ("Cors".to_owned(), enable_string(self.cors)),
+                ("Range".to_owned(), enable_string(self.range)),
+                ("Sort".to_owned(), enable_string(self.sort)),
+                ("Threads".to_owned(), self.threads.to_string()),
+                ("Compression".to_owned(), compression),
+            ]);
+            Some(Arc::new(ServerStats::new(config)))
+        } else {
+            None
+        };
+
+        let mut handler = MainHandler::new(root, live.clone());
+        handler.index = self.index;
+        handler.upload = self.upload;
+        handler.upload_only = self.upload_only;
+        handler.delete = self.upload && self.delete && !self.upload_only;
+        handler.csrf = self.csrf;
+        handler.etag = etag;
+        if let Some(ref url) = self.redirect {
+            handler.redirect_to = Some(
+                iron::Url::parse(url)
+                    .map_err(|e| StringError(format!("Invalid redirect {}: {}", url, e)))?,
+            );
+        }
+        if self.access_files {
+            handler.access = Some(Arc::new(AccessRules::new()));
+        }
+        if !self.protect_paths.is_empty() {
+            let specs = self
+                .protect_paths
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>();
+            handler.protected = Some(ProtectedPaths::new(&specs)?);
+        }
+        handler.range = self.range;
+        handler.listing = self.listing;
+        handler.sort = self.sort;
+        handler.listing_cache = self.listing_cache.map(ListingCache::new);
+        handler.stream_listings = self.stream_listings;
+        handler.pretty_urls = self.pretty_urls;
+        handler.canonical_redirect = self.canonical_redirect;
+        handler.html_header = read_fragment("header-file", &self.header_file)?;
+        handler.html_footer = read_fragment("footer-file", &self.footer_file)?;
+        handler.inject_html = self.inject_html;
+        handler.theme = self.theme.clone();
+        handler.page_size = self.page_size;
+        handler.force_download = self.force_download;
+        handler.upload_size_limit = self.upload_size_limit;
+        handler.upload_quota = self.upload_quota;
+        if !self.upload_allow.is_empty() || !self.upload_deny.is_empty() {
+            handler.upload_filter = Some(UploadFilter::new(
+                if self.upload_allow.is_empty() {
+                    None
+                } else {
+                    Some(self.upload_allow.clone())
+                },
+                self.upload_deny.clone(),
+                self.upload_sniff,
+            ));
+        }
+        if self.upload_limit_rate.is_some() || self.upload_max_per_hour.is_some() {
+            handler.upload_limit = Some(UploadLimiter::new(
+                self.upload_limit_rate,
+                self.upload_max_per_hour,
+            ));
+        }
+        handler.preserve_mtime = self.preserve_mtime;
+        if let Some(ref dir) = self.trash_dir {
+            handler.trash = Some(Trash::new(dir)?);
+        }
+        if let Some((ref command, timeout)) = self.on_upload {
+            handler.on_upload = Some(UploadCommand::new(command, timeout)?);
+        }
+        if let Some((ref url, downloads)) = self.webhook {
+            handler.webhook = Some(Webhook::new(url, downloads)?);
+        }
+        handler.try_file_404 = self.try_file_404.clone();
+        handler.mime_overrides = mime_overrides;
+        handler.charset = self.charset.clone();
+        handler.vhosts = vhosts;
+        if self.checksums {
+            handler.checksums = Some(ChecksumCache::new());
+        }
+        handler.manifest = self.manifest;
+        if let Some(ref cache_dir) = self.thumbnails {
+            handler.thumbnailer = Some(Thumbnailer::new(cache_dir.clone())?);
+        }
+        if self.highlight {
+            handler.highlighter = Some(Highlighter::new());
+        }
+        if let Some(ref bucket) = self.s3 {
+            let credentials = parse_credentials(self.auth.as_deref(), &auth_upload)?;
+            handler.s3 = Some(S3Gateway::new(
+                bucket.clone(),
+                credentials,
+                self.auth.is_some(),
+            ));
+        }
+        for spec in &self.cgi_dirs {
+            handler.cgi_dirs.push(CgiDir::parse(spec)?);
+        }
+        for spec in &self.fastcgi {
+            handler.fastcgi.push(FastCgi::parse(spec)?);
+        }
+        handler.no_robots = self.no_robots;
+        handler.stats = stats.clone();
+        handler.hooks = self.hooks.clone();
+        let sweeper = self.expire.map(|after| {
+            let expiry = Arc::new(Expiry::new(after));
+            let roots = std::iter::once(&handler.root)
+                .chain(handler.vhosts.values())
+                .cloned()
+                .collect::<Vec<PathBuf>>();
+            let keep = handler
+                .trash
+                .as_ref()
+                .map(|trash| trash.dir().to_path_buf());
+            handler.expiry = Some(expiry.clone());
+            (expiry, roots, keep)
+        });
+        if let Some(ref path) = self.audit_log {
+            handler.audit = Some(AuditLog::new(path)?);
+        }
+        if let Some(ref path) = self.one_time_links {
+            handler.one_time_links = Some(OneTimeLinks::new(path)?);
+        }
+        let sessions = self
+            .auth_session
+            .map(|max_age| Arc::new(Sessions::new(max_age)));
+        handler.sessions = sessions.clone();
+
+        let allow = Arc::new(iron::headers::Allow(handler.allow(None)).to_string());
+        let mut chain = Chain::new(handler);
+        if self.cors {
+            chain.link_around(CorsMiddleware::with_allow_any());
+        }
+        if !self.trusted_proxies.is_empty() {
+            chain.link_before(ForwardedHeaders::new(&self.trusted_proxies).map_err(StringError)?);
+        }
+        if let Some(ref rate) = self.rate_limit {
+            chain.link_before(RateLimiter::new(rate)?);
+        }
+        if self.client_cert_check {
+            chain.link_before(ClientCertChecker);
+        }
+        let auth_jwt = self.auth_jwt_secret.is_some() || self.auth_jwt_jwks_url.is_some();
+        // Users may come with a reloaded configuration
+        let authenticated = self.auth.is_some()
+            || !self.auth_upload.is_empty()
+            || !self.auth_tokens.is_empty()
+            || auth_jwt
+            || self.reloadable;
+        if self.status
+            && self.auth.is_none()
+            && self.auth_upload.is_empty()
+            && self.auth_tokens.is_empty()
+            && !auth_jwt
+        {
+            return Err(StringError(STATUS_CREDENTIALS.to_owned()));
+        }
+        if self.auth_max_failures > 0
+            && (authenticated || self.access_files || !self.protect_paths.is_empty())
+        {
+            let auth_guard = Arc::new(AuthGuard::new(
+                self.auth_max_failures,
+                Duration::from_secs(self.auth_ban_time),
+            ));
+            chain.link_before(auth_guard.clone());
+            chain.link_after(auth_guard);
+        }
+        let auth_checker = if authenticated {
+            let mut auth_checker =
+                AuthChecker::new(self.auth.as_deref(), &auth_upload, &auth_tokens)?;
+            auth_checker.accept_client_cert = self.client_cert_auth;
+            auth_checker.s3_bucket = self.s3.clone();
+            auth_checker.sessions = sessions;
+            auth_checker.one_time_links = self.one_time_links.is_some();
+            auth_checker.status = self.status;
+            if auth_jwt {
+                let mut jwt = JwtValidator::new(
+                    self.auth_jwt_secret.as_deref(),
+                    self.auth_jwt_jwks_url.as_deref(),
+                )?;
+                jwt.audience = self.auth_jwt_audience.clone();
+                jwt.issuer = self.auth_jwt_issuer.clone();
+                auth_checker.jwt = Some(jwt);
+            }
+            let auth_checker = Arc::new(auth_checker);
+            chain.link_before(auth_checker.clone());
+            Some(auth_checker)
+        } else {
+            None
+        };
+        self.hooks.link(&mut chain);
+        if self.no_robots {
+            chain.link_after(NoIndex);
+        }
+        // Given alone, an option sends its header only
+        let security_header = |value: &Option<String>, default: &str| match value.as_deref() {
+            Some("off") => None,
+            Some(value) => Some(value.to_owned()),
+            None if self.security_headers => Some(default.to_owned()),
+            None => None,
+        };
+        let security_headers = SecurityHeaders {
+            hsts: security_header(&self.hsts, DEFAULT_HSTS),
+            nosniff: self.security_headers,
+            frame_options: security_header(&self.frame_options, DEFAULT_FRAME_OPTIONS),
+            referrer_policy: security_header(&self.referrer_policy, DEFAULT_REFERRER_POLICY),
+        };
+        if security_headers.hsts.is_some()
+            || security_headers.nosniff
+            || security_headers.frame_options.is_some()
+            || security_headers.referrer_policy.is_some()
+        {
+            chain.link_after(security_headers);
+        }
+        if compress.is_some() || self.reloadable {
+            chain.link_after(CompressionHandler {
+                min_size: self.compress_min_size,
+            });
+        }
+        // `Connection: close` tells the clients too
+        let keepalive_requests = if self.keepalive {
+            self.keepalive_requests
+        } else {
+            Some(1)
+        };
+        if let Some(max_requests) = keepalive_requests {
+            chain.link_after(KeepAlive { max_requests });
+        }
+        let syslog = match self.log_syslog {
+            Some(ref facility) => Some(Arc::new(Syslog::new(facility)?)),
+            None => None,
+        };
+        let log_file = match self.log_file {
+            Some((ref path, max_size, keep)) => Some(Arc::new(LogFile::new(path, max_size, keep)?)),
+            None => None,
+        };
+        if self.log || stats.is_some() || syslog.is_some() || log_file.is_some() {
+            chain.link_after(RequestLogger {
+                printer: if self.log {
+                    Some(Arc::new(Printer::new()))
+                } else {
+                    None
+                },
+                stats: stats.clone(),
+                syslog,
+                log_file,
+            });
+        }
+
+        let limiter = if self.max_connections.is_some() || self.max_connections_per_ip.is_some() {
+            Some(Arc::new(ConnectionLimiter::new(
+                self.max_connections,
+                self.max_connections_per_ip,
+            )))
+        } else {
+            None
+        };
+        Ok(Server {
+            handler: SharedHandler {
+                chain: Arc::new(chain),
+                health: self
+                    .health_path
+                    .as_deref()
+                    .map(HealthCheck::new)
+                    .map(Arc::new),
+            },
+            live,
+            auth_checker,
+            stats,
+            sweeper,
+            allow,
+            threads: self.threads,
+            // The default of hyper
+            keep_alive: if self.keepalive {
+                Some(Duration::from_secs(5))
+            } else {
+                None
+            },
+            queue: self.queue,
+            limiter,
+            head_limits: HeadLimits {
+                request_line: self.max_url_length,
+                headers: self.max_header_size,
+            },
+            socket_opts: SocketOptions {
+                backlog: self.backlog,
+                reuse_port: self.reuse_port,
+                nodelay: self.nodelay,
+            },
+        })
+    }
+}
+
+/// A built server: its handler, what the reloads of `--config` replace, and the listeners to
+/// bind
+pub(crate) struct Server {
+    pub(crate) handler: SharedHandler,
+    pub(crate) live: Arc<LiveSettings>,
+    pub(crate) auth_checker: Option<Arc<AuthChecker>>,
+    pub(crate) stats: Option<Arc<ServerStats>>,
+    sweeper: Option<(Arc<Expiry>, Vec<PathBuf>, Option<PathBuf>)>,
+    // `Allow` of `OPTIONS *`
+    allow: Arc<String>,
+    threads: usize,
+    keep_alive: Option<Duration>,
+    queue: Option<usize>,
+    limiter: Option<Arc<ConnectionLimiter>>,
+    head_limits: HeadLimits,
+    socket_opts: SocketOptions,
+}
+
+impl Server {
+    /// Starts deleting the expired files (`--expire`), as the user switched to
+    pub(crate) fn spawn_sweeper(&mut self) {
+        if let Some((expiry, roots, keep)) = self.sweeper.take() {
+            expiry.spawn_sweeper(roots, keep);
+        }
+    }
+
+    /// Binds `addr`, not served yet: the command line drops its privileges before
+    pub(crate) fn bind(&self, addr: &SocketAddr, https: bool) -> io::Result<QueuedListener> {
+        let listener = listener::bind(addr, &self.socket_opts)?;
+        // Over TLS, the rejected connections are only closed
+        QueuedListener::new(listener, self.queue, !https, self.socket_opts.nodelay)
+    }
+
+    /// Serves a bound listener (`TlsListener` over it for HTTPS) with `threads` workers
+    pub(crate) fn serve_on<L>(&self, listener: L, protocol: Protocol) -> hyper::Result<Listening>
+    where
+        L: 'static + NetworkListener + Send + Clone,
+    {
+        let listener = LimitedListener::new(listener, self.limiter.clone());
+        let listener = HeadLimitListener::new(listener, self.head_limits, self.allow.clone());
+        let mut server = Iron::new(self.handler.clone());
+        server.threads = self.threads;
+        server.timeouts.keep_alive = self.keep_alive;
+        server.listen(
+            CountingListener::new(listener, self.stats.clone()),
+            protocol,
+        )
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use htmlescape::encode_minimal;
+use hyper::net::{NetworkListener, NetworkStream};
+use iron::headers::ContentType;
+use iron::response::WriteBody;
+use iron::status;
+use iron::Response;
+use pretty_bytes::converter::convert;
+
+use crate::util::{now_string, random_token, set_page_csp};
+
+/// Path of the status page (`--status`)
+pub const STATUS_PATH: &str = "__status";
+const MAX_RECENT_ERRORS: usize = 20;
+
+/// Counters shown by the status page, updated by the request logger and the listeners
+pub struct ServerStats {
+    started: Instant,
+    active_connections: AtomicUsize,
+    total_connections: AtomicU64,
+    total_requests: AtomicU64,
+    bytes_sent: AtomicU64,
+    next_transfer_id: AtomicU64,
+    transfers: Mutex<BTreeMap<u64, Arc<Transfer>>>,
+    recent_errors: Mutex<VecDeque<String>>,
+    config: Vec<(String, String)>,
+}
+
+struct Transfer {
+    client: IpAddr,
+    path: String,
+    total: Option<u64>,
+    sent: AtomicU64,
+    started: Instant,
+}
+
+impl ServerStats {
+    pub fn new(config: Vec<(String, String)>) -> ServerStats {
+        ServerStats {
+            started: Instant::now(),
+            active_connections: AtomicUsize::new(0),
+            total_connections: AtomicU64::new(0),
+            total_requests: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            next_transfer_id: AtomicU64::new(0),
+            transfers: Mutex::new(BTreeMap::new()),
+            recent_errors: Mutex::new(VecDeque::new()),
+            config,
+        }
+    }
+
+    pub fn record_request(&self) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self, message: String) {
+        let mut errors = self.recent_errors.lock().unwrap();
+        if errors.len() >= MAX_RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(message);
+    }
+
+    /// Count the bytes of `body` while it's being sent
+    pub fn track_body(
+        self: &Arc<Self>,
+        body: Box<dyn WriteBody>,
+        client: IpAddr,
+        path: String,
+        total: Option<u64>,
+    ) -> Box<dyn WriteBody> {
+        let id = self.next_transfer_id.fetch_add(1, Ordering::Relaxed);
+        let transfer = Arc::new(Transfer {
+            client,
+            path,
+            total,
+            sent: AtomicU64::new(0),
+            started: Instant::now(),
+        });
+        self.transfers.lock().unwrap().insert(id, transfer.clone());
+        Box::new(TrackedBody {
+            inner: body,
+            stats: self.clone(),
+            transfer,
+            id,
+        })
+    }
+
+    pub fn page(&self) -> Response {
+        let uptime = self.started.elapsed().as_secs();
+        let summary = [
+            (
+                "Uptime",
+                format!(
+                    "{}d {:02}:{:02}:{:02}",
+                    uptime / 86400,
+                    uptime % 86400 / 3600,
+                    uptime % 3600 / 60,
+                    uptime % 60
+                ),
+            ),
+            (
+                "Active connections",
+                self.active_connections.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "Total connections",
+                self.total_connections.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "Total requests",
+                self.total_requests.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "Bytes sent",
+                convert(self.bytes_sent.load(Ordering::Relaxed) as f64),
+            ),
+        ];
+        let summary_rows = summary
+            .iter()
+            .map(|(name, value)| format!("<tr><td>{}</td><td>{}</td></tr>", name, value))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let transfer_rows = self
+            .transfers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|transfer| {
+                let sent = transfer.sent.load(Ordering::Relaxed);
+                let elapsed = transfer.started.elapsed();
+                let progress = match transfer.total {
+                    Some(total) if total > 0 => format!(
+                        "{} / {} ({:.0}%)",
+                        convert(sent as f64),
+                        convert(total as f64),
+                        sent as f64 * 100.0 / total as f64
+                    ),
+                    _ => convert(sent as f64),
+                };
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}/s</td><td>{}s</td></tr>",
+                    transfer.client,
+                    encode_minimal(&transfer.path),
+                    progress,
+                    convert(speed(sent, elapsed)),
+                    elapsed.as_secs()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let error_rows = self
+            .recent_errors
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|message| format!("<tr><td>{}</td></tr>", encode_minimal(message)))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let config_rows = self
+            .config
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    encode_minimal(name),
+                    encode_minimal(value)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let nonce = random_token(16);
+        let body = format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <meta http-equiv="refresh" content="2">
+  <title>Status</title>
+  <style nonce="{nonce}"> td {{ padding: 0.2rem 1rem 0.2rem 0; }} </style>
+</head>
+<body>
+  <h3>Status</h3>
+  <table>{summary}</table>
+  <h3>Current transfers</h3>
+  <table>
+    <tr><th>Client</th><th>Path</th><th>Progress</th><th>Speed</th><th>Elapsed</th></tr>
+    {transfers}
+  </table>
+  <h3>Recent errors</h3>
+  <table>{errors}</table>
+  <h3>Configuration</h3>
+  <table>{config}</table>
+  <hr />
+  <small>{now}</small>
+</body>
+</html>"#,
+            nonce = nonce,
+            summary = summary_rows,
+            transfers = transfer_rows,
+            errors = error_rows,
+            config = config_rows,
+            now = now_string()
+        );
+        let mut resp = Response::with((status::Ok, body));
+        resp.headers.set(ContentType::html());
+        set_page_csp(&mut resp, &nonce);
+        resp
+    }
+}
+
+fn speed(bytes: u64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 {
+        bytes as f64 / seconds
+    } else {
+        0.0
+    }
+}
+
+struct TrackedBody {
+    inner: Box<dyn WriteBody>,
+    stats: Arc<ServerStats>,
+    transfer: Arc<Transfer>,
+    id: u64,
+}
+
+impl WriteBody for TrackedBody {
+    fn write_body(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        let mut w = CountingWriter {
+            inner: w,
+            stats: &self.stats,
+            transfer: &self.transfer,
+        };
+        self.inner.write_body(&mut w)
+    }
+}
+
+impl Drop for TrackedBody {
+    fn drop(&mut self) {
+        self.stats.transfers.lock().unwrap().remove(&self.id);
+    }
+}
+
+struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    stats: &'a ServerStats,
+    transfer: &'a Transfer,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.transfer.sent.fetch_add(n as u64, Ordering::Relaxed);
+        self.stats.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Counts the connections accepted by the inner listener
+#[derive(Clone)]
+pub struct CountingListener<L> {
+    inner: L,
+    stats: Option<Arc<ServerStats>>,
+}
+
+impl<L> CountingListener<L> {
+    pub fn new(inner: L, stats: Option<Arc<ServerStats>>) -> CountingListener<L> {
+        CountingListener { inner, stats }
+    }
+}
+
+impl<L: NetworkListener> NetworkListener for CountingListener<L> {
+    type Stream = CountedStream<L::Stream>;
+
+    fn accept(&mut self) -> hyper::Result<Self::Stream> {
+        let stream = self.inner.accept()?;
+        let guard = self.stats.clone().map(|stats| {
+            stats.active_connections.fetch_add(1, Ordering::Relaxed);
+            stats.total_connections.fetch_add(1, Ordering::Relaxed);
+            Arc::new(ConnectionGuard(stats))
+        });
+        Ok(CountedStream {
+            inner: stream,
+            _guard: guard,
+        })
+    }
+
+    fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&mut self, dur: Option<Duration>) {
+        self.inner.set_write_timeout(dur)
+    }
+}
+
+// The connection is closed once all the clones of its stream are dropped
+struct ConnectionGuard(Arc<ServerStats>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+pub struct CountedStream<S> {
+    inner: S,
+    _guard: Option<Arc<ConnectionGuard>>,
+}
+
+impl<S: Read> Read for CountedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for CountedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: NetworkStream> NetworkStream for CountedStream<S> {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: std::net::Shutdown) -> io::Result<()> {
+        self.inner.close(how)
+    }
+}
@@ -0,0 +1,111 @@
+use std::process;
+
+use chrono::Local;
+
+use crate::util::StringError;
+
+const FACILITIES: &[(&str, u8)] = &[
+    ("kern", 0),
+    ("user", 1),
+    ("daemon", 3),
+    ("auth", 4),
+    ("syslog", 5),
+    ("local0", 16),
+    ("local1", 17),
+    ("local2", 18),
+    ("local3", 19),
+    ("local4", 20),
+    ("local5", 21),
+    ("local6", 22),
+    ("local7", 23),
+];
+
+#[cfg(unix)]
+const SOCKETS: &[&str] = &["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+#[derive(Clone, Copy)]
+pub enum Severity {
+    Error = 3,
+    Warning = 4,
+    Info = 6,
+}
+
+pub fn parse_facility(s: &str) -> Result<u8, StringError> {
+    FACILITIES
+        .iter()
+        .find(|(name, _)| *name == s)
+        .map(|(_, code)| *code)
+        .ok_or_else(|| {
+            StringError(format!(
+                "Unknown syslog facility: {} (expected one of {})",
+                s,
+                FACILITIES
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+}
+
+/// Messages to the local syslog daemon (or journald) through its socket (`--log-syslog`)
+pub struct Syslog {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    #[cfg(unix)]
+    path: &'static str,
+    facility: u8,
+}
+
+impl Syslog {
+    #[cfg(unix)]
+    pub fn new(facility: &str) -> Result<Syslog, StringError> {
+        use std::os::unix::net::UnixDatagram;
+
+        let facility = parse_facility(facility)?;
+        let socket = UnixDatagram::unbound()
+            .map_err(|e| StringError(format!("Open syslog socket failed: {}", e)))?;
+        let path = SOCKETS
+            .iter()
+            .find(|path| socket.connect(path).is_ok())
+            .ok_or_else(|| {
+                StringError(format!(
+                    "No syslog socket found (tried {})",
+                    SOCKETS.join(", ")
+                ))
+            })?;
+        Ok(Syslog {
+            socket,
+            path,
+            facility,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_facility: &str) -> Result<Syslog, StringError> {
+        Err(StringError(
+            "Syslog is only supported on unix systems".to_owned(),
+        ))
+    }
+
+    /// Failures are ignored, logging must not break the requests
+    pub fn send(&self, severity: Severity, message: &str) {
+        let line = format!(
+            "<{}>{} simple-http-server[{}]: {}",
+            self.facility as u32 * 8 + severity as u32,
+            Local::now().format("%b %e %H:%M:%S"),
+            process::id(),
+            message
+        );
+        #[cfg(unix)]
+        {
+            // The daemon may have been restarted
+            if self.socket.send(line.as_bytes()).is_err() && self.socket.connect(self.path).is_ok()
+            {
+                let _ = self.socket.send(line.as_bytes());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = line;
+    }
+}
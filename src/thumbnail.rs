@@ -0,0 +1,90 @@
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageReader;
+use sha2::{Digest, Sha256};
+
+use crate::util::{random_token, StringError};
+
+/// Route of the thumbnails (`/__thumb?path=/photos/a.jpg`)
+pub const THUMBNAIL_PATH: &str = "__thumb";
+// Longest side of the thumbnails
+const THUMBNAIL_SIZE: u32 = 128;
+const JPEG_QUALITY: u8 = 80;
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+
+/// Renders thumbnails on demand and keeps them in `cache_dir`
+pub struct Thumbnailer {
+    cache_dir: PathBuf,
+}
+
+/// Whether a thumbnail can be rendered for `path`, by its extension
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+impl Thumbnailer {
+    pub fn new(cache_dir: PathBuf) -> Result<Thumbnailer, StringError> {
+        fs::create_dir_all(&cache_dir).map_err(|e| {
+            StringError(format!(
+                "Create thumbnail directory {} failed: {}",
+                cache_dir.display(),
+                e
+            ))
+        })?;
+        Ok(Thumbnailer { cache_dir })
+    }
+
+    /// The cached JPEG thumbnail of the image, rendered again when the image changes
+    pub fn thumbnail(&self, path: &Path) -> Result<PathBuf, StringError> {
+        let metadata = fs::metadata(path).map_err(|e| StringError(e.to_string()))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0);
+        let mut hasher = Sha256::new();
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(modified.to_le_bytes());
+        hasher.update(metadata.len().to_le_bytes());
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let cached = self.cache_dir.join(format!("{}.jpg", key));
+        if cached.is_file() {
+            return Ok(cached);
+        }
+
+        let image = ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| StringError(e.to_string()))?
+            .decode()
+            .map_err(|e| StringError(format!("Decode image failed: {}", e)))?;
+        let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8();
+        // Concurrent requests of the same image must not see a partial file
+        let tmp = self
+            .cache_dir
+            .join(format!("{}.{}.tmp", key, random_token(8)));
+        let rv = fs::File::create(&tmp)
+            .map_err(|e| StringError(e.to_string()))
+            .and_then(|file| {
+                JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY)
+                    .encode_image(&thumbnail)
+                    .map_err(|e| StringError(e.to_string()))
+            })
+            .and_then(|_| fs::rename(&tmp, &cached).map_err(|e| StringError(e.to_string())));
+        if rv.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        rv.map(|_| cached)
+    }
+}
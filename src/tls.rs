@@ -0,0 +1,426 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Clone)]
+pub enum Identity {
+    /// Certificate in pkcs#12 format
+    Pkcs12 { path: PathBuf, password: String },
+    /// Certificate chain and private key in PEM format
+    Pem { cert: String, key: String },
+    /// Certificate chain and private key files in PEM format
+    PemFiles { cert: PathBuf, key: PathBuf },
+    /// Obtain (and renew) the certificate from an ACME server
+    Acme(AcmeOptions),
+}
+
+impl Identity {
+    fn files(&self) -> Vec<PathBuf> {
+        match *self {
+            Identity::Pkcs12 { ref path, .. } => vec![path.clone()],
+            Identity::PemFiles { ref cert, ref key } => vec![cert.clone(), key.clone()],
+            Identity::Pem { .. } | Identity::Acme(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AcmeOptions {
+    /// The first one is the primary name of the certificate
+    pub domains: Vec<String>,
+    pub email: Option<String>,
+    pub directory_url: String,
+    /// Account key and issued certificates are kept here
+    pub cache_dir: PathBuf,
+    /// Addresses of the plain http listeners answering HTTP-01 challenges
+    pub http_addrs: Vec<SocketAddr>,
+    /// Port of the https server, other requests to the http listener are redirected there
+    pub https_port: u16,
+}
+
+#[derive(Clone)]
+pub struct TlsOptions {
+    pub identity: Identity,
+    /// Require client certificates signed by this CA (PEM)
+    pub client_ca: Option<PathBuf>,
+    /// Accept connections without client certificate (they fall back to other auth)
+    pub client_cert_optional: bool,
+    /// The certificates of the SNI names, `*.example.com` matches one label
+    pub sni: Vec<(String, Identity)>,
+    /// `1.0`, `1.1`, `1.2` or `1.3`
+    pub min_version: Option<String>,
+    /// OpenSSL cipher list, the `TLS_` suites are the TLS 1.3 ones
+    pub ciphers: Option<String>,
+}
+
+impl TlsOptions {
+    /// The certificate, key and CA files, read again when they change
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = self.identity.files();
+        for (_, identity) in &self.sni {
+            files.extend(identity.files());
+        }
+        files.extend(self.client_ca.clone());
+        files
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+pub use self::imp::OpensslServer;
+pub use self::imp::{build_server, peer_common_name, spawn_reload};
+
+// native-tls uses OpenSSL on these platforms anyway, use it directly to get the extra features
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+mod imp {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::fs;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex, RwLock};
+    use std::thread;
+    use std::time::Duration;
+
+    use hyper::net::{NetworkStream, SslServer};
+    use openssl::nid::Nid;
+    use openssl::pkcs12::Pkcs12;
+    use openssl::pkey::PKey;
+    use openssl::ssl::{
+        NameType, SniError, SslAcceptor, SslAcceptorBuilder, SslContext, SslMethod, SslOptions,
+        SslStream, SslVerifyMode, SslVersion,
+    };
+    use openssl::x509::{X509Name, X509};
+
+    use super::{Identity, TlsOptions};
+    use crate::acme;
+    use crate::config_file::Watcher;
+    use crate::util::StringError;
+
+    thread_local! {
+        // hyper handles a connection on the thread which accepted it, so the handler can
+        // find the client certificate of current connection here.
+        static PEER_COMMON_NAME: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    /// Subject CN of the verified client certificate of current connection
+    pub fn peer_common_name() -> Option<String> {
+        PEER_COMMON_NAME.with(|name| name.borrow().clone())
+    }
+
+    fn map_err(e: openssl::error::ErrorStack) -> StringError {
+        StringError(e.to_string())
+    }
+
+    pub fn build_server(opts: &TlsOptions) -> Result<OpensslServer, StringError> {
+        if let Identity::Acme(ref acme_opts) = opts.identity {
+            let (acme_client, identity) = acme::AcmeClient::start(acme_opts)?;
+            let server = build_server(&TlsOptions {
+                identity,
+                ..opts.clone()
+            })?;
+            acme_client.spawn_renewal(server.clone());
+            return Ok(server);
+        }
+        let acceptor = build_acceptor(opts)?;
+        Ok(OpensslServer {
+            opts: Arc::new(opts.clone()),
+            acceptor: Arc::new(RwLock::new(Arc::new(acceptor))),
+        })
+    }
+
+    /// Reads the certificate files again on SIGHUP or when they change (renewals), for the
+    /// connections accepted from then on
+    pub fn spawn_reload(server: &OpensslServer, silent: bool) {
+        let files = server.opts.files();
+        if files.is_empty() {
+            return;
+        }
+        let server = server.clone();
+        let mut watcher = Watcher::with_files(files);
+        thread::spawn(move || loop {
+            watcher.wait();
+            // A renewal may be half written, the next change retries
+            match build_acceptor(&server.opts) {
+                Ok(acceptor) => {
+                    *server.acceptor.write().unwrap() = Arc::new(acceptor);
+                    if !silent {
+                        println!("Reloaded the TLS certificates");
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Reload TLS certificates failed, the previous ones stay: {}",
+                    e
+                ),
+            }
+        });
+    }
+
+    fn set_identity(
+        builder: &mut SslAcceptorBuilder,
+        identity: &Identity,
+    ) -> Result<(), StringError> {
+        match *identity {
+            Identity::Pkcs12 {
+                ref path,
+                ref password,
+            } => {
+                let content = fs::read(path)
+                    .map_err(|e| StringError(format!("error reading identity: {}", e)))?;
+                let identity = Pkcs12::from_der(&content)
+                    .and_then(|p12| p12.parse2(password))
+                    .map_err(|e| StringError(format!("error parsing identity: {}", e)))?;
+                if let Some(ref pkey) = identity.pkey {
+                    builder.set_private_key(pkey).map_err(map_err)?;
+                }
+                if let Some(ref cert) = identity.cert {
+                    builder.set_certificate(cert).map_err(map_err)?;
+                }
+                if let Some(chain) = identity.ca {
+                    for cert in chain {
+                        builder.add_extra_chain_cert(cert).map_err(map_err)?;
+                    }
+                }
+            }
+            Identity::Pem { ref cert, ref key } => {
+                let pkey = PKey::private_key_from_pem(key.as_bytes())
+                    .map_err(|e| StringError(format!("error parsing private key: {}", e)))?;
+                let mut chain = X509::stack_from_pem(cert.as_bytes())
+                    .map_err(|e| StringError(format!("error parsing certificate: {}", e)))?
+                    .into_iter();
+                let leaf = chain
+                    .next()
+                    .ok_or_else(|| StringError("no certificate found".to_owned()))?;
+                builder.set_private_key(&pkey).map_err(map_err)?;
+                builder.set_certificate(&leaf).map_err(map_err)?;
+                for cert in chain {
+                    builder.add_extra_chain_cert(cert).map_err(map_err)?;
+                }
+            }
+            Identity::PemFiles { ref cert, ref key } => {
+                let read = |path: &PathBuf| {
+                    fs::read_to_string(path).map_err(|e| {
+                        StringError(format!("error reading {}: {}", path.display(), e))
+                    })
+                };
+                return set_identity(
+                    builder,
+                    &Identity::Pem {
+                        cert: read(cert)?,
+                        key: read(key)?,
+                    },
+                );
+            }
+            Identity::Acme(_) => unreachable!("ACME identity is resolved before"),
+        }
+        builder.check_private_key().map_err(map_err)
+    }
+
+    fn build_acceptor(opts: &TlsOptions) -> Result<SslAcceptor, StringError> {
+        let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).map_err(map_err)?;
+        set_identity(&mut builder, &opts.identity)?;
+
+        if let Some(ref version) = opts.min_version {
+            let version = match version.as_str() {
+                "1.0" => SslVersion::TLS1,
+                "1.1" => SslVersion::TLS1_1,
+                "1.2" => SslVersion::TLS1_2,
+                "1.3" => SslVersion::TLS1_3,
+                _ => return Err(StringError(format!("unknown TLS version: {}", version))),
+            };
+            // The intermediate profile stops at TLS 1.2, a minimum is not a maximum
+            builder.clear_options(SslOptions::NO_TLSV1_3);
+            builder
+                .set_min_proto_version(Some(version))
+                .map_err(map_err)?;
+        }
+        if let Some(ref ciphers) = opts.ciphers {
+            let (suites, ciphers): (Vec<&str>, Vec<&str>) = ciphers
+                .split(':')
+                .partition(|name| name.starts_with("TLS_"));
+            if !ciphers.is_empty() {
+                builder
+                    .set_cipher_list(&ciphers.join(":"))
+                    .map_err(|e| StringError(format!("error setting ciphers: {}", e)))?;
+            }
+            if !suites.is_empty() {
+                builder
+                    .set_ciphersuites(&suites.join(":"))
+                    .map_err(|e| StringError(format!("error setting ciphers: {}", e)))?;
+            }
+        }
+        if let Some(ref ca) = opts.client_ca {
+            builder.set_ca_file(ca).map_err(map_err)?;
+            builder.set_client_ca_list(X509Name::load_client_ca_file(ca).map_err(map_err)?);
+            let mut mode = SslVerifyMode::PEER;
+            if !opts.client_cert_optional {
+                mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+            }
+            builder.set_verify(mode);
+            // Required by session resumption when verifying peers
+            builder
+                .set_session_id_context(b"simple-http-server")
+                .map_err(map_err)?;
+        }
+        if !opts.sni.is_empty() {
+            let mut contexts = HashMap::new();
+            for (name, identity) in &opts.sni {
+                let acceptor = build_acceptor(&TlsOptions {
+                    identity: identity.clone(),
+                    sni: Vec::new(),
+                    ..opts.clone()
+                })
+                .map_err(|e| StringError(format!("{}: {}", name, e)))?;
+                contexts.insert(name.to_lowercase(), acceptor.into_context());
+            }
+            // Unknown names get the default certificate
+            builder.set_servername_callback(move |ssl, _| {
+                let context = ssl
+                    .servername(NameType::HOST_NAME)
+                    .and_then(|name| find_context(&contexts, name));
+                if let Some(context) = context {
+                    ssl.set_ssl_context(context)
+                        .map_err(|_| SniError::ALERT_FATAL)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(builder.build())
+    }
+
+    fn find_context<'a>(
+        contexts: &'a HashMap<String, SslContext>,
+        name: &str,
+    ) -> Option<&'a SslContext> {
+        let name = name.to_lowercase();
+        contexts.get(&name).or_else(|| {
+            let (_, parent) = name.split_once('.')?;
+            contexts.get(&format!("*.{}", parent))
+        })
+    }
+
+    #[derive(Clone)]
+    pub struct OpensslServer {
+        opts: Arc<TlsOptions>,
+        // Swapped when the certificate changes, established connections keep the old one
+        acceptor: Arc<RwLock<Arc<SslAcceptor>>>,
+    }
+
+    impl OpensslServer {
+        /// Use a new certificate for the connections accepted from now on
+        pub fn set_identity(&self, identity: Identity) -> Result<(), StringError> {
+            let acceptor = build_acceptor(&TlsOptions {
+                identity,
+                ..(*self.opts).clone()
+            })?;
+            *self.acceptor.write().unwrap() = Arc::new(acceptor);
+            Ok(())
+        }
+    }
+
+    impl<T> SslServer<T> for OpensslServer
+    where
+        T: NetworkStream + Send + Clone + fmt::Debug + Sync,
+    {
+        type Stream = TlsStream<T>;
+
+        fn wrap_server(&self, stream: T) -> hyper::Result<TlsStream<T>> {
+            PEER_COMMON_NAME.with(|name| *name.borrow_mut() = None);
+            let acceptor = self.acceptor.read().unwrap().clone();
+            match acceptor.accept(stream) {
+                Ok(s) => {
+                    let common_name = s.ssl().peer_certificate().and_then(|cert| {
+                        cert.subject_name()
+                            .entries_by_nid(Nid::COMMONNAME)
+                            .next()
+                            .and_then(|entry| entry.data().to_string().ok())
+                    });
+                    PEER_COMMON_NAME.with(|name| *name.borrow_mut() = common_name);
+                    Ok(TlsStream(Arc::new(Mutex::new(s))))
+                }
+                Err(e) => Err(hyper::Error::Ssl(Box::new(StringError(e.to_string())))),
+            }
+        }
+    }
+
+    // [Reference]: hyper-native-tls
+    #[derive(Clone)]
+    pub struct TlsStream<S>(Arc<Mutex<SslStream<S>>>);
+
+    impl<S> fmt::Debug for TlsStream<S> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("TlsStream")
+        }
+    }
+
+    impl<S: io::Read + io::Write> io::Read for TlsStream<S> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().read(buf)
+        }
+    }
+
+    impl<S: io::Read + io::Write> io::Write for TlsStream<S> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.lock().unwrap().flush()
+        }
+    }
+
+    impl<S: NetworkStream> NetworkStream for TlsStream<S> {
+        fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+            self.0.lock().unwrap().get_mut().peer_addr()
+        }
+
+        fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+            self.0.lock().unwrap().get_ref().set_read_timeout(dur)
+        }
+
+        fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+            self.0.lock().unwrap().get_ref().set_write_timeout(dur)
+        }
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "ios"))]
+mod imp {
+    use hyper_native_tls::NativeTlsServer;
+
+    use super::{Identity, TlsOptions};
+    use crate::util::StringError;
+
+    pub fn peer_common_name() -> Option<String> {
+        None
+    }
+
+    pub fn spawn_reload(_server: &NativeTlsServer, _silent: bool) {}
+
+    pub fn build_server(opts: &TlsOptions) -> Result<NativeTlsServer, StringError> {
+        if opts.client_ca.is_some() {
+            return Err(StringError(
+                "TLS client certificates are not supported on this platform".to_owned(),
+            ));
+        }
+        if !opts.sni.is_empty() {
+            return Err(StringError(
+                "Certificates by SNI name are not supported on this platform".to_owned(),
+            ));
+        }
+        if opts.min_version.is_some() || opts.ciphers.is_some() {
+            return Err(StringError(
+                "--tls-min-version and --tls-ciphers are not supported on this platform".to_owned(),
+            ));
+        }
+        match opts.identity {
+            Identity::Pkcs12 {
+                ref path,
+                ref password,
+            } => NativeTlsServer::new(path, password).map_err(|e| StringError(e.to_string())),
+            _ => Err(StringError(
+                "Only pkcs#12 certificates are supported on this platform".to_owned(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::util::{random_token, StringError};
+
+/// Deleted files are moved here instead of being removed (`--trash-dir`)
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(dir: &Path) -> Result<Trash, StringError> {
+        fs::create_dir_all(dir)
+            .and_then(|_| dir.canonicalize())
+            .map(|dir| Trash { dir })
+            .map_err(|e| {
+                StringError(format!(
+                    "Create trash directory {} failed: {}",
+                    dir.display(),
+                    e
+                ))
+            })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Files in the trash are removed for good
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.dir)
+    }
+
+    /// Moves the file or directory to `<trash>/<name>.<timestamp>`, returns the new path
+    pub fn move_into(&self, path: &Path) -> io::Result<PathBuf> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?
+            .to_string_lossy()
+            .to_string();
+        let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+        let mut target = self.dir.join(format!("{}.{}", name, timestamp));
+        if fs::symlink_metadata(&target).is_ok() {
+            target = self
+                .dir
+                .join(format!("{}.{}-{}", name, timestamp, random_token(4)));
+        }
+        fs::rename(path, &target)?;
+        Ok(target)
+    }
+}
@@ -0,0 +1,91 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::util::StringError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs `--on-upload` for every saved file
+pub struct UploadCommand {
+    // Not run through a shell: the uploaded file name can't inject anything
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl UploadCommand {
+    pub fn new(template: &str, timeout: Duration) -> Result<UploadCommand, StringError> {
+        let args = shell_words::split(template)
+            .map_err(|e| StringError(format!("Invalid command {}: {}", template, e)))?;
+        if args.is_empty() {
+            return Err(StringError("Empty command".to_owned()));
+        }
+        Ok(UploadCommand { args, timeout })
+    }
+
+    /// `{}` in the arguments is replaced by the path, or the path is appended if there's none
+    pub fn run(&self, path: &Path) {
+        let mut args = self
+            .args
+            .iter()
+            .map(|arg| {
+                if arg == "{}" {
+                    path.as_os_str().to_owned()
+                } else if arg.contains("{}") {
+                    OsString::from(arg.replace("{}", &path.to_string_lossy()))
+                } else {
+                    OsString::from(arg)
+                }
+            })
+            .collect::<Vec<OsString>>();
+        if !self.args.iter().any(|arg| arg.contains("{}")) {
+            args.push(path.as_os_str().to_owned());
+        }
+        let timeout = self.timeout;
+        let display = path.display().to_string();
+        thread::spawn(move || {
+            let mut child = match Command::new(&args[0])
+                .args(&args[1..])
+                .stdin(Stdio::null())
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    eprintln!(
+                        "  >> on-upload command failed to start ({}): {}",
+                        display, e
+                    );
+                    return;
+                }
+            };
+            let started = Instant::now();
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => {
+                        if !status.success() {
+                            eprintln!("  >> on-upload command {} ({})", status, display);
+                        }
+                        return;
+                    }
+                    Ok(None) if started.elapsed() >= timeout => {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        eprintln!(
+                            "  >> on-upload command killed after {}s ({})",
+                            timeout.as_secs(),
+                            display
+                        );
+                        return;
+                    }
+                    Ok(None) => thread::sleep(POLL_INTERVAL),
+                    Err(e) => {
+                        eprintln!("  >> on-upload command wait failed ({}): {}", display, e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::Read;
+use std::path::Path;
+
+use multipart::server::save::SavedData;
+
+// Leading bytes of executables and scripts, and the extensions they are checked as
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"MZ", "exe"),
+    (b"\x7fELF", "elf"),
+    (b"\xfe\xed\xfa\xce", "macho"),
+    (b"\xfe\xed\xfa\xcf", "macho"),
+    (b"\xce\xfa\xed\xfe", "macho"),
+    (b"\xcf\xfa\xed\xfe", "macho"),
+    (b"\xca\xfe\xba\xbe", "macho"),
+    (b"#!", "sh"),
+];
+
+/// How many bytes `--upload-sniff` looks at
+pub const SNIFF_LEN: usize = 4;
+
+/// Extensions accepted by uploads (`--upload-allow`, `--upload-deny`)
+pub struct UploadFilter {
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+    /// Executables and scripts are also checked as `exe`, `elf`, `macho` or `sh`
+    /// whatever their name is (`--upload-sniff`)
+    sniff: bool,
+}
+
+impl UploadFilter {
+    pub fn new(allow: Option<Vec<String>>, deny: Vec<String>, sniff: bool) -> UploadFilter {
+        let normalize = |exts: Vec<String>| {
+            exts.into_iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .filter(|ext| !ext.is_empty())
+                .collect::<Vec<String>>()
+        };
+        UploadFilter {
+            allow: allow.map(normalize),
+            deny: normalize(deny),
+            sniff,
+        }
+    }
+
+    fn accepts(&self, name: &str) -> bool {
+        let matches = |ext: &String| name.ends_with(&format!(".{}", ext));
+        !self.deny.iter().any(matches)
+            && self
+                .allow
+                .as_ref()
+                .is_none_or(|allow| allow.iter().any(matches))
+    }
+
+    /// The reason why the file is refused
+    pub fn check(&self, path: &Path, data: &SavedData) -> Result<(), String> {
+        let mut head = Vec::new();
+        if self.sniff {
+            if let Ok(reader) = data.readable() {
+                let _ = reader.take(SNIFF_LEN as u64).read_to_end(&mut head);
+            }
+        }
+        self.check_head(path, &head)
+    }
+
+    /// Same as `check`, with the first `SNIFF_LEN` bytes of the file
+    pub fn check_head(&self, path: &Path, head: &[u8]) -> Result<(), String> {
+        let name = path.to_string_lossy().to_lowercase();
+        if !self.accepts(&name) {
+            return Err(format!("File type not allowed: {}", path.display()));
+        }
+        if self.sniff {
+            let sniffed = MAGIC_NUMBERS
+                .iter()
+                .find(|(magic, _)| head.starts_with(magic))
+                .map(|(_, ext)| *ext);
+            if let Some(ext) = sniffed {
+                if !self.accepts(&format!(".{}", ext)) {
+                    return Err(format!(
+                        "File type not allowed: {} (content is {})",
+                        path.display(),
+                        ext
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
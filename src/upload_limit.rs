@@ -0,0 +1,149 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const HOUR: Duration = Duration::from_secs(3600);
+// Idle clients are dropped when there are this many
+const PRUNE_THRESHOLD: usize = 10000;
+// Read at most this much at once, for a smooth rate
+const MAX_CHUNK: usize = 16 * 1024;
+
+/// The uploads of each client: the bandwidth (`--upload-limit-rate`) shared by its uploads,
+/// and the files saved in the last hour (`--upload-max-per-hour`)
+pub struct UploadLimiter {
+    /// Bytes per second
+    rate: Option<u64>,
+    per_hour: Option<usize>,
+    clients: Mutex<HashMap<Client, Uploads>>,
+}
+
+/// The authenticated user, or the IP of the anonymous ones
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    User(String),
+    Ip(IpAddr),
+}
+
+struct Uploads {
+    // Bytes allowed to be read now, negative when the reader has to wait
+    allowance: f64,
+    updated: Instant,
+    saved: VecDeque<Instant>,
+}
+
+impl UploadLimiter {
+    pub fn new(rate: Option<u64>, per_hour: Option<usize>) -> UploadLimiter {
+        UploadLimiter {
+            rate,
+            per_hour,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn with_client<T, F: FnOnce(&mut Uploads) -> T>(&self, client: &Client, f: F) -> T {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= PRUNE_THRESHOLD {
+            clients.retain(|_, uploads| {
+                now.duration_since(uploads.updated) < HOUR
+                    || uploads
+                        .saved
+                        .back()
+                        .is_some_and(|t| now.duration_since(*t) < HOUR)
+            });
+        }
+        let uploads = clients.entry(client.clone()).or_insert(Uploads {
+            allowance: self.rate.unwrap_or(0) as f64,
+            updated: now,
+            saved: VecDeque::new(),
+        });
+        while uploads
+            .saved
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= HOUR)
+        {
+            uploads.saved.pop_front();
+        }
+        f(uploads)
+    }
+
+    /// The seconds until `client` may upload again, `None` if it still can
+    pub fn retry_after(&self, client: &Client) -> Option<u64> {
+        let per_hour = self.per_hour?;
+        self.with_client(client, |uploads| {
+            if uploads.saved.len() < per_hour {
+                return None;
+            }
+            let oldest = uploads.saved[uploads.saved.len() - per_hour];
+            Some((HOUR - oldest.elapsed()).as_secs().max(1))
+        })
+    }
+
+    /// Counts `files` about to be saved by `client`, or the seconds to wait if that's too many
+    pub fn reserve(&self, client: &Client, files: usize) -> Result<(), u64> {
+        let per_hour = match self.per_hour {
+            Some(per_hour) => per_hour,
+            None => return Ok(()),
+        };
+        self.with_client(client, |uploads| {
+            let count = uploads.saved.len();
+            if count + files > per_hour {
+                // Until enough of them are an hour old
+                let wait = match files <= per_hour {
+                    true => HOUR - uploads.saved[count + files - per_hour - 1].elapsed(),
+                    false => HOUR,
+                };
+                return Err(wait.as_secs().max(1));
+            }
+            let now = Instant::now();
+            uploads.saved.extend(std::iter::repeat_n(now, files));
+            Ok(())
+        })
+    }
+
+    /// The request body of an upload of `client`, read at the rate of its uploads together
+    pub fn throttle<R: Read>(&self, body: R, client: Client) -> Throttled<'_, R> {
+        Throttled {
+            inner: body,
+            limiter: self,
+            client,
+        }
+    }
+
+    // Takes `bytes` of the allowance, the time to wait before reading more
+    fn consume(&self, client: &Client, bytes: usize) -> Option<Duration> {
+        let rate = self.rate? as f64;
+        self.with_client(client, |uploads| {
+            let now = Instant::now();
+            let elapsed = now.duration_since(uploads.updated).as_secs_f64();
+            // A second of burst at most
+            uploads.allowance = (uploads.allowance + elapsed * rate).min(rate) - bytes as f64;
+            uploads.updated = now;
+            if uploads.allowance < 0.0 {
+                Some(Duration::from_secs_f64(-uploads.allowance / rate))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+pub struct Throttled<'a, R> {
+    inner: R,
+    limiter: &'a UploadLimiter,
+    client: Client,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(MAX_CHUNK);
+        let n = self.inner.read(&mut buf[..len])?;
+        if let Some(wait) = self.limiter.consume(&self.client, n) {
+            thread::sleep(wait);
+        }
+        Ok(n)
+    }
+}
@@ -1,14 +1,26 @@
+use std::borrow::Cow;
 use std::error::Error;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io;
 use std::ops::Deref;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use chrono::{DateTime, Local, TimeZone};
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
 use iron::headers;
+use iron::mime::Mime;
 use iron::status;
-use iron::{IronError, Response};
-use percent_encoding::{utf8_percent_encode, AsciiSet};
+use iron::{IronError, Request, Response};
+use lazy_static::lazy_static;
+use percent_encoding::{
+    percent_decode, percent_encode, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC,
+};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::Sha256;
 
 /// https://url.spec.whatwg.org/#fragment-percent-encode-set
 const FRAGMENT_ENCODE_SET: &AsciiSet = &percent_encoding::CONTROLS
@@ -20,6 +32,20 @@ const FRAGMENT_ENCODE_SET: &AsciiSet = &percent_encoding::CONTROLS
 /// https://url.spec.whatwg.org/#path-percent-encode-set
 const PATH_ENCODE_SET: &AsciiSet = &FRAGMENT_ENCODE_SET.add(b'#').add(b'?').add(b'{').add(b'}');
 const PATH_SEGMENT_ENCODE_SET: &AsciiSet = &PATH_ENCODE_SET.add(b'/').add(b'%');
+/// https://tools.ietf.org/html/rfc5987#section-3.2.1 (everything except attr-char)
+const ATTR_CHAR_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
 
 pub const ROOT_LINK: &str = r#"<a href="/"><strong>[Root]</strong></a>"#;
 
@@ -42,7 +68,7 @@ impl Deref for StringError {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &self.0
     }
 }
 
@@ -50,70 +76,300 @@ pub fn enable_string(value: bool) -> String {
     (if value { "enabled" } else { "disabled" }).to_owned()
 }
 
-pub fn encode_link_path(path: &[String]) -> String {
+pub fn encode_link_path<S: AsRef<OsStr>>(path: &[S]) -> String {
     path.iter()
-        .map(|s| utf8_percent_encode(s, PATH_SEGMENT_ENCODE_SET).to_string())
+        .map(|s| percent_encode(&os_str_bytes(s.as_ref()), PATH_SEGMENT_ENCODE_SET).to_string())
         .collect::<Vec<String>>()
         .join("/")
 }
 
-pub fn error_io2iron(err: io::Error) -> IronError {
-    let status = match err.kind() {
-        io::ErrorKind::PermissionDenied => status::Forbidden,
-        io::ErrorKind::NotFound => status::NotFound,
-        _ => status::InternalServerError,
-    };
-    IronError::new(err, status)
+/// Encode a path as one query value (`/a/b` => `%2Fa%2Fb`), decode it by `decode_path_segment`
+pub fn encode_query_path<S: AsRef<OsStr>>(path: &[S]) -> String {
+    path.iter()
+        .map(|s| {
+            format!(
+                "%2F{}",
+                percent_encode(&os_str_bytes(s.as_ref()), NON_ALPHANUMERIC)
+            )
+        })
+        .collect()
 }
 
-/* TODO: may not used
+/// Decode one url path segment, on unix the decoded bytes may be any (non-UTF-8) file name
+pub fn decode_path_segment(segment: &str) -> Result<OsString, StringError> {
+    let bytes = percent_decode(segment.as_bytes()).collect::<Vec<u8>>();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(OsString::from_vec(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(bytes)
+            .map(OsString::from)
+            .map_err(|_| StringError("Invalid UTF-8 sequence in url path".to_owned()))
+    }
+}
 
-use iron::headers::{Range, ByteRangeSpec};
+#[cfg(unix)]
+fn os_str_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(s.as_bytes())
+}
 
-#[allow(dead_code)]
-pub fn parse_range(ranges: &Vec<ByteRangeSpec>, total: u64)
-                   -> Result<Option<(u64, u64)>, IronError> {
-    if let Some(range) = ranges.get(0) {
-        let (offset, length) = match range {
-            &ByteRangeSpec::FromTo(x, mut y) => { // "x-y"
-                if x >= total || x > y {
-                    return Err(IronError::new(
-                        StringError(format!("Invalid range(x={}, y={})", x, y)),
-                        status::RangeNotSatisfiable
-                    ));
-                }
-                if y >= total {
-                    y = total - 1;
-                }
-                (x, y - x + 1)
+#[cfg(not(unix))]
+fn os_str_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    match s.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+        Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+    }
+}
+
+/// Parse one `--mime` argument (`ext=type`)
+pub fn parse_mime_override(s: &str) -> Result<(String, Mime), StringError> {
+    let parts = s.splitn(2, '=').collect::<Vec<&str>>();
+    if parts.len() != 2 || parts[0].is_empty() {
+        return Err(StringError(format!("not valid format ext=type: {}", s)));
+    }
+    let mime = parts[1]
+        .parse::<Mime>()
+        .map_err(|_| StringError(format!("not valid mime type: {}", parts[1])))?;
+    Ok((parts[0].trim_start_matches('.').to_lowercase(), mime))
+}
+
+/// Parse the content of a mime.types style file, each line is: `type ext1 ext2 ...`
+pub fn parse_mime_types_file(content: &str) -> Result<Vec<(String, Mime)>, StringError> {
+    let mut mappings = Vec::new();
+    for (lineno, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut parts = line.split_whitespace();
+        if let Some(mime_str) = parts.next() {
+            let mime = mime_str.parse::<Mime>().map_err(|_| {
+                StringError(format!(
+                    "not valid mime type at line {}: {}",
+                    lineno + 1,
+                    mime_str
+                ))
+            })?;
+            for ext in parts {
+                mappings.push((ext.trim_start_matches('.').to_lowercase(), mime.clone()));
             }
-            &ByteRangeSpec::AllFrom(x) => { // "x-"
-                if x >= total {
-                    return Err(IronError::new(
-                        StringError(format!(
-                            "Range::AllFrom to large (x={}), Content-Length: {})",
-                            x, total)),
-                        status::RangeNotSatisfiable
-                    ));
-                }
-                (x, total - x)
+        }
+    }
+    Ok(mappings)
+}
+
+/// Build a `Content-Disposition: attachment` value, non-ASCII names are sent by `filename*`
+pub fn content_disposition_attachment(filename: &str) -> String {
+    let fallback = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
             }
-            &ByteRangeSpec::Last(mut x) => { // "-x"
-                if x > total {
-                    x = total;
+        })
+        .collect::<String>();
+    format!(
+        r#"attachment; filename="{}"; filename*=UTF-8''{}"#,
+        fallback,
+        utf8_percent_encode(filename, ATTR_CHAR_ENCODE_SET)
+    )
+}
+
+/// Relative path sent by a client, like the name of an uploaded file (`a/b.txt`, or `a\\b.txt`
+/// from windows), `None` when it would escape the directory
+pub fn client_relative_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            _ if component.contains('\0') => return None,
+            _ => path.push(component),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Total size of the files under `path` (symlinks not followed)
+pub fn tree_size(path: &Path) -> u64 {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| tree_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// `90s`, `30m`, `24h` or `7d` (seconds without a unit)
+pub fn parse_duration(s: &str) -> Result<Duration, StringError> {
+    let s = s.trim();
+    let err = || StringError(format!("Invalid duration: {}, example: 24h", s));
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let count = s[..split].parse::<u64>().map_err(|_| err())?;
+    let seconds = match &s[split..] {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(err()),
+    };
+    count
+        .checked_mul(seconds)
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(err)
+}
+
+/// Case insensitive name matcher of the listing filter, `abc` means `*abc*`
+pub fn name_filter(pattern: &str) -> Result<globset::GlobMatcher, StringError> {
+    let pattern = if pattern.contains(['*', '?', '[']) {
+        pattern.to_owned()
+    } else {
+        format!("*{}*", pattern)
+    };
+    globset::GlobBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| StringError(format!("Invalid filter: {}", e)))
+}
+
+/// Query string of the request with the `overrides` replaced (or added)
+pub fn query_with(req: &Request, overrides: &[(&str, String)]) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (k, v) in req.url.as_ref().query_pairs() {
+        if overrides.iter().all(|(name, _)| *name != k) {
+            query.append_pair(&k, &v);
+        }
+    }
+    for (name, value) in overrides {
+        query.append_pair(name, value);
+    }
+    query.finish()
+}
+
+/// `; Secure` for the cookies set over https (TLS, or a trusted proxy of `X-Forwarded-Proto`)
+pub fn secure_attribute(req: &Request) -> &'static str {
+    if req.url.scheme() == "https" {
+        "; Secure"
+    } else {
+        ""
+    }
+}
+
+/// Get the value of cookie `name` from the `Cookie` header
+pub fn get_cookie(req: &Request, name: &str) -> Option<String> {
+    header_cookie(&req.headers, name)
+}
+
+pub fn header_cookie(headers: &headers::Headers, name: &str) -> Option<String> {
+    headers.get::<headers::Cookie>().and_then(|cookies| {
+        cookies
+            .iter()
+            .flat_map(|cookie| cookie.split(';'))
+            .filter_map(|pair| {
+                let mut parts = pair.trim().splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(k), Some(v)) if k == name => Some(v.to_owned()),
+                    _ => None,
                 }
-                (total - x, x)
+            })
+            .next()
+    })
+}
+
+pub fn random_token(len: usize) -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(len).collect()
+}
+
+lazy_static! {
+    static ref SECRET_KEY: String = random_token(32);
+}
+
+/// A password or token compared by its HMAC, in constant time
+pub fn same_secret(given: &str, expected: &str) -> bool {
+    let mac = |s: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET_KEY.as_bytes())
+            .expect("HMAC takes keys of any size");
+        mac.update(s.as_bytes());
+        mac
+    };
+    let expected = mac(expected).finalize().into_bytes();
+    mac(given).verify_slice(&expected).is_ok()
+}
+
+/// `Content-Security-Policy` of the generated pages: only their own `<script>` and `<style>`
+/// elements (those with the `nonce`) apply, not the markup a crafted file name would inject
+pub fn set_page_csp(resp: &mut Response, nonce: &str) {
+    let policy = format!(
+        "default-src 'self'; script-src 'nonce-{nonce}'; style-src 'self' 'nonce-{nonce}'; \
+         img-src 'self' data:; object-src 'none'; base-uri 'none'; form-action 'self'; \
+         frame-ancestors 'self'",
+        nonce = nonce
+    );
+    resp.headers
+        .set_raw("Content-Security-Policy", vec![policy.into_bytes()]);
+}
+
+/// Adds the `nonce` of `set_page_csp()` to the `<script>` and `<style>` tags of HTML written
+/// by the operator (`--header-file`, `--footer-file`)
+pub fn with_nonce(html: &str, nonce: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut rv = String::with_capacity(html.len());
+    let mut copied = 0;
+    for (start, _) in lower.match_indices('<') {
+        let tag = ["<script", "<style"]
+            .iter()
+            .find(|tag| lower[start..].starts_with(**tag));
+        if let Some(tag) = tag {
+            let end = start + tag.len();
+            // Not `<scripts>` nor `<styles>`
+            if lower[end..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+                rv.push_str(&html[copied..end]);
+                rv.push_str(&format!(" nonce=\"{}\"", nonce));
+                copied = end;
             }
-        };
-        Ok(Some((offset, length)))
-    } else {
-        return Err(IronError::new(
-            StringError("Empty range set".to_owned()),
-            status::RangeNotSatisfiable
-        ));
+        }
     }
+    rv.push_str(&html[copied..]);
+    rv
+}
+
+/// `/`-separated path of a file under the served root, for messages
+pub fn root_relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let segments = relative
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    format!("/{}", segments.join("/"))
+}
+
+pub fn error_io2iron(err: io::Error) -> IronError {
+    let status = match err.kind() {
+        io::ErrorKind::PermissionDenied => status::Forbidden,
+        io::ErrorKind::NotFound => status::NotFound,
+        _ => status::InternalServerError,
+    };
+    IronError::new(err, status)
 }
-*/
 
 pub fn now_string() -> String {
     Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
@@ -137,6 +393,7 @@ pub fn system_time_to_date_time(t: SystemTime) -> DateTime<Local> {
 }
 
 pub fn error_resp(s: status::Status, msg: &str) -> Response {
+    let nonce = random_token(16);
     let mut resp = Response::with((
         s,
         format!(
@@ -144,19 +401,23 @@ pub fn error_resp(s: status::Status, msg: &str) -> Response {
 <html>
 <head>
   <meta charset="utf-8">
+  <style nonce="{nonce}">.error {{ color:red; }}</style>
 </head>
 <body>
   {root_link}
   <hr />
-  <div>[<strong style=color:red;>ERROR {code}</strong>]: {msg}</div>
+  <div>[<strong class="error">ERROR {code}</strong>]: {msg}</div>
 </body>
 </html>
 "#,
+            nonce = nonce,
             root_link = ROOT_LINK,
             code = s.to_u16(),
-            msg = msg
+            // Echoes the paths and the query values of the requests
+            msg = encode_minimal(msg)
         ),
     ));
     resp.headers.set(headers::ContentType::html());
+    set_page_csp(&mut resp, &nonce);
     resp
 }
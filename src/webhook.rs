@@ -0,0 +1,72 @@
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use hyper::client::Client;
+use hyper::header::ContentType;
+use hyper::net::HttpsConnector;
+use hyper::Url;
+use hyper_native_tls::NativeTlsClient;
+use serde_json::{json, Value};
+
+use crate::util::StringError;
+
+// Events queued while the endpoint is slow, more are dropped
+const QUEUE_SIZE: usize = 256;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// POSTs a JSON payload to `--webhook-url` after uploads (and downloads)
+pub struct Webhook {
+    pub downloads: bool,
+    sender: SyncSender<Value>,
+}
+
+impl Webhook {
+    pub fn new(url: &str, downloads: bool) -> Result<Webhook, StringError> {
+        let url =
+            Url::parse(url).map_err(|e| StringError(format!("Invalid webhook url: {}", e)))?;
+        let tls = NativeTlsClient::new().map_err(|e| StringError(e.to_string()))?;
+        let mut client = Client::with_connector(HttpsConnector::new(tls));
+        client.set_read_timeout(Some(TIMEOUT));
+        client.set_write_timeout(Some(TIMEOUT));
+
+        let (sender, receiver) = sync_channel::<Value>(QUEUE_SIZE);
+        // Deliver in order, without blocking the requests
+        thread::spawn(move || {
+            for payload in receiver {
+                let body = payload.to_string();
+                let rv = client
+                    .post(url.clone())
+                    .header(ContentType::json())
+                    .body(body.as_str())
+                    .send();
+                match rv {
+                    Ok(mut resp) => {
+                        let _ = resp.read_to_end(&mut Vec::new());
+                        if !resp.status.is_success() {
+                            eprintln!("Webhook {} responded {}", url, resp.status);
+                        }
+                    }
+                    Err(e) => eprintln!("Webhook {} failed: {}", url, e),
+                }
+            }
+        });
+        Ok(Webhook { downloads, sender })
+    }
+
+    /// `path` is relative to the served root
+    pub fn notify(&self, event: &str, path: &str, size: u64, client_ip: IpAddr) {
+        let payload = json!({
+            "event": event,
+            "path": path,
+            "size": size,
+            "client_ip": client_ip.to_string(),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(payload) {
+            eprintln!("Webhook queue is full, {} event of {} dropped", event, path);
+        }
+    }
+}
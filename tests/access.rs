@@ -0,0 +1,127 @@
+mod common;
+
+use common::TestServer;
+
+// user:pass
+const USER: &str = "Basic dXNlcjpwYXNz";
+
+fn server(access: &[u8]) -> TestServer {
+    TestServer::start(
+        &[
+            ("public.txt", b"public"),
+            ("private/.shs-access", access),
+            ("private/secret.txt", b"secret"),
+            ("private/notes.tmp", b"notes"),
+            ("private/sub/deep.txt", b"deep"),
+        ],
+        |s| s.access_files(true).upload(true).csrf(false),
+    )
+}
+
+#[test]
+fn subtree_credentials() {
+    let server = server(b"auth = user:pass\n");
+    assert_eq!(server.get("/public.txt").status, 200);
+    let resp = server.get("/private/secret.txt");
+    assert_eq!(resp.status, 401);
+    assert_eq!(
+        resp.header("WWW-Authenticate"),
+        Some("Basic realm=\"/private\"")
+    );
+    assert_eq!(server.get("/private/sub/deep.txt").status, 401);
+    let resp = server.get_with("/private/secret.txt", &[("Authorization", USER)]);
+    assert_eq!(resp.body, b"secret");
+}
+
+#[test]
+fn read_only_subtree_users() {
+    let server = server(b"auth = user:pass:r\n");
+    let resp = server.upload("/private/", &[("b.txt", b"x")], &[("Authorization", USER)]);
+    assert_eq!(resp.status, 403);
+    // Nor through a path below a public directory
+    let resp = server.upload("/", &[("private/b.txt", b"x")], &[]);
+    assert_eq!(resp.status, 401);
+    assert!(!server.path("private/b.txt").exists());
+}
+
+#[test]
+fn ignore_and_listing_rules() {
+    let server = server(b"ignore = *.tmp\nlisting = false\n");
+    assert_eq!(server.get("/private/notes.tmp").status, 404);
+    assert_eq!(server.get("/private/secret.txt").status, 200);
+    assert_eq!(server.get("/private/").status, 403);
+    assert_eq!(server.get("/private/sub/").status, 403);
+    assert_eq!(server.get("/").status, 200);
+}
+
+#[test]
+fn access_files_are_hidden() {
+    let server = server(b"listing = true\n");
+    assert_eq!(server.get("/private/.shs-access").status, 404);
+    assert!(!server.get("/private/").text().contains(".shs-access"));
+    let resp = server.upload("/", &[(".shs-access", b"listing = false")], &[]);
+    assert_eq!(resp.status, 403);
+    assert!(!server.path(".shs-access").exists());
+}
+
+#[test]
+fn changes_apply_without_restart() {
+    let server = server(b"");
+    assert_eq!(server.get("/private/secret.txt").status, 200);
+    std::fs::write(server.path("private/.shs-access"), b"auth = user:pass\n").unwrap();
+    // The cache is keyed by the modification time
+    let mtime = filetime::FileTime::from_unix_time(1, 0);
+    filetime::set_file_mtime(server.path("private/.shs-access"), mtime).unwrap();
+    assert_eq!(server.get("/private/secret.txt").status, 401);
+}
+
+#[test]
+fn invalid_file_denies_the_subtree() {
+    let server = server(b"unknown = 1\n");
+    assert_eq!(server.get("/private/secret.txt").status, 500);
+    assert_eq!(server.get("/public.txt").status, 200);
+}
+
+#[test]
+fn protected_subtrees_are_left_out_of_archives() {
+    let server = server(b"auth = user:pass\n");
+    let resp = server.get("/?download=tar");
+    assert_eq!(resp.status, 200);
+    let body = resp.text();
+    assert!(body.contains("public"));
+    assert!(!body.contains("secret"));
+}
+
+#[test]
+fn denied_objects_over_s3() {
+    let server = TestServer::start(
+        &[
+            ("public.txt", b"public"),
+            ("private/.shs-access", b"auth = user:pass\n"),
+            ("private/secret.txt", b"secret"),
+            ("protected/pin.txt", b"pin"),
+        ],
+        |s| {
+            s.access_files(true)
+                .protect_path("/protected", "pw")
+                .upload(true)
+                .s3("files")
+        },
+    );
+    assert_eq!(server.get("/files/public.txt").body, b"public");
+    assert_eq!(server.get("/files/private/secret.txt").status, 403);
+    assert_eq!(server.get("/files/protected/pin.txt").status, 403);
+    let resp = server.request("PUT", "/files/private/b.txt", &[], b"x");
+    assert_eq!(resp.status, 403);
+    assert!(!server.path("private/b.txt").exists());
+    assert_eq!(
+        server
+            .request("DELETE", "/files/protected/pin.txt", &[], b"")
+            .status,
+        403
+    );
+    assert!(server.path("protected/pin.txt").exists());
+    let listing = server.get("/files?list-type=2").text();
+    assert!(listing.contains("public.txt"));
+    assert!(!listing.contains("secret.txt") && !listing.contains("pin.txt"));
+}
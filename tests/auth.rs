@@ -0,0 +1,661 @@
+mod common;
+
+use common::TestServer;
+use simple_http_server::SimpleHttpServer;
+
+// user:pass, user:wrong and uploader:secret
+const USER: &str = "Basic dXNlcjpwYXNz";
+const WRONG: &str = "Basic dXNlcjp3cm9uZw==";
+const UPLOADER: &str = "Basic dXBsb2FkZXI6c2VjcmV0";
+
+#[test]
+fn credentials_required() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| s.auth("user:pass"));
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.status, 401);
+    assert_eq!(
+        resp.header("WWW-Authenticate"),
+        Some("Basic realm=\"main\"")
+    );
+}
+
+#[test]
+fn right_credentials() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| s.auth("user:pass"));
+    let resp = server.get_with("/a.txt", &[("Authorization", USER)]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"hello");
+}
+
+#[test]
+fn wrong_password() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| s.auth("user:pass"));
+    let resp = server.get_with("/a.txt", &[("Authorization", WRONG)]);
+    assert_eq!(resp.status, 401);
+}
+
+#[test]
+fn upload_only_credentials() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.upload(true).csrf(false).auth_upload("uploader:secret")
+    });
+    // Downloads stay anonymous
+    assert_eq!(server.get("/a.txt").status, 200);
+    let resp = server.upload("/", &[("b.txt", b"x")], &[]);
+    assert_eq!(resp.status, 401);
+    assert!(!server.path("b.txt").exists());
+    let resp = server.upload("/", &[("b.txt", b"x")], &[("Authorization", UPLOADER)]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    assert!(server.path("b.txt").exists());
+}
+
+#[test]
+fn download_only_user_cannot_upload() {
+    let server = TestServer::start(&[], |s| s.upload(true).csrf(false).auth("user:pass:r"));
+    let resp = server.upload("/", &[("b.txt", b"x")], &[("Authorization", USER)]);
+    assert_eq!(resp.status, 403);
+    assert!(!server.path("b.txt").exists());
+}
+
+#[test]
+fn read_only_user_cannot_delete() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.upload(true).delete(true).csrf(false).auth("user:pass:ro")
+    });
+    assert_eq!(
+        server.get_with("/a.txt", &[("Authorization", USER)]).status,
+        200
+    );
+    let resp = server.request("DELETE", "/a.txt", &[("Authorization", USER)], b"");
+    assert_eq!(resp.status, 403);
+    assert!(server.path("a.txt").exists());
+}
+
+#[test]
+fn read_write_user_can_delete() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.upload(true).delete(true).csrf(false).auth("user:pass:rw")
+    });
+    let resp = server.request("DELETE", "/a.txt", &[("Authorization", USER)], b"");
+    assert_eq!(resp.status, 204);
+}
+
+#[test]
+fn status_page_requires_an_admin() {
+    let files: &[(&str, &[u8])] = &[("__status", b"status")];
+    let server = TestServer::start(files, |s| s.auth("user:pass:rw"));
+    let resp = server.get_with("/__status", &[("Authorization", USER)]);
+    assert_eq!(resp.status, 403);
+    // The default role of --auth
+    let server = TestServer::start(files, |s| s.auth("user:pass"));
+    let resp = server.get_with("/__status", &[("Authorization", USER)]);
+    assert_eq!(resp.status, 200);
+    let server = TestServer::start(files, |s| {
+        s.upload(true)
+            .auth("user:pass")
+            .auth_upload("uploader:secret:admin")
+    });
+    let resp = server.get_with("/__status", &[("Authorization", UPLOADER)]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn status_page_is_never_public() {
+    let root = std::env::temp_dir();
+    assert!(SimpleHttpServer::new(&root)
+        .status(true)
+        .serve("127.0.0.1:0")
+        .is_err());
+    // Only the uploads are protected, not the reads
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.upload(true).status(true).auth_upload("uploader:secret")
+    });
+    assert_eq!(server.get("/a.txt").status, 200);
+    assert_eq!(server.get("/__status").status, 401);
+    let resp = server.get_with("/__status", &[("Authorization", UPLOADER)]);
+    assert_eq!(resp.status, 403);
+    let server = TestServer::start(&[], |s| {
+        s.upload(true)
+            .status(true)
+            .auth_upload("uploader:secret:admin")
+    });
+    let resp = server.get_with("/__status", &[("Authorization", UPLOADER)]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn session_login() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.auth("user:pass").auth_session(3600)
+    });
+    // The form for the browsers, the Basic Auth prompt for the others
+    let resp = server.get_with("/a.txt?x=1", &[("Accept", "text/html,*/*")]);
+    assert_eq!(resp.status, 401);
+    assert!(resp.header("WWW-Authenticate").is_none());
+    assert!(resp.text().contains(r#"action="/__login""#));
+    assert!(resp.text().contains("&#x2F;a&#x2E;txt&#x3F;x&#x3D;1"));
+    assert_eq!(server.get("/a.txt").status, 401);
+    assert_eq!(
+        server.get_with("/a.txt", &[("Authorization", USER)]).status,
+        200
+    );
+
+    let form = [("Content-Type", "application/x-www-form-urlencoded")];
+    let resp = server.request(
+        "POST",
+        "/__login",
+        &form,
+        b"username=user&password=wrong&next=/a.txt",
+    );
+    assert_eq!(resp.status, 401);
+    assert!(resp.header("Set-Cookie").is_none());
+    assert!(resp.text().contains("Wrong username or password."));
+
+    let resp = server.request(
+        "POST",
+        "/__login",
+        &form,
+        b"username=user&password=pass&next=/a.txt",
+    );
+    assert_eq!(resp.status, 303);
+    assert_eq!(resp.header("Location"), Some("/a.txt"));
+    let cookie = resp
+        .header("Set-Cookie")
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    assert!(cookie.starts_with("shs_session="));
+    let resp = server.get_with("/a.txt", &[("Cookie", &cookie)]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"hello");
+    let listing = server.get_with("/", &[("Cookie", &cookie)]);
+    assert!(listing
+        .text()
+        .contains(r#"<form class="logout" method="POST" action="/__logout">"#));
+    let csrf = listing
+        .header("Set-Cookie")
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    let token = csrf.strip_prefix("csrf_token=").unwrap();
+    assert!(listing.text().contains(token));
+
+    // Signed by the server
+    let forged = cookie.replace("user", "root");
+    assert_eq!(
+        server.get_with("/a.txt", &[("Cookie", &forged)]).status,
+        401
+    );
+
+    // Posted with the CSRF token only, not by a link or another site
+    assert_eq!(
+        server.get_with("/__logout", &[("Cookie", &cookie)]).status,
+        405
+    );
+    let cookies = format!("{}; {}", cookie, csrf);
+    let headers = [
+        ("Cookie", cookies.as_str()),
+        ("Content-Type", "application/x-www-form-urlencoded"),
+    ];
+    let resp = server.request("POST", "/__logout", &headers, b"csrf_token=wrong");
+    assert_eq!(resp.status, 403);
+    assert!(resp.header("Set-Cookie").is_none());
+    let body = format!("csrf_token={}", token);
+    let resp = server.request("POST", "/__logout", &headers, body.as_bytes());
+    assert_eq!(resp.status, 303);
+    assert!(resp.header("Set-Cookie").unwrap().contains("Max-Age=0"));
+}
+
+#[test]
+fn session_login_stays_on_the_server() {
+    let server = TestServer::start(&[], |s| s.auth("user:pass").auth_session(3600));
+    let form = [("Content-Type", "application/x-www-form-urlencoded")];
+    let resp = server.request(
+        "POST",
+        "/__login",
+        &form,
+        b"username=user&password=pass&next=//example.com/",
+    );
+    assert_eq!(resp.status, 303);
+    assert_eq!(resp.header("Location"), Some("/"));
+    let resp = server.request(
+        "POST",
+        "/__login",
+        &form,
+        b"username=user&password=pass&next=/%09/example.com/",
+    );
+    assert_eq!(resp.header("Location"), Some("/"));
+}
+
+#[test]
+fn secure_cookies_over_https() {
+    let server = TestServer::start(&[], |s| {
+        s.auth("user:pass")
+            .auth_session(3600)
+            .upload(true)
+            .trusted_proxy("127.0.0.1/32")
+    });
+    let login = |headers: &[(&str, &str)]| {
+        let mut headers = headers.to_vec();
+        headers.push(("Content-Type", "application/x-www-form-urlencoded"));
+        let resp = server.request("POST", "/__login", &headers, b"username=user&password=pass");
+        resp.header("Set-Cookie").unwrap().to_owned()
+    };
+    let https = ("X-Forwarded-Proto", "https");
+    assert!(!login(&[]).contains("Secure"));
+    assert!(login(&[https]).ends_with("; Secure"));
+    let csrf_cookie = |headers: &[(&str, &str)]| {
+        let mut headers = headers.to_vec();
+        headers.push(("Authorization", USER));
+        server
+            .get_with("/", &headers)
+            .header("Set-Cookie")
+            .unwrap()
+            .to_owned()
+    };
+    assert!(!csrf_cookie(&[]).contains("Secure"));
+    assert!(csrf_cookie(&[https]).ends_with("; Secure"));
+}
+
+#[test]
+fn bearer_tokens() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.upload(true)
+            .csrf(false)
+            .auth("user:pass")
+            .auth_token("s3cret")
+            .auth_token("reader:ro")
+    });
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.status, 401);
+    let schemes = resp
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("WWW-Authenticate"))
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(schemes, ["Basic realm=\"main\"", "Bearer realm=\"main\""]);
+
+    let resp = server.get_with("/a.txt", &[("Authorization", "Bearer s3cret")]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"hello");
+    assert_eq!(server.get("/a.txt?access_token=s3cret").status, 200);
+    // Basic Auth still works
+    assert_eq!(
+        server.get_with("/a.txt", &[("Authorization", USER)]).status,
+        200
+    );
+
+    let resp = server.get_with("/a.txt", &[("Authorization", "Bearer wrong")]);
+    assert_eq!(resp.status, 401);
+    assert!(resp
+        .header("WWW-Authenticate")
+        .unwrap()
+        .contains("invalid_token"));
+
+    let resp = server.upload(
+        "/",
+        &[("b.txt", b"x")],
+        &[("Authorization", "Bearer reader")],
+    );
+    assert_eq!(resp.status, 403);
+    let resp = server.upload("/?access_token=s3cret", &[("b.txt", b"x")], &[]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    assert!(server.path("b.txt").exists());
+}
+
+#[test]
+fn tokens_without_users() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| s.auth_token("s3cret"));
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.status, 401);
+    assert_eq!(
+        resp.header("WWW-Authenticate"),
+        Some("Bearer realm=\"main\"")
+    );
+    assert_eq!(server.get("/a.txt?access_token=s3cret").status, 200);
+}
+
+// An HS256 JWT of `claims`, signed with `secret`
+fn jwt(claims: &str, secret: &str) -> String {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let encode = |bytes: &[u8]| {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let mut encoded = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+            for i in 0..=chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+        }
+        encoded
+    };
+    let message = format!(
+        "{}.{}",
+        encode(br#"{"alg":"HS256","typ":"JWT"}"#),
+        encode(claims.as_bytes())
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    format!("{}.{}", message, encode(&mac.finalize().into_bytes()))
+}
+
+#[test]
+fn jwt_validation() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.auth_jwt_secret("k3y")
+            .auth_jwt_audience("files")
+            .auth_jwt_issuer("https://sso.example.com")
+            .auth_max_failures(0)
+    });
+    let bearer = |token: String| {
+        server.get_with("/a.txt", &[("Authorization", &format!("Bearer {}", token))])
+    };
+    let exp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    let claims = |aud: &str, iss: &str, exp: u64| {
+        format!(
+            r#"{{"sub":"alice","aud":{},"iss":"{}","exp":{}}}"#,
+            aud, iss, exp
+        )
+    };
+
+    assert_eq!(server.get("/a.txt").status, 401);
+    let valid = jwt(
+        &claims(r#"["other","files"]"#, "https://sso.example.com", exp),
+        "k3y",
+    );
+    let resp = bearer(valid.clone());
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"hello");
+    assert_eq!(
+        server.get(&format!("/a.txt?access_token={}", valid)).status,
+        200
+    );
+
+    for (token, reason) in [
+        (
+            jwt(
+                &claims(r#""files""#, "https://sso.example.com", exp),
+                "wrong",
+            ),
+            "invalid signature",
+        ),
+        (
+            jwt(
+                &claims(r#""files""#, "https://sso.example.com", 1_000_000),
+                "k3y",
+            ),
+            "expired",
+        ),
+        (
+            jwt(&claims(r#""other""#, "https://sso.example.com", exp), "k3y"),
+            "wrong audience",
+        ),
+        (
+            jwt(
+                &claims(r#""files""#, "https://evil.example.com", exp),
+                "k3y",
+            ),
+            "wrong issuer",
+        ),
+        (
+            jwt(r#"{"aud":"files","iss":"https://sso.example.com"}"#, "k3y"),
+            "no exp claim",
+        ),
+    ] {
+        let resp = bearer(token);
+        assert_eq!(resp.status, 401, "{}", reason);
+        assert!(
+            resp.header("WWW-Authenticate").unwrap().contains(reason),
+            "{}",
+            reason
+        );
+    }
+    // Unsigned
+    let unsigned = valid
+        .rsplit_once('.')
+        .unwrap()
+        .0
+        .replace(valid.split('.').next().unwrap(), "eyJhbGciOiJub25lIn0");
+    assert_eq!(bearer(format!("{}.", unsigned)).status, 401);
+}
+
+#[test]
+fn failed_logins_back_off_then_ban() {
+    let server = TestServer::start(&[("a.txt", b"hello")], |s| {
+        s.auth("user:pass").auth_max_failures(4).auth_ban_time(60)
+    });
+    // The challenges aren't failures
+    for _ in 0..5 {
+        assert_eq!(server.get("/a.txt").status, 401);
+    }
+    assert_eq!(
+        server
+            .get_with("/a.txt", &[("Authorization", WRONG)])
+            .status,
+        401
+    );
+    assert_eq!(
+        server
+            .get_with("/a.txt", &[("Authorization", WRONG)])
+            .status,
+        401
+    );
+    // Logged in, forgiven
+    assert_eq!(
+        server.get_with("/a.txt", &[("Authorization", USER)]).status,
+        200
+    );
+    assert_eq!(
+        server
+            .get_with("/a.txt", &[("Authorization", WRONG)])
+            .status,
+        401
+    );
+    assert_eq!(
+        server
+            .get_with("/a.txt", &[("Authorization", WRONG)])
+            .status,
+        401
+    );
+    assert_eq!(
+        server
+            .get_with("/a.txt", &[("Authorization", WRONG)])
+            .status,
+        401
+    );
+    let resp = server.get_with("/a.txt", &[("Authorization", USER)]);
+    assert_eq!(resp.status, 429);
+    assert_eq!(resp.header("Retry-After"), Some("1"));
+
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    assert_eq!(
+        server
+            .get_with("/a.txt", &[("Authorization", WRONG)])
+            .status,
+        401
+    );
+    // The right credentials too
+    assert_eq!(
+        server.get_with("/a.txt", &[("Authorization", USER)]).status,
+        403
+    );
+    assert_eq!(server.get("/a.txt").status, 403);
+}
+
+#[test]
+fn one_time_links() {
+    let store =
+        std::env::temp_dir().join(format!("simple-http-server-once-{}", std::process::id()));
+    let _ = std::fs::remove_file(&store);
+    let server = TestServer::start(&[("dir/a.txt", b"hello")], |s| {
+        s.auth("user:pass").csrf(false).one_time_links(&store)
+    });
+    assert_eq!(
+        server
+            .request("POST", "/dir/a.txt?one_time", &[], b"")
+            .status,
+        401
+    );
+    let resp = server.request(
+        "POST",
+        "/dir/missing.txt?one_time",
+        &[("Authorization", USER)],
+        b"",
+    );
+    assert_eq!(resp.status, 404);
+    let resp = server.request(
+        "POST",
+        "/dir/a.txt?one_time",
+        &[("Authorization", USER)],
+        b"",
+    );
+    assert_eq!(resp.status, 201);
+    let created = serde_json::from_str::<serde_json::Value>(&resp.text()).unwrap();
+    let link = created["link"].as_str().unwrap().to_owned();
+    assert_eq!(resp.header("Location"), Some(&*link));
+    assert!(std::fs::read_to_string(&store).unwrap().contains("a.txt"));
+
+    // Downloaded once, without credentials
+    assert_eq!(server.request("HEAD", &link, &[], b"").status, 200);
+    let resp = server.get_with(&link, &[("Range", "bytes=0-1")]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"hello");
+    assert!(resp
+        .header("Content-Disposition")
+        .unwrap()
+        .contains("a.txt"));
+    assert_eq!(server.get(&link).status, 410);
+    assert_eq!(server.get("/__once/unknown").status, 404);
+    let _ = std::fs::remove_file(&store);
+}
+
+#[test]
+fn protected_paths() {
+    let files: &[(&str, &[u8])] = &[("a.txt", b"public"), ("secret/b.txt", b"secret")];
+    let server = TestServer::start(files, |s| s.protect_path("/secret", "pw"));
+    assert_eq!(server.get("/a.txt").status, 200);
+    let resp = server.get("/secret/b.txt");
+    assert_eq!(resp.status, 401);
+    assert!(resp.text().contains(r#"name="pw""#));
+    assert_eq!(server.get("/secret/b.txt?pw=wrong").status, 401);
+    let resp = server.get("/secret/b.txt?pw=pw");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"secret");
+    // Nor in the archives of the parent
+    let archive = server.get("/?download=tar").text();
+    assert!(archive.contains("a.txt") && !archive.contains("b.txt"));
+
+    // The form of the browsers, then the cookie
+    let resp = server.get_with("/secret/?pw=pw", &[("Accept", "text/html")]);
+    assert_eq!(resp.status, 303);
+    assert_eq!(resp.header("Location"), Some("/secret/"));
+    let cookie = resp
+        .header("Set-Cookie")
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    let resp = server.get_with("/secret/b.txt", &[("Cookie", &cookie)]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(
+        server
+            .get_with("/secret/b.txt", &[("Cookie", "shs_pw_0=forged")])
+            .status,
+        401
+    );
+}
+
+#[test]
+fn protected_pretty_urls_and_fallback() {
+    let files: &[(&str, &[u8])] = &[("page.html", b"page"), ("secret/index.html", b"secret")];
+    let server = TestServer::start_with(files, |s, root| {
+        s.pretty_urls(true)
+            .try_file_404(root.join("secret/index.html"))
+            .protect_path("/page.html", "pw")
+            .protect_path("/secret", "pw")
+    });
+    assert_eq!(server.get("/page").status, 401);
+    assert_eq!(server.get("/page?pw=pw").body, b"page");
+    let resp = server.get_with("/page?pw=pw", &[("Accept", "text/html")]);
+    assert_eq!(resp.status, 303);
+    assert_eq!(resp.header("Location"), Some("/page"));
+    assert_eq!(server.get("/missing").status, 401);
+    assert_eq!(server.get("/missing?pw=pw").body, b"secret");
+}
+
+#[test]
+fn protected_checksums() {
+    let files: &[(&str, &[u8])] = &[("a.txt", b"public"), ("b.txt", b"secret")];
+    let server = TestServer::start(files, |s| s.checksums(true).protect_path("/b.txt", "pw"));
+    assert!(server.get("/a.txt.sha256").text().contains("a.txt"));
+    // The sidecar is not below the protected path
+    let resp = server.get("/b.txt.sha256");
+    assert_eq!(resp.status, 401);
+    assert!(!resp.text().contains("b.txt"));
+    assert_eq!(server.get("/b.txt?hash=sha256").status, 401);
+    assert!(server.get("/b.txt.sha256?pw=pw").text().contains("b.txt"));
+}
+
+#[test]
+fn uploads_into_protected_and_ignored_paths() {
+    let files: &[(&str, &[u8])] = &[("secret/b.txt", b"secret")];
+    let server = TestServer::start(files, |s| {
+        s.protect_path("/secret", "pw")
+            .ignore("*.tmp")
+            .upload(true)
+            .csrf(false)
+    });
+    // By the relative path of a file below a public directory
+    let resp = server.upload("/", &[("secret/c.txt", b"x")], &[]);
+    assert_eq!(resp.status, 403);
+    assert!(!server.path("secret/c.txt").exists());
+    let resp = server.upload("/?pw=pw", &[("secret/c.txt", b"x")], &[]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    assert!(server.path("secret/c.txt").exists());
+    let resp = server.upload("/", &[("d.tmp", b"x")], &[]);
+    assert_eq!(resp.status, 403);
+    assert!(!server.path("d.tmp").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn protected_and_ignored_cgi_scripts() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scripts =
+        std::env::temp_dir().join(format!("simple-http-server-cgi-{}", std::process::id()));
+    std::fs::create_dir_all(&scripts).unwrap();
+    let script = scripts.join("hello.sh");
+    std::fs::write(
+        &script,
+        "#!/bin/sh\nprintf 'Content-Type: text/plain\\r\\n\\r\\nhello'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let spec = format!("/cgi-bin={}", scripts.display());
+    let server = TestServer::start(&[], |s| {
+        s.cgi_dir(&spec)
+            .protect_path("/cgi-bin", "pw")
+            .ignore("*.sh")
+    });
+    assert_eq!(server.get("/cgi-bin/hello.sh").status, 401);
+    assert_eq!(server.get("/cgi-bin/hello.sh?pw=pw").status, 404);
+    let server = TestServer::start(&[], |s| s.cgi_dir(&spec).protect_path("/cgi-bin", "pw"));
+    assert_eq!(server.get("/cgi-bin/hello.sh?pw=pw").body, b"hello");
+    let _ = std::fs::remove_dir_all(&scripts);
+}
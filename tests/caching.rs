@@ -0,0 +1,140 @@
+mod common;
+
+use common::TestServer;
+use filetime::FileTime;
+use sha2::{Digest, Sha256};
+
+const CONTENT: &[u8] = b"0123456789abcdefghij";
+
+fn server(etag: &str) -> TestServer {
+    TestServer::start(&[("file.bin", CONTENT)], |s| s.etag(etag))
+}
+
+fn etag(server: &TestServer) -> String {
+    server.get("/file.bin").header("ETag").unwrap().to_owned()
+}
+
+#[test]
+fn strong_etag_by_default() {
+    let server = TestServer::start(&[("file.bin", CONTENT)], |s| s);
+    let tag = etag(&server);
+    assert!(tag.starts_with('"'));
+    filetime::set_file_mtime(server.path("file.bin"), FileTime::from_unix_time(1, 0)).unwrap();
+    assert_ne!(etag(&server), tag);
+}
+
+#[test]
+fn weak_etag() {
+    let server = server("weak");
+    let tag = etag(&server);
+    assert!(tag.starts_with("W/"));
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=0-1"), ("If-Range", &tag)]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn content_hash_etag() {
+    let server = server("strong-hash");
+    let tag = etag(&server);
+    assert_eq!(tag, format!("\"{:x}\"", Sha256::digest(CONTENT)));
+    // A touch keeps it
+    filetime::set_file_mtime(server.path("file.bin"), FileTime::from_unix_time(1, 0)).unwrap();
+    assert_eq!(etag(&server), tag);
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=0-1"), ("If-Range", &tag)]);
+    assert_eq!(resp.status, 206);
+    std::fs::write(server.path("file.bin"), b"changed").unwrap();
+    assert_ne!(etag(&server), tag);
+}
+
+#[test]
+fn no_etag() {
+    let server = server("off");
+    let resp = server.get("/file.bin");
+    assert_eq!(resp.header("ETag"), None);
+    assert!(resp.header("Last-Modified").is_some());
+}
+
+// 1_000_000_000.5
+const MODIFIED: &str = "Sun, 09 Sep 2001 01:46:40 GMT";
+const BEFORE: &str = "Sun, 09 Sep 2001 01:46:39 GMT";
+
+fn dated_server() -> TestServer {
+    let server = TestServer::start(&[("file.bin", CONTENT)], |s| {
+        s.upload(true).delete(true).csrf(false)
+    });
+    let mtime = FileTime::from_unix_time(1_000_000_000, 500_000_000);
+    filetime::set_file_mtime(server.path("file.bin"), mtime).unwrap();
+    server
+}
+
+#[test]
+fn sub_second_modification_time() {
+    let server = dated_server();
+    let resp = server.get("/file.bin");
+    assert_eq!(resp.header("Last-Modified"), Some(MODIFIED));
+    let tag = resp.header("ETag").unwrap();
+    assert!(tag.ends_with(&format!(".{:x}\"", 500_000_000)), "{}", tag);
+    // Within the second of the date
+    let resp = server.get_with("/file.bin", &[("If-Modified-Since", MODIFIED)]);
+    assert_eq!(resp.status, 304);
+    let resp = server.get_with("/file.bin", &[("If-Unmodified-Since", MODIFIED)]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn precondition_precedence() {
+    let server = dated_server();
+    let tag = etag(&server);
+    let cases: &[(&[(&str, &str)], u16)] = &[
+        (&[("If-Match", &tag)], 200),
+        (&[("If-Match", "\"other\"")], 412),
+        (&[("If-Match", "*")], 200),
+        (&[("If-Unmodified-Since", BEFORE)], 412),
+        // If-Match first, If-Unmodified-Since is ignored
+        (&[("If-Match", &tag), ("If-Unmodified-Since", BEFORE)], 200),
+        (&[("If-None-Match", &tag)], 304),
+        (&[("If-None-Match", &format!("W/{}", tag))], 304),
+        (&[("If-None-Match", "\"other\"")], 200),
+        (&[("If-None-Match", "*")], 304),
+        (&[("If-Modified-Since", BEFORE)], 200),
+        // If-None-Match first, If-Modified-Since is ignored
+        (
+            &[
+                ("If-None-Match", "\"other\""),
+                ("If-Modified-Since", MODIFIED),
+            ],
+            200,
+        ),
+        (
+            &[("If-Match", "\"other\""), ("If-None-Match", "\"other\"")],
+            412,
+        ),
+    ];
+    for (headers, status) in cases {
+        let resp = server.get_with("/file.bin", headers);
+        assert_eq!(resp.status, *status, "{:?}", headers);
+    }
+}
+
+#[test]
+fn not_modified_has_the_validators() {
+    let server = dated_server();
+    let tag = etag(&server);
+    let resp = server.get_with("/file.bin", &[("If-None-Match", &tag)]);
+    assert_eq!(resp.status, 304);
+    assert_eq!(resp.header("ETag"), Some(tag.as_str()));
+    assert!(resp.header("Cache-Control").is_some());
+}
+
+#[test]
+fn delete_preconditions() {
+    let server = dated_server();
+    let tag = etag(&server);
+    let delete = |headers: &[(&str, &str)]| server.request("DELETE", "/file.bin", headers, b"");
+    assert_eq!(delete(&[("If-Match", "\"other\"")]).status, 412);
+    assert_eq!(delete(&[("If-Unmodified-Since", BEFORE)]).status, 412);
+    assert_eq!(delete(&[("If-None-Match", &tag)]).status, 412);
+    // Of GET and HEAD only
+    assert_eq!(delete(&[("If-Modified-Since", MODIFIED)]).status, 204);
+    assert!(!server.path("file.bin").exists());
+}
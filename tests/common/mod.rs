@@ -0,0 +1,208 @@
+//! An in-process server on an ephemeral port, with its own root directory, and a minimal
+//! HTTP/1.1 client to talk to it
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use simple_http_server::iron::Listening;
+use simple_http_server::SimpleHttpServer;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TestServer {
+    listening: Listening,
+    pub root: PathBuf,
+}
+
+impl TestServer {
+    /// Serves `files` (relative paths, created with their parent directories), configured by
+    /// `configure`
+    pub fn start<F>(files: &[(&str, &[u8])], configure: F) -> TestServer
+    where
+        F: FnOnce(SimpleHttpServer) -> SimpleHttpServer,
+    {
+        TestServer::start_with(files, |server, _| configure(server))
+    }
+
+    /// `start`, the options may refer to the root directory
+    pub fn start_with<F>(files: &[(&str, &[u8])], configure: F) -> TestServer
+    where
+        F: FnOnce(SimpleHttpServer, &Path) -> SimpleHttpServer,
+    {
+        let root = std::env::temp_dir().join(format!(
+            "simple-http-server-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let listening = configure(SimpleHttpServer::new(&root).threads(2), &root)
+            .serve("127.0.0.1:0")
+            .unwrap();
+        TestServer { listening, root }
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// A raw connection, for the tests of the listeners
+    pub fn connect(&self) -> TcpStream {
+        TcpStream::connect(self.listening.socket).unwrap()
+    }
+
+    pub fn get(&self, path: &str) -> Response {
+        self.request("GET", path, &[], b"")
+    }
+
+    pub fn get_with(&self, path: &str, headers: &[(&str, &str)]) -> Response {
+        self.request("GET", path, headers, b"")
+    }
+
+    /// A `multipart/form-data` upload of `files` (name, content) to the directory `path`
+    pub fn upload(
+        &self,
+        path: &str,
+        files: &[(&str, &[u8])],
+        headers: &[(&str, &str)],
+    ) -> Response {
+        let boundary = "----simple-http-server-test";
+        let mut body = Vec::new();
+        for (name, content) in files {
+            write!(
+                body,
+                "--{}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"{}\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n",
+                boundary, name
+            )
+            .unwrap();
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        write!(body, "--{}--\r\n", boundary).unwrap();
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        let mut all_headers = vec![("Content-Type", content_type.as_str())];
+        all_headers.extend_from_slice(headers);
+        self.request("POST", path, &all_headers, &body)
+    }
+
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Response {
+        let mut stream = TcpStream::connect(self.listening.socket).unwrap();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            self.listening.socket,
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).unwrap();
+        Response::parse(&raw, method == "HEAD")
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.listening.close();
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn parse(raw: &[u8], head: bool) -> Response {
+        let end = raw
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("end of the headers");
+        let head_text = String::from_utf8_lossy(&raw[..end]).to_string();
+        let mut lines = head_text.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .and_then(|code| code.parse().ok())
+            .expect("status line");
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+            .collect::<Vec<_>>();
+        let mut response = Response {
+            status,
+            headers,
+            body: Vec::new(),
+        };
+        let body = &raw[end + 4..];
+        if head {
+            return response;
+        }
+        response.body = if response
+            .header("Transfer-Encoding")
+            .is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+        {
+            dechunk(body)
+        } else {
+            body.to_vec()
+        };
+        response
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .expect("chunk size");
+        let size_text = String::from_utf8_lossy(&body[..line_end]).to_string();
+        let size = usize::from_str_radix(size_text.split(';').next().unwrap().trim(), 16).unwrap();
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return decoded;
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = &body[size + 2..];
+    }
+}
+
+pub fn read(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap()
+}
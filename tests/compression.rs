@@ -0,0 +1,224 @@
+mod common;
+
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use common::TestServer;
+
+fn content() -> Vec<u8> {
+    b"compressible text ".repeat(100)
+}
+
+fn server() -> TestServer {
+    let content = content();
+    TestServer::start(
+        &[
+            ("a.txt", &content),
+            ("a.bin", &content),
+            ("small.txt", b"tiny"),
+        ],
+        |s| s.compress("txt"),
+    )
+}
+
+#[test]
+fn gzip() {
+    let server = server();
+    let resp = server.get_with("/a.txt", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.header("Content-Encoding"), Some("gzip"));
+    let mut decoded = Vec::new();
+    GzDecoder::new(&resp.body[..])
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, content());
+}
+
+#[test]
+fn deflate() {
+    let server = server();
+    let resp = server.get_with("/a.txt", &[("Accept-Encoding", "deflate")]);
+    assert_eq!(resp.header("Content-Encoding"), Some("deflate"));
+    let mut decoded = Vec::new();
+    DeflateDecoder::new(&resp.body[..])
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, content());
+}
+
+#[test]
+fn not_accepted_by_the_client() {
+    let server = server();
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.body, content());
+}
+
+#[test]
+fn other_extensions_are_not_compressed() {
+    let server = server();
+    let resp = server.get_with("/a.bin", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.body, content());
+}
+
+#[test]
+fn small_files_are_not_compressed() {
+    let server = server();
+    let resp = server.get_with("/small.txt", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.body, b"tiny");
+}
+
+#[test]
+fn ranges_are_not_compressed() {
+    let server = server();
+    let resp = server.get_with(
+        "/a.txt",
+        &[("Accept-Encoding", "gzip"), ("Range", "bytes=0-9")],
+    );
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.body, &content()[..10]);
+}
+
+fn negotiated(path: &str, accept_encoding: &str) -> (u16, Option<String>) {
+    let resp = server().get_with(path, &[("Accept-Encoding", accept_encoding)]);
+    let encoding = resp.header("Content-Encoding").map(str::to_owned);
+    (resp.status, encoding)
+}
+
+#[test]
+fn highest_quality_wins() {
+    assert_eq!(
+        negotiated("/a.txt", "gzip;q=0.5, deflate"),
+        (200, Some("deflate".to_owned()))
+    );
+    assert_eq!(
+        negotiated("/a.txt", "deflate;q=0.5, gzip;q=0.5"),
+        (200, Some("gzip".to_owned()))
+    );
+    assert_eq!(negotiated("/a.txt", "identity, gzip;q=0.5"), (200, None));
+    assert_eq!(negotiated("/a.txt", "gzip;q=0"), (200, None));
+}
+
+#[test]
+fn wildcard() {
+    assert_eq!(negotiated("/a.txt", "*"), (200, Some("gzip".to_owned())));
+    assert_eq!(
+        negotiated("/a.txt", "gzip;q=0, *"),
+        (200, Some("deflate".to_owned()))
+    );
+}
+
+#[test]
+fn identity_refused() {
+    assert_eq!(
+        negotiated("/a.txt", "gzip, identity;q=0"),
+        (200, Some("gzip".to_owned()))
+    );
+    assert_eq!(negotiated("/a.bin", "identity;q=0").0, 406);
+    assert_eq!(negotiated("/a.bin", "*;q=0").0, 406);
+    assert_eq!(negotiated("/a.bin", "*;q=0, identity").0, 200);
+}
+
+#[test]
+fn listing_negotiation() {
+    assert_eq!(
+        negotiated("/", "deflate, gzip;q=0.9"),
+        (200, Some("deflate".to_owned()))
+    );
+    assert_eq!(negotiated("/", "br"), (200, None));
+}
+
+#[test]
+fn vary() {
+    let server = server();
+    let gzip = server.get_with("/a.txt", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(gzip.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(server.get("/a.txt").header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(server.get("/").header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(server.get("/a.bin").header("Vary"), None);
+}
+
+#[test]
+fn etag_per_encoding() {
+    let server = server();
+    let identity = server.get("/a.txt");
+    let gzip = server.get_with("/a.txt", &[("Accept-Encoding", "gzip")]);
+    let deflate = server.get_with("/a.txt", &[("Accept-Encoding", "deflate")]);
+    let tags = [&identity, &gzip, &deflate].map(|resp| resp.header("ETag").unwrap().to_owned());
+    assert_ne!(tags[0], tags[1]);
+    assert_ne!(tags[0], tags[2]);
+    assert_ne!(tags[1], tags[2]);
+    let resp = server.get_with(
+        "/a.txt",
+        &[("Accept-Encoding", "gzip"), ("If-None-Match", &tags[1])],
+    );
+    assert_eq!(resp.status, 304);
+    assert_eq!(resp.header("ETag"), Some(tags[1].as_str()));
+}
+
+#[test]
+fn no_identity_length() {
+    let server = server();
+    let resp = server.get_with("/a.txt", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(resp.header("Content-Length"), None);
+    let resp = server.request("HEAD", "/a.txt", &[("Accept-Encoding", "gzip")], b"");
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.header("Content-Length"), Some("1800"));
+}
+
+#[test]
+fn by_mime_type() {
+    let content = content();
+    let server = TestServer::start(
+        &[
+            ("a.txt", &content),
+            ("a.json", &content),
+            ("a.png", &content),
+            ("a.zip", &content),
+        ],
+        |s| s.compress("auto").compress("zip"),
+    );
+    let encoding = |path: &str| {
+        server
+            .get_with(path, &[("Accept-Encoding", "gzip")])
+            .header("Content-Encoding")
+            .map(str::to_owned)
+    };
+    assert_eq!(encoding("/a.txt").as_deref(), Some("gzip"));
+    assert_eq!(encoding("/a.json").as_deref(), Some("gzip"));
+    assert_eq!(encoding("/a.png"), None);
+    // Already compressed, even when listed
+    assert_eq!(encoding("/a.zip"), None);
+}
+
+#[test]
+fn min_size() {
+    let content = content();
+    let server = TestServer::start(&[("a.txt", &content)], |s| {
+        s.compress("text/*")
+            .compress_min_size(content.len() as u64 + 1)
+    });
+    let resp = server.get_with("/a.txt", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.body, content);
+}
+
+#[test]
+fn large_file() {
+    // Not too repetitive, to keep the encoder busy
+    let content = (0..8_000_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 % 16 + b'a')
+        .collect::<Vec<u8>>();
+    let server = TestServer::start(&[("large.txt", &content)], |s| s.compress("txt"));
+    let resp = server.get_with("/large.txt", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(resp.header("Transfer-Encoding"), Some("chunked"));
+    let mut decoded = Vec::new();
+    GzDecoder::new(&resp.body[..])
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, content);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use common::TestServer;
+
+fn files() -> Vec<(&'static str, &'static [u8])> {
+    vec![("a.txt", b"a")]
+}
+
+#[test]
+fn queue_overflow_is_answered_503() {
+    let server = TestServer::start(&files(), |s| s.threads(1).queue(1));
+    // The worker waits for the rest of this request, the next connection waits in the queue
+    let mut busy = server.connect();
+    busy.write_all(b"GET /a.txt HTTP/1.1\r\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let _queued = server.connect();
+    thread::sleep(Duration::from_millis(200));
+
+    let mut rejected = server.connect();
+    rejected
+        .write_all(b"GET /a.txt HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    rejected.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    assert!(response.contains("Retry-After: 1"), "{}", response);
+}
+
+#[test]
+fn oversized_heads_are_refused() {
+    let server = TestServer::start(&files(), |s| s.max_url_length(64).max_header_size(128));
+    assert_eq!(server.get("/a.txt").status, 200);
+    let long_url = format!("/a.txt?{}", "x".repeat(64));
+    assert_eq!(server.get(&long_url).status, 414);
+    let large_header = "y".repeat(128);
+    let resp = server.get_with("/a.txt", &[("X-Large", &large_header)]);
+    assert_eq!(resp.status, 431);
+}
+
+// The head of a response of a kept alive connection, its body read
+fn read_response(stream: &mut std::net::TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.parse().unwrap());
+    stream.read_exact(&mut vec![0; length]).unwrap();
+    head
+}
+
+#[test]
+fn keepalive_requests_close_the_connection() {
+    let server = TestServer::start(&files(), |s| s.keepalive_requests(2));
+    let mut stream = server.connect();
+    let request = b"GET /a.txt HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    stream.write_all(request).unwrap();
+    let first = read_response(&mut stream);
+    assert!(!first.contains("Connection: close"), "{}", first);
+    stream.write_all(request).unwrap();
+    let second = read_response(&mut stream);
+    assert!(second.contains("Connection: close"), "{}", second);
+    assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+}
+
+#[test]
+fn keepalive_off() {
+    let server = TestServer::start(&files(), |s| s.keepalive(false));
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /a.txt HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let head = read_response(&mut stream);
+    assert!(head.contains("Connection: close"), "{}", head);
+    assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+}
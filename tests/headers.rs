@@ -0,0 +1,48 @@
+mod common;
+
+use common::TestServer;
+
+fn files() -> Vec<(&'static str, &'static [u8])> {
+    vec![("a.txt", b"a")]
+}
+
+#[test]
+fn security_headers() {
+    let server = TestServer::start(&files(), |s| s.security_headers(true));
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.header("X-Content-Type-Options"), Some("nosniff"));
+    assert_eq!(resp.header("X-Frame-Options"), Some("SAMEORIGIN"));
+    assert_eq!(resp.header("Referrer-Policy"), Some("no-referrer"));
+    // Over HTTPS only
+    assert_eq!(resp.header("Strict-Transport-Security"), None);
+    // Also of the errors
+    let resp = server.get("/missing.txt");
+    assert_eq!(resp.status, 404);
+    assert_eq!(resp.header("X-Content-Type-Options"), Some("nosniff"));
+}
+
+#[test]
+fn security_header_overrides() {
+    let server = TestServer::start(&files(), |s| {
+        s.security_headers(true)
+            .frame_options("off")
+            .referrer_policy("same-origin")
+    });
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.header("X-Frame-Options"), None);
+    assert_eq!(resp.header("Referrer-Policy"), Some("same-origin"));
+
+    // Alone, an option sends its header only
+    let server = TestServer::start(&files(), |s| s.frame_options("DENY"));
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.header("X-Frame-Options"), Some("DENY"));
+    assert_eq!(resp.header("X-Content-Type-Options"), None);
+}
+
+#[test]
+fn no_security_headers_by_default() {
+    let server = TestServer::start(&files(), |s| s);
+    let resp = server.get("/a.txt");
+    assert_eq!(resp.header("X-Content-Type-Options"), None);
+    assert_eq!(resp.header("X-Frame-Options"), None);
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use common::TestServer;
+
+fn files() -> Vec<(&'static str, &'static [u8])> {
+    vec![
+        ("alpha.txt", b"a"),
+        ("beta.log", b"b"),
+        ("dir/inside.txt", b"c"),
+        ("site/index.html", b"<h1>home</h1>"),
+    ]
+}
+
+#[test]
+fn root_listing() {
+    let server = TestServer::start(&files(), |s| s);
+    let resp = server.get("/");
+    assert_eq!(resp.status, 200);
+    assert!(resp
+        .header("Content-Type")
+        .unwrap()
+        .starts_with("text/html"));
+    let html = resp.text();
+    for name in ["alpha.txt", "beta.log", "dir/", "site/"] {
+        assert!(html.contains(name), "{} missing", name);
+    }
+}
+
+#[test]
+fn subdirectory_listing() {
+    let server = TestServer::start(&files(), |s| s);
+    let html = server.get("/dir/").text();
+    assert!(html.contains("inside.txt"));
+    assert!(!html.contains("alpha.txt"));
+}
+
+#[test]
+fn missing_path() {
+    let server = TestServer::start(&files(), |s| s);
+    assert_eq!(server.get("/nothing").status, 404);
+}
+
+#[test]
+fn directory_without_slash_is_redirected() {
+    let server = TestServer::start(&files(), |s| s);
+    let resp = server.get("/dir");
+    assert_eq!(resp.status, 301);
+    assert!(resp.header("Location").unwrap().ends_with("/dir/"));
+}
+
+#[test]
+fn listing_disabled() {
+    let server = TestServer::start(&files(), |s| s.listing(false));
+    assert_eq!(server.get("/dir/").status, 403);
+    assert_eq!(server.get("/alpha.txt").body, b"a");
+}
+
+#[test]
+fn index_page() {
+    let server = TestServer::start(&files(), |s| s.index(true));
+    assert_eq!(server.get("/site/").body, b"<h1>home</h1>");
+}
+
+#[test]
+fn ignored_paths_are_hidden() {
+    let server = TestServer::start(&files(), |s| s.ignore("*.log"));
+    assert!(!server.get("/").text().contains("beta.log"));
+    assert_eq!(server.get("/beta.log").status, 404);
+}
+
+#[test]
+fn names_are_escaped() {
+    let server = TestServer::start(&[("<b>bold</b>.txt", b"x")], |s| s);
+    let html = server.get("/").text();
+    assert!(!html.contains("<b>bold</b>"));
+}
+
+#[test]
+fn gallery_names_are_escaped() {
+    let server = TestServer::start(&[("a\" onerror=\"x.png", b"x")], |s| s);
+    let html = server.get("/?view=gallery").text();
+    assert!(html.contains("gallery-item"));
+    assert!(!html.contains("\" onerror=\""));
+}
+
+#[test]
+fn listing_csp() {
+    let server = TestServer::start(&files(), |s| s);
+    let resp = server.get("/");
+    let csp = resp.header("Content-Security-Policy").unwrap();
+    let nonce = csp
+        .split("'nonce-")
+        .nth(1)
+        .and_then(|rest| rest.split('\'').next())
+        .unwrap();
+    let html = resp.text();
+    assert!(html.contains(&format!("<style nonce=\"{}\">", nonce)));
+    assert!(!html.contains("<script>"));
+    assert_ne!(server.get("/").header("Content-Security-Policy"), Some(csp));
+}
+
+#[test]
+fn breadcrumb_is_escaped() {
+    let server = TestServer::start(&[("<b>bold/inside.txt", b"x")], |s| s);
+    let html = server.get("/%3Cb%3Ebold/").text();
+    assert!(html.contains("inside.txt"));
+    assert!(!html.contains("<b>bold"));
+}
+
+#[test]
+fn listing_cache() {
+    let server = TestServer::start(&files(), |s| s.listing_cache(16));
+    let old = filetime::FileTime::from_unix_time(1_000_000, 0);
+    let settle = |path: &str| filetime::set_file_mtime(server.path(path), old).unwrap();
+    settle("dir/inside.txt");
+    settle("dir");
+    let page = server.get("/dir/").text();
+    assert!(page.contains("inside.txt"), "{}", page);
+    // The same modification times, the entries changed anyway
+    std::fs::write(server.path("dir/added.txt"), b"d").unwrap();
+    settle("dir/added.txt");
+    settle("dir");
+    assert!(server.get("/dir/").text().contains("added.txt"));
+    // Rewritten in place
+    std::fs::write(server.path("dir/inside.txt"), b"rewritten in place").unwrap();
+    settle("dir/inside.txt");
+    assert!(server.get("/dir/").text().contains("18 B"));
+    // Not with another sort
+    assert!(server
+        .get("/dir/?sort=size&order=asc")
+        .text()
+        .contains("added.txt"));
+}
+
+#[test]
+fn listing_cache_and_access_files() {
+    let mut files = files();
+    files.push((".shs-access", b""));
+    let server = TestServer::start(&files, |s| s.listing_cache(16).access_files(true));
+    let old = filetime::FileTime::from_unix_time(1_000_000, 0);
+    for path in ["dir/inside.txt", ".shs-access", "dir"] {
+        filetime::set_file_mtime(server.path(path), old).unwrap();
+    }
+    assert!(server.get("/dir/").text().contains("inside.txt"));
+    // Of a parent directory
+    std::fs::write(server.path(".shs-access"), b"ignore = inside.txt\n").unwrap();
+    assert!(!server.get("/dir/").text().contains("inside.txt"));
+}
+
+#[test]
+fn streamed_listing() {
+    let server = TestServer::start(&files(), |s| s.stream_listings(true));
+    let resp = server.get("/dir/");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.header("Content-Length"), None);
+    let page = resp.text();
+    assert!(page.contains("inside.txt"), "{}", page);
+    assert!(page.contains("0 directories, 1 file"), "{}", page);
+    assert!(page.trim_end().ends_with("</html>"), "{}", page);
+
+    let page = server.get("/?sort=name&order=desc").text();
+    let site = page.find("site/").unwrap();
+    let alpha = page.find("alpha.txt").unwrap();
+    assert!(site < alpha, "{}", page);
+    assert!(!page.contains("Gallery view"), "{}", page);
+}
+
+#[test]
+fn streamed_listing_gallery_toggle() {
+    let mut files = files();
+    files.push(("photos/a.jpg", b"jpeg"));
+    let server = TestServer::start(&files, |s| s.stream_listings(true));
+    // After the rows, once an image was read
+    let page = server.get("/photos/").text();
+    let toggle = page.find("?view=gallery").expect(&page);
+    assert!(page.find("a.jpg").unwrap() < toggle, "{}", page);
+    assert!(server
+        .get("/photos/?view=gallery")
+        .text()
+        .contains("gallery-item"));
+}
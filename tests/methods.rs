@@ -0,0 +1,69 @@
+mod common;
+
+use common::TestServer;
+
+const FILES: &[(&str, &[u8])] = &[("dir/a.txt", b"hi")];
+
+#[test]
+fn options_of_a_file() {
+    let server = TestServer::start(FILES, |s| s);
+    let resp = server.request("OPTIONS", "/dir/a.txt", &[], b"");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.header("Allow"), Some("GET, HEAD, OPTIONS"));
+    assert_eq!(resp.header("Content-Length"), Some("0"));
+}
+
+#[test]
+fn options_of_a_directory() {
+    let server = TestServer::start(FILES, |s| s);
+    let resp = server.request("OPTIONS", "/dir/", &[], b"");
+    assert_eq!(resp.header("Allow"), Some("GET, HEAD, OPTIONS, POST"));
+    let server = TestServer::start(FILES, |s| s.upload(true));
+    let resp = server.request("OPTIONS", "/dir/", &[], b"");
+    assert_eq!(resp.header("Allow"), Some("GET, HEAD, OPTIONS, POST"));
+    let server = TestServer::start(FILES, |s| s.upload(true).delete(true));
+    let resp = server.request("OPTIONS", "/dir/", &[], b"");
+    assert_eq!(
+        resp.header("Allow"),
+        Some("GET, HEAD, OPTIONS, POST, DELETE")
+    );
+}
+
+#[test]
+fn options_of_a_missing_path() {
+    let server = TestServer::start(FILES, |s| s.ignore("*.txt"));
+    assert_eq!(
+        server.request("OPTIONS", "/dir/b.txt", &[], b"").status,
+        404
+    );
+    assert_eq!(
+        server.request("OPTIONS", "/dir/a.txt", &[], b"").status,
+        404
+    );
+}
+
+#[test]
+fn unsupported_methods() {
+    let server = TestServer::start(FILES, |s| s);
+    for (method, path, allow) in [
+        ("DELETE", "/dir/a.txt", "GET, HEAD, OPTIONS"),
+        ("PATCH", "/dir/a.txt", "GET, HEAD, OPTIONS"),
+        ("PUT", "/dir/a.txt", "GET, HEAD, OPTIONS"),
+        ("POST", "/dir/a.txt", "GET, HEAD, OPTIONS"),
+        ("PATCH", "/dir/", "GET, HEAD, OPTIONS, POST"),
+    ] {
+        let resp = server.request(method, path, &[], b"");
+        assert_eq!(resp.status, 405, "{} {}", method, path);
+        assert_eq!(resp.header("Allow"), Some(allow), "{} {}", method, path);
+    }
+}
+
+#[test]
+fn trace_is_disabled() {
+    let server = TestServer::start(FILES, |s| s.upload(true));
+    let resp = server.request("TRACE", "/dir/a.txt", &[("Cookie", "secret=1")], b"");
+    assert_eq!(resp.status, 405);
+    assert!(!resp.text().contains("secret"));
+    let allow = resp.header("Allow").unwrap();
+    assert!(!allow.contains("TRACE"), "{}", allow);
+}
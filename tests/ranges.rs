@@ -0,0 +1,241 @@
+mod common;
+
+use common::TestServer;
+
+const CONTENT: &[u8] = b"0123456789abcdefghij";
+
+fn server() -> TestServer {
+    TestServer::start(&[("file.bin", CONTENT)], |s| s)
+}
+
+#[test]
+fn full_file_advertises_ranges() {
+    let server = server();
+    let resp = server.get("/file.bin");
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, CONTENT);
+    assert_eq!(resp.header("Accept-Ranges"), Some("bytes"));
+    assert_eq!(resp.header("Content-Length"), Some("20"));
+}
+
+#[test]
+fn from_to_range() {
+    let server = server();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=2-5")]);
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, b"2345");
+    assert_eq!(resp.header("Content-Range"), Some("bytes 2-5/20"));
+    assert_eq!(resp.header("Content-Length"), Some("4"));
+}
+
+#[test]
+fn open_ended_range() {
+    let server = server();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=15-")]);
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, b"fghij");
+    assert_eq!(resp.header("Content-Range"), Some("bytes 15-19/20"));
+}
+
+#[test]
+fn suffix_range() {
+    let server = server();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=-3")]);
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, b"hij");
+    assert_eq!(resp.header("Content-Range"), Some("bytes 17-19/20"));
+}
+
+#[test]
+fn end_past_the_file_is_clamped() {
+    let server = server();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=18-100")]);
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, b"ij");
+    assert_eq!(resp.header("Content-Range"), Some("bytes 18-19/20"));
+}
+
+#[test]
+fn unsatisfiable_range() {
+    let server = server();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=20-")]);
+    assert_eq!(resp.status, 416);
+    assert_eq!(resp.header("Content-Range"), Some("bytes */20"));
+}
+
+#[test]
+fn invalid_range_is_ignored() {
+    let server = server();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=5-2")]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, CONTENT);
+}
+
+#[test]
+fn outdated_if_range_sends_the_whole_file() {
+    let server = server();
+    let resp = server.get_with(
+        "/file.bin",
+        &[("Range", "bytes=0-1"), ("If-Range", "\"outdated\"")],
+    );
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, CONTENT);
+}
+
+#[test]
+fn ranges_disabled() {
+    let server = TestServer::start(&[("file.bin", CONTENT)], |s| s.range(false));
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=2-5")]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, CONTENT);
+}
+
+#[test]
+fn if_range_etag() {
+    let server = server();
+    let etag = server.get("/file.bin").header("ETag").unwrap().to_owned();
+    assert!(!etag.starts_with("W/"));
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=0-1"), ("If-Range", &etag)]);
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, b"01");
+    // Weak validators never match
+    let weak = format!("W/{}", etag);
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=0-1"), ("If-Range", &weak)]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn if_range_date() {
+    let server = server();
+    filetime::set_file_mtime(
+        server.path("file.bin"),
+        filetime::FileTime::from_unix_time(1_000_000_000, 0),
+    )
+    .unwrap();
+    let date = server
+        .get("/file.bin")
+        .header("Last-Modified")
+        .unwrap()
+        .to_owned();
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=0-1"), ("If-Range", &date)]);
+    assert_eq!(resp.status, 206);
+    let later = "Sun, 09 Sep 2001 01:46:41 GMT";
+    let resp = server.get_with("/file.bin", &[("Range", "bytes=0-1"), ("If-Range", later)]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn if_range_of_a_compressed_representation() {
+    let content = CONTENT.repeat(100);
+    let server = TestServer::start(&[("file.txt", &content)], |s| s.compress("txt"));
+    let gzip = server.get_with("/file.txt", &[("Accept-Encoding", "gzip")]);
+    let etag = gzip.header("ETag").unwrap();
+    let resp = server.get_with(
+        "/file.txt",
+        &[
+            ("Accept-Encoding", "gzip"),
+            ("Range", "bytes=0-1"),
+            ("If-Range", etag),
+        ],
+    );
+    // The ranges are of the identity
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.header("Content-Encoding"), Some("gzip"));
+    let resp = server.get_with(
+        "/file.txt",
+        &[("Accept-Encoding", "gzip"), ("Range", "bytes=0-1")],
+    );
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.header("Content-Encoding"), None);
+    assert_eq!(resp.body, b"01");
+}
+
+// The start and the end of the selected bytes of `CONTENT`
+type Selected = Option<(usize, usize)>;
+
+// (Range, status, selected bytes)
+const SELECTIONS: &[(&str, u16, Selected)] = &[
+    ("bytes=0-", 206, Some((0, 20))),
+    ("bytes=0-0", 206, Some((0, 1))),
+    ("bytes=19-19", 206, Some((19, 20))),
+    ("bytes=19-", 206, Some((19, 20))),
+    ("bytes=5-100", 206, Some((5, 20))),
+    ("bytes=20-", 416, None),
+    ("bytes=20-25", 416, None),
+    ("bytes=-1", 206, Some((19, 20))),
+    ("bytes=-20", 206, Some((0, 20))),
+    ("bytes=-25", 206, Some((0, 20))),
+    ("bytes=-0", 416, None),
+    // Merged when overlapping or adjacent, the whole file when disjoint
+    ("bytes=0-1,2-3", 206, Some((0, 4))),
+    ("bytes=3-8,0-5", 206, Some((0, 9))),
+    ("bytes=0-1,-2", 200, None),
+    ("bytes=0-1,5-6", 200, None),
+    // The unsatisfiable ones are left out
+    ("bytes=0-1,30-40", 206, Some((0, 2))),
+    ("bytes=30-40,-0", 416, None),
+    // Invalid, or of another unit: ignored
+    ("bytes=5-2", 200, None),
+    ("bytes=abc", 200, None),
+    ("bytes=", 200, None),
+    ("items=0-1", 200, None),
+];
+
+#[test]
+fn range_selection() {
+    let server = server();
+    for &(range, status, selected) in SELECTIONS {
+        let resp = server.get_with("/file.bin", &[("Range", range)]);
+        assert_eq!(resp.status, status, "{}", range);
+        match (status, selected) {
+            (206, Some((start, end))) => {
+                assert_eq!(resp.body, &CONTENT[start..end], "{}", range);
+                assert_eq!(
+                    resp.header("Content-Range"),
+                    Some(format!("bytes {}-{}/20", start, end - 1).as_str()),
+                    "{}",
+                    range
+                );
+            }
+            (416, _) => assert_eq!(resp.header("Content-Range"), Some("bytes */20")),
+            _ => {
+                assert_eq!(resp.body, CONTENT, "{}", range);
+                assert_eq!(resp.header("Content-Range"), None, "{}", range);
+            }
+        }
+    }
+}
+
+#[test]
+fn ranges_of_an_empty_file() {
+    let server = TestServer::start(&[("empty.bin", b"")], |s| s);
+    for range in ["bytes=0-", "bytes=0-0", "bytes=-0", "bytes=-5", "bytes=3-"] {
+        let resp = server.get_with("/empty.bin", &[("Range", range)]);
+        assert_eq!(resp.status, 200, "{}", range);
+        assert_eq!(resp.header("Content-Length"), Some("0"), "{}", range);
+        assert!(resp.body.is_empty());
+    }
+}
+
+#[test]
+fn ranges_of_a_one_byte_file() {
+    let server = TestServer::start(&[("one.bin", b"x")], |s| s);
+    for (range, status) in [
+        ("bytes=0-", 206),
+        ("bytes=0-0", 206),
+        ("bytes=0-9", 206),
+        ("bytes=-1", 206),
+        ("bytes=-9", 206),
+        ("bytes=1-", 416),
+        ("bytes=-0", 416),
+    ] {
+        let resp = server.get_with("/one.bin", &[("Range", range)]);
+        assert_eq!(resp.status, status, "{}", range);
+        if status == 206 {
+            assert_eq!(resp.body, b"x");
+            assert_eq!(resp.header("Content-Range"), Some("bytes 0-0/1"));
+        } else {
+            assert_eq!(resp.header("Content-Range"), Some("bytes */1"));
+        }
+    }
+}
@@ -0,0 +1,246 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use common::{read, TestServer};
+
+#[test]
+fn upload_saves_the_files() {
+    let server = TestServer::start(&[("dir/.keep", b"")], |s| s.upload(true).csrf(false));
+    let resp = server.upload("/dir/", &[("a.txt", b"first"), ("b.txt", b"second")], &[]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    assert_eq!(read(&server.path("dir/a.txt")), b"first");
+    assert_eq!(read(&server.path("dir/b.txt")), b"second");
+}
+
+#[test]
+fn upload_disabled() {
+    // The POST is answered like a GET
+    let server = TestServer::start(&[], |s| s.csrf(false));
+    server.upload("/", &[("a.txt", b"first")], &[]);
+    assert!(!server.path("a.txt").exists());
+}
+
+#[test]
+fn upload_requires_the_csrf_token() {
+    let server = TestServer::start(&[], |s| s.upload(true));
+    let resp = server.upload("/", &[("a.txt", b"first")], &[]);
+    assert!(resp.status >= 400, "status {}", resp.status);
+    assert!(!server.path("a.txt").exists());
+
+    let listing = server.get("/");
+    let cookie = listing
+        .header("Set-Cookie")
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    let token = cookie.strip_prefix("csrf_token=").unwrap();
+    let headers = [("Cookie", cookie.as_str()), ("X-CSRF-Token", token)];
+    let resp = server.upload("/", &[("a.txt", b"first")], &headers);
+    assert_eq!(resp.status, 302);
+    assert_eq!(read(&server.path("a.txt")), b"first");
+    // The first field of the form
+    let boundary = "----simple-http-server-test";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\n{token}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"b.txt\"\r\n\r\n\
+         second\r\n--{b}--\r\n",
+        b = boundary,
+        token = token
+    );
+    let content_type = format!("multipart/form-data; boundary={}", boundary);
+    let headers = [
+        ("Cookie", cookie.as_str()),
+        ("Content-Type", content_type.as_str()),
+    ];
+    let resp = server.request("POST", "/", &headers, body.as_bytes());
+    assert_eq!(resp.status, 302);
+    assert_eq!(read(&server.path("b.txt")), b"second");
+}
+
+#[test]
+fn upload_rejected_before_the_files() {
+    let server = TestServer::start(&[], |s| s.upload(true));
+    let boundary = "----simple-http-server-test";
+    let part = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\nwrong\r\n\
+         --{}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"a.txt\"\r\n\r\n",
+        boundary, boundary
+    );
+    // The first 4 KB of the file, the rest of the 100 MB is never sent
+    let mut stream = server.connect();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\nHost: localhost\r\nCookie: csrf_token=right\r\n\
+         Content-Type: multipart/form-data; boundary={}\r\nContent-Length: 100000000\r\n\r\n{}",
+        boundary, part
+    )
+    .unwrap();
+    stream.write_all(&[b'x'; 4096]).unwrap();
+    let mut head = [0; 12];
+    stream.read_exact(&mut head).unwrap();
+    assert_eq!(&head, b"HTTP/1.1 403");
+    assert!(!server.path("a.txt").exists());
+}
+
+#[test]
+fn upload_size_limit() {
+    let server = TestServer::start(&[], |s| s.upload(true).csrf(false).upload_size_limit(1000));
+    let resp = server.upload("/", &[("big.bin", &[b'x'; 4000])], &[]);
+    assert_eq!(resp.status, 413);
+    assert!(!server.path("big.bin").exists());
+}
+
+#[test]
+fn upload_does_not_escape_the_root() {
+    let server = TestServer::start(&[("dir/.keep", b"")], |s| s.upload(true).csrf(false));
+    server.upload("/dir/", &[("../../escaped.txt", b"x")], &[]);
+    assert!(!server.root.parent().unwrap().join("escaped.txt").exists());
+}
+
+#[test]
+fn audit_log_of_the_changes() {
+    let log = std::env::temp_dir().join(format!("simple-http-server-audit-{}", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let server = TestServer::start(&[("dir/.keep", b"")], |s| {
+        s.upload(true)
+            .delete(true)
+            .csrf(false)
+            .auth("user:pass:rw")
+            .audit_log(&log)
+    });
+    // user:pass
+    let user = [("Authorization", "Basic dXNlcjpwYXNz")];
+    server.upload("/dir/", &[("a.txt", b"first")], &user);
+    server.request("DELETE", "/dir/a.txt", &user, b"");
+    server.request("DELETE", "/missing.txt", &user, b"");
+    let lines = String::from_utf8(read(&log)).unwrap();
+    let _ = std::fs::remove_file(&log);
+    let events = lines
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(events.len(), 3, "{}", lines);
+    assert_eq!(events[0]["event"], "upload");
+    assert_eq!(events[0]["path"], "/dir/a.txt");
+    assert_eq!(events[0]["size"], 5);
+    assert_eq!(events[0]["user"], "user");
+    assert_eq!(events[0]["client_ip"], "127.0.0.1");
+    assert_eq!(events[0]["result"], "ok");
+    assert_eq!(events[1]["event"], "delete");
+    assert_eq!(events[1]["path"], "/dir/a.txt");
+    assert_eq!(events[1]["result"], "ok");
+    assert_eq!(events[2]["path"], "/missing.txt");
+    assert_eq!(events[2]["result"], "failed");
+    assert_eq!(events[2]["status"], 404);
+}
+
+#[test]
+fn upload_only_drop_box() {
+    let files: &[(&str, &[u8])] = &[("dir/secret.txt", b"secret")];
+    let server = TestServer::start(files, |s| s.upload(true).upload_only(true).csrf(false));
+    let resp = server.get("/dir/");
+    assert_eq!(resp.status, 200);
+    let page = resp.text();
+    assert!(page.contains("upload-form"), "{}", page);
+    assert!(!page.contains("secret.txt"), "{}", page);
+    assert_eq!(server.get("/dir/secret.txt").status, 404);
+    // Nor archived
+    assert!(!server.get("/dir/?download=tar").text().contains("secret"));
+    let resp = server.request("DELETE", "/dir/secret.txt", &[], b"");
+    assert_eq!(resp.status, 404);
+    assert!(server.path("dir/secret.txt").exists());
+
+    let resp = server.upload("/dir/", &[("new.txt", b"new")], &[]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    assert_eq!(read(&server.path("dir/new.txt")), b"new");
+    // Nothing replaced
+    let resp = server.upload("/dir/", &[("secret.txt", b"replaced")], &[]);
+    assert_eq!(resp.status, 409);
+    assert_eq!(read(&server.path("dir/secret.txt")), b"secret");
+}
+
+#[test]
+fn uploads_per_hour_limit() {
+    let server = TestServer::start(&[], |s| s.upload(true).csrf(false).upload_max_per_hour(2));
+    let resp = server.upload(
+        "/",
+        &[("a.txt", b"a"), ("b.txt", b"b"), ("c.txt", b"c")],
+        &[],
+    );
+    assert_eq!(resp.status, 429);
+    assert!(!server.path("a.txt").exists());
+    let resp = server.upload("/", &[("a.txt", b"a"), ("b.txt", b"b")], &[]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    let resp = server.upload("/", &[("c.txt", b"c")], &[]);
+    assert_eq!(resp.status, 429);
+    assert!(resp.header("Retry-After").is_some());
+    assert!(!server.path("c.txt").exists());
+}
+
+#[test]
+fn upload_bandwidth_limit() {
+    let server = TestServer::start(&[], |s| {
+        s.upload(true).csrf(false).upload_limit_rate(20_000)
+    });
+    let started = std::time::Instant::now();
+    // A second of burst, then 20 KB/s
+    let resp = server.upload("/", &[("big.bin", &[b'x'; 40_000])], &[]);
+    assert!(resp.status < 400, "status {}", resp.status);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(900));
+    assert_eq!(read(&server.path("big.bin")).len(), 40_000);
+}
+
+#[test]
+fn uploads_expire() {
+    let server = TestServer::start(&[("dir/.keep", b"")], |s| {
+        s.upload(true)
+            .csrf(false)
+            .expire(std::time::Duration::from_secs(2))
+    });
+    server.upload("/dir/", &[("a.txt", b"first")], &[]);
+    assert!(server.get("/dir/").text().contains("expires in"));
+    assert_eq!(server.get("/dir/a.txt").status, 200);
+    std::thread::sleep(std::time::Duration::from_millis(2500));
+    // Gone even before the sweeper runs
+    assert_eq!(server.get("/dir/a.txt").status, 404);
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    assert!(!server.path("dir/a.txt").exists());
+}
+
+#[test]
+fn expired_objects_are_missing_over_s3() {
+    let server = TestServer::start(&[], |s| {
+        s.upload(true)
+            .s3("files")
+            .expire(std::time::Duration::from_secs(2))
+    });
+    assert_eq!(
+        server.request("PUT", "/files/a.txt", &[], b"first").status,
+        200
+    );
+    assert_eq!(server.get("/files/a.txt").body, b"first");
+    std::thread::sleep(std::time::Duration::from_millis(2500));
+    // Until the sweeper deletes it
+    assert_eq!(server.get("/files/a.txt").status, 404);
+    assert!(!server.get("/files?list-type=2").text().contains("a.txt"));
+}
+
+#[test]
+fn deletes_are_opt_in() {
+    let files: &[(&str, &[u8])] = &[("dir/a.txt", b"a")];
+    let server = TestServer::start(files, |s| s.upload(true).csrf(false));
+    assert!(!server.get("/dir/").text().contains(r#"class="delete""#));
+    assert_eq!(server.request("DELETE", "/dir/a.txt", &[], b"").status, 405);
+    assert!(server.path("dir/a.txt").exists());
+    let server = TestServer::start(files, |s| s.upload(true).delete(true).csrf(false));
+    assert!(server.get("/dir/").text().contains(r#"class="delete""#));
+    assert_eq!(server.request("DELETE", "/dir/a.txt", &[], b"").status, 204);
+    assert!(!server.path("dir/a.txt").exists());
+}